@@ -45,6 +45,8 @@ fn main() {
     copy("plume-front/pkg/plume_front_bg.wasm", "static/plume_front_bg.wasm")
         .and_then(|_| copy("plume-front/pkg/plume_front.js", "static/plume_front.js"))
         .ok();
+    println!("cargo:rerun-if-changed=plume-front/sw.js");
+    copy("plume-front/sw.js", "static/plume_sw.js").expect("Couldn't copy the service worker");
 
     println!("cargo:rustc-env=CACHE_ID={}", cache_id)
 }
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE timeline_definition ADD COLUMN notify BOOLEAN NOT NULL DEFAULT 'f';
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE posts ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT now();
+UPDATE posts SET updated_at = creation_date;
+CREATE INDEX posts_updated_at ON posts (updated_at);
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE timeline_definition ADD COLUMN feed_token VARCHAR UNIQUE;
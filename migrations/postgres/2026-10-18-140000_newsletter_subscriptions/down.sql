@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE email_suppressions;
+DROP TABLE newsletter_subscriptions;
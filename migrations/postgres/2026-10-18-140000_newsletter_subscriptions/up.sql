@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE newsletter_subscriptions(
+	id SERIAL PRIMARY KEY,
+	blog_id integer NOT NULL REFERENCES blogs(id) ON DELETE CASCADE,
+	email VARCHAR NOT NULL,
+	token VARCHAR NOT NULL UNIQUE,
+	confirmed BOOLEAN NOT NULL DEFAULT 'f',
+	expiration_date TIMESTAMP NOT NULL,
+	unsubscribe_token VARCHAR NOT NULL UNIQUE,
+	lang VARCHAR NOT NULL DEFAULT '',
+	CONSTRAINT newsletter_subscriptions_unique_blog_email UNIQUE(blog_id, email)
+);
+CREATE TABLE email_suppressions(
+	id SERIAL PRIMARY KEY,
+	email VARCHAR NOT NULL UNIQUE,
+	creation_date TIMESTAMP NOT NULL DEFAULT now()
+);
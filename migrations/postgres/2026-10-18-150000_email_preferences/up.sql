@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE email_preferences(
+	id SERIAL PRIMARY KEY,
+	user_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	kind VARCHAR NOT NULL,
+	frequency integer NOT NULL DEFAULT 0,
+	lang VARCHAR NOT NULL DEFAULT 'en',
+	last_sent_date TIMESTAMP NOT NULL DEFAULT now(),
+	CONSTRAINT email_preferences_unique_user_kind UNIQUE(user_id, kind)
+);
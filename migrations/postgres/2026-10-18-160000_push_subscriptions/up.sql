@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE push_subscriptions(
+	id SERIAL PRIMARY KEY,
+	user_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	endpoint VARCHAR NOT NULL UNIQUE,
+	p256dh VARCHAR NOT NULL,
+	auth VARCHAR NOT NULL,
+	lang VARCHAR NOT NULL DEFAULT 'en',
+	creation_date TIMESTAMP NOT NULL DEFAULT now(),
+	last_notification_id integer NOT NULL DEFAULT 0
+);
+CREATE TABLE vapid_keys(
+	id SERIAL PRIMARY KEY,
+	private_key TEXT NOT NULL,
+	public_key VARCHAR NOT NULL
+);
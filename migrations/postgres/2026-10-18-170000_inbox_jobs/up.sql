@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE inbox_jobs(
+	id SERIAL PRIMARY KEY,
+	activity TEXT NOT NULL,
+	actor_id VARCHAR NOT NULL,
+	attempts INTEGER NOT NULL DEFAULT 0,
+	next_attempt_date TIMESTAMP NOT NULL DEFAULT now(),
+	last_error TEXT,
+	failed BOOLEAN NOT NULL DEFAULT 'f',
+	creation_date TIMESTAMP NOT NULL DEFAULT now()
+);
+CREATE INDEX inbox_jobs_next_attempt_date ON inbox_jobs (failed, next_attempt_date);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users DROP COLUMN ed25519_private_key;
+ALTER TABLE users DROP COLUMN ed25519_public_key;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE relays(
+	id SERIAL PRIMARY KEY,
+	actor_id VARCHAR NOT NULL UNIQUE,
+	inbox_url VARCHAR NOT NULL,
+	public_key TEXT NOT NULL,
+	follow_id VARCHAR NOT NULL,
+	follow_object VARCHAR NOT NULL,
+	state INTEGER NOT NULL DEFAULT 0,
+	creation_date TIMESTAMP NOT NULL DEFAULT now()
+);
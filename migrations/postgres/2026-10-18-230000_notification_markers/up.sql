@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE notification_markers(
+	id SERIAL PRIMARY KEY,
+	user_id integer NOT NULL UNIQUE REFERENCES users(id) ON DELETE CASCADE,
+	last_read_id integer NOT NULL
+);
+-- The notifications that were already there are not new
+INSERT INTO notification_markers(user_id, last_read_id) SELECT user_id, MAX(id) FROM notifications GROUP BY user_id;
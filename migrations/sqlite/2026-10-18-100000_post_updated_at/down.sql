@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX posts_updated_at;
+ALTER TABLE posts DROP COLUMN updated_at;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE posts ADD COLUMN updated_at DATETIME NOT NULL DEFAULT '1970-01-01 00:00:00';
+UPDATE posts SET updated_at = creation_date;
+CREATE INDEX posts_updated_at ON posts (updated_at);
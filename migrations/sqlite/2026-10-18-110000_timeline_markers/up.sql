@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE timeline_markers(
+	id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	user_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	timeline_id integer NOT NULL REFERENCES timeline_definition(id) ON DELETE CASCADE,
+	last_read_date DATETIME NOT NULL,
+	CONSTRAINT timeline_markers_unique_user_timeline UNIQUE(user_id, timeline_id)
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX timeline_definition_feed_token;
+ALTER TABLE timeline_definition DROP COLUMN feed_token;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE timeline_definition ADD COLUMN feed_token VARCHAR;
+CREATE UNIQUE INDEX timeline_definition_feed_token ON timeline_definition(feed_token);
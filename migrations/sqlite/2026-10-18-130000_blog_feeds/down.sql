@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE blog_feed_items;
+DROP TABLE blog_feeds;
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE blog_feeds(
+	id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	blog_id integer NOT NULL REFERENCES blogs(id) ON DELETE CASCADE,
+	author_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	url VARCHAR NOT NULL,
+	last_fetched_at DATETIME,
+	last_error VARCHAR,
+	CONSTRAINT blog_feeds_unique_blog_url UNIQUE(blog_id, url)
+);
+CREATE TABLE blog_feed_items(
+	id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	feed_id integer NOT NULL REFERENCES blog_feeds(id) ON DELETE CASCADE,
+	post_id integer NOT NULL UNIQUE REFERENCES posts(id) ON DELETE CASCADE,
+	guid VARCHAR NOT NULL,
+	url VARCHAR NOT NULL,
+	CONSTRAINT blog_feed_items_unique_feed_guid UNIQUE(feed_id, guid)
+);
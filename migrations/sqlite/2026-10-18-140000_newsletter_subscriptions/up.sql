@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE newsletter_subscriptions(
+	id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	blog_id integer NOT NULL REFERENCES blogs(id) ON DELETE CASCADE,
+	email VARCHAR NOT NULL,
+	token VARCHAR NOT NULL UNIQUE,
+	confirmed BOOLEAN NOT NULL DEFAULT 'f',
+	expiration_date DATETIME NOT NULL,
+	unsubscribe_token VARCHAR NOT NULL UNIQUE,
+	lang VARCHAR NOT NULL DEFAULT '',
+	CONSTRAINT newsletter_subscriptions_unique_blog_email UNIQUE(blog_id, email)
+);
+CREATE TABLE email_suppressions(
+	id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	email VARCHAR NOT NULL UNIQUE,
+	creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
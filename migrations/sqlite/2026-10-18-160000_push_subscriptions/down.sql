@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE vapid_keys;
+DROP TABLE push_subscriptions;
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE push_subscriptions(
+	id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	user_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	endpoint VARCHAR NOT NULL UNIQUE,
+	p256dh VARCHAR NOT NULL,
+	auth VARCHAR NOT NULL,
+	lang VARCHAR NOT NULL DEFAULT 'en',
+	creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	last_notification_id integer NOT NULL DEFAULT 0
+);
+CREATE TABLE vapid_keys(
+	id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	private_key TEXT NOT NULL,
+	public_key VARCHAR NOT NULL
+);
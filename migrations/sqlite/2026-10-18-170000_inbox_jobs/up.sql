@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE inbox_jobs(
+	id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	activity TEXT NOT NULL,
+	actor_id VARCHAR NOT NULL,
+	attempts INTEGER NOT NULL DEFAULT 0,
+	next_attempt_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	last_error TEXT,
+	failed BOOLEAN NOT NULL DEFAULT 'f',
+	creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX inbox_jobs_next_attempt_date ON inbox_jobs (failed, next_attempt_date);
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE users ADD COLUMN ed25519_private_key TEXT;
+ALTER TABLE users ADD COLUMN ed25519_public_key TEXT;
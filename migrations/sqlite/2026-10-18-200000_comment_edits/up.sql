@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE comment_edits(
+	id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	comment_id INTEGER NOT NULL REFERENCES comments(id) ON DELETE CASCADE,
+	content TEXT NOT NULL,
+	spoiler_text TEXT NOT NULL DEFAULT '',
+	creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+ALTER TABLE timeline_definition ADD COLUMN uses_counts BOOLEAN NOT NULL DEFAULT 'f';
+-- Queries can't be parsed here, so all the timelines that may use counts are flagged. The flag is computed
+-- exactly the next time their query is saved.
+UPDATE timeline_definition SET uses_counts = 't'
+    WHERE LOWER(query) LIKE '%likes%' OR LOWER(query) LIKE '%reshares%' OR LOWER(query) LIKE '%comments%';
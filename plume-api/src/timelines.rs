@@ -0,0 +1,37 @@
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ExplanationData {
+    pub clause: String,
+    pub matched: bool,
+    // Set if this clause could not be evaluated, for instance because of a missing list
+    pub error: Option<String>,
+    pub children: Vec<ExplanationData>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PostPreviewData {
+    pub post_id: i32,
+    pub title: String,
+    pub matched: bool,
+    pub explanation: ExplanationData,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TimelinePreviewData {
+    pub query: String,
+    pub tree: String,
+    pub posts: Vec<PostPreviewData>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TimelineMarkerData {
+    pub timeline_id: i32,
+    // The creation date of the most recent post that was read, if any
+    pub last_read_date: Option<String>,
+    pub unread: i64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct NewTimelineMarkerData {
+    // The most recent post that was read
+    pub post_id: i32,
+}
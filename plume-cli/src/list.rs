@@ -1,6 +1,6 @@
 use clap::{Arg, ArgMatches, Command};
 
-use plume_models::{blogs::Blog, instance::Instance, lists::*, users::User, Connection};
+use plume_models::{blogs::Blog, instance::Instance, lists::*, timeline::Timeline, users::User, Connection};
 
 pub fn command() -> Command {
     Command::new("lists")
@@ -190,6 +190,7 @@ async fn add(mut args: ArgMatches, conn: &mut Connection) {
             }
         }
     }
+    reevaluate_timelines(&list, conn).await;
 }
 
 async fn rm(mut args: ArgMatches, conn: &mut Connection) {
@@ -236,4 +237,11 @@ async fn rm(mut args: ArgMatches, conn: &mut Connection) {
             }
         }
     }
+    reevaluate_timelines(&list, conn).await;
+}
+
+async fn reevaluate_timelines(list: &List, conn: &mut Connection) {
+    for timeline in Timeline::list_using_list(conn, list).expect("Failed to list the timelines using this list") {
+        crate::timeline::reevaluate(&timeline, conn).await;
+    }
 }
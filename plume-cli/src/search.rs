@@ -1,9 +1,13 @@
 use clap::{Arg, ArgMatches, Command};
 
-use plume_models::{search::Searcher, Connection, CONFIG};
+use chrono::{NaiveDate, NaiveDateTime};
+use plume_models::{
+    search::{ReindexOptions, Searcher},
+    Connection, CONFIG,
+};
 use std::fs::{read_dir, remove_file};
-use std::io::ErrorKind;
-use std::path::Path;
+use std::io::{stderr, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 
 pub fn command() -> Command {
     Command::new("search")
@@ -37,8 +41,41 @@ pub fn command() -> Command {
                         .required(false)
                         .help("Path to Plume's working directory"),
                 )
+                .arg(
+                    Arg::new("since")
+                        .short('s')
+                        .long("since")
+                        .action(clap::ArgAction::Set)
+                        .help("Only reindex posts modified since this date (YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS)"),
+                )
+                .arg(
+                    Arg::new("batch-size")
+                        .short('b')
+                        .long("batch-size")
+                        .action(clap::ArgAction::Set)
+                        .value_parser(clap::value_parser!(i64))
+                        .help("Number of posts to index between two commits"),
+                )
+                .arg(
+                    Arg::new("restart")
+                        .long("restart")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Ignore the checkpoint left by an interrupted refill and start over"),
+                )
                 .about("Regenerate Plume's search index"),
         )
+        .subcommand(
+            Command::new("verify")
+                .arg(
+                    Arg::new("path")
+                        .short('p')
+                        .long("path")
+                        .action(clap::ArgAction::Set)
+                        .required(false)
+                        .help("Path to Plume's working directory"),
+                )
+                .about("Report posts missing from the search index, and stale or outdated index entries"),
+        )
         .subcommand(
             Command::new("unlock")
                 .arg(
@@ -57,7 +94,8 @@ pub fn run(mut args: ArgMatches, conn: &mut Connection) {
     args.remove_subcommand()
         .map(|(c, a)| match c.as_str() {
             "init" => init(a, conn),
-            "refill" => refill(a, conn, None),
+            "refill" => refill(a, conn),
+            "verify" => verify(a, conn),
             "unlock" => unlock(a),
             _ => command().print_help().unwrap(),
         })
@@ -85,22 +123,101 @@ fn init(mut args: ArgMatches, conn: &mut Connection) {
     };
     if can_do || force {
         let searcher = Searcher::create(&path, &CONFIG.search_tokenizers).unwrap();
-        refill(args, conn, Some(searcher));
+        reindex(
+            conn,
+            &searcher,
+            &ReindexOptions {
+                resume: false,
+                ..ReindexOptions::default()
+            },
+        );
     } else {
         eprintln!("Can't create new index, {} exist and is not empty", path.to_str().unwrap());
     }
 }
 
-fn refill(mut args: ArgMatches, conn: &mut Connection, searcher: Option<Searcher>) {
-    let path = match args.remove_one::<String>("path") {
+fn index_path(args: &mut ArgMatches) -> PathBuf {
+    match args.remove_one::<String>("path") {
         Some(path) => Path::new(&path).join("search_index"),
         None => Path::new(&CONFIG.search_index).to_path_buf(),
+    }
+}
+
+fn parse_since(since: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(since, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDate::parse_from_str(since, "%Y-%m-%d").map(|d| d.and_hms_opt(0, 0, 0).unwrap()))
+        .expect("Invalid date, expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS")
+}
+
+fn refill(mut args: ArgMatches, conn: &mut Connection) {
+    let path = index_path(&mut args);
+    // recreates the index if it was created with an older schema
+    let searcher = Searcher::open_or_recreate(&path, &CONFIG.search_tokenizers);
+
+    let mut options = ReindexOptions {
+        since: args.remove_one::<String>("since").map(|since| parse_since(&since)),
+        resume: !args.get_flag("restart"),
+        ..ReindexOptions::default()
     };
-    let searcher = searcher.unwrap_or_else(|| Searcher::open(&path, &CONFIG.search_tokenizers).unwrap());
+    if let Some(batch_size) = args.remove_one::<i64>("batch-size") {
+        options.batch_size = batch_size;
+    }
 
-    searcher.fill(conn).expect("Couldn't import post");
-    println!("Commiting result");
-    searcher.commit();
+    if let Some(checkpoint) = searcher.reindex_checkpoint() {
+        // a recreated index starts before the first post
+        if options.resume && checkpoint.since == options.since && checkpoint.last_post_id >= 0 {
+            println!("Resuming interrupted refill after post {}", checkpoint.last_post_id);
+        }
+    }
+    reindex(conn, &searcher, &options);
+}
+
+fn reindex(conn: &mut Connection, searcher: &Searcher, options: &ReindexOptions) {
+    const BAR_WIDTH: i64 = 40;
+    searcher
+        .reindex(conn, options, |done, total| {
+            let filled = if total == 0 {
+                BAR_WIDTH
+            } else {
+                done * BAR_WIDTH / total
+            };
+            eprint!(
+                "\r[{}{}] {}/{}",
+                "#".repeat(filled as usize),
+                " ".repeat((BAR_WIDTH - filled) as usize),
+                done,
+                total
+            );
+            stderr().flush().ok();
+        })
+        .expect("Couldn't import post");
+    eprintln!();
+    println!("Search index refilled");
+}
+
+fn verify(mut args: ArgMatches, conn: &mut Connection) {
+    let path = index_path(&mut args);
+    let searcher = Searcher::open(&path, &CONFIG.search_tokenizers).unwrap();
+
+    let report = searcher.verify(conn).expect("Couldn't verify the search index");
+    let print_ids = |ids: &[i32]| ids.iter().map(i32::to_string).collect::<Vec<_>>().join(", ");
+    if !report.missing.is_empty() {
+        println!("{} post(s) missing from the index: {}", report.missing.len(), print_ids(&report.missing));
+    }
+    if !report.stale.is_empty() {
+        println!("{} stale index entry(ies): {}", report.stale.len(), print_ids(&report.stale));
+    }
+    if !report.duplicated.is_empty() {
+        println!("{} post(s) indexed more than once: {}", report.duplicated.len(), print_ids(&report.duplicated));
+    }
+    if !report.outdated.is_empty() {
+        println!("{} post(s) indexed with outdated values: {}", report.outdated.len(), print_ids(&report.outdated));
+    }
+    if report.is_clean() {
+        println!("The search index is up to date");
+    } else {
+        println!("Run `plm search refill` to fix the index");
+    }
 }
 
 fn unlock(mut args: ArgMatches) {
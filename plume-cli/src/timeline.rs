@@ -1,6 +1,7 @@
 use clap::{Arg, ArgMatches, Command};
 
-use plume_models::{instance::Instance, posts::Post, timeline::*, users::*, Connection};
+use plume_models::{instance::Instance, posts::Post, timeline::*, users::*, Connection, CONFIG};
+use std::io::{stderr, Write};
 
 pub fn command() -> Command {
     Command::new("timeline")
@@ -35,6 +36,12 @@ pub fn command() -> Command {
                         .action(clap::ArgAction::Set)
                         .help("Number of posts to try to preload in this timeline at its creation"),
                 )
+                .arg(
+                    Arg::new("notify")
+                        .long("notify")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Notify the owner of this timeline when a post is added to it"),
+                )
                 .about("Create a new timeline"),
         )
         .subcommand(
@@ -79,6 +86,13 @@ pub fn command() -> Command {
                         .action(clap::ArgAction::Set)
                         .help("The query posts in this timelines have to match"),
                 )
+                .arg(
+                    Arg::new("notify")
+                        .long("notify")
+                        .action(clap::ArgAction::Set)
+                        .value_parser(clap::value_parser!(bool))
+                        .help("Whether the owner of this timeline should be notified when a post is added to it"),
+                )
                 .about("Edit the query of a timeline"),
         )
         .subcommand(
@@ -104,7 +118,33 @@ pub fn command() -> Command {
                         .action(clap::ArgAction::Set)
                         .help("Number of posts to try to preload in this timeline at its creation"),
                 )
-                .about("Repopulate a timeline. Run this after modifying a list the timeline depends on."),
+                .about("Repopulate a timeline from scratch"),
+        )
+        .subcommand(
+            Command::new("preview")
+                .arg(
+                    Arg::new("query")
+                        .short('q')
+                        .long("query")
+                        .action(clap::ArgAction::Set)
+                        .help("The query to preview"),
+                )
+                .arg(
+                    Arg::new("user")
+                        .short('u')
+                        .long("user")
+                        .action(clap::ArgAction::Set)
+                        .help("Username of whom the timeline would be for. Empty for an instance timeline"),
+                )
+                .arg(
+                    Arg::new("count")
+                        .short('c')
+                        .long("count")
+                        .action(clap::ArgAction::Set)
+                        .value_parser(clap::value_parser!(i64))
+                        .help("Number of recent posts to test the query against"),
+                )
+                .about("Show how a query would match the latest posts, without saving it"),
         )
 }
 
@@ -112,9 +152,10 @@ pub async fn run(mut args: ArgMatches, conn: &mut Connection) {
     if let Some((c, a)) = args.remove_subcommand() {
         match c.as_str() {
             "new" => new(a, conn).await,
-            "edit" => edit(a, conn),
+            "edit" => edit(a, conn).await,
             "delete" => delete(a, conn),
             "repopulate" => repopulate(a, conn).await,
+            "preview" => preview(a, conn).await,
             _ => command().print_help().unwrap(),
         }
     } else {
@@ -130,8 +171,12 @@ fn get_timeline_identifier(args: &mut ArgMatches) -> (String, Option<String>) {
 
 fn get_query(args: &mut ArgMatches) -> String {
     let query = args.remove_one::<String>("query").expect("No query provided");
+    check_query(&query);
+    query
+}
 
-    match TimelineQuery::parse(&query) {
+fn check_query(query: &str) {
+    match TimelineQuery::parse(query) {
         Ok(_) => (),
         Err(QueryError::SyntaxError(start, end, message)) => {
             panic!("Query parsing error between {} and {}: {}", start, end, message)
@@ -141,8 +186,6 @@ fn get_query(args: &mut ArgMatches) -> String {
         }
         Err(QueryError::RuntimeError(message)) => panic!("Query parsing error: {}", message),
     }
-
-    query
 }
 
 fn get_preload_count(args: &mut ArgMatches) -> usize {
@@ -181,30 +224,63 @@ async fn new(mut args: ArgMatches, conn: &mut Connection) {
     let (name, user) = get_timeline_identifier(&mut args);
     let query = get_query(&mut args);
     let preload_count = get_preload_count(&mut args);
+    let notify = args.get_flag("notify");
 
     let user = user.map(|user| resolve_user(&user, conn));
 
-    let timeline = if let Some(user) = user {
+    let mut timeline = if let Some(user) = user {
         Timeline::new_for_user(conn, user.id, name, query)
     } else {
         Timeline::new_for_instance(conn, name, query)
     }
     .expect("Failed to create new timeline");
 
+    if notify {
+        timeline.notify = true;
+        timeline = timeline.update(conn).expect("Failed to update timeline");
+    }
+
     preload(timeline, preload_count, conn).await;
 }
 
-fn edit(mut args: ArgMatches, conn: &mut Connection) {
+async fn edit(mut args: ArgMatches, conn: &mut Connection) {
     let (name, user) = get_timeline_identifier(&mut args);
-    let query = get_query(&mut args);
+    let query = args.remove_one::<String>("query");
+    if let Some(ref query) = query {
+        check_query(query);
+    }
+    let notify = args.remove_one::<bool>("notify");
 
     let user = user.map(|user| resolve_user(&user, conn));
 
     let mut timeline = Timeline::find_for_user_by_name(conn, user.map(|u| u.id), &name).expect("timeline not found");
 
-    timeline.query = query;
+    let query_changed = query.is_some();
+    if let Some(query) = query {
+        timeline.query = query;
+    }
+    if let Some(notify) = notify {
+        timeline.notify = notify;
+    }
+
+    let timeline = timeline.update(conn).expect("Failed to update timeline");
+    if query_changed {
+        reevaluate(&timeline, conn).await;
+    }
+}
 
-    timeline.update(conn).expect("Failed to update timeline");
+/// Evaluates a timeline again against the latest posts.
+///
+/// The server is not told about changes made from here, so it has to be done right away.
+pub async fn reevaluate(timeline: &Timeline, conn: &mut Connection) {
+    timeline
+        .reevaluate(conn, CONFIG.timeline_reevaluation_window, |done, total| {
+            eprint!("\rUpdating timeline '{}': {}/{}", timeline.name, done, total);
+            stderr().flush().ok();
+        })
+        .await
+        .expect("Failed to update timeline");
+    eprintln!();
 }
 
 fn delete(mut args: ArgMatches, conn: &mut Connection) {
@@ -230,3 +306,53 @@ async fn repopulate(mut args: ArgMatches, conn: &mut Connection) {
     let timeline = Timeline::find_for_user_by_name(conn, user.map(|u| u.id), &name).expect("timeline not found");
     preload(timeline, preload_count, conn).await;
 }
+
+fn print_explanation(explanation: &Explanation, depth: usize) {
+    let status = match explanation.result {
+        Ok(true) => "✓".to_owned(),
+        Ok(false) => "✗".to_owned(),
+        Err(ref message) => format!("error: {}", message),
+    };
+    println!("{}{} {}", "  ".repeat(depth), status, explanation.clause);
+    for child in &explanation.children {
+        print_explanation(child, depth + 1);
+    }
+}
+
+async fn preview(mut args: ArgMatches, conn: &mut Connection) {
+    let query = get_query(&mut args);
+    let user = args.remove_one::<String>("user");
+    let count = args.remove_one::<i64>("count").unwrap_or(plume_models::ITEMS_PER_PAGE.into());
+
+    let user = user.map(|user| resolve_user(&user, conn));
+    let parsed = TimelineQuery::parse(&query).expect("Query was already checked");
+    println!("Query: {}", parsed);
+    println!("{}", parsed.dump_tree());
+
+    let posts = match Timeline::preview(conn, user.map(|u| u.id), &query, count).await {
+        Ok(posts) => posts,
+        Err(plume_models::Error::TimelineQuery(err)) => {
+            eprintln!("Invalid query: {}", err);
+            return;
+        }
+        Err(err) => panic!("Failed to preview timeline: {:?}", err),
+    };
+
+    let matching = posts.iter().filter(|(_, explanation)| explanation.matched()).count();
+    for (post, explanation) in &posts {
+        println!();
+        println!(
+            "[{}] {} (#{})",
+            if explanation.matched() {
+                "match"
+            } else {
+                "no match"
+            },
+            post.title,
+            post.id
+        );
+        print_explanation(explanation, 1);
+    }
+    println!();
+    println!("{} of the {} latest posts match this query", matching, posts.len());
+}
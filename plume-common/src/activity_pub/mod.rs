@@ -48,6 +48,7 @@ pub fn context() -> serde_json::Value {
     json!([
         CONTEXT_URL,
         "https://w3id.org/security/v1",
+        "https://w3id.org/security/multikey/v1",
         {
             "manuallyApprovesFollowers": "as:manuallyApprovesFollowers",
             "sensitive": "as:sensitive",
@@ -149,6 +150,77 @@ where
     .connect_timeout(std::time::Duration::from_secs(5))
     .build()
     .expect("Can't build client");
+    let deliveries = boxes
+        .into_iter()
+        .filter_map(|inbox| {
+            let body = signed.to_string();
+            let mut headers = request::headers();
+            let url = match Url::parse(&inbox) {
+                Ok(url) => url,
+                Err(_) => {
+                    warn!("Inbox is invalid URL: {:?}", &inbox);
+                    return None;
+                }
+            };
+            if !url.has_host() {
+                warn!("Inbox doesn't have host: {:?}", &inbox);
+                return None;
+            };
+            let host_header_value = HeaderValue::from_str(url.host_str().expect("Unreachable"));
+            if host_header_value.is_err() {
+                warn!("Header value is invalid: {:?}", url.host_str());
+                return None;
+            }
+            headers.insert("Host", host_header_value.unwrap());
+            headers.insert("Digest", request::Digest::digest(&body));
+            headers.insert("Content-Digest", request::Digest::content_digest(&body));
+
+            let host = url.host_str().expect("Unreachable").to_owned();
+            let message_signed = |algorithm| {
+                let (input, signature) = request::message_signature(sender, algorithm, &headers, "post", &url)
+                    .expect("activity_pub::broadcast: request signature error");
+                client
+                    .post(&inbox)
+                    .headers(headers.clone())
+                    .header("Signature-Input", input)
+                    .header("Signature", signature)
+                    .body(body.clone())
+            };
+            let (message_signed, ed25519_signed) = match request::signature_kind(&host) {
+                request::SignatureKind::Message(algorithm) => {
+                    let ed25519_signed = (algorithm != sign::Algorithm::Ed25519
+                        && sender.key_id_with(sign::Algorithm::Ed25519).is_some())
+                    .then(|| message_signed(sign::Algorithm::Ed25519));
+                    (Some((algorithm, message_signed(algorithm))), ed25519_signed)
+                }
+                request::SignatureKind::Cavage => (None, None),
+            };
+            headers.insert(
+                "Signature",
+                request::signature(sender, &headers, ("post", url.path(), url.query()))
+                    .expect("activity_pub::broadcast: request signature error"),
+            );
+            let cavage_signed = client.post(&inbox).headers(headers.clone()).body(body);
+            Some(Delivery {
+                host,
+                message_signed,
+                ed25519_signed,
+                cavage_signed,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // A runtime can't be started on a thread that is already driving one, like the ones of Rocket or of the inbox
+    // workers: the activity is then delivered from another thread.
+    if runtime::Handle::try_current().is_ok() {
+        std::thread::spawn(move || deliver(deliveries));
+    } else {
+        deliver(deliveries);
+    }
+}
+
+/// Posts the activities, a few at a time
+fn deliver(deliveries: Vec<Delivery>) {
     let rt = runtime::Builder::new_current_thread()
         .enable_all()
         .build()
@@ -158,64 +230,74 @@ where
         // after broadcasting, target instance sends request to this instance,
         // and Plume accesses database at that time.
         let capacity = 6;
-        let (tx, rx) = flume::bounded::<RequestBuilder>(capacity);
+        let (tx, rx) = flume::bounded::<Delivery>(capacity);
         let mut handles = Vec::with_capacity(capacity);
         for _ in 0..capacity {
             let rx = rx.clone();
             let handle = rt.spawn(async move {
-                while let Ok(request_builder) = rx.recv_async().await {
+                while let Ok(delivery) = rx.recv_async().await {
                     // After broadcasting, target instance sends request to this instance.
                     // Sleep here in order to reduce requests at once
                     sleep(Duration::from_millis(500)).await;
-                    let _ = request_builder
-                        .send()
-                        .await
-                        .map(move |r| {
-                            if r.status().is_success() {
-                                debug!("Successfully sent activity to inbox ({})", &r.url());
-                            } else {
-                                warn!("Error while sending to inbox ({:?})", &r)
-                            }
-                            debug!("Response: \"{:?}\"\n", r);
-                        })
-                        .map_err(|e| warn!("Error while sending to inbox ({:?})", e));
+                    delivery.send().await;
                 }
             });
             handles.push(handle);
         }
-        for inbox in boxes {
-            let body = signed.to_string();
-            let mut headers = request::headers();
-            let url = Url::parse(&inbox);
-            if url.is_err() {
-                warn!("Inbox is invalid URL: {:?}", &inbox);
-                continue;
-            }
-            let url = url.unwrap();
-            if !url.has_host() {
-                warn!("Inbox doesn't have host: {:?}", &inbox);
-                continue;
-            };
-            let host_header_value = HeaderValue::from_str(url.host_str().expect("Unreachable"));
-            if host_header_value.is_err() {
-                warn!("Header value is invalid: {:?}", url.host_str());
-                continue;
-            }
-            headers.insert("Host", host_header_value.unwrap());
-            headers.insert("Digest", request::Digest::digest(&body));
-            headers.insert(
-                "Signature",
-                request::signature(sender, &headers, ("post", url.path(), url.query()))
-                    .expect("activity_pub::broadcast: request signature error"),
-            );
-            let request_builder = client.post(&inbox).headers(headers.clone()).body(body);
-            let _ = tx.send_async(request_builder).await;
+        for delivery in deliveries {
+            let _ = tx.send_async(delivery).await;
         }
         drop(tx);
         join_all(handles).await;
     });
 }
 
+/// An activity to post to an inbox, signed with all the kinds of HTTP signatures the host may ask for
+struct Delivery {
+    host: String,
+    /// Signed with RFC 9421, unless the host recently asked for draft-cavage signatures
+    message_signed: Option<(sign::Algorithm, RequestBuilder)>,
+    /// Signed with RFC 9421 and the Ed25519 key, if the sender has one and it wasn't used for `message_signed`
+    ed25519_signed: Option<RequestBuilder>,
+    cavage_signed: RequestBuilder,
+}
+
+impl Delivery {
+    /// Posts the activity with an RFC 9421 signature, or with the kind of signature the host asks for if it rejects it
+    async fn send(self) {
+        if let Some((algorithm, request_builder)) = self.message_signed {
+            let res = request_builder.send().await;
+            let retry = res.as_ref().ok().and_then(|r| {
+                request::signature_retry(r.status(), r.headers(), algorithm, self.ed25519_signed.is_some())
+            });
+            match retry {
+                None => return Self::log(res),
+                Some(kind) => {
+                    debug!("{} rejected an RFC 9421 signature, now sending {:?}", self.host, kind);
+                    request::remember_signature_kind(&self.host, kind);
+                    if let (request::SignatureKind::Message(_), Some(ed25519_signed)) = (kind, self.ed25519_signed) {
+                        return Self::log(ed25519_signed.send().await);
+                    }
+                }
+            }
+        }
+        Self::log(self.cavage_signed.send().await)
+    }
+
+    fn log(res: reqwest::Result<reqwest::Response>) {
+        let _ = res
+            .map(move |r| {
+                if r.status().is_success() {
+                    debug!("Successfully sent activity to inbox ({})", &r.url());
+                } else {
+                    warn!("Error while sending to inbox ({:?})", &r)
+                }
+                debug!("Response: \"{:?}\"\n", r);
+            })
+            .map_err(|e| warn!("Error while sending to inbox ({:?})", e));
+    }
+}
+
 #[derive(Shrinkwrap, Clone, Serialize, Deserialize)]
 pub struct Id(String);
 
@@ -267,6 +349,63 @@ where
     }
 }
 
+/// A public key, in the format of the Controlled Identifiers specification
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Multikey {
+    pub id: IriString,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub controller: IriString,
+    pub public_key_multibase: String,
+}
+
+/// The keys an actor can sign with, other than its `publicKey`
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AssertionMethod {
+    pub assertion_method: Option<Vec<Multikey>>,
+}
+
+impl AssertionMethod {
+    /// The Ed25519 key of the actor, as PEM, if it has a valid one
+    pub fn ed25519_public_key(&self) -> Option<String> {
+        self.assertion_method
+            .iter()
+            .flatten()
+            .filter(|key| key.kind == "Multikey")
+            .find_map(|key| sign::ed25519_from_multibase(&key.public_key_multibase).ok())
+    }
+}
+
+impl<U> UnparsedExtension<U> for AssertionMethod
+where
+    U: UnparsedMutExt,
+{
+    type Error = serde_json::Error;
+
+    fn try_from_unparsed(unparsed_mut: &mut U) -> Result<Self, Self::Error> {
+        // Some actors have a single key, or keys of other types: these are ignored
+        let assertion_method: Option<serde_json::Value> = unparsed_mut.remove("assertionMethod")?;
+        Ok(AssertionMethod {
+            assertion_method: assertion_method.map(|keys| {
+                let keys = match keys {
+                    serde_json::Value::Array(keys) => keys,
+                    key => vec![key],
+                };
+                keys.into_iter().filter_map(|key| serde_json::from_value(key).ok()).collect()
+            }),
+        })
+    }
+
+    fn try_into_unparsed(self, unparsed_mut: &mut U) -> Result<(), Self::Error> {
+        if let Some(assertion_method) = self.assertion_method {
+            unparsed_mut.insert("assertionMethod", assertion_method)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct SourceProperty {
@@ -291,7 +430,7 @@ where
     }
 }
 
-pub type CustomPerson = Ext1<ApActor<Person>, ApSignature>;
+pub type CustomPerson = Ext2<ApActor<Person>, ApSignature, AssertionMethod>;
 pub type CustomGroup = Ext2<ApActor<Group>, ApSignature, SourceProperty>;
 
 kind!(HashtagType, Hashtag);
@@ -576,6 +715,14 @@ mod tests {
                     public_key_pem: "pubKeyPem".into(),
                 },
             },
+            AssertionMethod {
+                assertion_method: Some(vec![Multikey {
+                    id: "https://example.com/owner#ed25519-key".parse().unwrap(),
+                    kind: "Multikey".into(),
+                    controller: "https://example.com/owner".parse().unwrap(),
+                    public_key_multibase: "z6Mkabc".into(),
+                }]),
+            },
         );
         let expected = json!({
             "inbox": "https://example.com/inbox",
@@ -584,11 +731,61 @@ mod tests {
                 "id": "https://example.com/pubkey",
                 "owner": "https://example.com/owner",
                 "publicKeyPem": "pubKeyPem"
-            }
+            },
+            "assertionMethod": [{
+                "id": "https://example.com/owner#ed25519-key",
+                "type": "Multikey",
+                "controller": "https://example.com/owner",
+                "publicKeyMultibase": "z6Mkabc"
+            }]
         });
         assert_eq!(to_value(person).unwrap(), expected);
     }
 
+    #[test]
+    fn de_assertion_method() {
+        let (public_key, _) = sign::gen_ed25519_keypair();
+        let public_key = String::from_utf8(public_key).unwrap();
+        let multibase = sign::ed25519_to_multibase(&public_key).unwrap();
+        assert!(multibase.starts_with("z6Mk"));
+
+        let person: CustomPerson = from_str(&format!(
+            r#"{{
+                "type": "Person",
+                "inbox": "https://example.com/inbox",
+                "publicKey": {{
+                    "id": "https://example.com/pubkey",
+                    "owner": "https://example.com/owner",
+                    "publicKeyPem": "pubKeyPem"
+                }},
+                "assertionMethod": {{
+                    "id": "https://example.com/owner#ed25519-key",
+                    "type": "Multikey",
+                    "controller": "https://example.com/owner",
+                    "publicKeyMultibase": "{}"
+                }}
+            }}"#,
+            multibase
+        ))
+        .unwrap();
+        assert_eq!(person.ext_two.ed25519_public_key(), Some(public_key));
+
+        // Actors without other keys still have a valid public key
+        let person: CustomPerson = from_str(
+            r#"{
+                "type": "Person",
+                "inbox": "https://example.com/inbox",
+                "publicKey": {
+                    "id": "https://example.com/pubkey",
+                    "owner": "https://example.com/owner",
+                    "publicKeyPem": "pubKeyPem"
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(person.ext_two, AssertionMethod::default());
+    }
+
     #[test]
     fn se_custom_group() {
         let group = CustomGroup::new(
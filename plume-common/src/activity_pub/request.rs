@@ -4,13 +4,15 @@ use openssl::hash::{Hasher, MessageDigest};
 use reqwest::{
     blocking::{ClientBuilder, Response},
     header::{HeaderMap, HeaderValue, InvalidHeaderValue, ACCEPT, CONTENT_TYPE, DATE, HOST, USER_AGENT},
-    Proxy, Url,
+    Proxy, StatusCode, Url,
 };
+use std::collections::HashMap;
 use std::ops::Deref;
-use std::time::SystemTime;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 use tracing::warn;
 
-use crate::activity_pub::sign::Signer;
+use crate::activity_pub::sign::{message_signature_base, Algorithm, Signer};
 use crate::activity_pub::{ap_accept_header, AP_CONTENT_TYPE};
 
 const PLUME_USER_AGENT: &str = concat!("Plume/", env!("CARGO_PKG_VERSION"));
@@ -83,6 +85,25 @@ impl Digest {
         }
     }
 
+    /// The `Content-Digest` header of a body, as defined by RFC 9530
+    pub fn content_digest(body: &str) -> HeaderValue {
+        let mut hasher = Hasher::new(MessageDigest::sha256()).expect("Digest::digest: initialization error");
+        hasher.update(body.as_bytes()).expect("Digest::digest: content insertion error");
+        let res = BASE64_STANDARD.encode(hasher.finish().expect("Digest::digest: finalizing error"));
+        HeaderValue::from_str(&format!("sha-256=:{}:", res)).expect("Digest::digest: header creation error")
+    }
+
+    /// Checks the SHA-256 value of a `Content-Digest` header against this digest
+    pub fn verify_content_digest(&self, header: &str) -> bool {
+        header
+            .split(',')
+            .filter_map(|member| member.trim().split_once('='))
+            .find(|(algorithm, _)| algorithm.eq_ignore_ascii_case("sha-256"))
+            .and_then(|(_, value)| value.strip_prefix(':')?.strip_suffix(':'))
+            .and_then(|value| BASE64_STANDARD.decode(value).ok())
+            .map_or(false, |value| self.algorithm() == "SHA-256" && value == self.value())
+    }
+
     pub fn from_body(body: &str) -> Self {
         let mut hasher = Hasher::new(MessageDigest::sha256()).expect("Digest::digest: initialization error");
         hasher.update(body.as_bytes()).expect("Digest::digest: content insertion error");
@@ -151,34 +172,164 @@ pub fn signature(
     .map_err(|_| Error())
 }
 
+/// The headers that are covered by RFC 9421 signatures, when they are present
+const MESSAGE_SIGNATURE_HEADERS: [&str; 4] = ["host", "date", "digest", "content-digest"];
+
+/// Signs a request with RFC 9421 HTTP Message Signatures, using the key of `signer` of the given kind.
+///
+/// Returns the `Signature-Input` and `Signature` headers.
+pub fn message_signature(
+    signer: &dyn Signer,
+    algorithm: Algorithm,
+    headers: &HeaderMap,
+    method: &str,
+    url: &Url,
+) -> Result<(HeaderValue, HeaderValue), Error> {
+    let key_id = signer.key_id_with(algorithm).ok_or(Error())?;
+    let mut components =
+        vec![("@method".to_owned(), method.to_uppercase()), ("@target-uri".to_owned(), url.to_string())];
+    for name in MESSAGE_SIGNATURE_HEADERS {
+        if let Some(value) = headers.get(name) {
+            components.push((name.to_owned(), value.to_str().map_err(|_| Error())?.trim().to_owned()));
+        }
+    }
+    let signature_params = format!(
+        "({});created={};keyid=\"{}\";alg=\"{}\"",
+        components.iter().map(|(name, _)| format!("\"{}\"", name)).collect::<Vec<_>>().join(" "),
+        Utc::now().timestamp(),
+        key_id,
+        algorithm.message_signature_name()
+    );
+
+    let base = message_signature_base(&components, &signature_params);
+    let data = signer.sign_with(algorithm, &base).map_err(|_| Error())?;
+    Ok((
+        HeaderValue::from_str(&format!("sig1={}", signature_params))?,
+        HeaderValue::from_str(&format!("sig1=:{}:", BASE64_STANDARD.encode(data)))?,
+    ))
+}
+
+/// The kinds of HTTP signatures requests can be signed with
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SignatureKind {
+    /// RFC 9421, with a given kind of key
+    Message(Algorithm),
+    /// The draft-cavage signatures older servers understand
+    Cavage,
+}
+
+/// How long the kind of signature a host asked for is remembered
+const SIGNATURE_KIND_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The hosts that rejected the RFC 9421 signatures made with the RSA key, with the kind of signature they asked for
+/// instead, and when they did
+static SIGNATURE_KINDS: LazyLock<Mutex<HashMap<String, (SignatureKind, Instant)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The kind of signature to try first for requests to `host`
+pub fn signature_kind(host: &str) -> SignatureKind {
+    SIGNATURE_KINDS
+        .lock()
+        .ok()
+        .and_then(|kinds| {
+            kinds.get(host).filter(|(_, since)| since.elapsed() < SIGNATURE_KIND_TTL).map(|(kind, _)| *kind)
+        })
+        .unwrap_or(SignatureKind::Message(Algorithm::RsaSha256))
+}
+
+/// Remembers that `host` asked for another kind of signature, for a day
+pub fn remember_signature_kind(host: &str, kind: SignatureKind) {
+    if let Ok(mut kinds) = SIGNATURE_KINDS.lock() {
+        kinds.retain(|_, (_, since)| since.elapsed() < SIGNATURE_KIND_TTL);
+        kinds.insert(host.to_owned(), (kind, Instant::now()));
+    }
+}
+
+/// The kind of signature to send again a request with, if its RFC 9421 signature made with `used` was rejected.
+///
+/// Servers that only understand draft-cavage signatures answer `401 Unauthorized` or `403 Forbidden`, most of the
+/// time without an `Accept-Signature` header: the request is then signed with a draft-cavage signature. It is signed
+/// with the Ed25519 key instead if the peer asks for it and the signer has one.
+pub fn signature_retry(
+    status: StatusCode,
+    headers: &HeaderMap,
+    used: Algorithm,
+    has_ed25519_key: bool,
+) -> Option<SignatureKind> {
+    if status != StatusCode::UNAUTHORIZED && status != StatusCode::FORBIDDEN {
+        return None;
+    }
+    let asks_for_ed25519 =
+        headers.get("Accept-Signature").and_then(|accepted| accepted.to_str().ok()).map_or(false, |accepted| {
+            accepted.contains(&format!("alg=\"{}\"", Algorithm::Ed25519.message_signature_name()))
+        });
+    if asks_for_ed25519 && has_ed25519_key && used != Algorithm::Ed25519 {
+        Some(SignatureKind::Message(Algorithm::Ed25519))
+    } else {
+        Some(SignatureKind::Cavage)
+    }
+}
+
+/// Fetches `url_str` with a signed request.
+///
+/// The request is signed with RFC 9421 first. If the peer rejects the signature, the request is sent again with the
+/// kind of signature it asked for, which is then used for the following requests to this host for a while.
 pub fn get(url_str: &str, sender: &dyn Signer, proxy: Option<Proxy>) -> Result<Response, Error> {
     let mut headers = headers();
     let url = Url::parse(url_str)?;
     if !url.has_host() {
         return Err(Error());
     }
-    let host_header_value = HeaderValue::from_str(url.host_str().expect("Unreachable"))?;
-    headers.insert(HOST, host_header_value);
-    if let Some(proxy) = proxy {
+    let host = url.host_str().expect("Unreachable");
+    headers.insert(HOST, HeaderValue::from_str(host)?);
+    let client = if let Some(proxy) = proxy {
         ClientBuilder::new().proxy(proxy)
     } else {
         ClientBuilder::new()
     }
     .connect_timeout(Some(std::time::Duration::from_secs(5)))
-    .build()?
-    .get(url_str)
-    .headers(headers.clone())
-    .header("Signature", signature(sender, &headers, ("get", url.path(), url.query()))?)
-    .send()
-    .map_err(|_| Error())
+    .build()?;
+    let send_message_signed = |algorithm| -> Result<Response, Error> {
+        let (input, signature) = message_signature(sender, algorithm, &headers, "get", &url)?;
+        client
+            .get(url_str)
+            .headers(headers.clone())
+            .header("Signature-Input", input)
+            .header("Signature", signature)
+            .send()
+            .map_err(|_| Error())
+    };
+
+    if let SignatureKind::Message(algorithm) = signature_kind(host) {
+        let res = send_message_signed(algorithm)?;
+        let has_ed25519_key = sender.key_id_with(Algorithm::Ed25519).is_some();
+        match signature_retry(res.status(), res.headers(), algorithm, has_ed25519_key) {
+            None => return Ok(res),
+            Some(kind) => {
+                remember_signature_kind(host, kind);
+                if let SignatureKind::Message(algorithm) = kind {
+                    return send_message_signed(algorithm);
+                }
+            }
+        }
+    }
+    client
+        .get(url_str)
+        .headers(headers.clone())
+        .header("Signature", signature(sender, &headers, ("get", url.path(), url.query()))?)
+        .send()
+        .map_err(|_| Error())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::signature;
-    use crate::activity_pub::sign::{gen_keypair, Error, Result, Signer};
+    use super::{signature, signature_retry, SignatureKind};
+    use crate::activity_pub::sign::{gen_keypair, Algorithm, Error, Result, Signer};
     use openssl::{hash::MessageDigest, pkey::PKey, rsa::Rsa};
-    use reqwest::header::HeaderMap;
+    use reqwest::{
+        header::{HeaderMap, HeaderValue},
+        StatusCode,
+    };
 
     struct MySigner {
         public_key: String,
@@ -225,4 +376,35 @@ mod tests {
         let sign = &fields[3][11..(fields[3].len() - 1)];
         assert!(signer.verify("post /inbox", sign.as_bytes()).is_ok());
     }
+
+    #[test]
+    fn test_signature_retry() {
+        let mut headers = HeaderMap::new();
+        // Errors that are not about the signature are not retried
+        assert_eq!(signature_retry(StatusCode::NOT_FOUND, &headers, Algorithm::RsaSha256, true), None);
+        assert_eq!(signature_retry(StatusCode::INTERNAL_SERVER_ERROR, &headers, Algorithm::RsaSha256, true), None);
+        // Servers that don't know RFC 9421 don't tell which signatures they accept
+        assert_eq!(
+            signature_retry(StatusCode::UNAUTHORIZED, &headers, Algorithm::RsaSha256, true),
+            Some(SignatureKind::Cavage)
+        );
+        assert_eq!(
+            signature_retry(StatusCode::FORBIDDEN, &headers, Algorithm::RsaSha256, true),
+            Some(SignatureKind::Cavage)
+        );
+
+        headers.insert("Accept-Signature", HeaderValue::from_static(r#"sig1=("@method" "@target-uri");alg="ed25519""#));
+        assert_eq!(
+            signature_retry(StatusCode::UNAUTHORIZED, &headers, Algorithm::RsaSha256, true),
+            Some(SignatureKind::Message(Algorithm::Ed25519))
+        );
+        assert_eq!(
+            signature_retry(StatusCode::UNAUTHORIZED, &headers, Algorithm::RsaSha256, false),
+            Some(SignatureKind::Cavage)
+        );
+        assert_eq!(
+            signature_retry(StatusCode::UNAUTHORIZED, &headers, Algorithm::Ed25519, true),
+            Some(SignatureKind::Cavage)
+        );
+    }
 }
@@ -1,8 +1,13 @@
 use super::request;
 use base64::prelude::{Engine as _, BASE64_STANDARD};
 use chrono::{naive::NaiveDateTime, DateTime, Duration, Utc};
-use openssl::{pkey::PKey, rsa::Rsa, sha::sha256};
+use openssl::{
+    pkey::{Id, PKey},
+    rsa::Rsa,
+    sha::sha256,
+};
 use rocket::http::HeaderMap;
+use url::Url;
 
 /// Returns (public key, private key)
 pub fn gen_keypair() -> (Vec<u8>, Vec<u8>) {
@@ -14,6 +19,75 @@ pub fn gen_keypair() -> (Vec<u8>, Vec<u8>) {
     )
 }
 
+/// Returns (public key, private key) of a new Ed25519 keypair, as PEM
+pub fn gen_ed25519_keypair() -> (Vec<u8>, Vec<u8>) {
+    let keypair = PKey::generate_ed25519().expect("sign::gen_ed25519_keypair: key generation error");
+    (
+        keypair.public_key_to_pem().expect("sign::gen_ed25519_keypair: public key encoding error"),
+        keypair.private_key_to_pem_pkcs8().expect("sign::gen_ed25519_keypair: private key encoding error"),
+    )
+}
+
+/// The multicodec prefix of Ed25519 public keys
+const ED25519_MULTICODEC: [u8; 2] = [0xed, 0x01];
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+fn base58_encode(data: &[u8]) -> String {
+    let mut digits: Vec<u8> = vec![];
+    for byte in data {
+        let mut carry = u32::from(*byte);
+        for digit in digits.iter_mut() {
+            carry += u32::from(*digit) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = data.iter().take_while(|b| **b == 0).count();
+    std::iter::repeat(b'1')
+        .take(zeros)
+        .chain(digits.iter().rev().map(|d| BASE58_ALPHABET[*d as usize]))
+        .map(char::from)
+        .collect()
+}
+
+fn base58_decode(data: &str) -> Result<Vec<u8>> {
+    let mut bytes: Vec<u8> = vec![];
+    for c in data.bytes() {
+        let mut carry = BASE58_ALPHABET.iter().position(|a| *a == c).ok_or(Error())? as u32;
+        for byte in bytes.iter_mut() {
+            carry += u32::from(*byte) * 58;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = data.bytes().take_while(|c| *c == b'1').count();
+    Ok(std::iter::repeat(0).take(zeros).chain(bytes.into_iter().rev()).collect())
+}
+
+/// Encodes an Ed25519 public key given as PEM as the `publicKeyMultibase` of a Multikey
+pub fn ed25519_to_multibase(public_key_pem: &str) -> Result<String> {
+    let key = PKey::public_key_from_pem(public_key_pem.as_bytes())?;
+    let raw = [&ED25519_MULTICODEC[..], &key.raw_public_key()?].concat();
+    Ok(format!("z{}", base58_encode(&raw)))
+}
+
+/// Decodes the `publicKeyMultibase` of a Multikey into a PEM, if it is an Ed25519 key
+pub fn ed25519_from_multibase(multibase: &str) -> Result<String> {
+    let raw = base58_decode(multibase.strip_prefix('z').ok_or(Error())?)?;
+    let raw = raw.strip_prefix(&ED25519_MULTICODEC[..]).ok_or(Error())?;
+    let key = PKey::public_key_from_raw_bytes(raw, Id::ED25519)?;
+    String::from_utf8(key.public_key_to_pem()?).map_err(|_| Error())
+}
+
 #[derive(Debug)]
 pub struct Error();
 pub type Result<T> = std::result::Result<T, Error>;
@@ -24,6 +98,31 @@ impl From<openssl::error::ErrorStack> for Error {
     }
 }
 
+/// The kinds of keys signatures can be made with
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Algorithm {
+    /// The main key of actors
+    RsaSha256,
+    /// The key published in the `assertionMethod` of actors that have one
+    Ed25519,
+}
+
+impl Algorithm {
+    /// The name of the algorithm in the `alg` parameter of RFC 9421 signatures
+    pub fn message_signature_name(self) -> &'static str {
+        match self {
+            Algorithm::RsaSha256 => "rsa-v1_5-sha256",
+            Algorithm::Ed25519 => "ed25519",
+        }
+    }
+
+    pub fn from_message_signature_name(name: &str) -> Option<Self> {
+        [Algorithm::RsaSha256, Algorithm::Ed25519]
+            .into_iter()
+            .find(|algorithm| algorithm.message_signature_name() == name)
+    }
+}
+
 pub trait Signer {
     fn get_key_id(&self) -> String;
 
@@ -31,6 +130,60 @@ pub trait Signer {
     fn sign(&self, to_sign: &str) -> Result<Vec<u8>>;
     /// Verify if the signature is valid
     fn verify(&self, data: &str, signature: &[u8]) -> Result<bool>;
+
+    /// Verify if the signature is valid, for a given kind of key. Signers only have an RSA key by default.
+    fn verify_with(&self, algorithm: Algorithm, data: &str, signature: &[u8]) -> Result<bool> {
+        match algorithm {
+            Algorithm::RsaSha256 => self.verify(data, signature),
+            Algorithm::Ed25519 => Ok(false),
+        }
+    }
+
+    /// The id of the key of a given kind, if the signer has one. Signers only have an RSA key by default.
+    fn key_id_with(&self, algorithm: Algorithm) -> Option<String> {
+        match algorithm {
+            Algorithm::RsaSha256 => Some(self.get_key_id()),
+            Algorithm::Ed25519 => None,
+        }
+    }
+
+    /// Sign some data with a given kind of key
+    fn sign_with(&self, algorithm: Algorithm, to_sign: &str) -> Result<Vec<u8>> {
+        match algorithm {
+            Algorithm::RsaSha256 => self.sign(to_sign),
+            Algorithm::Ed25519 => Err(Error()),
+        }
+    }
+}
+
+/// Signs some data with an Ed25519 private key given as PEM
+pub fn sign_ed25519(private_key_pem: &str, data: &str) -> Result<Vec<u8>> {
+    let key = PKey::private_key_from_pem(private_key_pem.as_bytes())?;
+    let mut signer = openssl::sign::Signer::new_without_digest(&key)?;
+    Ok(signer.sign_oneshot_to_vec(data.as_bytes())?)
+}
+
+/// Verifies an Ed25519 signature with a public key given as PEM
+pub fn verify_ed25519(public_key_pem: &str, data: &str, signature: &[u8]) -> Result<bool> {
+    let key = PKey::public_key_from_pem(public_key_pem.as_bytes())?;
+    let mut verifier = openssl::sign::Verifier::new_without_digest(&key)?;
+    Ok(verifier.verify_oneshot(signature, data.as_bytes())?)
+}
+
+/// Verifies a signature made with the key announced by the `algorithm` of a signature, or with any key of `sender` if
+/// it doesn't tell (`hs2019`).
+fn verify_announced<S: Signer + ?Sized>(
+    sender: &S,
+    algorithm: Option<Algorithm>,
+    data: &str,
+    signature: &[u8],
+) -> bool {
+    match algorithm {
+        Some(algorithm) => sender.verify_with(algorithm, data, signature).unwrap_or(false),
+        None => [Algorithm::RsaSha256, Algorithm::Ed25519]
+            .iter()
+            .any(|algorithm| sender.verify_with(*algorithm, data, signature).unwrap_or(false)),
+    }
 }
 
 pub trait Signable {
@@ -201,3 +354,608 @@ pub fn verify_http_headers<S: Signer + ::std::fmt::Debug>(
         SignatureValidity::Outdated
     }
 }
+
+/// The headers a request must sign to be accepted by [`verify_http_headers_strict`]
+pub const REQUIRED_SIGNED_HEADERS: [&str; 4] = ["(request-target)", "host", "date", "digest"];
+
+/// Why an HTTP signature was rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    /// There is no `Signature` header
+    Absent,
+    /// The `Signature` header doesn't have a `keyId`, `headers` or `signature`
+    Malformed,
+    UnsupportedAlgorithm(String),
+    /// The key doesn't belong to the actor of the activity
+    ForeignKey {
+        key_id: String,
+        actor_id: String,
+    },
+    /// One of the [`REQUIRED_SIGNED_HEADERS`] is not signed
+    UnsignedHeader(&'static str),
+    /// A signed header is not in the request
+    MissingHeader(String),
+    /// The signature doesn't match the headers, or the key
+    InvalidSignature,
+    /// The body doesn't match the `Digest` header
+    DigestMismatch,
+    /// The `Date` header can't be parsed, or is more than 12 hours away from now
+    Outdated,
+}
+
+/// The id of the key a request was signed with, from its RFC 9421 or draft-cavage signature
+pub fn signature_key_id(all_headers: &HeaderMap<'_>) -> Option<String> {
+    if let Some(input) = all_headers.get_one("Signature-Input") {
+        let (_, params) = split_members(input).first()?.split_once(')')?;
+        params
+            .split(';')
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| *name == "keyid")
+            .map(|(_, value)| value.trim_matches('"').to_owned())
+    } else {
+        all_headers
+            .get_one("Signature")?
+            .split(',')
+            .filter_map(|part| part.trim().split_once('='))
+            .find(|(name, _)| *name == "keyId")
+            .map(|(_, value)| value.trim_matches('"').to_owned())
+    }
+}
+
+/// Tells whether `key_id` is one of the keys of `actor_id`: either a fragment of the actor, or a path under it.
+fn key_belongs_to(key_id: &str, actor_id: &str) -> bool {
+    let (key, actor) = match (Url::parse(key_id), Url::parse(actor_id)) {
+        (Ok(key), Ok(actor)) => (key, actor),
+        _ => return false,
+    };
+    if key.origin() != actor.origin() {
+        return false;
+    }
+    let key_path = key.path().trim_end_matches('/');
+    let actor_path = actor.path().trim_end_matches('/');
+    key_path == actor_path || key_path.starts_with(&format!("{}/", actor_path))
+}
+
+/// Checks the HTTP signature of a request sent by `actor_id`, whose body has the digest `data`. Requests without a
+/// body, like signed fetches, have no digest.
+///
+/// Unlike [`verify_http_headers`], the request target, the host, the date and the digest must all be signed, and the
+/// key must belong to the actor. Keys that are not under the actor only belong to it if `key_owner`, the owner given
+/// by the document of the key, is the actor.
+pub fn verify_http_headers_strict<S: Signer + ?Sized>(
+    sender: &S,
+    actor_id: &str,
+    key_owner: Option<&str>,
+    all_headers: &HeaderMap<'_>,
+    data: Option<&request::Digest>,
+) -> std::result::Result<(), SignatureError> {
+    let sig_header = all_headers.get_one("Signature").ok_or(SignatureError::Absent)?;
+
+    let mut key_id = None;
+    let mut algorithm = None;
+    let mut headers = None;
+    let mut signature = None;
+    for part in sig_header.split(',') {
+        if let Some((name, value)) = part.trim().split_once('=') {
+            let value = value.trim_matches('"');
+            match name {
+                "keyId" => key_id = Some(value),
+                "algorithm" => algorithm = Some(value),
+                "headers" => headers = Some(value),
+                "signature" => signature = Some(value),
+                _ => {}
+            }
+        }
+    }
+    let (key_id, headers, signature) = match (key_id, headers, signature) {
+        (Some(key_id), Some(headers), Some(signature)) => (key_id, headers, signature),
+        _ => return Err(SignatureError::Malformed),
+    };
+
+    let algorithm = match algorithm.map(str::to_lowercase).as_deref() {
+        Some("rsa-sha256") => Some(Algorithm::RsaSha256),
+        Some("ed25519") => Some(Algorithm::Ed25519),
+        Some("hs2019") | None => None,
+        Some(other) => return Err(SignatureError::UnsupportedAlgorithm(other.to_owned())),
+    };
+    if key_owner != Some(actor_id) && !key_belongs_to(key_id, actor_id) {
+        return Err(SignatureError::ForeignKey {
+            key_id: key_id.to_owned(),
+            actor_id: actor_id.to_owned(),
+        });
+    }
+    let headers = headers.split_whitespace().map(str::to_lowercase).collect::<Vec<_>>();
+    for required in REQUIRED_SIGNED_HEADERS {
+        if required == "digest" && data.is_none() {
+            continue;
+        }
+        if !headers.iter().any(|h| h == required) {
+            return Err(SignatureError::UnsignedHeader(required));
+        }
+    }
+
+    let mut signed = Vec::with_capacity(headers.len());
+    for header in &headers {
+        let value = all_headers.get_one(header).ok_or_else(|| SignatureError::MissingHeader(header.clone()))?;
+        signed.push(format!("{}: {}", header, value));
+    }
+    let signature = BASE64_STANDARD.decode(signature).map_err(|_| SignatureError::Malformed)?;
+    if !verify_announced(sender, algorithm, &signed.join("\n"), &signature) {
+        return Err(SignatureError::InvalidSignature);
+    }
+
+    if let Some(data) = data {
+        let digest = all_headers.get_one("digest").and_then(|d| request::Digest::from_header(d).ok());
+        if !digest.map_or(false, |d| d.algorithm() == "SHA-256" && d.verify_header(data)) {
+            return Err(SignatureError::DigestMismatch);
+        }
+    }
+
+    let date = all_headers
+        .get_one("date")
+        .and_then(|d| NaiveDateTime::parse_from_str(d, "%a, %d %h %Y %T GMT").ok())
+        .ok_or(SignatureError::Outdated)?;
+    let diff = Utc::now().naive_utc() - date;
+    if diff < Duration::hours(12) && diff > Duration::hours(-12) {
+        Ok(())
+    } else {
+        Err(SignatureError::Outdated)
+    }
+}
+
+/// Splits a structured field list or dictionary on the commas that are not quoted or in an inner list
+fn split_members(field: &str) -> Vec<&str> {
+    let mut members = vec![];
+    let (mut start, mut quoted, mut depth) = (0, false, 0);
+    for (i, c) in field.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                members.push(field[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    members.push(field[start..].trim());
+    members
+}
+
+/// Builds the string that is signed by an RFC 9421 signature, from the values of its covered components
+pub fn message_signature_base(components: &[(String, String)], signature_params: &str) -> String {
+    components
+        .iter()
+        .map(|(name, value)| format!("\"{}\": {}\n", name, value))
+        .chain(std::iter::once(format!("\"@signature-params\": {}", signature_params)))
+        .collect()
+}
+
+/// Checks the RFC 9421 signature (`Signature-Input` and `Signature` headers) of a request sent by `actor_id`, whose
+/// body has the digest `data`, if it has a body.
+///
+/// The requirements are the same as [`verify_http_headers_strict`]: the method, the target, and the digest of the
+/// body must be signed, the signature must be recent, and the key must belong to the actor.
+pub fn verify_http_message_signature<S: Signer + ?Sized>(
+    sender: &S,
+    actor_id: &str,
+    key_owner: Option<&str>,
+    all_headers: &HeaderMap<'_>,
+    data: Option<&request::Digest>,
+) -> std::result::Result<(), SignatureError> {
+    let input = all_headers.get_one("Signature-Input").ok_or(SignatureError::Absent)?;
+    let signatures = all_headers.get_one("Signature").ok_or(SignatureError::Absent)?;
+
+    // Only the first signature is checked, other ones may have been added by proxies
+    let (label, signature_params) =
+        split_members(input).first().and_then(|member| member.split_once('=')).ok_or(SignatureError::Malformed)?;
+    let signature = split_members(signatures)
+        .into_iter()
+        .filter_map(|member| member.split_once('='))
+        .find(|(name, _)| *name == label)
+        .and_then(|(_, value)| value.strip_prefix(':')?.strip_suffix(':'))
+        .and_then(|value| BASE64_STANDARD.decode(value).ok())
+        .ok_or(SignatureError::Malformed)?;
+
+    let (covered, params) =
+        signature_params.strip_prefix('(').and_then(|s| s.split_once(')')).ok_or(SignatureError::Malformed)?;
+    let covered = covered
+        .split_whitespace()
+        .map(|c| c.strip_prefix('"').and_then(|c| c.strip_suffix('"')))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(SignatureError::Malformed)?;
+
+    let mut key_id = None;
+    let mut algorithm = None;
+    let mut created = None;
+    let mut expires = None;
+    for param in params.split(';').filter(|p| !p.is_empty()) {
+        if let Some((name, value)) = param.split_once('=') {
+            let value = value.trim_matches('"');
+            match name {
+                "keyid" => key_id = Some(value),
+                "alg" => algorithm = Some(value),
+                "created" => created = value.parse::<i64>().ok(),
+                "expires" => expires = value.parse::<i64>().ok(),
+                _ => {}
+            }
+        }
+    }
+    let key_id = key_id.ok_or(SignatureError::Malformed)?;
+    let algorithm = match algorithm {
+        Some(name) => Some(
+            Algorithm::from_message_signature_name(name)
+                .ok_or_else(|| SignatureError::UnsupportedAlgorithm(name.to_owned()))?,
+        ),
+        None => None,
+    };
+    if key_owner != Some(actor_id) && !key_belongs_to(key_id, actor_id) {
+        return Err(SignatureError::ForeignKey {
+            key_id: key_id.to_owned(),
+            actor_id: actor_id.to_owned(),
+        });
+    }
+
+    let has = |name: &str| covered.contains(&name);
+    if !has("@method") {
+        return Err(SignatureError::UnsignedHeader("@method"));
+    }
+    let has_target =
+        has("@target-uri") || has("@request-target") || ((has("@authority") || has("host")) && has("@path"));
+    if !has_target {
+        return Err(SignatureError::UnsignedHeader("@target-uri"));
+    }
+    if data.is_some() && !has("content-digest") && !has("digest") {
+        return Err(SignatureError::UnsignedHeader("content-digest"));
+    }
+
+    let (method, target) = all_headers
+        .get_one("(request-target)")
+        .and_then(|t| t.split_once(' '))
+        .ok_or_else(|| SignatureError::MissingHeader("(request-target)".to_owned()))?;
+    let host = all_headers.get_one("host").ok_or_else(|| SignatureError::MissingHeader("host".to_owned()))?;
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (target, None),
+    };
+
+    let mut components = Vec::with_capacity(covered.len());
+    for name in &covered {
+        let value = match *name {
+            "@method" => method.to_uppercase(),
+            "@target-uri" => format!("https://{}{}", host, target),
+            "@authority" => host.to_lowercase(),
+            "@path" => path.to_owned(),
+            "@query" => format!("?{}", query.unwrap_or_default()),
+            "@request-target" => target.to_owned(),
+            derived if derived.starts_with('@') => return Err(SignatureError::Malformed),
+            header => all_headers.get(header).map(str::trim).collect::<Vec<_>>().join(", "),
+        };
+        if !name.starts_with('@') && all_headers.get_one(name).is_none() {
+            return Err(SignatureError::MissingHeader((*name).to_owned()));
+        }
+        components.push(((*name).to_owned(), value));
+    }
+    let base = message_signature_base(&components, signature_params);
+    if !verify_announced(sender, algorithm, &base, &signature) {
+        return Err(SignatureError::InvalidSignature);
+    }
+
+    if let Some(data) = data {
+        let digest_matches = if has("content-digest") {
+            all_headers.get_one("content-digest").map_or(false, |d| data.verify_content_digest(d))
+        } else {
+            let digest = all_headers.get_one("digest").and_then(|d| request::Digest::from_header(d).ok());
+            digest.map_or(false, |d| d.algorithm() == "SHA-256" && d.verify_header(data))
+        };
+        if !digest_matches {
+            return Err(SignatureError::DigestMismatch);
+        }
+    }
+
+    let now = Utc::now().timestamp();
+    if expires.map_or(false, |expires| expires < now) {
+        return Err(SignatureError::Outdated);
+    }
+    let age = match created {
+        Some(created) => Duration::seconds(now - created),
+        None if has("date") => all_headers
+            .get_one("date")
+            .and_then(|d| NaiveDateTime::parse_from_str(d, "%a, %d %h %Y %T GMT").ok())
+            .map(|date| Utc::now().naive_utc() - date)
+            .ok_or(SignatureError::Outdated)?,
+        None => return Err(SignatureError::Outdated),
+    };
+    if age < Duration::hours(12) && age > Duration::hours(-12) {
+        Ok(())
+    } else {
+        Err(SignatureError::Outdated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::{hash::MessageDigest, pkey::Private};
+    use rocket::http::Header;
+
+    #[derive(Debug)]
+    struct TestSigner {
+        key: PKey<Private>,
+        ed25519: PKey<Private>,
+    }
+
+    impl TestSigner {
+        fn new() -> Self {
+            TestSigner {
+                key: PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap(),
+                ed25519: PKey::generate_ed25519().unwrap(),
+            }
+        }
+
+        fn sign_ed25519(&self, to_sign: &str) -> Vec<u8> {
+            let mut signer = openssl::sign::Signer::new_without_digest(&self.ed25519).unwrap();
+            signer.sign_oneshot_to_vec(to_sign.as_bytes()).unwrap()
+        }
+    }
+
+    impl Signer for TestSigner {
+        fn get_key_id(&self) -> String {
+            "https://plu.me/@/test/#main-key".into()
+        }
+
+        fn sign(&self, to_sign: &str) -> Result<Vec<u8>> {
+            let mut signer = openssl::sign::Signer::new(MessageDigest::sha256(), &self.key)?;
+            signer.update(to_sign.as_bytes())?;
+            Ok(signer.sign_to_vec()?)
+        }
+
+        fn verify(&self, data: &str, signature: &[u8]) -> Result<bool> {
+            let mut verifier = openssl::sign::Verifier::new(MessageDigest::sha256(), &self.key)?;
+            verifier.update(data.as_bytes())?;
+            Ok(verifier.verify(signature)?)
+        }
+
+        fn verify_with(&self, algorithm: Algorithm, data: &str, signature: &[u8]) -> Result<bool> {
+            match algorithm {
+                Algorithm::RsaSha256 => self.verify(data, signature),
+                Algorithm::Ed25519 => {
+                    let public_key = String::from_utf8(self.ed25519.public_key_to_pem()?).map_err(|_| Error())?;
+                    verify_ed25519(&public_key, data, signature)
+                }
+            }
+        }
+    }
+
+    fn signed_request(signer: &TestSigner, key_id: &str, signed: &[&str], body: &str) -> HeaderMap<'static> {
+        let date = Utc::now().format("%a, %d %b %Y %T GMT").to_string();
+        let values = [
+            ("(request-target)", "post /inbox".to_owned()),
+            ("host", "plu.me".to_owned()),
+            ("date", date),
+            ("digest", request::Digest::digest(body).to_str().unwrap().to_owned()),
+        ];
+        let to_sign = signed
+            .iter()
+            .map(|h| format!("{}: {}", h, values.iter().find(|(name, _)| name == h).unwrap().1))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let signature = BASE64_STANDARD.encode(signer.sign(&to_sign).unwrap());
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in values {
+            headers.add(Header::new(name, value));
+        }
+        headers.add(Header::new(
+            "Signature",
+            format!(
+                "keyId=\"{}\",algorithm=\"rsa-sha256\",headers=\"{}\",signature=\"{}\"",
+                key_id,
+                signed.join(" "),
+                signature
+            ),
+        ));
+        headers
+    }
+
+    #[test]
+    fn test_strict_verification() {
+        let signer = TestSigner::new();
+        let actor = "https://plu.me/@/test/";
+        let body = r#"{"type":"Follow"}"#;
+        let digest = request::Digest::from_body(body);
+
+        let headers = signed_request(&signer, &signer.get_key_id(), &REQUIRED_SIGNED_HEADERS, body);
+        assert_eq!(verify_http_headers_strict(&signer, actor, None, &headers, Some(&digest)), Ok(()));
+        // The body was changed
+        assert_eq!(
+            verify_http_headers_strict(&signer, actor, None, &headers, Some(&request::Digest::from_body("{}"))),
+            Err(SignatureError::DigestMismatch)
+        );
+
+        let headers = signed_request(&signer, &signer.get_key_id(), &["(request-target)", "host", "digest"], body);
+        assert_eq!(
+            verify_http_headers_strict(&signer, actor, None, &headers, Some(&digest)),
+            Err(SignatureError::UnsignedHeader("date"))
+        );
+        // Lenient verification accepts it
+        assert!(verify_http_headers(&signer, &headers, &digest).is_secure());
+
+        let headers = signed_request(&signer, "https://evil.example/@/test/#main-key", &REQUIRED_SIGNED_HEADERS, body);
+        assert!(matches!(
+            verify_http_headers_strict(&signer, actor, None, &headers, Some(&digest)),
+            Err(SignatureError::ForeignKey { .. })
+        ));
+        let headers = signed_request(&signer, "https://plu.me/@/test/main-key", &REQUIRED_SIGNED_HEADERS, body);
+        assert_eq!(verify_http_headers_strict(&signer, actor, None, &headers, Some(&digest)), Ok(()));
+        // Keys that are documents of their own can be anywhere, as long as they give the actor as their owner
+        let headers = signed_request(&signer, "https://plu.me/keys/1", &REQUIRED_SIGNED_HEADERS, body);
+        assert!(matches!(
+            verify_http_headers_strict(&signer, actor, None, &headers, Some(&digest)),
+            Err(SignatureError::ForeignKey { .. })
+        ));
+        assert_eq!(verify_http_headers_strict(&signer, actor, Some(actor), &headers, Some(&digest)), Ok(()));
+
+        let mut headers = signed_request(&signer, &signer.get_key_id(), &REQUIRED_SIGNED_HEADERS, body);
+        let sig = headers.get_one("Signature").unwrap().replace("rsa-sha256", "rsa-md5");
+        headers.replace(Header::new("Signature", sig));
+        assert_eq!(
+            verify_http_headers_strict(&signer, actor, None, &headers, Some(&digest)),
+            Err(SignatureError::UnsupportedAlgorithm("rsa-md5".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_signed_fetch() {
+        let signer = TestSigner::new();
+        let actor = "https://plu.me/@/test/";
+
+        // Fetches have no body to sign
+        let headers = signed_request(&signer, &signer.get_key_id(), &["(request-target)", "host", "date"], "");
+        assert_eq!(verify_http_headers_strict(&signer, actor, None, &headers, None), Ok(()));
+        assert_eq!(signature_key_id(&headers), Some(signer.get_key_id()));
+        let headers = signed_request(&signer, &signer.get_key_id(), &["(request-target)", "host"], "");
+        assert_eq!(
+            verify_http_headers_strict(&signer, actor, None, &headers, None),
+            Err(SignatureError::UnsignedHeader("date"))
+        );
+
+        let rsa = r#";keyid="https://plu.me/@/test/#main-key";alg="rsa-v1_5-sha256""#;
+        let headers = message_signed_request(&signer, &["@method", "@target-uri"], rsa, "");
+        assert_eq!(verify_http_message_signature(&signer, actor, None, &headers, None), Ok(()));
+        assert_eq!(signature_key_id(&headers), Some(signer.get_key_id()));
+    }
+
+    #[test]
+    fn test_hs2019() {
+        let signer = TestSigner::new();
+        let actor = "https://plu.me/@/test/";
+        let body = r#"{"type":"Follow"}"#;
+        let digest = request::Digest::from_body(body);
+
+        let mut headers = signed_request(&signer, &signer.get_key_id(), &REQUIRED_SIGNED_HEADERS, body);
+        let sig = headers.get_one("Signature").unwrap().replace("rsa-sha256", "hs2019");
+        headers.replace(Header::new("Signature", sig));
+        assert_eq!(verify_http_headers_strict(&signer, actor, None, &headers, Some(&digest)), Ok(()));
+
+        // The same headers, signed with the Ed25519 key
+        let to_sign = REQUIRED_SIGNED_HEADERS
+            .iter()
+            .map(|h| format!("{}: {}", h, headers.get_one(h).unwrap()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        headers.replace(Header::new(
+            "Signature",
+            format!(
+                "keyId=\"https://plu.me/@/test/#ed25519-key\",algorithm=\"hs2019\",headers=\"{}\",signature=\"{}\"",
+                REQUIRED_SIGNED_HEADERS.join(" "),
+                BASE64_STANDARD.encode(signer.sign_ed25519(&to_sign))
+            ),
+        ));
+        assert_eq!(verify_http_headers_strict(&signer, actor, None, &headers, Some(&digest)), Ok(()));
+    }
+
+    fn message_signed_request(signer: &TestSigner, covered: &[&str], params: &str, body: &str) -> HeaderMap<'static> {
+        let values = [
+            ("(request-target)", "post /inbox".to_owned()),
+            ("host", "plu.me".to_owned()),
+            ("content-digest", request::Digest::content_digest(body).to_str().unwrap().to_owned()),
+        ];
+        let components = covered
+            .iter()
+            .map(|name| {
+                let value = match *name {
+                    "@method" => "POST".to_owned(),
+                    "@target-uri" => "https://plu.me/inbox".to_owned(),
+                    header => values.iter().find(|(h, _)| *h == header).unwrap().1.clone(),
+                };
+                ((*name).to_owned(), value)
+            })
+            .collect::<Vec<_>>();
+        let signature_params = format!(
+            "({});created={}{}",
+            covered.iter().map(|c| format!("\"{}\"", c)).collect::<Vec<_>>().join(" "),
+            Utc::now().timestamp(),
+            params
+        );
+        let base = message_signature_base(&components, &signature_params);
+        let signature = if params.contains("ed25519") {
+            signer.sign_ed25519(&base)
+        } else {
+            signer.sign(&base).unwrap()
+        };
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in values {
+            headers.add(Header::new(name, value));
+        }
+        headers.add(Header::new("Signature-Input", format!("sig1={}", signature_params)));
+        headers.add(Header::new("Signature", format!("sig1=:{}:", BASE64_STANDARD.encode(signature))));
+        headers
+    }
+
+    #[test]
+    fn test_message_signature() {
+        let signer = TestSigner::new();
+        let actor = "https://plu.me/@/test/";
+        let body = r#"{"type":"Follow"}"#;
+        let digest = request::Digest::from_body(body);
+        let covered = ["@method", "@target-uri", "content-digest"];
+
+        let rsa = r#";keyid="https://plu.me/@/test/#main-key";alg="rsa-v1_5-sha256""#;
+        let headers = message_signed_request(&signer, &covered, rsa, body);
+        assert_eq!(verify_http_message_signature(&signer, actor, None, &headers, Some(&digest)), Ok(()));
+        assert_eq!(
+            verify_http_message_signature(&signer, actor, None, &headers, Some(&request::Digest::from_body("{}"))),
+            Err(SignatureError::DigestMismatch)
+        );
+
+        let ed25519 = r#";keyid="https://plu.me/@/test/#ed25519-key";alg="ed25519""#;
+        let headers = message_signed_request(&signer, &covered, ed25519, body);
+        assert_eq!(verify_http_message_signature(&signer, actor, None, &headers, Some(&digest)), Ok(()));
+        // Without an algorithm, all the keys are tried
+        let headers = message_signed_request(&signer, &covered, r#";keyid="https://plu.me/@/test/#ed25519-key""#, body);
+        assert_eq!(verify_http_message_signature(&signer, actor, None, &headers, Some(&digest)), Ok(()));
+
+        let headers = message_signed_request(&signer, &["@method", "@target-uri"], rsa, body);
+        assert_eq!(
+            verify_http_message_signature(&signer, actor, None, &headers, Some(&digest)),
+            Err(SignatureError::UnsignedHeader("content-digest"))
+        );
+        let foreign = r#";keyid="https://evil.example/@/test/#main-key";alg="rsa-v1_5-sha256""#;
+        let headers = message_signed_request(&signer, &covered, foreign, body);
+        assert!(matches!(
+            verify_http_message_signature(&signer, actor, None, &headers, Some(&digest)),
+            Err(SignatureError::ForeignKey { .. })
+        ));
+        let expired = format!("{};expires={}", rsa, Utc::now().timestamp() - 60);
+        let headers = message_signed_request(&signer, &covered, &expired, body);
+        assert_eq!(
+            verify_http_message_signature(&signer, actor, None, &headers, Some(&digest)),
+            Err(SignatureError::Outdated)
+        );
+    }
+
+    #[test]
+    fn test_multibase() {
+        assert_eq!(base58_encode(b"Hello World!"), "2NEpo7TZRRrLZSi2U");
+        assert_eq!(base58_decode("2NEpo7TZRRrLZSi2U").unwrap(), b"Hello World!");
+        assert_eq!(base58_decode(&base58_encode(&[0, 0, 1, 2])).unwrap(), vec![0, 0, 1, 2]);
+
+        let (public_key, _) = gen_ed25519_keypair();
+        let public_key = String::from_utf8(public_key).unwrap();
+        let multibase = ed25519_to_multibase(&public_key).unwrap();
+        assert_eq!(ed25519_from_multibase(&multibase).unwrap(), public_key);
+        assert!(ed25519_from_multibase("zabc").is_err());
+    }
+
+    #[test]
+    fn test_sign_ed25519() {
+        let (public_key, private_key) = gen_ed25519_keypair();
+        let public_key = String::from_utf8(public_key).unwrap();
+        let signature = sign_ed25519(&String::from_utf8(private_key).unwrap(), "data").unwrap();
+        assert!(verify_ed25519(&public_key, "data", &signature).unwrap());
+        assert!(!verify_ed25519(&public_key, "other data", &signature).unwrap());
+    }
+}
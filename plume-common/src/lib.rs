@@ -7,3 +7,4 @@ extern crate serde_json;
 
 pub mod activity_pub;
 pub mod utils;
+pub mod web_push;
@@ -2,6 +2,7 @@ use openssl::rand::rand_bytes;
 use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, LinkType, Options, Parser, Tag};
 use regex_syntax::is_word_character;
 use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
 use syntect::html::{ClassStyle, ClassedHTMLGenerator};
 use syntect::parsing::SyntaxSet;
 
@@ -404,10 +405,94 @@ pub fn escape(string: &str) -> askama_escape::Escaped<'_, askama_escape::Html> {
     askama_escape::escape(string, askama_escape::Html)
 }
 
+/// Checks that `url` is an HTTP(S) address whose host only resolves to public IP addresses.
+///
+/// It is used before making requests to addresses given by users, so that they can't be used to
+/// reach the server itself or the private network it is in.
+pub fn is_public_url(url: &str) -> bool {
+    public_addrs(url).is_some()
+}
+
+/// Resolves the host of `url`, if it is an HTTP(S) address that only resolves to public IP addresses.
+///
+/// The host may resolve to other addresses later: requests should connect to the ones returned here.
+pub fn public_addrs(url: &str) -> Option<Vec<SocketAddr>> {
+    let url = url::Url::parse(url).ok()?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs = match url.host()? {
+        url::Host::Ipv4(ip) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+        url::Host::Ipv6(ip) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+        url::Host::Domain(domain) => (domain, port).to_socket_addrs().ok()?.collect(),
+    };
+    if !addrs.is_empty() && addrs.iter().all(|addr| is_public_ip(addr.ip())) {
+        Some(addrs)
+    } else {
+        None
+    }
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8, 100.64.0.0/10 (shared address space) and 240.0.0.0/4 (reserved)
+        || a == 0
+        || (a == 100 && (b & 0xc0) == 64)
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // fc00::/7 (unique local) and fe80::/10 (link local)
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_is_public_url() {
+        assert!(is_public_url("https://93.184.215.14/feed.xml"));
+        assert!(is_public_url("http://[2606:2800:21f:cb07:6820:80da:af6b:8b2c]/"));
+        assert!(!is_public_url("ftp://93.184.215.14/"));
+        assert!(!is_public_url("not a url"));
+        assert!(!is_public_url("http://127.0.0.1:8080/"));
+        assert!(!is_public_url("http://localhost/"));
+        assert!(!is_public_url("http://10.1.2.3/"));
+        assert!(!is_public_url("http://172.16.0.1/"));
+        assert!(!is_public_url("http://192.168.1.1/"));
+        assert!(!is_public_url("http://169.254.169.254/latest/meta-data/"));
+        assert!(!is_public_url("http://100.64.0.1/"));
+        assert!(!is_public_url("http://0.0.0.0/"));
+        assert!(!is_public_url("http://[::1]/"));
+        assert!(!is_public_url("http://[fd00::1]/"));
+        assert!(!is_public_url("http://[fe80::1]/"));
+        assert!(!is_public_url("http://[::ffff:127.0.0.1]/"));
+    }
+
     #[test]
     fn test_mentions() {
         let tests = vec![
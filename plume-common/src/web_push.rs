@@ -0,0 +1,294 @@
+//! Web Push messages, encrypted as described in RFC 8291 and authenticated with VAPID (RFC 8292)
+
+use base64::prelude::{Engine as _, BASE64_URL_SAFE_NO_PAD};
+use openssl::{
+    bn::BigNumContext,
+    derive::Deriver,
+    ec::{EcGroup, EcKey, EcPoint, PointConversionForm},
+    ecdsa::EcdsaSig,
+    error::ErrorStack,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    rand::rand_bytes,
+    sha::sha256,
+    sign::Signer,
+    symm::{encrypt_aead, Cipher},
+};
+use reqwest::{
+    blocking::ClientBuilder,
+    header::{AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE},
+    redirect::Policy,
+    Proxy, StatusCode, Url,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The size of the only record of the encrypted content
+const RECORD_SIZE: u32 = 4096;
+
+/// How long push services should keep a message for an offline device, in seconds
+const TTL: u64 = 60 * 60 * 24;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The keys of the subscription or of the instance are invalid
+    Crypto,
+    /// The push service couldn't be reached, or refused the message
+    Request,
+    /// The subscription doesn't exist anymore, and should be forgotten
+    Gone,
+}
+
+impl From<ErrorStack> for Error {
+    fn from(_: ErrorStack) -> Self {
+        Error::Crypto
+    }
+}
+
+impl From<base64::DecodeError> for Error {
+    fn from(_: base64::DecodeError) -> Self {
+        Error::Crypto
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(_: reqwest::Error) -> Self {
+        Error::Request
+    }
+}
+
+fn group() -> Result<EcGroup, ErrorStack> {
+    EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)
+}
+
+fn public_key_bytes(key: &EcKey<Private>) -> Result<Vec<u8>, ErrorStack> {
+    key.public_key().to_bytes(&group()?, PointConversionForm::UNCOMPRESSED, &mut BigNumContext::new()?)
+}
+
+/// Browsers give keys in URL-safe base64, with or without padding
+fn decode(data: &str) -> Result<Vec<u8>, Error> {
+    Ok(BASE64_URL_SAFE_NO_PAD.decode(data.trim_end_matches('='))?)
+}
+
+fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> Result<Vec<u8>, ErrorStack> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    for d in data {
+        signer.update(d)?;
+    }
+    signer.sign_to_vec()
+}
+
+/// HKDF (RFC 5869) with SHA-256, for outputs of at most 32 bytes
+fn hkdf(salt: &[u8], ikm: &[u8], info: &[u8], len: usize) -> Result<Vec<u8>, ErrorStack> {
+    let prk = hmac_sha256(salt, &[ikm])?;
+    let mut okm = hmac_sha256(&prk, &[info, &[1u8][..]])?;
+    okm.truncate(len);
+    Ok(okm)
+}
+
+/// Generates the keys this instance uses to identify itself to push services.
+///
+/// Returns (private key as PEM, public key as URL-safe base64), the public key being what browsers expect as
+/// `applicationServerKey`.
+pub fn gen_vapid_keys() -> (String, String) {
+    let key = EcKey::generate(&group().expect("web_push::gen_vapid_keys: curve error"))
+        .expect("web_push::gen_vapid_keys: key generation error");
+    let private = key.private_key_to_pem().expect("web_push::gen_vapid_keys: private key encoding error");
+    let public = public_key_bytes(&key).expect("web_push::gen_vapid_keys: public key encoding error");
+    (
+        String::from_utf8(private).expect("web_push::gen_vapid_keys: PEM is not UTF-8"),
+        BASE64_URL_SAFE_NO_PAD.encode(public),
+    )
+}
+
+/// Encrypts `payload` for a subscription, with the `aes128gcm` content coding.
+///
+/// `p256dh` and `auth` are the keys given by the browser when subscribing.
+pub fn encrypt(payload: &[u8], p256dh: &str, auth: &str) -> Result<Vec<u8>, Error> {
+    let mut salt = [0; 16];
+    rand_bytes(&mut salt)?;
+    encrypt_with(payload, &decode(p256dh)?, &decode(auth)?, EcKey::generate(&group()?)?, &salt)
+}
+
+/// Encrypts `payload` with a given ephemeral key and salt, that must never be used twice
+fn encrypt_with(
+    payload: &[u8],
+    ua_public: &[u8],
+    auth_secret: &[u8],
+    as_key: EcKey<Private>,
+    salt: &[u8],
+) -> Result<Vec<u8>, Error> {
+    if payload.len() + 17 > RECORD_SIZE as usize {
+        return Err(Error::Crypto);
+    }
+
+    let group = group()?;
+    let ua_point = EcPoint::from_bytes(&group, ua_public, &mut BigNumContext::new()?)?;
+    let ua_key = PKey::from_ec_key(EcKey::from_public_key(&group, &ua_point)?)?;
+    let as_public = public_key_bytes(&as_key)?;
+    let as_key = PKey::from_ec_key(as_key)?;
+
+    let mut deriver = Deriver::new(&as_key)?;
+    deriver.set_peer(&ua_key)?;
+    let ecdh_secret = deriver.derive_to_vec()?;
+
+    let key_info = [&b"WebPush: info\0"[..], ua_public, &as_public[..]].concat();
+    let ikm = hkdf(auth_secret, &ecdh_secret, &key_info, 32)?;
+    let cek = hkdf(salt, &ikm, b"Content-Encoding: aes128gcm\0", 16)?;
+    let nonce = hkdf(salt, &ikm, b"Content-Encoding: nonce\0", 12)?;
+
+    // A single record, ended by the padding delimiter
+    let plaintext = [payload, &[2u8][..]].concat();
+    let mut tag = [0; 16];
+    let ciphertext = encrypt_aead(Cipher::aes_128_gcm(), &cek, Some(&nonce[..]), &[], &plaintext, &mut tag)?;
+
+    let mut body = Vec::with_capacity(86 + ciphertext.len() + tag.len());
+    body.extend_from_slice(salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.len() as u8);
+    body.extend_from_slice(&as_public);
+    body.extend_from_slice(&ciphertext);
+    body.extend_from_slice(&tag);
+    Ok(body)
+}
+
+/// The `Authorization` header proving to the push service behind `endpoint` that the message comes from us
+pub fn vapid_authorization(
+    endpoint: &str,
+    private_key: &str,
+    public_key: &str,
+    subject: &str,
+) -> Result<String, Error> {
+    let url = Url::parse(endpoint).map_err(|_| Error::Request)?;
+    let audience = url.origin().ascii_serialization();
+    let expiration = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|_| Error::Crypto)?.as_secs() + 12 * 60 * 60;
+
+    let header = BASE64_URL_SAFE_NO_PAD.encode(json!({ "typ": "JWT", "alg": "ES256" }).to_string());
+    let claims =
+        BASE64_URL_SAFE_NO_PAD.encode(json!({ "aud": audience, "exp": expiration, "sub": subject }).to_string());
+    let signed = format!("{}.{}", header, claims);
+
+    let key = EcKey::private_key_from_pem(private_key.as_bytes())?;
+    let signature = EcdsaSig::sign(&sha256(signed.as_bytes()), &key)?;
+    let signature = [signature.r().to_vec_padded(32)?, signature.s().to_vec_padded(32)?].concat();
+
+    Ok(format!("vapid t={}.{}, k={}", signed, BASE64_URL_SAFE_NO_PAD.encode(signature), public_key))
+}
+
+/// A browser that asked to receive push messages
+pub struct Subscription<'a> {
+    pub endpoint: &'a str,
+    pub p256dh: &'a str,
+    pub auth: &'a str,
+}
+
+/// The keys of this instance, and how to contact its administrators
+pub struct Vapid<'a> {
+    pub private_key: &'a str,
+    pub public_key: &'a str,
+    pub subject: &'a str,
+}
+
+/// Sends an encrypted message to a subscription
+pub fn send(
+    subscription: &Subscription<'_>,
+    vapid: &Vapid<'_>,
+    payload: &[u8],
+    proxy: Option<Proxy>,
+) -> Result<(), Error> {
+    let body = encrypt(payload, subscription.p256dh, subscription.auth)?;
+    let authorization = vapid_authorization(subscription.endpoint, vapid.private_key, vapid.public_key, vapid.subject)?;
+
+    let client = if let Some(proxy) = proxy {
+        ClientBuilder::new().proxy(proxy)
+    } else {
+        ClientBuilder::new()
+    };
+    let res = client
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(Duration::from_secs(10))
+        // The endpoint was checked when subscribing, the addresses it redirects to weren't
+        .redirect(Policy::none())
+        .build()?
+        .post(subscription.endpoint)
+        .header(AUTHORIZATION, authorization)
+        .header(CONTENT_ENCODING, "aes128gcm")
+        .header(CONTENT_TYPE, "application/octet-stream")
+        .header("TTL", TTL.to_string())
+        .body(body)
+        .send()?;
+    match res.status() {
+        StatusCode::NOT_FOUND | StatusCode::GONE => Err(Error::Gone),
+        status if status.is_success() => Ok(()),
+        _ => Err(Error::Request),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::bn::BigNum;
+
+    #[test]
+    fn test_encrypt() {
+        // The example of RFC 8291, appendix A
+        let as_public =
+            decode("BP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A8").unwrap();
+        let as_private = decode("yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw").unwrap();
+        let group = group().unwrap();
+        let as_key = EcKey::from_private_components(
+            &group,
+            &BigNum::from_slice(&as_private).unwrap(),
+            &EcPoint::from_bytes(&group, &as_public, &mut BigNumContext::new().unwrap()).unwrap(),
+        )
+        .unwrap();
+
+        let body = encrypt_with(
+            b"When I grow up, I want to be a watermelon",
+            &decode("BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4").unwrap(),
+            &decode("BTBZMqHH6r4Tts7J_aSIgg").unwrap(),
+            as_key,
+            &decode("DGv6ra1nlYgDCS1FRnbzlw").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            BASE64_URL_SAFE_NO_PAD.encode(body),
+            "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN"
+        );
+
+        // Random keys and salts are used otherwise
+        let ua_public = public_key_bytes(&EcKey::generate(&group).unwrap()).unwrap();
+        let p256dh = BASE64_URL_SAFE_NO_PAD.encode(ua_public);
+        let auth = BASE64_URL_SAFE_NO_PAD.encode([7u8; 16]);
+        assert_ne!(encrypt(b"Hello", &p256dh, &auth).unwrap(), encrypt(b"Hello", &p256dh, &auth).unwrap());
+        assert_eq!(encrypt(&[0; 4096], &p256dh, &auth), Err(Error::Crypto));
+    }
+
+    #[test]
+    fn test_vapid_authorization() {
+        let (private_key, public_key) = gen_vapid_keys();
+        let header =
+            vapid_authorization("https://push.example.org/send/abc", &private_key, &public_key, "https://plu.me")
+                .unwrap();
+        let (token, key) = header.trim_start_matches("vapid t=").split_once(", k=").unwrap();
+        assert_eq!(key, public_key);
+
+        let parts = token.split('.').collect::<Vec<_>>();
+        assert_eq!(parts.len(), 3);
+        let claims: serde_json::Value = serde_json::from_slice(&decode(parts[1]).unwrap()).unwrap();
+        assert_eq!(claims["aud"], "https://push.example.org");
+        assert_eq!(claims["sub"], "https://plu.me");
+
+        // The signature can be checked with the public key
+        let signature = decode(parts[2]).unwrap();
+        let signature = EcdsaSig::from_private_components(
+            openssl::bn::BigNum::from_slice(&signature[..32]).unwrap(),
+            openssl::bn::BigNum::from_slice(&signature[32..]).unwrap(),
+        )
+        .unwrap();
+        let key = EcKey::private_key_from_pem(private_key.as_bytes()).unwrap();
+        let digest = sha256(format!("{}.{}", parts[0], parts[1]).as_bytes());
+        assert!(signature.verify(&digest, &key).unwrap());
+    }
+}
@@ -54,6 +54,8 @@ init_i18n!(
 );
 
 mod editor;
+mod live;
+mod push;
 
 compile_i18n!();
 
@@ -77,6 +79,8 @@ pub fn main() -> Result<(), JsValue> {
     menu();
     search();
     editor::init().map_err(|e| console::error_1(&format!("Editor error: {:?}", e).into())).ok();
+    live::init().map_err(|e| console::error_1(&format!("Live updates error: {:?}", e).into())).ok();
+    push::init().map_err(|e| console::error_1(&format!("Push notifications error: {:?}", e).into())).ok();
     Ok(())
 }
 
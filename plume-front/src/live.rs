@@ -0,0 +1,88 @@
+use crate::{document, CATALOG};
+use serde_derive::Deserialize;
+use std::{cell::Cell, rc::Rc};
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{EventSource, MessageEvent};
+
+#[derive(Deserialize)]
+struct TimelineEntry {
+    timeline: i32,
+}
+
+#[derive(Deserialize)]
+struct Unread {
+    count: i64,
+}
+
+/// Shows `count` in the notification badge, or hides it if there is nothing new
+fn show_notifications(count: i64) {
+    if let Some(badge) = document().get_element_by_id("notifications-badge") {
+        let text = if count > 99 {
+            "99+".to_owned()
+        } else {
+            count.to_string()
+        };
+        badge.set_text_content(Some(&text));
+        if count > 0 {
+            badge.class_list().remove_1("hidden").ok();
+        } else {
+            badge.class_list().add_1("hidden").ok();
+        }
+    }
+}
+
+/// Updates the notification badge, and shows when articles are added to the current timeline, without reloading
+pub fn init() -> Result<(), JsValue> {
+    let url = match document().get_element_by_id("notifications-link").and_then(|l| l.get_attribute("data-stream")) {
+        Some(url) => url,
+        None => return Ok(()), // Not logged in
+    };
+    let source = EventSource::new(&url)?;
+
+    // The server first sends the number of unread notifications, again each time it reconnects
+    let notifications = Rc::new(Cell::new(0));
+    let unread = notifications.clone();
+    let on_unread = Closure::wrap(Box::new(move |evt: MessageEvent| {
+        let count = evt.data().as_string().and_then(|data| serde_json::from_str::<Unread>(&data).ok());
+        if let Some(count) = count.map(|u| u.count) {
+            unread.set(count);
+            show_notifications(count);
+        }
+    }) as Box<dyn FnMut(MessageEvent)>);
+    source.add_event_listener_with_callback("unread", on_unread.as_ref().unchecked_ref())?;
+    on_unread.forget();
+
+    let on_notification = Closure::wrap(Box::new(move |_: MessageEvent| {
+        notifications.set(notifications.get() + 1);
+        show_notifications(notifications.get());
+    }) as Box<dyn FnMut(MessageEvent)>);
+    source.add_event_listener_with_callback("notification", on_notification.as_ref().unchecked_ref())?;
+    on_notification.forget();
+
+    if let Some(notice) = document().get_element_by_id("timeline-live") {
+        let timeline = notice.get_attribute("data-timeline").and_then(|t| t.parse::<i32>().ok());
+        let mut new_articles = 0;
+        let on_entry = Closure::wrap(Box::new(move |evt: MessageEvent| {
+            let entry = evt.data().as_string().and_then(|data| serde_json::from_str::<TimelineEntry>(&data).ok());
+            if entry.map(|e| e.timeline) != timeline {
+                return;
+            }
+            new_articles += 1;
+            if let Ok(Some(link)) = notice.query_selector("a") {
+                link.set_text_content(Some(&i18n!(
+                    CATALOG,
+                    "One new article, click to see it",
+                    "{0} new articles, click to see them";
+                    new_articles
+                )));
+            }
+            notice.class_list().remove_1("hidden").ok();
+        }) as Box<dyn FnMut(MessageEvent)>);
+        source.add_event_listener_with_callback("timeline", on_entry.as_ref().unchecked_ref())?;
+        on_entry.forget();
+    }
+
+    // The connection stays open as long as the page
+    std::mem::forget(source);
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use crate::document;
+use js_sys::{Promise, Reflect, Uint8Array, JSON};
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{
+    console, window, HtmlFormElement, HtmlInputElement, MouseEvent, PushSubscriptionOptionsInit,
+    ServiceWorkerRegistration,
+};
+
+/// Where the service worker showing the push messages is served, next to `plume_front.js`
+const SERVICE_WORKER: &str = "/static/plume_sw.js";
+
+/// Decodes the URL-safe base64 key of the instance, as expected by `PushManager.subscribe`
+fn decode_key(key: &str) -> Result<Uint8Array, JsValue> {
+    let mut key = key.replace('-', "+").replace('_', "/");
+    while key.len() % 4 != 0 {
+        key.push('=');
+    }
+    let bytes = window().unwrap().atob(&key)?.chars().map(|c| c as u8).collect::<Vec<u8>>();
+    Ok(Uint8Array::from(&bytes[..]))
+}
+
+fn subscribe(key: &str) -> Result<(), JsValue> {
+    let options = PushSubscriptionOptionsInit::new();
+    options.set_user_visible_only(true);
+    options.set_application_server_key(&decode_key(key)?.into());
+
+    let on_subscription = Closure::wrap(Box::new(|subscription: JsValue| {
+        let json = JSON::stringify(&subscription).ok().and_then(|json| json.as_string());
+        let input =
+            document().get_element_by_id("push-subscription").and_then(|i| i.dyn_into::<HtmlInputElement>().ok());
+        let form = document().get_element_by_id("push-form").and_then(|f| f.dyn_into::<HtmlFormElement>().ok());
+        if let (Some(json), Some(input), Some(form)) = (json, input, form) {
+            input.set_value(&json);
+            form.submit().ok();
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+    let on_error = Closure::wrap(Box::new(|err: JsValue| {
+        console::error_2(&"Couldn't subscribe to push notifications:".into(), &err);
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let on_registration = Closure::wrap(Box::new(move |registration: JsValue| {
+        let subscription = registration
+            .dyn_into::<ServiceWorkerRegistration>()
+            .and_then(|r| r.push_manager())
+            .and_then(|manager| manager.subscribe_with_options(&options));
+        match subscription {
+            Ok(promise) => {
+                promise.then2(&on_subscription, &on_error);
+            }
+            Err(err) => console::error_1(&err),
+        }
+    }) as Box<dyn FnMut(JsValue)>);
+
+    let registration: Promise = window().unwrap().navigator().service_worker().register(SERVICE_WORKER);
+    registration.then(&on_registration);
+    on_registration.forget();
+    Ok(())
+}
+
+/// Lets users receive their notifications in their browser, from the notification settings
+pub fn init() -> Result<(), JsValue> {
+    let button = match document().get_element_by_id("push-subscribe") {
+        Some(button) => button,
+        None => return Ok(()),
+    };
+    let window = window().unwrap();
+    let supported =
+        Reflect::has(&window.navigator(), &"serviceWorker".into())? && Reflect::has(&window, &"PushManager".into())?;
+    if !supported {
+        return Ok(());
+    }
+    button.class_list().remove_1("hidden")?;
+
+    let key = button.get_attribute("data-key").unwrap_or_default();
+    let on_click = Closure::wrap(Box::new(move |_: MouseEvent| {
+        subscribe(&key).map_err(|e| console::error_1(&e)).ok();
+    }) as Box<dyn FnMut(MouseEvent)>);
+    button.add_event_listener_with_callback("click", on_click.as_ref().unchecked_ref())?;
+    on_click.forget();
+    Ok(())
+}
@@ -0,0 +1,17 @@
+// Shows the notifications pushed by the instance, even when Plume isn't open
+self.addEventListener('push', event => {
+	const data = event.data ? event.data.json() : {}
+	event.waitUntil(self.registration.showNotification(data.title || 'Plume', {
+		body: data.body,
+		icon: '/static/icons/trwnh/feather/plumeFeather256.png',
+		data: { url: data.url }
+	}))
+})
+
+self.addEventListener('notificationclick', event => {
+	event.notification.close()
+	const url = event.notification.data && event.notification.data.url
+	if (url) {
+		event.waitUntil(self.clients.openWindow(url))
+	}
+})
@@ -0,0 +1,151 @@
+use crate::{
+    db_conn::DbConn, headers::Headers, inbox::fetch_actor, instance::Instance, users::User, Connection, CONFIG,
+};
+use once_cell::sync::Lazy;
+use plume_common::{
+    activity_pub::{
+        request,
+        sign::{signature_key_id, verify_http_headers_strict, verify_http_message_signature},
+    },
+    utils::is_public_url,
+};
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome, Request},
+    tokio::task::spawn_blocking,
+};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::debug;
+use url::Url;
+
+/// How long the owner of a key that is a document of its own is remembered, or that it couldn't be found
+const KEY_OWNER_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How many keys are remembered at most
+const KEY_OWNERS_MAX: usize = 10_000;
+
+/// The owners of the keys that were fetched recently, and until when they are remembered
+static KEY_OWNERS: Lazy<Mutex<HashMap<String, (Option<String>, Instant)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Request guard for the routes serving ActivityPub documents.
+///
+/// When `AUTHORIZED_FETCH` is enabled, requests must be signed by an actor whose instance is not blocked. The
+/// instance actor is always served, since other instances need its key to check the requests it signs.
+pub struct AuthorizedFetch;
+
+/// The actor a signature was made by. Keys with a fragment are part of the document of their actor, and other keys
+/// are documents of their own, giving their `owner`.
+///
+/// Keys that are documents of their own are usually next to their actor: they are only fetched if that actor isn't
+/// known already, if they are on a public address, and not again for a while.
+async fn key_owner(conn: &mut Connection, key_id: &str) -> Option<String> {
+    let mut url = Url::parse(key_id).ok()?;
+    if url.fragment().is_some() {
+        url.set_fragment(None);
+        return Some(url.to_string());
+    }
+
+    let now = Instant::now();
+    if let Some((owner, until)) = KEY_OWNERS.lock().unwrap().get(key_id) {
+        if *until > now {
+            return owner.clone();
+        }
+    }
+    if let Ok(mut parent) = url.path_segments_mut() {
+        parent.pop_if_empty().pop();
+    }
+    if let Ok(actor) = User::find_by_ap_url(conn, url.as_str()) {
+        return Some(actor.ap_url);
+    }
+
+    let sender = Instance::get_local_instance_user()?;
+    let id = key_id.to_owned();
+    let owner = spawn_blocking(move || {
+        if !is_public_url(&id) {
+            return None;
+        }
+        let key = request::get(&id, sender, CONFIG.proxy().cloned()).ok()?.text().ok()?;
+        let key = serde_json::from_str::<serde_json::Value>(&key).ok()?;
+        // Some servers serve the whole actor for its key
+        key["owner"].as_str().or_else(|| key["publicKey"]["owner"].as_str()).map(str::to_owned)
+    })
+    .await
+    .ok()?;
+
+    let mut owners = KEY_OWNERS.lock().unwrap();
+    owners.retain(|_, (_, until)| *until > now);
+    if owners.len() < KEY_OWNERS_MAX {
+        owners.insert(key_id.to_owned(), (owner.clone(), now + KEY_OWNER_TTL));
+    }
+    owner
+}
+
+fn is_instance_actor(request: &Request<'_>) -> bool {
+    Instance::get_local_instance_user()
+        .and_then(|user| Url::parse(&user.ap_url).ok())
+        .map_or(false, |url| url.path().trim_end_matches('/') == request.uri().path().as_str().trim_end_matches('/'))
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthorizedFetch {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<AuthorizedFetch, Self::Error> {
+        if !CONFIG.authorized_fetch || is_instance_actor(request) {
+            return Outcome::Success(AuthorizedFetch);
+        }
+
+        let headers = match request.guard::<Headers<'_>>().await {
+            Outcome::Success(headers) => headers,
+            _ => return Outcome::Error((Status::Unauthorized, ())),
+        };
+        let key_id = match signature_key_id(&headers.0) {
+            Some(key_id) => key_id,
+            None => return Outcome::Error((Status::Unauthorized, ())),
+        };
+        let mut conn = match request.guard::<DbConn>().await {
+            Outcome::Success(conn) => conn,
+            _ => return Outcome::Error((Status::ServiceUnavailable, ())),
+        };
+
+        if Instance::is_blocked(&mut conn, &key_id).unwrap_or(true) {
+            debug!("Refused to serve {} to {}, from a blocked instance", request.uri(), key_id);
+            return Outcome::Error((Status::Forbidden, ()));
+        }
+        let actor_id = match key_owner(&mut conn, &key_id).await {
+            Some(actor_id) => actor_id,
+            None => {
+                debug!("Couldn't find the owner of {}, who signed a request for {}", key_id, request.uri());
+                return Outcome::Error((Status::Unauthorized, ()));
+            }
+        };
+        if Instance::is_blocked(&mut conn, &actor_id).unwrap_or(true) {
+            debug!("Refused to serve {} to {}, from a blocked instance", request.uri(), actor_id);
+            return Outcome::Error((Status::Forbidden, ()));
+        }
+        let actor = match fetch_actor(&mut conn, &actor_id).await {
+            Ok(actor) => actor,
+            Err(e) => {
+                debug!("Couldn't fetch {}, who signed a request for {}: {:?}", actor_id, request.uri(), e);
+                return Outcome::Error((Status::Unauthorized, ()));
+            }
+        };
+        // The actor is the owner of the key, whether it was found from its id or from its document
+        let verified = if headers.0.contains("Signature-Input") {
+            verify_http_message_signature(&actor, &actor_id, Some(&actor_id), &headers.0, None)
+        } else {
+            verify_http_headers_strict(&actor, &actor_id, Some(&actor_id), &headers.0, None)
+        };
+        match verified {
+            Ok(()) => Outcome::Success(AuthorizedFetch),
+            Err(reason) => {
+                debug!(actor = actor_id, ?reason, "Refused to serve {} to an invalid signature", request.uri());
+                Outcome::Error((Status::Unauthorized, ()))
+            }
+        }
+    }
+}
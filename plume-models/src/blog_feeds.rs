@@ -0,0 +1,559 @@
+use crate::{
+    blogs::Blog,
+    instance::Instance,
+    live,
+    post_authors::{NewPostAuthor, PostAuthor},
+    posts::{NewPost, Post},
+    safe_string::SafeString,
+    schema::{blog_feed_items, blog_feeds},
+    tags::{NewTag, Tag},
+    timeline::{Kind, Timeline},
+    users::User,
+    Connection, Error, Result, CONFIG,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::{self, BoolExpressionMethods, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use once_cell::sync::Lazy;
+use plume_common::{activity_pub::broadcast, utils::public_addrs};
+use quick_xml::{events::Event, Reader};
+use reqwest::{
+    blocking::{Client, ClientBuilder},
+    header::{ACCEPT, LOCATION},
+    redirect::Policy,
+};
+use rocket::tokio::runtime::{Builder, Runtime};
+use std::time::Duration;
+use tracing::{info, warn};
+use url::Url;
+
+/// How many redirections are followed to find a feed
+const MAX_REDIRECTIONS: usize = 10;
+/// How many feeds are downloaded at the same time
+const FETCH_CONCURRENCY: usize = 8;
+
+/// Runs the imports, that add the new articles to the timelines
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("feeds-runtime")
+        .enable_all()
+        .build()
+        .expect("Error while initializing tokio runtime for the external feeds")
+});
+
+/// An external RSS or Atom feed, whose entries are imported as articles of a blog
+#[derive(Clone, Debug, Queryable, Identifiable)]
+pub struct BlogFeed {
+    pub id: i32,
+    pub blog_id: i32,
+    /// The user the imported articles are attributed to
+    pub author_id: i32,
+    pub url: String,
+    pub last_fetched_at: Option<NaiveDateTime>,
+    /// Why the last poll failed, if it did
+    pub last_error: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = blog_feeds)]
+pub struct NewBlogFeed {
+    pub blog_id: i32,
+    pub author_id: i32,
+    pub url: String,
+}
+
+/// Links an imported article to the entry of the feed it comes from
+#[derive(Clone, Debug, Queryable, Identifiable)]
+pub struct BlogFeedItem {
+    pub id: i32,
+    pub feed_id: i32,
+    pub post_id: i32,
+    /// The identifier of the entry in the feed
+    pub guid: String,
+    /// Where the article was originally published
+    pub url: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = blog_feed_items)]
+pub struct NewBlogFeedItem {
+    pub feed_id: i32,
+    pub post_id: i32,
+    pub guid: String,
+    pub url: String,
+}
+
+/// An entry of an external feed, whatever its format
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeedEntry {
+    pub guid: String,
+    pub url: String,
+    pub title: String,
+    /// The HTML content of the entry, that may be empty if the feed only gives summaries
+    pub content: String,
+    pub summary: String,
+    pub published: Option<NaiveDateTime>,
+    pub tags: Vec<String>,
+}
+
+impl BlogFeed {
+    insert!(blog_feeds, NewBlogFeed);
+    get!(blog_feeds);
+    list_by!(blog_feeds, list_for_blog, blog_id as i32);
+
+    /// Lists the feeds that were not polled during the last `interval`
+    pub fn list_due(conn: &mut Connection, interval: chrono::Duration) -> Result<Vec<Self>> {
+        let limit = Utc::now().naive_utc() - interval;
+        blog_feeds::table
+            .filter(blog_feeds::last_fetched_at.is_null().or(blog_feeds::last_fetched_at.lt(limit)))
+            .load(conn)
+            .map_err(Error::from)
+    }
+
+    /// Stops polling this feed. The articles that were already imported are kept.
+    pub fn delete(&self, conn: &mut Connection) -> Result<()> {
+        diesel::delete(self).execute(conn).map(|_| ()).map_err(Error::from)
+    }
+
+    /// Downloads the feed and returns its entries.
+    ///
+    /// Feeds that are not on a public address, or that redirect to one that is not, are refused. The redirections are
+    /// followed one by one, connecting to the addresses that were checked.
+    pub fn fetch(&self) -> Result<Vec<FeedEntry>> {
+        let mut url = Url::parse(&self.url).map_err(|_| Error::Unauthorized)?;
+        for _ in 0..=MAX_REDIRECTIONS {
+            let response = pinned_client(&url)?
+                .get(url.as_str())
+                .header(ACCEPT, "application/atom+xml, application/rss+xml, application/xml;q=0.9, text/xml;q=0.8")
+                .send()?;
+            if response.status().is_redirection() {
+                let location = response.headers().get(LOCATION).and_then(|l| l.to_str().ok()).ok_or(Error::Request)?;
+                url = url.join(location).map_err(|_| Error::Request)?;
+                continue;
+            }
+            return parse_feed(&response.error_for_status()?.text()?);
+        }
+        Err(Error::Request)
+    }
+
+    /// Imports the entries of the feed, or records why it couldn't be downloaded.
+    ///
+    /// The outcome is saved, so that the authors of the blog can see if something went wrong.
+    pub async fn save(&self, conn: &mut Connection, fetched: Result<Vec<FeedEntry>>) -> Result<Vec<Post>> {
+        let res = match fetched {
+            Ok(entries) => self.import(conn, entries).await,
+            Err(e) => Err(e),
+        };
+        let last_error = res.as_ref().err().map(|e| {
+            warn!("Couldn't poll {}: {:?}", self.url, e);
+            error_message(e).to_owned()
+        });
+        diesel::update(self)
+            .set((blog_feeds::last_fetched_at.eq(Utc::now().naive_utc()), blog_feeds::last_error.eq(last_error)))
+            .execute(conn)?;
+        res
+    }
+
+    /// Polls all the feeds that are due, logging the failures.
+    ///
+    /// A few feeds are downloaded at the same time, and then imported one after the other.
+    pub fn poll_all(conn: &mut Connection) {
+        let interval = chrono::Duration::minutes(CONFIG.external_feeds_poll_interval);
+        let feeds = match Self::list_due(conn, interval) {
+            Ok(feeds) => feeds,
+            Err(e) => {
+                warn!("Couldn't list the external feeds to poll: {:?}", e);
+                return;
+            }
+        };
+        for batch in feeds.chunks(FETCH_CONCURRENCY) {
+            let fetched = std::thread::scope(|scope| {
+                batch
+                    .iter()
+                    .map(|feed| scope.spawn(move || feed.fetch()))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .map(|handle| handle.join().unwrap_or(Err(Error::Request)))
+                    .collect::<Vec<_>>()
+            });
+            for (feed, entries) in batch.iter().zip(fetched) {
+                match RUNTIME.block_on(feed.save(conn, entries)) {
+                    Ok(posts) if !posts.is_empty() => info!("Imported {} articles from {}", posts.len(), feed.url),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    async fn import(&self, conn: &mut Connection, mut entries: Vec<FeedEntry>) -> Result<Vec<Post>> {
+        let blog = Blog::get(conn, self.blog_id)?;
+        let author = User::get(conn, self.author_id)?;
+        // The articles that were in the feed when it was added are not sent to the followers of the blog
+        let federate = self.last_fetched_at.is_some();
+
+        entries.sort_by_key(|e| e.published);
+        let mut posts = vec![];
+        for entry in entries {
+            if entry.guid.is_empty() || BlogFeedItem::find_by_guid(conn, self.id, &entry.guid)?.is_some() {
+                continue;
+            }
+            // An entry is imported entirely or not at all, to be retried at the next poll. It is indexed once committed.
+            let post = live::transaction(conn, |conn| self.import_entry(conn, &blog, &author, entry))?;
+            if federate {
+                let act = post.create_activity(conn)?;
+                let dest = User::one_by_instance(conn)?;
+                broadcast(&author, act, dest, CONFIG.proxy().cloned());
+            }
+            Timeline::add_to_all_timelines(conn, &post, &Kind::Original).await?;
+            posts.push(post);
+        }
+        Ok(posts)
+    }
+
+    fn import_entry(&self, conn: &mut Connection, blog: &Blog, author: &User, entry: FeedEntry) -> Result<Post> {
+        let title = if entry.title.is_empty() {
+            entry.url.clone()
+        } else {
+            entry.title
+        };
+        // Two entries can have the same title, but not the same slug
+        let mut slug = Post::slug(&title).to_string();
+        let mut n = 1;
+        while Post::find_by_slug(conn, &slug, blog.id).is_ok() {
+            n += 1;
+            slug = format!("{}-{}", Post::slug(&title), n);
+        }
+        let content = if entry.content.is_empty() {
+            entry.summary
+        } else {
+            entry.content
+        };
+
+        let post = Post::insert(
+            conn,
+            NewPost {
+                blog_id: blog.id,
+                slug,
+                title,
+                content: SafeString::new(&content),
+                published: true,
+                license: Instance::get_local()?.default_license,
+                ap_url: String::new(),
+                creation_date: entry.published,
+                subtitle: String::new(),
+                source: content,
+                cover_id: None,
+            },
+        )?;
+        PostAuthor::insert(
+            conn,
+            NewPostAuthor {
+                post_id: post.id,
+                author_id: author.id,
+            },
+        )?;
+        for tag in entry.tags {
+            Tag::insert(
+                conn,
+                NewTag {
+                    tag,
+                    is_hashtag: false,
+                    post_id: post.id,
+                },
+            )?;
+        }
+        BlogFeedItem::insert(
+            conn,
+            NewBlogFeedItem {
+                feed_id: self.id,
+                post_id: post.id,
+                guid: entry.guid,
+                url: entry.url,
+            },
+        )?;
+        Ok(post)
+    }
+}
+
+/// Builds a client that only connects to the public addresses the host of `url` resolves to now, and doesn't follow
+/// redirections
+fn pinned_client(url: &Url) -> Result<Client> {
+    let addrs = public_addrs(url.as_str()).ok_or(Error::Unauthorized)?;
+    let mut client = if let Some(proxy) = CONFIG.proxy() {
+        ClientBuilder::new().proxy(proxy.clone())
+    } else {
+        ClientBuilder::new()
+    };
+    if let Some(domain) = url.domain() {
+        client = client.resolve_to_addrs(domain, &addrs);
+    }
+    client
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(Duration::from_secs(30))
+        .user_agent(concat!("Plume/", env!("CARGO_PKG_VERSION")))
+        .redirect(Policy::none())
+        .build()
+        .map_err(Error::from)
+}
+
+/// Describes why polling a feed failed, in a way that can be shown to the authors of the blog
+fn error_message(err: &Error) -> &'static str {
+    match err {
+        Error::Unauthorized => "the address of the feed is not public",
+        Error::Request => "the feed couldn't be downloaded",
+        Error::InvalidValue | Error::SerDe => "this is not a valid RSS or Atom feed",
+        _ => "the articles couldn't be imported",
+    }
+}
+
+impl BlogFeedItem {
+    insert!(blog_feed_items, NewBlogFeedItem);
+    get!(blog_feed_items);
+
+    /// Returns where `post` was originally published, if it was imported from a feed
+    pub fn find_for_post(conn: &mut Connection, post_id: i32) -> Result<Option<Self>> {
+        blog_feed_items::table.filter(blog_feed_items::post_id.eq(post_id)).first(conn).optional().map_err(Error::from)
+    }
+
+    fn find_by_guid(conn: &mut Connection, feed_id: i32, guid: &str) -> Result<Option<Self>> {
+        blog_feed_items::table
+            .filter(blog_feed_items::feed_id.eq(feed_id))
+            .filter(blog_feed_items::guid.eq(guid))
+            .first(conn)
+            .optional()
+            .map_err(Error::from)
+    }
+}
+
+/// Reads an Atom or RSS (0.9x, 1.0 or 2.0) feed
+pub fn parse_feed(body: &str) -> Result<Vec<FeedEntry>> {
+    match body.parse::<atom_syndication::Feed>() {
+        Ok(feed) => Ok(feed.entries().iter().map(atom_entry).collect()),
+        Err(_) => parse_rss(body),
+    }
+}
+
+fn atom_entry(entry: &atom_syndication::Entry) -> FeedEntry {
+    FeedEntry {
+        guid: entry.id().to_owned(),
+        url: entry
+            .links()
+            .iter()
+            .find(|l| l.rel() == "alternate")
+            .map(|l| l.href().to_owned())
+            .unwrap_or_else(|| entry.id().to_owned()),
+        title: entry.title().value.trim().to_owned(),
+        content: entry.content().and_then(|c| c.value()).unwrap_or_default().to_owned(),
+        summary: entry.summary().map(|s| s.value.clone()).unwrap_or_default(),
+        published: Some(entry.published().unwrap_or_else(|| entry.updated()).naive_utc()),
+        tags: entry.categories().iter().map(|c| c.term().to_owned()).collect(),
+    }
+}
+
+fn parse_rss(body: &str) -> Result<Vec<FeedEntry>> {
+    let mut reader = Reader::from_str(body);
+    let mut is_rss = false;
+    let mut entries = vec![];
+    let mut entry: Option<FeedEntry> = None;
+    let mut text = String::new();
+
+    loop {
+        match reader.read_event().map_err(|_| Error::InvalidValue)? {
+            Event::Start(e) => {
+                match e.name().as_ref() {
+                    b"rss" | b"rdf:RDF" => is_rss = true,
+                    b"item" => entry = Some(FeedEntry::default()),
+                    _ => {}
+                }
+                text.clear();
+            }
+            Event::Text(t) => text.push_str(&t.unescape().map_err(|_| Error::InvalidValue)?),
+            Event::CData(c) => text.push_str(&String::from_utf8_lossy(&c)),
+            Event::End(e) => {
+                let name = e.name();
+                if name.as_ref() == b"item" {
+                    if let Some(mut item) = entry.take() {
+                        if item.guid.is_empty() {
+                            item.guid = item.url.clone();
+                        }
+                        entries.push(item);
+                    }
+                } else if let Some(item) = entry.as_mut() {
+                    let value = text.trim();
+                    match name.as_ref() {
+                        b"title" => item.title = value.to_owned(),
+                        b"link" => item.url = value.to_owned(),
+                        b"guid" => item.guid = value.to_owned(),
+                        b"description" => item.summary = value.to_owned(),
+                        b"content:encoded" => item.content = value.to_owned(),
+                        b"category" => item.tags.push(value.to_owned()),
+                        b"pubDate" => item.published = DateTime::parse_from_rfc2822(value).ok().map(|d| d.naive_utc()),
+                        b"dc:date" => item.published = DateTime::parse_from_rfc3339(value).ok().map(|d| d.naive_utc()),
+                        _ => {}
+                    }
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if is_rss {
+        Ok(entries)
+    } else {
+        Err(Error::InvalidValue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blogs::tests as blogTests, tests::db};
+    use diesel::Connection;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_parse_atom() {
+        let entries = parse_feed(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+    <title>Example</title>
+    <id>https://example.org/</id>
+    <updated>2026-01-02T10:00:00Z</updated>
+    <entry>
+        <title>Hello &amp; welcome</title>
+        <id>tag:example.org,2026:1</id>
+        <link rel="alternate" href="https://example.org/hello"/>
+        <published>2026-01-01T10:00:00+01:00</published>
+        <updated>2026-01-02T10:00:00Z</updated>
+        <category term="news"/>
+        <content type="html">&lt;p&gt;Hello!&lt;/p&gt;</content>
+    </entry>
+</feed>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![FeedEntry {
+                guid: "tag:example.org,2026:1".to_owned(),
+                url: "https://example.org/hello".to_owned(),
+                title: "Hello & welcome".to_owned(),
+                content: "<p>Hello!</p>".to_owned(),
+                summary: String::new(),
+                published: chrono::NaiveDate::from_ymd_opt(2026, 1, 1).unwrap().and_hms_opt(9, 0, 0),
+                tags: vec!["news".to_owned()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_rss() {
+        let entries = parse_feed(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+    <channel>
+        <title>Example</title>
+        <link>https://example.org/</link>
+        <item>
+            <title>Hello &amp; welcome</title>
+            <link>https://example.org/hello</link>
+            <description>Short</description>
+            <content:encoded><![CDATA[<p>Hello!</p>]]></content:encoded>
+            <pubDate>Thu, 01 Jan 2026 10:00:00 +0100</pubDate>
+            <category>news</category>
+        </item>
+        <item>
+            <title>No date</title>
+            <guid isPermaLink="false">2</guid>
+            <link>https://example.org/no-date</link>
+        </item>
+    </channel>
+</rss>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                FeedEntry {
+                    guid: "https://example.org/hello".to_owned(),
+                    url: "https://example.org/hello".to_owned(),
+                    title: "Hello & welcome".to_owned(),
+                    content: "<p>Hello!</p>".to_owned(),
+                    summary: "Short".to_owned(),
+                    published: chrono::NaiveDate::from_ymd_opt(2026, 1, 1).unwrap().and_hms_opt(9, 0, 0),
+                    tags: vec!["news".to_owned()],
+                },
+                FeedEntry {
+                    guid: "2".to_owned(),
+                    url: "https://example.org/no-date".to_owned(),
+                    title: "No date".to_owned(),
+                    ..FeedEntry::default()
+                },
+            ]
+        );
+
+        assert!(parse_feed("<html><body>Not a feed</body></html>").is_err());
+    }
+
+    #[test]
+    fn test_import() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = blogTests::fill_database(conn);
+            let feed = BlogFeed::insert(
+                conn,
+                NewBlogFeed {
+                    blog_id: blogs[0].id,
+                    author_id: users[0].id,
+                    url: "https://example.org/feed.xml".to_owned(),
+                },
+            )
+            .unwrap();
+            let entry = FeedEntry {
+                guid: "1".to_owned(),
+                url: "https://example.org/hello".to_owned(),
+                title: "Hello".to_owned(),
+                summary: "<p>Hello!</p>".to_owned(),
+                ..FeedEntry::default()
+            };
+
+            let posts = block_on(feed.import(conn, vec![entry.clone()])).unwrap();
+            assert_eq!(posts.len(), 1);
+            assert_eq!(posts[0].blog_id, blogs[0].id);
+            assert_eq!(posts[0].get_authors(conn).unwrap()[0].id, users[0].id);
+            let item = BlogFeedItem::find_for_post(conn, posts[0].id).unwrap().unwrap();
+            assert_eq!(item.url, "https://example.org/hello");
+
+            // Entries are only imported once
+            assert!(block_on(feed.import(conn, vec![entry.clone()])).unwrap().is_empty());
+
+            // A new entry with the same title gets another slug
+            let posts2 = block_on(feed.import(
+                conn,
+                vec![FeedEntry {
+                    guid: "2".to_owned(),
+                    ..entry
+                }],
+            ))
+            .unwrap();
+            assert_ne!(posts[0].slug, posts2[0].slug);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_fetch_private_address() {
+        let feed = BlogFeed {
+            id: 0,
+            blog_id: 0,
+            author_id: 0,
+            url: "http://127.0.0.1:7878/feed.xml".to_owned(),
+            last_fetched_at: None,
+            last_error: None,
+        };
+        assert!(matches!(feed.fetch(), Err(Error::Unauthorized)));
+        assert_eq!(error_message(&Error::Unauthorized), "the address of the feed is not public");
+    }
+}
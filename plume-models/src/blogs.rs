@@ -1,16 +1,18 @@
 use crate::{
     instance::*, medias::Media, posts::Post, safe_string::SafeString, schema::blogs, users::User, Connection, Error,
-    PlumeRocket, Result, CONFIG, ITEMS_PER_PAGE,
+    Result, CONFIG, ITEMS_PER_PAGE,
 };
 use activitystreams::{
+    activity::{Delete, Update},
     actor::{ApActor, ApActorExt, AsApActor, Group},
-    base::AnyBase,
+    base::{AnyBase, Base},
     collection::{OrderedCollection, OrderedCollectionPage},
     iri_string::types::IriString,
-    object::{kind::ImageType, ApObject, Image, ObjectExt},
+    object::{kind::ImageType, ApObject, Image, ObjectExt, Tombstone},
     prelude::*,
+    primitives::OneOrMany,
 };
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use diesel::{self, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl, SaveChangesDsl};
 use openssl::{
     hash::MessageDigest,
@@ -20,9 +22,9 @@ use openssl::{
 };
 use plume_common::{
     activity_pub::{
-        inbox::{AsActor, FromId},
+        inbox::{AsActor, AsObject, FromId},
         sign, ActivityStream, ApSignature, CustomGroup, Id, IntoId, PublicKey, Source, SourceProperty, ToAsString,
-        ToAsUri,
+        ToAsUri, PUBLIC_VISIBILITY,
     },
     utils::iri_percent_encode_seg,
 };
@@ -214,6 +216,25 @@ impl Blog {
         Ok(CustomGroup::new(blog, ap_signature, source))
     }
 
+    /// The `Update` sent to other instances when the title, summary, icon or banner of the blog change
+    pub fn update_activity(&self, conn: &mut Connection) -> Result<Update> {
+        let mut act =
+            Update::new(self.ap_url.parse::<IriString>()?, Base::retract(self.to_activity(conn)?)?.into_generic()?);
+        act.set_id(format!("{}#update-{}", self.ap_url, Utc::now().timestamp()).parse::<IriString>()?);
+        act.set_many_tos(vec![PUBLIC_VISIBILITY.parse::<IriString>()?]);
+        Ok(act)
+    }
+
+    pub fn delete_activity(&self) -> Result<Delete> {
+        let mut tombstone = Tombstone::new();
+        tombstone.set_id(self.ap_url.parse()?);
+
+        let mut del = Delete::new(self.ap_url.parse::<IriString>()?, Base::retract(tombstone)?.into_generic()?);
+        del.set_id(format!("{}#delete", self.ap_url).parse()?);
+        del.set_many_tos(vec![PUBLIC_VISIBILITY.parse::<IriString>()?]);
+        Ok(del)
+    }
+
     pub fn outbox(&self, conn: &mut Connection) -> Result<ActivityStream<OrderedCollection>> {
         self.outbox_collection(conn).map(ActivityStream::new)
     }
@@ -353,39 +374,8 @@ impl FromId<Connection> for Blog {
         new_blog.summary_html =
             SafeString::new(&object.summary().and_then(|summary| summary.to_as_string()).unwrap_or_default());
 
-        let icon_id = match object
-            .icon()
-            .and_then(|icons| icons.iter().next())
-            .and_then(|icon| icon.to_owned().extend::<Image, ImageType>().ok().flatten())
-            .and_then(|icon| {
-                let i = icon.url().and_then(|b| b.to_as_uri());
-                let o = icon.attributed_to().and_then(|b| b.to_as_uri());
-                i.zip(o)
-            }) {
-            Some((icon, owner)) => User::from_id(conn, &owner, None, CONFIG.proxy())
-                .await
-                .ok()
-                .and_then(|user| Media::save_remote(conn, icon, &user).ok().map(|m| m.id)),
-            None => None,
-        };
-        new_blog.icon_id = icon_id;
-
-        let banner_id = match object
-            .image()
-            .and_then(|banners| banners.iter().next())
-            .and_then(|banner| banner.to_owned().extend::<Image, ImageType>().ok().flatten())
-            .and_then(|banner| {
-                let b = banner.url().and_then(|b| b.to_as_uri());
-                let o = banner.attributed_to().and_then(|b| b.to_as_uri());
-                b.zip(o)
-            }) {
-            Some((banner, owner)) => User::from_id(conn, &owner, None, CONFIG.proxy())
-                .await
-                .ok()
-                .and_then(|user| Media::save_remote(conn, banner, &user).ok().map(|m| m.id)),
-            None => None,
-        };
-        new_blog.banner_id = banner_id;
+        new_blog.icon_id = remote_image(conn, object.icon()).await;
+        new_blog.banner_id = remote_image(conn, object.image()).await;
 
         new_blog.summary = acct.ext_two.source.content;
 
@@ -421,7 +411,115 @@ impl FromId<Connection> for Blog {
     }
 }
 
-impl AsActor<&PlumeRocket> for Blog {
+/// Saves the first image of an icon or banner property, owned by the user it is attributed to
+async fn remote_image(conn: &mut Connection, images: Option<&OneOrMany<AnyBase>>) -> Option<i32> {
+    let (url, owner) = images
+        .and_then(|images| images.iter().next())
+        .and_then(|image| image.to_owned().extend::<Image, ImageType>().ok().flatten())
+        .and_then(|image| {
+            let u = image.url().and_then(|u| u.to_as_uri());
+            let o = image.attributed_to().and_then(|o| o.to_as_uri());
+            u.zip(o)
+        })?;
+    let user = User::from_id(conn, &owner, None, CONFIG.proxy()).await.ok()?;
+    Media::save_remote(conn, url, &user).ok().map(|m| m.id)
+}
+
+/// The new information of a remote blog, from an `Update` it sent
+pub struct BlogUpdate {
+    pub ap_url: String,
+    pub title: Option<String>,
+    pub summary: String,
+    pub summary_html: Option<String>,
+    /// The new icon: `None` if the blog has no icon anymore, `Some(None)` if it couldn't be saved
+    pub icon_id: Option<Option<i32>>,
+    /// The new banner: `None` if the blog has no banner anymore, `Some(None)` if it couldn't be saved
+    pub banner_id: Option<Option<i32>>,
+}
+
+impl FromId<Connection> for BlogUpdate {
+    type Error = Error;
+    type Object = CustomGroup;
+
+    fn from_db(_: &mut Connection, _: &str) -> Result<Self> {
+        // Always fail because we always want to deserialize the AP object
+        Err(Error::NotFound)
+    }
+
+    async fn from_activity(conn: &mut Connection, updated: Self::Object) -> Result<Self> {
+        let summary = updated.ext_two.source.content;
+        let object = ApObject::new(updated.inner);
+        let title = object.name().and_then(|name| name.to_as_string());
+        let summary_html = object.summary().and_then(|summary| summary.to_as_string());
+        let icon_id = match object.icon() {
+            Some(icon) => Some(remote_image(conn, Some(icon)).await),
+            None => None,
+        };
+        let banner_id = match object.image() {
+            Some(image) => Some(remote_image(conn, Some(image)).await),
+            None => None,
+        };
+        let ap_url = AnyBase::from_extended(object)?.id().ok_or(Error::MissingApProperty)?.to_string();
+
+        Ok(BlogUpdate {
+            ap_url,
+            title,
+            summary,
+            summary_html,
+            icon_id,
+            banner_id,
+        })
+    }
+
+    fn get_sender() -> &'static dyn sign::Signer {
+        Instance::get_local_instance_user().expect("Failed to local instance user")
+    }
+}
+
+impl AsObject<Blog, Update, &mut Connection> for BlogUpdate {
+    type Error = Error;
+    type Output = ();
+
+    async fn activity(self, conn: &mut Connection, mut actor: Blog, _id: &str) -> Result<()> {
+        // Blogs can only update themselves
+        if self.ap_url != actor.ap_url || actor.is_local() {
+            return Err(Error::Unauthorized);
+        }
+
+        if let Some(title) = self.title {
+            actor.title = title;
+        }
+        if let Some(summary_html) = self.summary_html {
+            actor.summary_html = SafeString::new(&summary_html);
+        }
+        actor.summary = self.summary;
+        // The current images are kept if the new ones couldn't be downloaded, and removed if there are none anymore
+        match self.icon_id {
+            Some(None) => {}
+            icon_id => actor.icon_id = icon_id.flatten(),
+        }
+        match self.banner_id {
+            Some(None) => {}
+            banner_id => actor.banner_id = banner_id.flatten(),
+        }
+        actor.save(conn).map(|_| ())
+    }
+}
+
+impl AsObject<Blog, Delete, &mut Connection> for Blog {
+    type Error = Error;
+    type Output = ();
+
+    async fn activity(self, conn: &mut Connection, actor: Blog, _id: &str) -> Result<()> {
+        if self.id == actor.id && !self.is_local() {
+            self.delete(conn)
+        } else {
+            Err(Error::Unauthorized)
+        }
+    }
+}
+
+impl AsActor<&mut Connection> for Blog {
     fn get_inbox_url(&self) -> String {
         self.inbox_url.clone()
     }
@@ -474,11 +572,12 @@ impl NewBlog {
 pub(crate) mod tests {
     use super::*;
     use crate::{
-        blog_authors::*, instance::tests as instance_tests, medias::NewMedia, tests::db, users::tests as usersTests,
-        Connection as Conn,
+        blog_authors::*, inbox::inbox, instance::tests as instance_tests, medias::NewMedia, tests::db,
+        users::tests as usersTests, Connection as Conn,
     };
     use assert_json_diff::assert_json_eq;
     use diesel::Connection;
+    use futures::executor::block_on;
     use serde_json::to_value;
 
     pub(crate) fn fill_database(conn: &Conn) -> (Vec<User>, Vec<Blog>) {
@@ -963,4 +1062,111 @@ pub(crate) mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn delete_activity() {
+        let conn = &db();
+        conn.test_transaction::<_, Error, _>(|| {
+            let (_users, blogs) = fill_database(conn);
+            let act = blogs[0].delete_activity()?;
+
+            let expected = json!({
+                "actor": "https://plu.me/~/BlogName/",
+                "id": "https://plu.me/~/BlogName/#delete",
+                "object": {
+                    "id": "https://plu.me/~/BlogName/",
+                    "type": "Tombstone",
+                },
+                "to": ["https://www.w3.org/ns/activitystreams#Public"],
+                "type": "Delete",
+            });
+
+            assert_json_eq!(to_value(act)?, expected);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn remote_update_and_delete() {
+        let conn = &db();
+        conn.test_transaction::<_, Error, _>(|| {
+            let (_users, blogs) = fill_database(conn);
+            let remote = Blog::insert(
+                conn,
+                NewBlog {
+                    actor_id: "Remote".to_owned(),
+                    title: "Remote blog".to_owned(),
+                    summary: "A blog from elsewhere".to_owned(),
+                    instance_id: Instance::find_by_domain(conn, "1plu.me")?.id,
+                    public_key: blogs[0].public_key.clone(),
+                    icon_id: blogs[0].icon_id,
+                    banner_id: blogs[0].banner_id,
+                    ..NewBlog::default()
+                },
+            )?;
+            assert!(!remote.is_local());
+            assert!(remote.icon_id.is_some() && remote.banner_id.is_some());
+            let group = |blog: &Blog, title: &str| {
+                json!({
+                    "id": blog.ap_url,
+                    "type": "Group",
+                    "preferredUsername": blog.actor_id,
+                    "name": title,
+                    "summary": "<p>New summary</p>",
+                    "source": { "content": "New summary", "mediaType": "text/markdown" },
+                    "inbox": blog.inbox_url,
+                    "outbox": blog.outbox_url,
+                    "publicKey": {
+                        "id": format!("{}#main-key", blog.ap_url),
+                        "owner": blog.ap_url,
+                        "publicKeyPem": blog.public_key,
+                    },
+                })
+            };
+
+            block_on(inbox(
+                conn,
+                json!({
+                    "id": format!("{}#update-1", remote.ap_url),
+                    "type": "Update",
+                    "actor": remote.ap_url,
+                    "object": group(&remote, "Renamed blog"),
+                }),
+            ))?;
+            let updated = Blog::get(conn, remote.id)?;
+            assert_eq!(updated.title, "Renamed blog");
+            assert_eq!(updated.summary, "New summary");
+            assert_eq!(updated.summary_html.get(), "<p>New summary</p>");
+            // The update has no images anymore
+            assert_eq!(updated.icon_id, None);
+            assert_eq!(updated.banner_id, None);
+
+            // A blog can't update another one
+            assert!(block_on(inbox(
+                conn,
+                json!({
+                    "id": format!("{}#update-2", remote.ap_url),
+                    "type": "Update",
+                    "actor": remote.ap_url,
+                    "object": group(&blogs[0], "Stolen blog"),
+                }),
+            ))
+            .is_err());
+            assert_eq!(Blog::get(conn, blogs[0].id)?.title, blogs[0].title);
+
+            block_on(inbox(
+                conn,
+                json!({
+                    "id": format!("{}#delete", remote.ap_url),
+                    "type": "Delete",
+                    "actor": remote.ap_url,
+                    "object": { "id": remote.ap_url, "type": "Tombstone" },
+                }),
+            ))?;
+            assert!(Blog::get(conn, remote.id).is_err());
+
+            Ok(())
+        });
+    }
 }
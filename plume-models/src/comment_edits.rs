@@ -0,0 +1,43 @@
+use crate::{safe_string::SafeString, schema::comment_edits, Connection, Error, Result};
+use chrono::NaiveDateTime;
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+
+/// A previous version of a comment, kept when it is edited
+#[derive(Queryable, Identifiable, Clone)]
+pub struct CommentEdit {
+    pub id: i32,
+    pub comment_id: i32,
+    pub content: SafeString,
+    pub spoiler_text: String,
+    /// When this version was replaced
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = comment_edits)]
+pub struct NewCommentEdit {
+    pub comment_id: i32,
+    pub content: SafeString,
+    pub spoiler_text: String,
+}
+
+impl CommentEdit {
+    insert!(comment_edits, NewCommentEdit);
+
+    /// The previous versions of a comment, the most recent first
+    pub fn list_for_comment(conn: &mut Connection, comment_id: i32) -> Result<Vec<Self>> {
+        comment_edits::table
+            .filter(comment_edits::comment_id.eq(comment_id))
+            .order(comment_edits::id.desc())
+            .load::<Self>(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn count_for_comment(conn: &mut Connection, comment_id: i32) -> Result<i64> {
+        comment_edits::table
+            .filter(comment_edits::comment_id.eq(comment_id))
+            .count()
+            .get_result(conn)
+            .map_err(Error::from)
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    comment_edits::{CommentEdit, NewCommentEdit},
     comment_seers::{CommentSeers, NewCommentSeers},
     instance::Instance,
     medias::Media,
@@ -7,11 +8,12 @@ use crate::{
     posts::Post,
     safe_string::SafeString,
     schema::comments,
+    timeline::Timeline,
     users::User,
     Connection, Error, Result, CONFIG,
 };
 use activitystreams::{
-    activity::{Create, Delete},
+    activity::{Create, Delete, Update},
     base::{AnyBase, Base},
     iri_string::types::IriString,
     link::{self, kind::MentionType},
@@ -20,8 +22,8 @@ use activitystreams::{
     primitives::OneOrMany,
     time::OffsetDateTime,
 };
-use chrono::{self, NaiveDateTime};
-use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl, SaveChangesDsl};
+use chrono::{self, NaiveDateTime, Utc};
+use diesel::{self, Connection as _, ExpressionMethods, QueryDsl, RunQueryDsl, SaveChangesDsl};
 use plume_common::{
     activity_pub::{
         inbox::{AsActor, AsObject, FromId},
@@ -44,6 +46,8 @@ pub struct Comment {
     pub sensitive: bool,
     pub spoiler_text: String,
     pub public_visibility: bool,
+    /// The Markdown the comment was written in, for the comments of local users
+    pub source: String,
 }
 
 #[derive(Insertable, Default)]
@@ -57,6 +61,7 @@ pub struct NewComment {
     pub sensitive: bool,
     pub spoiler_text: String,
     pub public_visibility: bool,
+    pub source: String,
 }
 
 impl Comment {
@@ -80,6 +85,10 @@ impl Comment {
         Post::get(conn, self.post_id)
     }
 
+    pub fn count_for_post(conn: &mut Connection, post_id: i32) -> Result<i64> {
+        comments::table.filter(comments::post_id.eq(post_id)).count().get_result(conn).map_err(Error::from)
+    }
+
     pub fn count_local(conn: &mut Connection) -> Result<i64> {
         use crate::schema::users;
         let local_authors = users::table.filter(users::instance_id.eq(Instance::get_local()?.id)).select(users::id);
@@ -148,6 +157,78 @@ impl Comment {
         Ok(act)
     }
 
+    /// The `Update` sent when the comment is edited
+    pub async fn update_activity(&self, conn: &mut Connection) -> Result<Update> {
+        let author = self.get_author(conn)?;
+
+        let mut note = self.to_activity(conn).await?;
+        note.set_updated(OffsetDateTime::now_utc());
+        let to = note.to().ok_or(Error::MissingApProperty)?.clone();
+
+        let mut act = Update::new(author.ap_url.parse::<IriString>()?, Base::retract(note)?.into_generic()?);
+        act.set_id(
+            format!("{}/update-{}", self.ap_url.clone().ok_or(Error::MissingApProperty)?, Utc::now().timestamp())
+                .parse::<IriString>()?,
+        );
+        act.set_many_tos(to);
+        act.set_many_ccs(vec![author.followers_endpoint.parse::<IriString>()?]);
+        Ok(act)
+    }
+
+    /// Replaces the content of the comment, and its Markdown source for the comments of local users, keeping the
+    /// previous version in its history
+    pub fn edit(
+        &self,
+        conn: &mut Connection,
+        content: SafeString,
+        source: String,
+        spoiler_text: String,
+    ) -> Result<Comment> {
+        conn.transaction(|conn| {
+            CommentEdit::insert(
+                conn,
+                NewCommentEdit {
+                    comment_id: self.id,
+                    content: self.content.clone(),
+                    spoiler_text: self.spoiler_text.clone(),
+                },
+            )?;
+            diesel::update(self)
+                .set((
+                    comments::content.eq(content),
+                    comments::source.eq(source),
+                    comments::sensitive.eq(!spoiler_text.is_empty()),
+                    comments::spoiler_text.eq(spoiler_text),
+                ))
+                .execute(conn)?;
+            Comment::get(conn, self.id)
+        })
+    }
+
+    /// Adds the new mentions of an edited comment, notifying the users they mention, and removes the ones that are
+    /// gone
+    pub fn update_mentions(&self, conn: &mut Connection, mentions: Vec<link::Mention>) -> Result<()> {
+        let mentions = mentions
+            .into_iter()
+            .map(|m| (m.href().and_then(|ap_url| User::find_by_ap_url(conn, ap_url.as_ref()).ok()).map(|u| u.id), m))
+            .filter_map(|(id, m)| id.map(|id| (m, id)))
+            .collect::<Vec<_>>();
+
+        let old_mentions = Mention::list_for_comment(conn, self.id)?;
+        let old_user_mentioned = old_mentions.iter().map(|m| m.mentioned_id).collect::<HashSet<_>>();
+        for (m, id) in &mentions {
+            if !old_user_mentioned.contains(id) {
+                Mention::from_activity(conn, m, self.id, false, true)?;
+            }
+        }
+
+        let new_mentions = mentions.into_iter().map(|(_m, id)| id).collect::<HashSet<_>>();
+        for m in old_mentions.iter().filter(|m| !new_mentions.contains(&m.mentioned_id)) {
+            m.delete(conn)?;
+        }
+        Ok(())
+    }
+
     pub fn notify(&self, conn: &mut Connection) -> Result<()> {
         for author in self.get_post(conn)?.get_authors(conn)? {
             if Mention::list_for_comment(conn, self.id)?
@@ -246,6 +327,7 @@ impl FromId<Connection> for Comment {
                     author_id,
                     sensitive,
                     public_visibility,
+                    source: String::new(),
                 },
             )?;
 
@@ -303,6 +385,7 @@ impl FromId<Connection> for Comment {
         }
 
         comm.notify(conn)?;
+        Timeline::reevaluate_counts(conn, &comm.get_post(conn)?).await?;
         Ok(comm)
     }
 
@@ -346,16 +429,84 @@ impl AsObject<User, Delete, &mut Connection> for Comment {
             .set(comments::in_response_to_id.eq(self.in_response_to_id))
             .execute(conn)?;
         diesel::delete(&self).execute(conn)?;
+        Timeline::reevaluate_counts(conn, &self.get_post(conn)?).await?;
         Ok(())
     }
 }
 
+/// The new version of a remote comment, from an `Update` its author sent
+pub struct CommentUpdate {
+    pub comment: Comment,
+    pub content: Option<String>,
+    pub spoiler_text: Option<String>,
+    pub mentions: Vec<link::Mention>,
+}
+
+impl FromId<Connection> for CommentUpdate {
+    type Error = Error;
+    type Object = Note;
+
+    fn from_db(_: &mut Connection, _: &str) -> Result<Self> {
+        // Always fail because we always want to deserialize the AP object
+        Err(Error::NotFound)
+    }
+
+    fn deref(_: &str, _: Option<reqwest::Proxy>) -> std::result::Result<Note, (Option<serde_json::Value>, Error)> {
+        // Notes are always sent with their updates: anything else is the update of another kind of object, that
+        // shouldn't be fetched for nothing
+        Err((None, Error::NotFound))
+    }
+
+    async fn from_activity(conn: &mut Connection, note: Note) -> Result<Self> {
+        // Notes that are not comments on articles we know are not stored here: their update is left to the next
+        // handlers
+        let comment = Comment::find_by_ap_url(conn, note.id_unchecked().ok_or(Error::MissingApProperty)?.as_str())
+            .map_err(|_| Error::NotFound)?;
+        let mentions = note
+            .tag()
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(|tag| tag.clone().extend::<link::Mention, MentionType>().ok().flatten())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(CommentUpdate {
+            comment,
+            content: note.content().and_then(|content| content.to_as_string()),
+            spoiler_text: note.summary().and_then(|summary| summary.to_as_string()),
+            mentions,
+        })
+    }
+
+    fn get_sender() -> &'static dyn Signer {
+        Instance::get_local_instance_user().expect("Failed to local instance user")
+    }
+}
+
+impl AsObject<User, Update, &mut Connection> for CommentUpdate {
+    type Error = Error;
+    type Output = ();
+
+    async fn activity(self, conn: &mut Connection, actor: User, _id: &str) -> Result<()> {
+        let comment = self.comment;
+        if comment.author_id != actor.id {
+            return Err(Error::Unauthorized);
+        }
+
+        let content = self.content.map(|content| SafeString::new(&content)).unwrap_or_else(|| comment.content.clone());
+        let comment = comment.edit(conn, content, String::new(), self.spoiler_text.unwrap_or_default())?;
+        comment.update_mentions(conn, self.mentions)
+    }
+}
+
 pub struct CommentTree {
     pub comment: Comment,
     pub comment_author: Option<User>,
     pub author_image_url: Option<String>,
     pub post: Option<Post>,
     pub responses: Vec<CommentTree>,
+    /// The comment has been edited since it was posted
+    pub edited: bool,
 }
 
 impl CommentTree {
@@ -389,6 +540,7 @@ impl CommentTree {
         let comment_author = comment.get_author(conn).ok();
         let author_image_url = comment_author.as_ref().and_then(|u| u.avatar_url(conn));
         let post = comment.get_post(conn).ok();
+        let edited = CommentEdit::count_for_comment(conn, comment.id).map(|n| n > 0).unwrap_or(false);
 
         Ok(CommentTree {
             comment,
@@ -396,6 +548,7 @@ impl CommentTree {
             author_image_url,
             post,
             responses,
+            edited,
         })
     }
 }
@@ -410,6 +563,7 @@ mod tests {
     use crate::tests::{db, format_datetime};
     use assert_json_diff::assert_json_eq;
     use diesel::Connection;
+    use futures::executor::block_on;
     use serde_json::{json, to_value};
 
     fn prepare_activity(conn: &DbConn) -> (Comment, Vec<Post>, Vec<User>, Vec<Blog>) {
@@ -426,6 +580,7 @@ mod tests {
                 sensitive: true,
                 spoiler_text: "My CW".into(),
                 public_visibility: true,
+                source: "My comment, mentioning to @user".into(),
             },
         )
         .unwrap();
@@ -479,6 +634,7 @@ mod tests {
                     sensitive: false,
                     spoiler_text: "".into(),
                     public_visibility: true,
+                    source: String::new(),
                 },
             )
             .unwrap();
@@ -580,4 +736,60 @@ mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn edit() {
+        let conn = &db();
+        conn.test_transaction::<_, Error, _>(|| {
+            let (comment, _posts, users, _blogs) = prepare_activity(conn);
+            let comment =
+                comment.edit(conn, SafeString::new("<p>Edited comment</p>"), "Edited comment".into(), String::new())?;
+            assert_eq!(comment.content.get(), "<p>Edited comment</p>");
+            assert_eq!(comment.source, "Edited comment");
+            assert!(!comment.sensitive);
+            assert!(CommentTree::from_comment(conn, comment.clone(), None)?.edited);
+
+            let ap_url = comment.ap_url.clone().unwrap();
+            let update = |actor: &User, content: &str| {
+                json!({
+                    "id": format!("{}/update-{}", ap_url, content.len()),
+                    "type": "Update",
+                    "actor": actor.ap_url,
+                    "object": {
+                        "id": ap_url,
+                        "type": "Note",
+                        "attributedTo": actor.ap_url,
+                        "content": content,
+                        "summary": "New CW",
+                        "tag": [{ "href": users[2].ap_url, "name": "@other", "type": "Mention" }],
+                        "to": [PUBLIC_VISIBILITY],
+                    },
+                })
+            };
+
+            // Updates of notes that are not comments are left to other handlers
+            let mut unknown = update(&users[0], "Not a comment");
+            unknown["object"]["id"] = json!("https://plu.me/notes/unknown");
+            assert!(block_on(inbox(conn, unknown)).is_err());
+
+            // Only the author can edit a comment
+            assert!(block_on(inbox(conn, update(&users[1], "Stolen comment"))).is_err());
+            assert_eq!(Comment::get(conn, comment.id)?.content.get(), "<p>Edited comment</p>");
+
+            block_on(inbox(conn, update(&users[0], "Edited again, for @other")))?;
+            let comment = Comment::get(conn, comment.id)?;
+            assert_eq!(comment.content.get(), "Edited again, for @other");
+            assert_eq!(comment.spoiler_text, "New CW");
+            let mentions = Mention::list_for_comment(conn, comment.id)?;
+            assert_eq!(mentions.len(), 1);
+            assert_eq!(mentions[0].mentioned_id, users[2].id);
+
+            let history = CommentEdit::list_for_comment(conn, comment.id)?;
+            assert_eq!(history.len(), 2);
+            assert_eq!(history[0].content.get(), "<p>Edited comment</p>");
+            assert_eq!(history[1].spoiler_text, "My CW");
+
+            Ok(())
+        });
+    }
 }
@@ -31,6 +31,17 @@ pub struct Config {
     pub ldap: Option<LdapConfig>,
     pub proxy: Option<ProxyConfig>,
     pub s3: Option<S3Config>,
+    /// How many of the latest posts are checked again when a timeline, or a list it uses, changes
+    pub timeline_reevaluation_window: i64,
+    /// How often external feeds imported in blogs are checked for new entries, in minutes
+    pub external_feeds_poll_interval: i64,
+    /// Whether incoming activities must sign their request target, host, date and digest with a key of their actor,
+    /// an LD signature only being enough for activities forwarded by another actor
+    pub strict_signatures: bool,
+    /// Whether ActivityPub documents are only served to signed requests from instances that are not blocked
+    pub authorized_fetch: bool,
+    /// Whether public notes, pages and polls of the accounts local users follow are shown as articles
+    pub ingest_notes: bool,
 }
 
 impl Config {
@@ -422,5 +433,18 @@ lazy_static! {
         ldap: get_ldap_config(),
         proxy: get_proxy_config(),
         s3: get_s3_config(),
+        timeline_reevaluation_window: var("TIMELINE_REEVALUATION_WINDOW")
+            .map_or(1000, |s| s.parse::<i64>().expect("Couldn't parse TIMELINE_REEVALUATION_WINDOW into i64")),
+        external_feeds_poll_interval: var("EXTERNAL_FEEDS_POLL_INTERVAL")
+            .map_or(60, |s| s.parse::<i64>().expect("Couldn't parse EXTERNAL_FEEDS_POLL_INTERVAL into i64")),
+        strict_signatures: string_to_bool(
+            &var("STRICT_HTTP_SIGNATURES").unwrap_or_else(|_| "false".to_owned()),
+            "STRICT_HTTP_SIGNATURES"
+        ),
+        authorized_fetch: string_to_bool(
+            &var("AUTHORIZED_FETCH").unwrap_or_else(|_| "false".to_owned()),
+            "AUTHORIZED_FETCH"
+        ),
+        ingest_notes: string_to_bool(&var("INGEST_NOTES").unwrap_or_else(|_| "false".to_owned()), "INGEST_NOTES"),
     };
 }
@@ -0,0 +1,324 @@
+use crate::{
+    notifications::{notification_kind, Notification},
+    schema::{email_preferences, notifications, users},
+    users::User,
+    Connection, Error, Result,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{self, BoolExpressionMethods, ExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, RunQueryDsl};
+use std::convert::TryFrom;
+
+/// The kinds of notifications that can be received by email
+pub const EMAIL_KINDS: [&str; 5] = [
+    notification_kind::COMMENT,
+    notification_kind::FOLLOW,
+    notification_kind::LIKE,
+    notification_kind::MENTION,
+    notification_kind::RESHARE,
+];
+
+/// How often a kind of notification is mailed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmailFrequency {
+    Off,
+    Immediate,
+    Daily,
+    Weekly,
+}
+
+impl TryFrom<i32> for EmailFrequency {
+    type Error = ();
+
+    fn try_from(i: i32) -> std::result::Result<Self, ()> {
+        match i {
+            0 => Ok(EmailFrequency::Off),
+            1 => Ok(EmailFrequency::Immediate),
+            2 => Ok(EmailFrequency::Daily),
+            3 => Ok(EmailFrequency::Weekly),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<EmailFrequency> for i32 {
+    fn from(frequency: EmailFrequency) -> Self {
+        match frequency {
+            EmailFrequency::Off => 0,
+            EmailFrequency::Immediate => 1,
+            EmailFrequency::Daily => 2,
+            EmailFrequency::Weekly => 3,
+        }
+    }
+}
+
+impl EmailFrequency {
+    pub fn all() -> [Self; 4] {
+        [EmailFrequency::Off, EmailFrequency::Immediate, EmailFrequency::Daily, EmailFrequency::Weekly]
+    }
+
+    /// How long to wait between two mails
+    fn interval(self) -> Option<Duration> {
+        match self {
+            EmailFrequency::Off => None,
+            EmailFrequency::Immediate => Some(Duration::zero()),
+            EmailFrequency::Daily => Some(Duration::days(1)),
+            EmailFrequency::Weekly => Some(Duration::weeks(1)),
+        }
+    }
+}
+
+/// How a local user wants to receive one kind of notification by email
+///
+/// Kinds without a preference are not mailed.
+#[derive(Clone, Debug, Queryable, Identifiable)]
+pub struct EmailPreference {
+    pub id: i32,
+    pub user_id: i32,
+    pub kind: String,
+    pub frequency: i32,
+    /// The language the mails are written in
+    pub lang: String,
+    /// The notifications created before this date have already been mailed
+    pub last_sent_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = email_preferences)]
+struct NewEmailPreference<'a> {
+    user_id: i32,
+    kind: &'a str,
+    frequency: i32,
+    lang: &'a str,
+    last_sent_date: NaiveDateTime,
+}
+
+/// The notifications to mail to a user at once
+pub struct EmailDigest {
+    pub user: User,
+    pub lang: String,
+    pub notifications: Vec<Notification>,
+    preferences: Vec<i32>,
+    until: NaiveDateTime,
+}
+
+impl EmailDigest {
+    /// Remembers that these notifications have been mailed
+    pub fn mark_sent(&self, conn: &mut Connection) -> Result<()> {
+        diesel::update(email_preferences::table.filter(email_preferences::id.eq_any(&self.preferences)))
+            .set(email_preferences::last_sent_date.eq(self.until))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
+
+impl EmailPreference {
+    get!(email_preferences);
+    list_by!(email_preferences, list_for_user, user_id as i32);
+
+    pub fn frequency(&self) -> EmailFrequency {
+        EmailFrequency::try_from(self.frequency).unwrap_or(EmailFrequency::Off)
+    }
+
+    /// The frequency of each kind of notification for a user
+    pub fn frequencies_for_user(conn: &mut Connection, user_id: i32) -> Result<Vec<(&'static str, EmailFrequency)>> {
+        let preferences = Self::list_for_user(conn, user_id)?;
+        Ok(EMAIL_KINDS
+            .iter()
+            .map(|kind| {
+                let frequency =
+                    preferences.iter().find(|p| p.kind == *kind).map_or(EmailFrequency::Off, |p| p.frequency());
+                (*kind, frequency)
+            })
+            .collect())
+    }
+
+    /// Changes how a kind of notification is mailed. Enabling it doesn't mail the notifications the user already has.
+    pub fn set(conn: &mut Connection, user_id: i32, kind: &str, frequency: EmailFrequency, lang: &str) -> Result<()> {
+        if !EMAIL_KINDS.contains(&kind) {
+            return Err(Error::InvalidValue);
+        }
+
+        let now = Utc::now().naive_utc();
+        let existing = email_preferences::table
+            .filter(email_preferences::user_id.eq(user_id))
+            .filter(email_preferences::kind.eq(kind))
+            .first::<Self>(conn)
+            .optional()?;
+        match existing {
+            Some(preference) => {
+                let last_sent_date = if preference.frequency() == EmailFrequency::Off {
+                    now
+                } else {
+                    preference.last_sent_date
+                };
+                diesel::update(&preference)
+                    .set((
+                        email_preferences::frequency.eq(i32::from(frequency)),
+                        email_preferences::lang.eq(lang),
+                        email_preferences::last_sent_date.eq(last_sent_date),
+                    ))
+                    .execute(conn)?;
+            }
+            None => {
+                diesel::insert_into(email_preferences::table)
+                    .values(NewEmailPreference {
+                        user_id,
+                        kind,
+                        frequency: frequency.into(),
+                        lang,
+                        last_sent_date: now,
+                    })
+                    .execute(conn)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Gathers the notifications that should be mailed at `now`, by user.
+    ///
+    /// They are not considered as sent until [`EmailDigest::mark_sent`] is called. The preferences that are due but
+    /// have nothing to mail are marked as sent right away, so that the next notifications wait for the next digest.
+    pub fn due(conn: &mut Connection, now: NaiveDateTime) -> Result<Vec<EmailDigest>> {
+        let due_for = |frequency: EmailFrequency| {
+            let since = now - frequency.interval().unwrap_or_else(Duration::zero);
+            email_preferences::frequency.eq(i32::from(frequency)).and(email_preferences::last_sent_date.le(since))
+        };
+        let preferences = email_preferences::table
+            .filter(
+                due_for(EmailFrequency::Immediate)
+                    .or(due_for(EmailFrequency::Daily))
+                    .or(due_for(EmailFrequency::Weekly)),
+            )
+            .order_by(email_preferences::user_id)
+            .load::<Self>(conn)?;
+        if preferences.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let ids = preferences.iter().map(|p| p.id).collect::<Vec<_>>();
+        let mut notifications = notifications::table
+            .inner_join(
+                email_preferences::table.on(email_preferences::user_id
+                    .eq(notifications::user_id)
+                    .and(email_preferences::kind.eq(notifications::kind))
+                    .and(notifications::creation_date.gt(email_preferences::last_sent_date))),
+            )
+            .filter(email_preferences::id.eq_any(&ids))
+            .filter(notifications::creation_date.le(now))
+            .select((email_preferences::id, notifications::all_columns))
+            .load::<(i32, Notification)>(conn)?;
+        let user_ids = preferences.iter().map(|p| p.user_id).collect::<Vec<_>>();
+        let mut users =
+            users::table.filter(users::id.eq_any(&user_ids)).filter(users::email.is_not_null()).load::<User>(conn)?;
+
+        let mut digests: Vec<EmailDigest> = vec![];
+        let mut idle = vec![];
+        for preference in preferences {
+            let mut mailed =
+                notifications.iter().filter(|(id, _)| *id == preference.id).map(|(_, n)| n.clone()).collect::<Vec<_>>();
+            notifications.retain(|(id, _)| *id != preference.id);
+            if mailed.is_empty() {
+                idle.push(preference.id);
+                continue;
+            }
+
+            match digests.last_mut() {
+                Some(digest) if digest.user.id == preference.user_id => {
+                    digest.notifications.append(&mut mailed);
+                    digest.preferences.push(preference.id);
+                }
+                _ => match users.iter().position(|u| u.id == preference.user_id) {
+                    Some(i) => digests.push(EmailDigest {
+                        user: users.swap_remove(i),
+                        lang: preference.lang,
+                        notifications: mailed,
+                        preferences: vec![preference.id],
+                        until: now,
+                    }),
+                    // There is nowhere to mail them
+                    None => idle.push(preference.id),
+                },
+            }
+        }
+
+        diesel::update(email_preferences::table.filter(email_preferences::id.eq_any(&idle)))
+            .set(email_preferences::last_sent_date.eq(now))
+            .execute(conn)?;
+        for digest in digests.iter_mut() {
+            digest.notifications.sort_by(|a, b| b.creation_date.cmp(&a.creation_date));
+        }
+        Ok(digests)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notifications::NewNotification, tests::db, users::tests as userTests, Connection as Conn};
+    use diesel::Connection;
+
+    fn notify(conn: &mut Conn, user: &User, kind: &str) {
+        Notification::insert(
+            conn,
+            NewNotification {
+                user_id: user.id,
+                kind: kind.to_owned(),
+                object_id: 0,
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_digests() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let users = userTests::fill_database(conn);
+            let user = &users[0];
+            EmailPreference::set(conn, user.id, notification_kind::LIKE, EmailFrequency::Immediate, "en").unwrap();
+            EmailPreference::set(conn, user.id, notification_kind::FOLLOW, EmailFrequency::Daily, "en").unwrap();
+            EmailPreference::set(conn, user.id, notification_kind::MENTION, EmailFrequency::Daily, "en").unwrap();
+            assert!(
+                EmailPreference::set(conn, user.id, notification_kind::TIMELINE, EmailFrequency::Daily, "en").is_err()
+            );
+            let now = Utc::now().naive_utc() + Duration::hours(1);
+            diesel::update(email_preferences::table)
+                .set(email_preferences::last_sent_date.eq(now - Duration::hours(2)))
+                .execute(conn)
+                .unwrap();
+
+            notify(conn, user, notification_kind::LIKE);
+            notify(conn, user, notification_kind::FOLLOW);
+            notify(conn, user, notification_kind::COMMENT);
+            let digests = EmailPreference::due(conn, now).unwrap();
+            assert_eq!(digests.len(), 1);
+            assert_eq!(digests[0].user.id, user.id);
+            // Follows are only mailed once a day, and comments not at all
+            assert_eq!(digests[0].notifications.len(), 1);
+            assert_eq!(digests[0].notifications[0].kind, notification_kind::LIKE);
+
+            digests[0].mark_sent(conn).unwrap();
+            assert!(EmailPreference::due(conn, now).unwrap().is_empty());
+
+            // The next day, the follow is mailed
+            let digests = EmailPreference::due(conn, now + Duration::days(1)).unwrap();
+            assert_eq!(digests[0].notifications.len(), 1);
+            assert_eq!(digests[0].notifications[0].kind, notification_kind::FOLLOW);
+            // Mentions are due too, but there are none: the next ones will wait for the next day
+            let mentions = EmailPreference::list_for_user(conn, user.id)
+                .unwrap()
+                .into_iter()
+                .find(|p| p.kind == notification_kind::MENTION)
+                .unwrap();
+            assert!(mentions.last_sent_date > now);
+
+            assert_eq!(
+                EmailPreference::frequencies_for_user(conn, user.id).unwrap()[1],
+                (notification_kind::FOLLOW, EmailFrequency::Daily)
+            );
+
+            Ok(())
+        });
+    }
+}
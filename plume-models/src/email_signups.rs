@@ -36,7 +36,7 @@ impl Deref for Token {
 }
 
 impl Token {
-    fn generate() -> Self {
+    pub(crate) fn generate() -> Self {
         Self(random_hex())
     }
 }
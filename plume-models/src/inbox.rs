@@ -1,14 +1,32 @@
 use activitystreams::activity::{Announce, Create, Delete, Follow, Like, Undo, Update};
 
 use crate::{
-    comments::Comment,
+    blogs::{Blog, BlogUpdate},
+    comments::{Comment, CommentUpdate},
     follows, likes,
     posts::{Post, PostUpdate},
+    relays::Relay,
+    remote_notes::RemoteNote,
     reshares::Reshare,
-    users::User,
+    users::{User, UserUpdate},
     Connection, Error, CONFIG,
 };
-use plume_common::activity_pub::inbox::Inbox;
+use once_cell::sync::Lazy;
+use plume_common::activity_pub::inbox::{FromId, Inbox};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long an actor that couldn't be fetched is considered as unreachable
+const FAILED_ACTOR_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// How many unreachable actors are remembered at most
+const FAILED_ACTORS_MAX: usize = 10_000;
+
+/// The actors that couldn't be fetched recently, and when they will be tried again
+static FAILED_ACTORS: Lazy<Mutex<HashMap<String, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
 macro_rules! impl_into_inbox_result {
     ( $( $t:ty => $variant:ident ),+ ) => {
@@ -47,6 +65,21 @@ impl_into_inbox_result! {
 
 #[async_recursion::async_recursion]
 pub async fn inbox(conn: &mut Connection, act: serde_json::Value) -> Result<InboxResult, Error> {
+    // Relays are not users: their activities are handled separately
+    let actor_id = act["actor"].as_str().or_else(|| act["actor"]["id"].as_str()).unwrap_or_default();
+    if let Ok(relay) = Relay::find_by_actor_id(conn, actor_id) {
+        return relay.receive(conn, act).await;
+    }
+    // Blogs only tell us about themselves
+    if Blog::find_by_ap_url(conn, actor_id).is_ok() {
+        return Inbox::handle(conn, act)
+            .with::<Blog, Update, BlogUpdate>(CONFIG.proxy())
+            .await
+            .with::<Blog, Delete, Blog>(CONFIG.proxy())
+            .await
+            .done();
+    }
+
     Inbox::handle(conn, act)
         .with::<User, Announce, Post>(CONFIG.proxy())
         .await
@@ -54,6 +87,8 @@ pub async fn inbox(conn: &mut Connection, act: serde_json::Value) -> Result<Inbo
         .await
         .with::<User, Create, Post>(CONFIG.proxy())
         .await
+        .with::<User, Create, RemoteNote>(CONFIG.proxy())
+        .await
         .with::<User, Delete, Comment>(CONFIG.proxy())
         .await
         .with::<User, Delete, Post>(CONFIG.proxy())
@@ -70,11 +105,37 @@ pub async fn inbox(conn: &mut Connection, act: serde_json::Value) -> Result<Inbo
         .await
         .with::<User, Undo, likes::Like>(CONFIG.proxy())
         .await
+        .with::<User, Update, UserUpdate>(CONFIG.proxy())
+        .await
+        .with::<User, Update, CommentUpdate>(CONFIG.proxy())
+        .await
         .with::<User, Update, PostUpdate>(CONFIG.proxy())
         .await
+        .with::<User, Update, RemoteNote>(CONFIG.proxy())
+        .await
         .done()
 }
 
+/// Finds the author of an incoming activity, fetching it if it is not known yet.
+///
+/// When it can't be fetched, it isn't tried again for a while: the activities claiming to come from it are refused
+/// without sending any request.
+pub async fn fetch_actor(conn: &mut Connection, actor_id: &str) -> Result<User, Error> {
+    let now = Instant::now();
+    if FAILED_ACTORS.lock().unwrap().get(actor_id).map_or(false, |retry| *retry > now) {
+        return Err(Error::NotFound);
+    }
+
+    User::from_id(conn, actor_id, None, CONFIG.proxy()).await.map_err(|(_, e)| {
+        let mut failed = FAILED_ACTORS.lock().unwrap();
+        failed.retain(|_, retry| *retry > now);
+        if failed.len() < FAILED_ACTORS_MAX {
+            failed.insert(actor_id.to_owned(), now + FAILED_ACTOR_TTL);
+        }
+        e
+    })
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use super::InboxResult;
@@ -418,6 +479,7 @@ pub(crate) mod tests {
                     sensitive: false,
                     spoiler_text: "spoiler".to_owned(),
                     public_visibility: true,
+                    source: "My comment".to_owned(),
                 },
             )
             .unwrap();
@@ -679,4 +741,73 @@ pub(crate) mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn unreachable_actor() {
+        use crate::instance::{Instance, NewInstance};
+        use std::{
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+        };
+
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            fill_database(conn);
+            Instance::cache_local(conn);
+            Instance::cache_local_instance_user(conn);
+
+            // A stand-in for a remote instance, which doesn't know the actor
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let domain = listener.local_addr().unwrap().to_string();
+            let requests = Arc::new(AtomicUsize::new(0));
+            let counter = requests.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let mut stream = stream.unwrap();
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let mut reader = BufReader::new(&stream);
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap() > 2 {
+                        line.clear();
+                    }
+                    stream
+                        .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                        .unwrap();
+                }
+            });
+
+            let actor_id = format!("http://{}/@/ghost/", domain);
+            assert!(futures::executor::block_on(super::fetch_actor(conn, &actor_id)).is_err());
+            assert_eq!(requests.load(Ordering::SeqCst), 1);
+            // It is not fetched again right away
+            assert!(futures::executor::block_on(super::fetch_actor(conn, &actor_id)).is_err());
+            assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+            assert!(!Instance::is_blocked(conn, &actor_id).unwrap());
+            let instance = Instance::insert(
+                conn,
+                NewInstance {
+                    public_domain: domain,
+                    name: "Unreachable".to_owned(),
+                    local: false,
+                    open_registrations: true,
+                    short_description: SafeString::new(""),
+                    long_description: SafeString::new(""),
+                    default_license: "CC-BY-SA".to_owned(),
+                    long_description_html: String::new(),
+                    short_description_html: String::new(),
+                },
+            )
+            .unwrap();
+            instance.toggle_block(conn).unwrap();
+            assert!(Instance::is_blocked(conn, &actor_id).unwrap());
+            assert!(!Instance::is_blocked(conn, "https://plu.me/@/admin/").unwrap());
+
+            Ok(())
+        });
+    }
 }
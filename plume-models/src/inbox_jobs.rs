@@ -0,0 +1,269 @@
+use crate::{inbox::inbox, live, schema::inbox_jobs, Connection, Error, Result};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+use once_cell::sync::Lazy;
+use plume_common::activity_pub::inbox::InboxError;
+use rocket::tokio::runtime::{Builder, Runtime};
+use tracing::{debug, info, warn};
+
+/// How many times an activity is processed before giving up on it
+pub const MAX_ATTEMPTS: i32 = 6;
+
+/// How long a worker can keep a job before another one is allowed to take it, in case the first one crashed
+const LEASE_MINUTES: i64 = 10;
+
+/// The runtime the handlers of the activities run on, so that they can send requests and wait
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("inbox-runtime")
+        .enable_all()
+        .build()
+        .expect("Error while initializing tokio runtime for the inbox")
+});
+
+/// An incoming activity, already authenticated, waiting to be processed by the workers
+#[derive(Clone, Debug, Identifiable, Queryable)]
+pub struct InboxJob {
+    pub id: i32,
+    /// The activity, as received
+    pub activity: String,
+    pub actor_id: String,
+    pub attempts: i32,
+    pub next_attempt_date: NaiveDateTime,
+    /// Why the last attempt failed
+    pub last_error: Option<String>,
+    /// The job has been tried `MAX_ATTEMPTS` times, and is kept for the administrators to look at
+    pub failed: bool,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = inbox_jobs)]
+struct NewInboxJob<'a> {
+    activity: &'a str,
+    actor_id: &'a str,
+}
+
+impl InboxJob {
+    get!(inbox_jobs);
+
+    /// Stores an activity until a worker processes it
+    pub fn enqueue(conn: &mut Connection, activity: &serde_json::Value, actor_id: &str) -> Result<()> {
+        diesel::insert_into(inbox_jobs::table)
+            .values(NewInboxJob {
+                activity: &activity.to_string(),
+                actor_id,
+            })
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Reserves up to `limit` jobs that should be processed now.
+    ///
+    /// Their next attempt is postponed, so that other workers don't take them too, and so that they are tried again if
+    /// this worker stops before finishing them.
+    pub fn take_due(conn: &mut Connection, now: NaiveDateTime, limit: i64) -> Result<Vec<Self>> {
+        let due = inbox_jobs::table
+            .filter(inbox_jobs::failed.eq(false))
+            .filter(inbox_jobs::next_attempt_date.le(now))
+            .order(inbox_jobs::next_attempt_date.asc())
+            .limit(limit)
+            .load::<Self>(conn)?;
+
+        let mut taken = vec![];
+        for job in due {
+            let updated = diesel::update(
+                inbox_jobs::table
+                    .filter(inbox_jobs::id.eq(job.id))
+                    .filter(inbox_jobs::next_attempt_date.eq(job.next_attempt_date)),
+            )
+            .set(inbox_jobs::next_attempt_date.eq(now + Duration::minutes(LEASE_MINUTES)))
+            .execute(conn)?;
+            // Another worker was faster
+            if updated == 1 {
+                taken.push(job);
+            }
+        }
+        Ok(taken)
+    }
+
+    /// Runs the activity through the inbox.
+    ///
+    /// Its changes are saved in a single transaction. The job is deleted if it succeeds, or if it is refused: no handler
+    /// accepts this kind of activity, or the activity is invalid. When it fails for a reason that may go away, like a
+    /// network or database error, it is tried again later, waiting twice as long after each attempt, until it is
+    /// marked as failed.
+    pub fn process(self, conn: &mut Connection) -> Result<()> {
+        let activity = serde_json::from_str(&self.activity)?;
+        // The notifications are only sent if the activity is completely handled
+        match live::transaction(conn, |conn| RUNTIME.block_on(inbox(conn, activity))) {
+            Ok(_) => self.delete(conn),
+            Err(Error::Inbox(ref e)) if matches!(**e, InboxError::NoMatch) => {
+                debug!("No handler for the activity of job {}", self.id);
+                self.delete(conn)
+            }
+            Err(e) if is_transient(&e) => {
+                warn!("Couldn't process the activity of job {} from {}: {:?}", self.id, self.actor_id, e);
+                self.retry_later(conn, &format!("{:?}", e))
+            }
+            Err(e) => {
+                info!("Refused the activity of job {} from {}: {:?}", self.id, self.actor_id, e);
+                self.delete(conn)
+            }
+        }
+    }
+
+    fn retry_later(&self, conn: &mut Connection, error: &str) -> Result<()> {
+        let attempts = self.attempts + 1;
+        let next_attempt_date = Utc::now().naive_utc() + Duration::minutes(1 << attempts.min(MAX_ATTEMPTS));
+        diesel::update(self)
+            .set((
+                inbox_jobs::attempts.eq(attempts),
+                inbox_jobs::next_attempt_date.eq(next_attempt_date),
+                inbox_jobs::last_error.eq(error),
+                inbox_jobs::failed.eq(attempts >= MAX_ATTEMPTS),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    /// Processes the jobs that are due, until there are none left
+    pub fn process_due(conn: &mut Connection) {
+        loop {
+            let jobs = match Self::take_due(conn, Utc::now().naive_utc(), 20) {
+                Ok(jobs) if !jobs.is_empty() => jobs,
+                Ok(_) => return,
+                Err(e) => {
+                    warn!("Couldn't list the incoming activities to process: {:?}", e);
+                    return;
+                }
+            };
+            for job in jobs {
+                let id = job.id;
+                if let Err(e) = job.process(conn) {
+                    warn!("Couldn't update inbox job {}: {:?}", id, e);
+                }
+            }
+        }
+    }
+
+    /// Gives another chance to a failed job
+    pub fn retry(&self, conn: &mut Connection) -> Result<()> {
+        diesel::update(self)
+            .set((
+                inbox_jobs::attempts.eq(0),
+                inbox_jobs::next_attempt_date.eq(Utc::now().naive_utc()),
+                inbox_jobs::failed.eq(false),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    pub fn delete(&self, conn: &mut Connection) -> Result<()> {
+        diesel::delete(self).execute(conn).map(|_| ()).map_err(Error::from)
+    }
+
+    pub fn page_failed(conn: &mut Connection, (min, max): (i32, i32)) -> Result<Vec<Self>> {
+        inbox_jobs::table
+            .filter(inbox_jobs::failed.eq(true))
+            .order(inbox_jobs::creation_date.desc())
+            .offset(min.into())
+            .limit((max - min).into())
+            .load::<Self>(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn count_failed(conn: &mut Connection) -> Result<i64> {
+        inbox_jobs::table.filter(inbox_jobs::failed.eq(true)).count().get_result(conn).map_err(Error::from)
+    }
+
+    /// The number of jobs waiting to be processed
+    pub fn count_pending(conn: &mut Connection) -> Result<i64> {
+        inbox_jobs::table.filter(inbox_jobs::failed.eq(false)).count().get_result(conn).map_err(Error::from)
+    }
+}
+
+/// Whether processing the activity again later may succeed
+fn is_transient(err: &Error) -> bool {
+    match err {
+        Error::Db(diesel::result::Error::NotFound) => false,
+        Error::Db(_) | Error::Io(_) | Error::Request | Error::Search(_) | Error::Webfinger => true,
+        Error::Inbox(e) => match **e {
+            InboxError::DerefError => true,
+            InboxError::InvalidActor(Some(ref e)) | InboxError::InvalidObject(Some(ref e)) => is_transient(e),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inbox::tests as inboxTests, tests::db};
+    use diesel::Connection;
+
+    #[test]
+    fn test_queue() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (posts, users, _) = inboxTests::fill_database(conn);
+            let actor = &users[0].ap_url;
+            // An unsupported activity is dropped
+            InboxJob::enqueue(conn, &json!({ "type": "Dance", "actor": actor }), actor).unwrap();
+            // So is an invalid one
+            let spoofed = json!({
+                "id": "https://plu.me/comment/1/activity",
+                "actor": actor,
+                "object": {
+                    "type": "Note",
+                    "id": "https://plu.me/comment/1",
+                    "attributedTo": users[1].ap_url,
+                    "inReplyTo": posts[0].ap_url,
+                    "content": "Hello.",
+                    "to": [plume_common::activity_pub::PUBLIC_VISIBILITY]
+                },
+                "type": "Create",
+            });
+            InboxJob::enqueue(conn, &spoofed, actor).unwrap();
+            assert_eq!(InboxJob::count_pending(conn).unwrap(), 2);
+
+            let now = Utc::now().naive_utc() + Duration::seconds(1);
+            let jobs = InboxJob::take_due(conn, now, 10).unwrap();
+            assert_eq!(jobs.len(), 2);
+            // Taken jobs are not given to another worker
+            assert!(InboxJob::take_due(conn, now, 10).unwrap().is_empty());
+            for job in jobs {
+                job.process(conn).unwrap();
+            }
+            assert_eq!(InboxJob::count_pending(conn).unwrap(), 0);
+            assert_eq!(InboxJob::count_failed(conn).unwrap(), 0);
+
+            // An activity that failed for a reason that may go away is tried again later
+            InboxJob::enqueue(conn, &json!({ "type": "Dance", "actor": actor }), actor).unwrap();
+            let job = InboxJob::take_due(conn, now, 10).unwrap().remove(0);
+            job.retry_later(conn, "Request").unwrap();
+            let job = inbox_jobs::table.first::<InboxJob>(conn).unwrap();
+            assert_eq!(job.attempts, 1);
+            assert!(job.last_error.is_some());
+            assert!(job.next_attempt_date > now);
+
+            // Give up after too many attempts
+            diesel::update(&job).set(inbox_jobs::attempts.eq(MAX_ATTEMPTS - 1)).execute(conn).unwrap();
+            InboxJob::get(conn, job.id).unwrap().retry_later(conn, "Request").unwrap();
+            assert_eq!(InboxJob::count_failed(conn).unwrap(), 1);
+            assert!(InboxJob::take_due(conn, now + Duration::days(1), 10).unwrap().is_empty());
+
+            let failed = InboxJob::page_failed(conn, (0, 10)).unwrap();
+            failed[0].retry(conn).unwrap();
+            assert_eq!(InboxJob::count_failed(conn).unwrap(), 0);
+            assert_eq!(InboxJob::take_due(conn, now + Duration::seconds(1), 10).unwrap().len(), 1);
+
+            Ok(())
+        });
+    }
+}
@@ -11,6 +11,7 @@ use diesel::{self, result::Error::NotFound, ExpressionMethods, QueryDsl, RunQuer
 use once_cell::sync::OnceCell;
 use plume_common::utils::{iri_percent_encode_seg, md_to_html};
 use std::sync::RwLock;
+use url::Url;
 
 #[derive(Clone, Identifiable, Queryable)]
 pub struct Instance {
@@ -121,14 +122,19 @@ impl Instance {
     }
 
     /// id: AP object id
+    /// Tells whether the object or actor `id` comes from a blocked instance, by only looking at its domain
     pub fn is_blocked(conn: &mut Connection, id: &str) -> Result<bool> {
-        for block in instances::table.filter(instances::blocked.eq(true)).get_results::<Instance>(conn)? {
-            if id.starts_with(&format!("https://{}/", block.public_domain)) {
-                return Ok(true);
-            }
-        }
-
-        Ok(false)
+        let url = Url::parse(id)?;
+        let domain = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_owned(),
+            (None, _) => return Err(Error::Url),
+        };
+        diesel::select(diesel::dsl::exists(
+            instances::table.filter(instances::blocked.eq(true)).filter(instances::public_domain.eq(domain)),
+        ))
+        .get_result(conn)
+        .map_err(Error::from)
     }
 
     pub fn has_admin(&self, conn: &mut Connection) -> Result<bool> {
@@ -15,6 +15,7 @@ use once_cell::sync::Lazy;
 use plume_common::activity_pub::{inbox::InboxError, request, sign};
 use posts::PostEvent;
 use riker::actors::{channel, ActorSystem, ChannelRef, SystemBuilder};
+use timeline::TimelineEvent;
 use users::UserEvent;
 
 #[cfg(not(any(feature = "sqlite", feature = "postgres")))]
@@ -37,6 +38,9 @@ pub(crate) static USER_CHAN: Lazy<ChannelRef<UserEvent>> =
 pub(crate) static POST_CHAN: Lazy<ChannelRef<PostEvent>> =
     Lazy::new(|| channel("post_events", &*ACTOR_SYS).expect("Failed to create post channel"));
 
+pub(crate) static TIMELINE_CHAN: Lazy<ChannelRef<TimelineEvent>> =
+    Lazy::new(|| channel("timeline_events", &*ACTOR_SYS).expect("Failed to create timeline channel"));
+
 /// All the possible errors that can be encoutered in this crate
 #[derive(Debug)]
 pub enum Error {
@@ -364,28 +368,38 @@ mod tests {
 pub mod admin;
 pub mod api_tokens;
 pub mod apps;
+pub mod authorized_fetch;
 pub mod blocklisted_emails;
 pub mod blog_authors;
+pub mod blog_feeds;
 pub mod blogs;
+pub mod comment_edits;
 pub mod comment_seers;
 pub mod comments;
 pub mod db_conn;
+pub mod email_preferences;
 pub mod email_signups;
 pub mod follows;
 pub mod headers;
 pub mod inbox;
+pub mod inbox_jobs;
 pub mod instance;
 pub mod likes;
+pub mod live;
 pub mod lists;
 pub mod medias;
 pub mod mentions;
+pub mod newsletters;
 pub mod migrations;
 pub mod notifications;
 pub mod password_reset_requests;
 pub mod plume_rocket;
 pub mod post_authors;
 pub mod posts;
+pub mod push_subscriptions;
+pub mod relays;
 pub mod remote_fetch_actor;
+pub mod remote_notes;
 pub mod reshares;
 pub mod safe_string;
 #[allow(unused_imports)]
@@ -154,6 +154,7 @@ impl AsObject<User, Undo, &mut Connection> for Like {
             if let Ok(notif) = Notification::find(conn, notification_kind::LIKE, self.id) {
                 diesel::delete(&notif).execute(conn)?;
             }
+            Timeline::reevaluate_counts(conn, &Post::get(conn, self.post_id)?).await?;
             Ok(())
         } else {
             Err(Error::Unauthorized)
@@ -1,10 +1,13 @@
 use crate::{
     blogs::Blog,
-    schema::{blogs, list_elems, lists, users},
+    live,
+    schema::{blogs, list_elems, lists, timeline_definition, users},
+    timeline::{Timeline, TimelineEvent, TimelineQuery},
     users::User,
-    Connection, Error, Result,
+    Connection, Error, Result, TIMELINE_CHAN,
 };
-use diesel::{self, ExpressionMethods, QueryDsl, RunQueryDsl};
+use diesel::{self, Connection as _, ExpressionMethods, QueryDsl, RunQueryDsl};
+use riker::actors::{Publish, Tell};
 use std::convert::{TryFrom, TryInto};
 
 /// Represent what a list is supposed to store. Represented in database as an integer
@@ -112,6 +115,7 @@ macro_rules! func {
                         .collect::<Vec<_>>(),
                 )
                 .execute(conn)?;
+            self.publish_updated();
             Ok(())
         }
     };
@@ -130,15 +134,31 @@ macro_rules! func {
         }
     };
 
-
+    (remove: $fn:ident, $kind:ident, $column:ident) => {
+        pub fn $fn(&self, conn: &mut Connection, vals: &[func!(@in_type $kind)]) -> Result<()> {
+            if self.kind() != ListType::$kind {
+                return Err(Error::InvalidValue);
+            }
+            diesel::delete(
+                list_elems::table
+                    .filter(list_elems::list_id.eq(self.id))
+                    .filter(list_elems::$column.eq_any(vals.to_vec())),
+            )
+            .execute(conn)?;
+            self.publish_updated();
+            Ok(())
+        }
+    };
 
     (set: $fn:ident, $kind:ident, $add:ident) => {
         pub fn $fn(&self, conn: &mut Connection, val: &[func!(@in_type $kind)]) -> Result<()> {
             if self.kind() != ListType::$kind {
                 return Err(Error::InvalidValue);
             }
-            self.clear(conn)?;
-            self.$add(conn, val)
+            live::transaction(conn, |conn| {
+                self.clear(conn)?;
+                self.$add(conn, val)
+            })
         }
     }
 }
@@ -243,6 +263,18 @@ impl List {
     // Insert new prefixes in a list
     func! {add: add_prefixes, Prefix}
 
+    // Remove users from a list
+    func! {remove: remove_users, User, user_id}
+
+    // Remove blogs from a list
+    func! {remove: remove_blogs, Blog, blog_id}
+
+    // Remove words from a list
+    func! {remove: remove_words, Word, word}
+
+    // Remove prefixes from a list
+    func! {remove: remove_prefixes, Prefix, word}
+
     // Get all users in the list
     func! {list: list_users, User, users}
 
@@ -274,11 +306,26 @@ impl List {
             .map(|r| r.into_iter().flatten().collect::<Vec<String>>())
     }
 
+    /// Renames this list, and updates the queries of the timelines using it
+    pub fn rename(&mut self, conn: &mut Connection, name: &str) -> Result<()> {
+        conn.transaction(|conn| {
+            let timelines = Timeline::list_using_list(conn, self)?;
+            diesel::update(lists::table.filter(lists::id.eq(self.id))).set(lists::name.eq(name)).execute(conn)?;
+            for timeline in timelines {
+                // The meaning of the query doesn't change, no need to go through Timeline::update
+                let query = TimelineQuery::rename_list(&timeline.query, &self.name, name)?;
+                diesel::update(&timeline).set(timeline_definition::query.eq(query)).execute(conn)?;
+            }
+            Ok::<_, Error>(())
+        })?;
+        self.name = name.to_owned();
+        Ok(())
+    }
+
     pub fn clear(&self, conn: &mut Connection) -> Result<()> {
-        diesel::delete(list_elems::table.filter(list_elems::list_id.eq(self.id)))
-            .execute(conn)
-            .map(|_| ())
-            .map_err(Error::from)
+        diesel::delete(list_elems::table.filter(list_elems::list_id.eq(self.id))).execute(conn)?;
+        self.publish_updated();
+        Ok(())
     }
 
     pub fn delete(&self, conn: &mut Connection) -> Result<()> {
@@ -295,6 +342,20 @@ impl List {
         }
     }
 
+    /// Asks for the timelines using this list to be evaluated again, once the change is committed
+    fn publish_updated(&self) {
+        let id = self.id;
+        live::after_commit(move || {
+            TIMELINE_CHAN.tell(
+                Publish {
+                    msg: TimelineEvent::ListUpdated(id),
+                    topic: "list.updated".into(),
+                },
+                None,
+            )
+        });
+    }
+
     func! {set: set_users, User, add_users}
     func! {set: set_blogs, Blog, add_blogs}
     func! {set: set_words, Word, add_words}
@@ -429,6 +490,11 @@ mod tests {
             assert_eq!(1, l.list_users(conn).unwrap().len());
             assert!(users[0] == l.list_users(conn).unwrap()[0]);
 
+            assert!(l.add_users(conn, &[users[1].id]).is_ok());
+            assert!(l.remove_users(conn, &[users[0].id]).is_ok());
+            assert!(!l.contains_user(conn, users[0].id).unwrap());
+            assert!(l.contains_user(conn, users[1].id).unwrap());
+
             l.clear(conn).unwrap();
             assert!(l.list_users(conn).unwrap().is_empty());
 
@@ -496,6 +562,11 @@ mod tests {
             assert_eq!(1, l.list_words(conn).unwrap().len());
             assert_eq!("plume", l.list_words(conn).unwrap()[0]);
 
+            assert!(l.add_words(conn, &["amsterdam"]).is_ok());
+            assert!(l.remove_words(conn, &["plume"]).is_ok());
+            assert!(!l.contains_word(conn, "plume").unwrap());
+            assert!(l.contains_word(conn, "amsterdam").unwrap());
+
             l.clear(conn).unwrap();
             assert!(l.list_words(conn).unwrap().is_empty());
 
@@ -536,4 +607,27 @@ mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn test_rename() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, _) = blog_tests::fill_database(conn);
+
+            let mut l = List::new(conn, "friends", Some(&users[0]), ListType::User).unwrap();
+            let used = Timeline::new_for_user(conn, users[0].id, "friends".to_owned(), "author in friends".to_owned())
+                .unwrap();
+            let unused =
+                Timeline::new_for_user(conn, users[0].id, "others".to_owned(), "author in [friends]".to_owned())
+                    .unwrap();
+            assert_eq!(Timeline::list_using_list(conn, &l).unwrap(), vec![used.clone()]);
+
+            l.rename(conn, "close friends").unwrap();
+            assert_eq!(l.name, "close friends");
+            assert_eq!(List::get(conn, l.id).unwrap().name, "close friends");
+            assert_eq!(Timeline::get(conn, used.id).unwrap().query, r#"author in "close friends""#);
+            assert_eq!(Timeline::get(conn, unused.id).unwrap().query, "author in [friends]");
+            Ok(())
+        });
+    }
 }
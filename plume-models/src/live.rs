@@ -0,0 +1,135 @@
+use crate::{Connection, Result};
+use diesel::Connection as _;
+use once_cell::sync::Lazy;
+use rocket::tokio::sync::broadcast::{self, Receiver, Sender};
+use std::cell::{Cell, RefCell};
+
+/// How many events a slow listener can miss before it starts skipping them
+const CAPACITY: usize = 256;
+
+/// Something that users who currently have Plume open should see without reloading
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LiveEvent {
+    /// A notification was sent to a user
+    Notification {
+        user_id: i32,
+        notification_id: i32,
+    },
+    /// A post was added to a timeline. `user_id` is the owner of the timeline, if it isn't an instance timeline.
+    TimelineEntry {
+        timeline_id: i32,
+        user_id: Option<i32>,
+        post_id: i32,
+    },
+}
+
+static LIVE_CHAN: Lazy<Sender<LiveEvent>> = Lazy::new(|| broadcast::channel(CAPACITY).0);
+
+thread_local! {
+    /// How many calls to [`transaction`] are running on this thread
+    static DEPTH: Cell<u32> = const { Cell::new(0) };
+    /// What the transaction running on this thread does once it is committed
+    static PENDING: RefCell<Vec<Box<dyn FnOnce()>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Tells the current listeners about `event`. Nothing happens if nobody listens.
+pub fn publish(event: LiveEvent) {
+    let _ = LIVE_CHAN.send(event);
+}
+
+/// Tells the current listeners about `event` once the rows it refers to can be read from other connections.
+///
+/// Inside of [`transaction`], the event is published when it is committed, and dropped if it is rolled back.
+/// Otherwise, it is published right away.
+pub fn publish_after_commit(event: LiveEvent) {
+    after_commit(move || publish(event));
+}
+
+/// Runs `f` once the current [`transaction`] is committed, so that other connections can read what it wrote.
+///
+/// Nothing happens if the transaction is rolled back. Outside of a transaction, `f` runs right away.
+pub fn after_commit<F>(f: F)
+where
+    F: FnOnce() + 'static,
+{
+    if DEPTH.with(Cell::get) > 0 {
+        PENDING.with(|pending| pending.borrow_mut().push(Box::new(f)));
+    } else {
+        f();
+    }
+}
+
+/// Runs `f` in a transaction, publishing the events it produces when it is committed
+pub fn transaction<T, F>(conn: &mut Connection, f: F) -> Result<T>
+where
+    F: FnOnce(&mut Connection) -> Result<T>,
+{
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            DEPTH.with(|depth| depth.set(depth.get() - 1));
+        }
+    }
+
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let guard = Guard;
+    let res = conn.transaction(f);
+    drop(guard);
+
+    if DEPTH.with(Cell::get) == 0 {
+        let pending = PENDING.with(|pending| pending.take());
+        if res.is_ok() {
+            pending.into_iter().for_each(|f| f());
+        }
+    }
+    res
+}
+
+/// Listens to the events published from now on
+pub fn subscribe() -> Receiver<LiveEvent> {
+    LIVE_CHAN.subscribe()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::db;
+
+    #[test]
+    fn test_publish() {
+        let mut rx = subscribe();
+        let event = LiveEvent::Notification {
+            user_id: -1,
+            notification_id: -1,
+        };
+        publish(event);
+        // Other tests may publish events at the same time
+        assert!(std::iter::from_fn(|| rx.try_recv().ok()).any(|e| e == event));
+    }
+
+    #[test]
+    fn test_publish_after_commit() {
+        let conn = &mut db();
+        let mut rx = subscribe();
+        let event = |id| LiveEvent::Notification {
+            user_id: -2,
+            notification_id: id,
+        };
+
+        let res = transaction(conn, |_| {
+            publish_after_commit(event(1));
+            // Nothing is published before the commit
+            assert!(!std::iter::from_fn(|| rx.try_recv().ok()).any(|e| e == event(1)));
+            Ok(())
+        });
+        assert!(res.is_ok());
+        assert!(std::iter::from_fn(|| rx.try_recv().ok()).any(|e| e == event(1)));
+
+        let res: Result<()> = transaction(conn, |_| {
+            publish_after_commit(event(2));
+            Err(crate::Error::NotFound)
+        });
+        assert!(res.is_err());
+        assert!(!std::iter::from_fn(|| rx.try_recv().ok()).any(|e| e == event(2)));
+    }
+}
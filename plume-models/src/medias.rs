@@ -103,6 +103,29 @@ impl Media {
         }
     }
 
+    /// Guesses the MIME type of this file from its extension
+    pub fn mime_type(&self) -> &'static str {
+        let path = if self.is_remote {
+            self.remote_url.as_deref().unwrap_or_default()
+        } else {
+            &self.file_path
+        };
+        match &*path.rsplit_once('.').map(|x| x.1).unwrap_or("").to_lowercase() {
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "svg" => "image/svg+xml",
+            "mp3" => "audio/mpeg",
+            "wav" => "audio/wav",
+            "flac" => "audio/flac",
+            "mp4" => "video/mp4",
+            "avi" => "video/x-msvideo",
+            "webm" => "video/webm",
+            "mov" => "video/quicktime",
+            _ => "application/octet-stream",
+        }
+    }
+
     pub fn html(&self) -> Result<SafeString> {
         let url = self.url()?;
         Ok(match self.category() {
@@ -0,0 +1,222 @@
+use crate::{
+    blocklisted_emails::BlocklistedEmail,
+    blogs::Blog,
+    email_signups::Token,
+    remote_notes::is_notes_blog,
+    schema::{email_suppressions, newsletter_subscriptions},
+    Connection, Error, Result,
+};
+use chrono::{offset::Utc, Duration, NaiveDateTime};
+use diesel::{self, Connection as _, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+
+const TOKEN_VALIDITY_HOURS: i64 = 48;
+
+/// A reader receiving the new articles of a blog by email
+///
+/// The subscription only becomes active once the link sent to the address has been followed. The newsletters link to
+/// another token to unsubscribe, so that they can't be used to confirm a subscription.
+#[derive(Clone, Debug, Identifiable, Queryable)]
+pub struct NewsletterSubscription {
+    pub id: i32,
+    pub blog_id: i32,
+    pub email: String,
+    pub token: String,
+    pub confirmed: bool,
+    /// When an unconfirmed subscription stops being valid
+    pub expiration_date: NaiveDateTime,
+    pub unsubscribe_token: String,
+    /// The language the newsletters are written in
+    pub lang: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = newsletter_subscriptions)]
+struct NewNewsletterSubscription<'a> {
+    blog_id: i32,
+    email: &'a str,
+    token: &'a str,
+    confirmed: bool,
+    expiration_date: NaiveDateTime,
+    unsubscribe_token: &'a str,
+    lang: &'a str,
+}
+
+impl NewsletterSubscription {
+    get!(newsletter_subscriptions);
+    find_by!(newsletter_subscriptions, find_by_token, token as &str);
+    find_by!(newsletter_subscriptions, find_by_unsubscribe_token, unsubscribe_token as &str);
+
+    /// Starts the subscription of `email` to `blog`, with newsletters written in `lang`.
+    ///
+    /// Returns the token to send to this address to confirm the subscription, or `None` if it is already subscribed
+    /// or if a confirmation was already sent and is still valid. The blogs gathering the notes of remote users can't be
+    /// followed.
+    pub fn start(conn: &mut Connection, blog: &Blog, email: &str, lang: &str) -> Result<Option<Token>> {
+        if is_notes_blog(blog) {
+            return Err(Error::Unauthorized);
+        }
+        if let Some(x) = BlocklistedEmail::matches_blocklist(conn, email)? {
+            return Err(Error::Blocklisted(x.notify_user, x.notification_text));
+        }
+
+        conn.transaction(|conn| {
+            let existing = newsletter_subscriptions::table
+                .filter(newsletter_subscriptions::blog_id.eq(blog.id))
+                .filter(newsletter_subscriptions::email.eq(email))
+                .first::<Self>(conn)
+                .optional()?;
+            if let Some(existing) = existing {
+                // Subscribing again is the only way to get the newsletter again after a bounce
+                if existing.confirmed && !EmailSuppression::is_suppressed(conn, email)? {
+                    return Ok(None);
+                }
+                // Don't mail the same address again until the previous link expired
+                if !existing.confirmed && existing.expiration_date >= Utc::now().naive_utc() {
+                    return Ok(None);
+                }
+                existing.delete(conn)?;
+            }
+
+            let token = Token::generate();
+            let expiration_date = Utc::now()
+                .naive_utc()
+                .checked_add_signed(Duration::hours(TOKEN_VALIDITY_HOURS))
+                .expect("could not calculate expiration date");
+            diesel::insert_into(newsletter_subscriptions::table)
+                .values(NewNewsletterSubscription {
+                    blog_id: blog.id,
+                    email,
+                    token: &token,
+                    confirmed: false,
+                    expiration_date,
+                    unsubscribe_token: &Token::generate(),
+                    lang,
+                })
+                .execute(conn)?;
+            Ok(Some(token))
+        })
+    }
+
+    /// Activates the subscription, which proves that its address can receive mails again if it was suppressed
+    pub fn confirm(&self, conn: &mut Connection) -> Result<()> {
+        if self.confirmed {
+            return Ok(());
+        }
+        if self.expiration_date < Utc::now().naive_utc() {
+            self.delete(conn)?;
+            return Err(Error::Expired);
+        }
+
+        conn.transaction(|conn| {
+            diesel::update(self).set(newsletter_subscriptions::confirmed.eq(true)).execute(conn)?;
+            EmailSuppression::lift(conn, &self.email)
+        })
+    }
+
+    pub fn delete(&self, conn: &mut Connection) -> Result<()> {
+        diesel::delete(self).execute(conn).map(|_| ()).map_err(Error::from)
+    }
+
+    /// The subscriptions the new articles of a blog should be sent to
+    pub fn list_recipients(conn: &mut Connection, blog_id: i32) -> Result<Vec<Self>> {
+        newsletter_subscriptions::table
+            .filter(newsletter_subscriptions::blog_id.eq(blog_id))
+            .filter(newsletter_subscriptions::confirmed.eq(true))
+            .filter(diesel::dsl::not(
+                newsletter_subscriptions::email.eq_any(email_suppressions::table.select(email_suppressions::email)),
+            ))
+            .load::<Self>(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn count_for_blog(conn: &mut Connection, blog_id: i32) -> Result<i64> {
+        newsletter_subscriptions::table
+            .filter(newsletter_subscriptions::blog_id.eq(blog_id))
+            .filter(newsletter_subscriptions::confirmed.eq(true))
+            .count()
+            .get_result(conn)
+            .map_err(Error::from)
+    }
+}
+
+/// An address no newsletter should be sent to anymore, because delivering to it permanently failed
+#[derive(Clone, Debug, Identifiable, Queryable)]
+pub struct EmailSuppression {
+    pub id: i32,
+    pub email: String,
+    pub creation_date: NaiveDateTime,
+}
+
+impl EmailSuppression {
+    pub fn suppress(conn: &mut Connection, email: &str) -> Result<()> {
+        if Self::is_suppressed(conn, email)? {
+            return Ok(());
+        }
+        diesel::insert_into(email_suppressions::table)
+            .values(email_suppressions::email.eq(email))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    pub fn is_suppressed(conn: &mut Connection, email: &str) -> Result<bool> {
+        diesel::select(diesel::dsl::exists(email_suppressions::table.filter(email_suppressions::email.eq(email))))
+            .get_result(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn lift(conn: &mut Connection, email: &str) -> Result<()> {
+        diesel::delete(email_suppressions::table.filter(email_suppressions::email.eq(email)))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blogs::tests as blogTests, tests::db};
+    use diesel::Connection;
+
+    #[test]
+    fn test_subscription() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (_, blogs) = blogTests::fill_database(conn);
+            let email = "reader@example.org";
+
+            let token = NewsletterSubscription::start(conn, &blogs[0], email, "fr").unwrap().unwrap();
+            assert!(NewsletterSubscription::list_recipients(conn, blogs[0].id).unwrap().is_empty());
+            // The confirmation link isn't sent again while it is valid
+            assert!(NewsletterSubscription::start(conn, &blogs[0], email, "fr").unwrap().is_none());
+
+            let sub = NewsletterSubscription::find_by_token(conn, &token).unwrap();
+            assert_ne!(sub.unsubscribe_token, sub.token);
+            assert_eq!(sub.lang, "fr");
+            sub.confirm(conn).unwrap();
+            assert_eq!(NewsletterSubscription::list_recipients(conn, blogs[0].id).unwrap().len(), 1);
+            assert_eq!(NewsletterSubscription::count_for_blog(conn, blogs[1].id).unwrap(), 0);
+
+            // Already subscribed: nothing to confirm
+            assert!(NewsletterSubscription::start(conn, &blogs[0], email, "fr").unwrap().is_none());
+
+            // A bounce stops the newsletter, until the reader subscribes again
+            EmailSuppression::suppress(conn, email).unwrap();
+            assert!(NewsletterSubscription::list_recipients(conn, blogs[0].id).unwrap().is_empty());
+            let token = NewsletterSubscription::start(conn, &blogs[0], email, "fr").unwrap().unwrap();
+            NewsletterSubscription::find_by_token(conn, &token).unwrap().confirm(conn).unwrap();
+            assert!(!EmailSuppression::is_suppressed(conn, email).unwrap());
+            assert_eq!(NewsletterSubscription::list_recipients(conn, blogs[0].id).unwrap().len(), 1);
+
+            let sub = NewsletterSubscription::find_by_token(conn, &token).unwrap();
+            NewsletterSubscription::find_by_unsubscribe_token(conn, &sub.unsubscribe_token)
+                .unwrap()
+                .delete(conn)
+                .unwrap();
+            assert!(NewsletterSubscription::list_recipients(conn, blogs[0].id).unwrap().is_empty());
+
+            Ok(())
+        });
+    }
+}
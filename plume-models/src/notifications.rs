@@ -2,15 +2,16 @@ use crate::{
     comments::Comment,
     follows::Follow,
     likes::Like,
+    live::{self, LiveEvent},
     mentions::Mention,
     posts::Post,
     reshares::Reshare,
-    schema::{follows, notifications},
+    schema::{follows, notification_markers, notifications},
     users::User,
     Connection, Error, Result,
 };
 use chrono::NaiveDateTime;
-use diesel::{self, ExpressionMethods, JoinOnDsl, QueryDsl, RunQueryDsl};
+use diesel::{self, ExpressionMethods, JoinOnDsl, OptionalExtension, QueryDsl, RunQueryDsl};
 
 pub mod notification_kind {
     pub const COMMENT: &str = "COMMENT";
@@ -18,6 +19,8 @@ pub mod notification_kind {
     pub const LIKE: &str = "LIKE";
     pub const MENTION: &str = "MENTION";
     pub const RESHARE: &str = "RESHARE";
+    /// A post was added to one of the user's timelines. `object_id` is the ID of the post.
+    pub const TIMELINE: &str = "TIMELINE";
 }
 
 #[derive(Clone, Queryable, Identifiable)]
@@ -38,7 +41,13 @@ pub struct NewNotification {
 }
 
 impl Notification {
-    insert!(notifications, NewNotification);
+    insert!(notifications, NewNotification, |inserted, _conn| {
+        live::publish_after_commit(LiveEvent::Notification {
+            user_id: inserted.user_id,
+            notification_id: inserted.id,
+        });
+        Ok(inserted)
+    });
     get!(notifications);
 
     pub fn find_for_user(conn: &mut Connection, user: &User) -> Result<Vec<Notification>> {
@@ -89,6 +98,43 @@ impl Notification {
             .map_err(Error::from)
     }
 
+    /// Counts the notifications `user_id` received since they last opened the list of their notifications
+    pub fn count_unread(conn: &mut Connection, user_id: i32) -> Result<i64> {
+        let last_read_id = notification_markers::table
+            .filter(notification_markers::user_id.eq(user_id))
+            .select(notification_markers::last_read_id)
+            .first::<i32>(conn)
+            .optional()?
+            .unwrap_or(0);
+        notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::id.gt(last_read_id))
+            .count()
+            .get_result(conn)
+            .map_err(Error::from)
+    }
+
+    /// Marks all the current notifications of `user_id` as read
+    pub fn mark_all_read(conn: &mut Connection, user_id: i32) -> Result<()> {
+        let last_id = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .select(diesel::dsl::max(notifications::id))
+            .first::<Option<i32>>(conn)?;
+        let last_id = match last_id {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let updated = diesel::update(notification_markers::table.filter(notification_markers::user_id.eq(user_id)))
+            .set(notification_markers::last_read_id.eq(last_id))
+            .execute(conn)?;
+        if updated == 0 {
+            diesel::insert_into(notification_markers::table)
+                .values((notification_markers::user_id.eq(user_id), notification_markers::last_read_id.eq(last_id)))
+                .execute(conn)?;
+        }
+        Ok(())
+    }
+
     pub fn find<S: Into<String>>(conn: &mut Connection, kind: S, obj: i32) -> Result<Notification> {
         notifications::table
             .filter(notifications::kind.eq(kind.into()))
@@ -111,6 +157,7 @@ impl Notification {
                     })
                 })
                 .ok(),
+            notification_kind::TIMELINE => Post::get(conn, self.object_id).and_then(|p| p.url(conn)).ok(),
             _ => None,
         }
     }
@@ -126,6 +173,7 @@ impl Notification {
             notification_kind::RESHARE => {
                 Reshare::get(conn, self.object_id).and_then(|reshare| reshare.get_post(conn)).ok()
             }
+            notification_kind::TIMELINE => Post::get(conn, self.object_id).ok(),
             _ => None,
         }
     }
@@ -143,6 +191,9 @@ impl Notification {
             }
             notification_kind::MENTION => Mention::get(conn, self.object_id)?.get_user(conn)?,
             notification_kind::RESHARE => Reshare::get(conn, self.object_id)?.get_user(conn)?,
+            notification_kind::TIMELINE => {
+                Post::get(conn, self.object_id)?.get_authors(conn)?.into_iter().next().ok_or(Error::NotFound)?
+            }
             _ => unreachable!("Notification::get_actor: Unknow type"),
         })
     }
@@ -154,6 +205,7 @@ impl Notification {
             notification_kind::LIKE => "icon-heart",
             notification_kind::MENTION => "icon-at-sign",
             notification_kind::RESHARE => "icon-repeat",
+            notification_kind::TIMELINE => "icon-search",
             _ => unreachable!("Notification::get_actor: Unknow type"),
         }
     }
@@ -162,3 +214,45 @@ impl Notification {
         diesel::delete(self).execute(conn).map(|_| ()).map_err(Error::from)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::db, users::tests as userTests, Connection as Conn};
+    use diesel::Connection;
+
+    fn notify(conn: &mut Conn, user: &User) {
+        Notification::insert(
+            conn,
+            NewNotification {
+                user_id: user.id,
+                kind: notification_kind::FOLLOW.to_owned(),
+                object_id: 0,
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_count_unread() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let users = userTests::fill_database(conn);
+            notify(conn, &users[0]);
+            notify(conn, &users[0]);
+            notify(conn, &users[1]);
+            assert_eq!(Notification::count_unread(conn, users[0].id).unwrap(), 2);
+
+            Notification::mark_all_read(conn, users[0].id).unwrap();
+            assert_eq!(Notification::count_unread(conn, users[0].id).unwrap(), 0);
+            assert_eq!(Notification::count_unread(conn, users[1].id).unwrap(), 1);
+
+            notify(conn, &users[0]);
+            assert_eq!(Notification::count_unread(conn, users[0].id).unwrap(), 1);
+            Notification::mark_all_read(conn, users[0].id).unwrap();
+            assert_eq!(Notification::count_unread(conn, users[0].id).unwrap(), 0);
+
+            Ok(())
+        });
+    }
+}
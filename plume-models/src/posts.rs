@@ -1,7 +1,7 @@
 use crate::{
-    ap_url, blogs::Blog, instance::Instance, medias::Media, mentions::Mention, post_authors::*,
-    safe_string::SafeString, schema::posts, tags::*, timeline::*, users::User, Connection, Error, PostEvent::*, Result,
-    CONFIG, POST_CHAN,
+    ap_url, blog_feeds::BlogFeedItem, blogs::Blog, instance::Instance, live, medias::Media, mentions::Mention,
+    post_authors::*, safe_string::SafeString, schema::posts, tags::*, timeline::*, users::User, Connection, Error,
+    PostEvent::*, Result, CONFIG, POST_CHAN,
 };
 use activitystreams::{
     activity::{Create, Delete, Update},
@@ -27,6 +27,9 @@ use riker::actors::{Publish, Tell};
 use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 
+/// The maximum length of the summary of a post without subtitle, in characters
+const SUMMARY_LENGTH: usize = 280;
+
 static BLOG_FQN_CACHE: Lazy<Mutex<HashMap<i32, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
 #[derive(Queryable, Identifiable, Clone, AsChangeset, Debug)]
@@ -44,6 +47,7 @@ pub struct Post {
     pub subtitle: String,
     pub source: String,
     pub cover_id: Option<i32>,
+    pub updated_at: NaiveDateTime,
 }
 
 #[derive(Insertable)]
@@ -73,7 +77,8 @@ impl Post {
             let blog = Blog::get(conn, new.blog_id)?;
             new.ap_url = Self::ap_url(blog, &new.slug);
         }
-        diesel::insert_into(posts::table).values(new).execute(conn)?;
+        // SQLite can't default the column to the current date
+        diesel::insert_into(posts::table).values((new, posts::updated_at.eq(Utc::now().naive_utc()))).execute(conn)?;
         let post = Self::last(conn)?;
 
         if post.published {
@@ -84,7 +89,9 @@ impl Post {
     }
 
     pub fn update(&self, conn: &mut Connection) -> Result<Self> {
-        diesel::update(self).set(self).execute(conn)?;
+        let mut post = self.clone();
+        post.updated_at = Utc::now().naive_utc();
+        diesel::update(self).set(&post).execute(conn)?;
         let post = Self::get(conn, self.id)?;
         // TODO: Call publish_published() when newly published
         if post.published {
@@ -485,6 +492,40 @@ impl Post {
         self.cover_id.and_then(|i| Media::get(conn, i).ok()).and_then(|c| c.url().ok())
     }
 
+    /// Where this post was originally published, if it was imported from an external feed
+    pub fn original_url(&self, conn: &mut Connection) -> Result<Option<String>> {
+        Ok(BlogFeedItem::find_for_post(conn, self.id)?.map(|item| item.url))
+    }
+
+    /// A short text introducing this post: its subtitle if it has one, or the beginning of its content
+    pub fn summary(&self) -> String {
+        if !self.subtitle.is_empty() {
+            return self.subtitle.clone();
+        }
+
+        let html = ammonia::Builder::empty().clean(self.content.get()).to_string();
+        // Stripping the tags leaves the text escaped, but the summary is plain text
+        let text = quick_xml::escape::unescape_with(&html, |entity| match entity {
+            "nbsp" => Some("\u{a0}"),
+            _ => None,
+        })
+        .map(|text| text.into_owned())
+        .unwrap_or(html);
+        let mut summary = String::new();
+        for word in text.split_whitespace() {
+            // Keep some room for the space before the word, and for the ellipsis
+            if summary.chars().count() + word.chars().count() + 2 > SUMMARY_LENGTH {
+                summary.push('…');
+                break;
+            }
+            if !summary.is_empty() {
+                summary.push(' ');
+            }
+            summary.push_str(word);
+        }
+        summary
+    }
+
     pub fn build_delete(&self, conn: &mut Connection) -> Result<Delete> {
         let mut tombstone = Tombstone::new();
         tombstone.set_id(self.ap_url.parse()?);
@@ -500,23 +541,31 @@ impl Post {
     }
 
     fn publish_published(&self) {
-        POST_CHAN.tell(
-            Publish {
-                msg: PostPublished(Arc::new(self.clone())),
-                topic: "post.published".into(),
-            },
-            None,
-        )
+        let post = Arc::new(self.clone());
+        // The search index reads the post from another connection
+        live::after_commit(move || {
+            POST_CHAN.tell(
+                Publish {
+                    msg: PostPublished(post),
+                    topic: "post.published".into(),
+                },
+                None,
+            )
+        });
     }
 
     fn publish_updated(&self) {
-        POST_CHAN.tell(
-            Publish {
-                msg: PostUpdated(Arc::new(self.clone())),
-                topic: "post.updated".into(),
-            },
-            None,
-        )
+        let post = Arc::new(self.clone());
+        // The search index reads the post from another connection
+        live::after_commit(move || {
+            POST_CHAN.tell(
+                Publish {
+                    msg: PostUpdated(post),
+                    topic: "post.updated".into(),
+                },
+                None,
+            )
+        });
     }
 
     fn publish_deleted(&self) {
@@ -1126,4 +1175,30 @@ mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn summary() {
+        let conn = db();
+        conn.test_transaction::<_, Error, _>(|| {
+            let (posts, _users, _blogs) = fill_database(&conn);
+            let mut post = posts[0].clone();
+
+            post.subtitle = "A subtitle".to_string();
+            assert_eq!(post.summary(), "A subtitle");
+
+            post.subtitle = String::new();
+            post.content = SafeString::new("<p>Hello <strong>world</strong>!</p>\n<p>Bye.</p>");
+            assert_eq!(post.summary(), "Hello world! Bye.");
+
+            post.content = SafeString::new("<p>Tom &amp; Jerry&nbsp;&lt;3 &eacute;t&eacute;</p>");
+            assert_eq!(post.summary(), "Tom & Jerry <3 été");
+
+            post.content = SafeString::new(&format!("<p>{}</p>", "word ".repeat(100)));
+            let summary = post.summary();
+            assert!(summary.ends_with('…'));
+            assert!(summary.chars().count() <= SUMMARY_LENGTH);
+
+            Ok(())
+        });
+    }
 }
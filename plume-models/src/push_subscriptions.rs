@@ -0,0 +1,260 @@
+use crate::{
+    notifications::{notification_kind, Notification},
+    schema::{notifications, push_subscriptions, vapid_keys},
+    Connection, Error, Result, CONFIG,
+};
+use chrono::NaiveDateTime;
+use diesel::{self, dsl::exists, Connection as _, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+use plume_common::{utils::is_public_url, web_push};
+
+/// The kinds of notifications that are pushed to browsers
+const PUSHED_KINDS: [&str; 3] = [notification_kind::MENTION, notification_kind::COMMENT, notification_kind::FOLLOW];
+
+/// How many notifications are pushed to a browser at once, the next ones waiting for the next time
+const PUSH_BATCH: i64 = 10;
+
+/// A browser of a local user that accepts Web Push messages
+#[derive(Clone, Debug, Identifiable, Queryable)]
+pub struct PushSubscription {
+    pub id: i32,
+    pub user_id: i32,
+    /// Where the messages are posted, on the push service of the browser
+    pub endpoint: String,
+    pub p256dh: String,
+    pub auth: String,
+    /// The language the messages are written in
+    pub lang: String,
+    pub creation_date: NaiveDateTime,
+    /// The last notification that was pushed, or that was already there when the browser subscribed
+    pub last_notification_id: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = push_subscriptions)]
+pub struct NewPushSubscription<'a> {
+    pub user_id: i32,
+    pub endpoint: &'a str,
+    pub p256dh: &'a str,
+    pub auth: &'a str,
+    pub lang: &'a str,
+}
+
+impl PushSubscription {
+    insert!(push_subscriptions, NewPushSubscription<'_>);
+    get!(push_subscriptions);
+    find_by!(push_subscriptions, find_by_endpoint, endpoint as &str);
+    list_by!(push_subscriptions, list_for_user, user_id as i32);
+
+    /// Registers a browser for `user_id`.
+    ///
+    /// A browser only has one subscription: if it was already registered, maybe by another account, the previous
+    /// subscription is replaced. The push service must be on a public address, since this instance will post to it.
+    pub fn subscribe(conn: &mut Connection, new: NewPushSubscription<'_>) -> Result<Self> {
+        if !new.endpoint.starts_with("https://")
+            || !is_public_url(new.endpoint)
+            || new.p256dh.is_empty()
+            || new.auth.is_empty()
+        {
+            return Err(Error::InvalidValue);
+        }
+
+        conn.transaction(|conn| {
+            diesel::delete(push_subscriptions::table.filter(push_subscriptions::endpoint.eq(new.endpoint)))
+                .execute(conn)?;
+            let subscription = Self::insert(conn, new)?;
+            // Only the notifications that come after are pushed
+            let last_notification_id = notifications::table
+                .filter(notifications::user_id.eq(subscription.user_id))
+                .select(diesel::dsl::max(notifications::id))
+                .first::<Option<i32>>(conn)?
+                .unwrap_or_default();
+            diesel::update(&subscription)
+                .set(push_subscriptions::last_notification_id.eq(last_notification_id))
+                .execute(conn)?;
+            Self::get(conn, subscription.id)
+        })
+    }
+
+    /// Lists the subscriptions that have notifications to push
+    pub fn list_pending(conn: &mut Connection) -> Result<Vec<Self>> {
+        push_subscriptions::table
+            .filter(exists(
+                notifications::table
+                    .filter(notifications::user_id.eq(push_subscriptions::user_id))
+                    .filter(notifications::id.gt(push_subscriptions::last_notification_id))
+                    .filter(notifications::kind.eq_any(PUSHED_KINDS)),
+            ))
+            .load(conn)
+            .map_err(Error::from)
+    }
+
+    /// The next notifications to push to this browser, oldest first
+    pub fn pending(&self, conn: &mut Connection) -> Result<Vec<Notification>> {
+        notifications::table
+            .filter(notifications::user_id.eq(self.user_id))
+            .filter(notifications::id.gt(self.last_notification_id))
+            .filter(notifications::kind.eq_any(PUSHED_KINDS))
+            .order(notifications::id.asc())
+            .limit(PUSH_BATCH)
+            .load(conn)
+            .map_err(Error::from)
+    }
+
+    /// Remembers that the notifications up to `notification_id` were pushed, whether the push service accepted them
+    /// or not
+    pub fn mark_pushed(&self, conn: &mut Connection, notification_id: i32) -> Result<()> {
+        diesel::update(self)
+            .set(push_subscriptions::last_notification_id.eq(notification_id))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    pub fn delete(&self, conn: &mut Connection) -> Result<()> {
+        diesel::delete(self).execute(conn).map(|_| ()).map_err(Error::from)
+    }
+
+    /// Sends a message to this browser. It doesn't need a database connection, so that many browsers can be reached at
+    /// once.
+    ///
+    /// Fails with `Error::NotFound` if the push service doesn't know this subscription anymore: it should be deleted.
+    pub fn push(&self, keys: &VapidKeys, payload: &[u8]) -> Result<()> {
+        // Where the host points to may have changed since the subscription
+        if !is_public_url(&self.endpoint) {
+            return Err(Error::Unauthorized);
+        }
+        let subject = format!("https://{}", CONFIG.base_url);
+        let res = web_push::send(
+            &web_push::Subscription {
+                endpoint: &self.endpoint,
+                p256dh: &self.p256dh,
+                auth: &self.auth,
+            },
+            &web_push::Vapid {
+                private_key: &keys.private_key,
+                public_key: &keys.public_key,
+                subject: &subject,
+            },
+            payload,
+            CONFIG.proxy().cloned(),
+        );
+        match res {
+            Ok(()) => Ok(()),
+            Err(web_push::Error::Gone) => Err(Error::NotFound),
+            Err(web_push::Error::Crypto) => Err(Error::Signature),
+            Err(web_push::Error::Request) => Err(Error::Request),
+        }
+    }
+}
+
+/// The keys this instance signs its push messages with, generated the first time they are needed
+#[derive(Clone, Debug, Identifiable, Queryable)]
+pub struct VapidKeys {
+    pub id: i32,
+    pub private_key: String,
+    /// What browsers need to subscribe, as URL-safe base64
+    pub public_key: String,
+}
+
+impl VapidKeys {
+    pub fn get_or_create(conn: &mut Connection) -> Result<Self> {
+        conn.transaction(|conn| {
+            if let Some(keys) = vapid_keys::table.order(vapid_keys::id.asc()).first::<Self>(conn).optional()? {
+                return Ok(keys);
+            }
+            let (private_key, public_key) = web_push::gen_vapid_keys();
+            diesel::insert_into(vapid_keys::table)
+                .values((vapid_keys::private_key.eq(private_key), vapid_keys::public_key.eq(public_key)))
+                .execute(conn)?;
+            vapid_keys::table.order(vapid_keys::id.asc()).first::<Self>(conn).map_err(Error::from)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notifications::NewNotification, tests::db, users::tests as userTests};
+    use diesel::Connection;
+
+    #[test]
+    fn test_subscribe() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let users = userTests::fill_database(conn);
+            let new = |user_id, endpoint| NewPushSubscription {
+                user_id,
+                endpoint,
+                p256dh: "BNcRdreALRFXTkOOUHK1EtK2wtaz5Ry4YfYCA_0QTpQtUbVlUls0VJXg7A8u-Ts1XbjhazAkj7I99e8QcYP7DkM",
+                auth: "tBHItJI5svbpez7KI4CCXg",
+                lang: "en",
+            };
+
+            PushSubscription::subscribe(conn, new(users[0].id, "https://93.184.215.14/a")).unwrap();
+            PushSubscription::subscribe(conn, new(users[0].id, "https://93.184.215.14/b")).unwrap();
+            assert!(PushSubscription::subscribe(conn, new(users[0].id, "http://93.184.215.14/c")).is_err());
+            // Push services can't be on the network of the instance
+            assert!(PushSubscription::subscribe(conn, new(users[0].id, "https://127.0.0.1/d")).is_err());
+            assert!(PushSubscription::subscribe(conn, new(users[0].id, "https://192.168.1.1/e")).is_err());
+            assert_eq!(PushSubscription::list_for_user(conn, users[0].id).unwrap().len(), 2);
+
+            // The browser is now used by another account
+            PushSubscription::subscribe(conn, new(users[1].id, "https://93.184.215.14/a")).unwrap();
+            assert_eq!(PushSubscription::list_for_user(conn, users[0].id).unwrap().len(), 1);
+            let sub = PushSubscription::find_by_endpoint(conn, "https://93.184.215.14/a").unwrap();
+            assert_eq!(sub.user_id, users[1].id);
+
+            // The same keys are always used
+            let keys = VapidKeys::get_or_create(conn).unwrap();
+            assert_eq!(VapidKeys::get_or_create(conn).unwrap().public_key, keys.public_key);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_pending() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let users = userTests::fill_database(conn);
+            let notify = |conn, kind: &str| {
+                Notification::insert(
+                    conn,
+                    NewNotification {
+                        user_id: users[0].id,
+                        kind: kind.to_owned(),
+                        object_id: users[1].id,
+                    },
+                )
+                .unwrap()
+            };
+            // Notifications from before the subscription are not pushed
+            notify(conn, notification_kind::FOLLOW);
+            let sub = PushSubscription::subscribe(
+                conn,
+                NewPushSubscription {
+                    user_id: users[0].id,
+                    endpoint: "https://93.184.215.14/a",
+                    p256dh: "BNcRdreALRFXTkOOUHK1EtK2wtaz5Ry4YfYCA_0QTpQtUbVlUls0VJXg7A8u-Ts1XbjhazAkj7I99e8QcYP7DkM",
+                    auth: "tBHItJI5svbpez7KI4CCXg",
+                    lang: "en",
+                },
+            )
+            .unwrap();
+            assert!(PushSubscription::list_pending(conn).unwrap().is_empty());
+
+            // Likes are not pushed
+            notify(conn, notification_kind::LIKE);
+            assert!(PushSubscription::list_pending(conn).unwrap().is_empty());
+            let follow = notify(conn, notification_kind::FOLLOW);
+            let pending = PushSubscription::list_pending(conn).unwrap();
+            assert_eq!(pending.len(), 1);
+            assert_eq!(pending[0].pending(conn).unwrap().iter().map(|n| n.id).collect::<Vec<_>>(), vec![follow.id]);
+
+            sub.mark_pushed(conn, follow.id).unwrap();
+            assert!(PushSubscription::list_pending(conn).unwrap().is_empty());
+
+            Ok(())
+        });
+    }
+}
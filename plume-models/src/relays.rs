@@ -0,0 +1,461 @@
+use crate::{
+    inbox::InboxResult, instance::Instance, posts::Post, schema::relays, users::User, Connection, Error, Result, CONFIG,
+};
+use activitystreams::{
+    activity::{Announce, Create, Follow, Undo},
+    iri_string::types::IriString,
+    prelude::*,
+};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{self, Connection as _, ExpressionMethods, QueryDsl, RunQueryDsl};
+use openssl::{hash::MessageDigest, pkey::PKey, rsa::Rsa};
+use plume_common::{
+    activity_pub::{
+        broadcast,
+        inbox::{AsActor, FromId},
+        request::{self, Digest},
+        sign::{
+            verify_http_headers_strict, verify_http_message_signature, Error as SignError, Result as SignResult,
+            Signable, SignatureError, Signer,
+        },
+        PUBLIC_VISIBILITY,
+    },
+    utils::random_hex,
+};
+use rocket::http::HeaderMap;
+use tracing::debug;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelayState {
+    /// The relay didn't answer our `Follow` yet
+    Pending = 0,
+    Accepted = 1,
+    Rejected = 2,
+}
+
+/// An ActivityPub relay the instance actor is subscribed to: it forwards our public posts to other instances, and
+/// announces theirs to us.
+#[derive(Clone, Debug, Identifiable, Queryable)]
+pub struct Relay {
+    pub id: i32,
+    pub actor_id: String,
+    pub inbox_url: String,
+    pub public_key: String,
+    /// The id of the `Follow` activity we sent
+    pub follow_id: String,
+    /// What was followed: the public collection for Mastodon-style relays, the relay actor for LitePub ones
+    pub follow_object: String,
+    pub state: i32,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = relays)]
+pub struct NewRelay {
+    pub actor_id: String,
+    pub inbox_url: String,
+    pub public_key: String,
+    pub follow_id: String,
+    pub follow_object: String,
+}
+
+impl Relay {
+    insert!(relays, NewRelay);
+    get!(relays);
+    find_by!(relays, find_by_actor_id, actor_id as &str);
+
+    pub fn list(conn: &mut Connection) -> Result<Vec<Self>> {
+        relays::table.order(relays::creation_date.desc()).load::<Self>(conn).map_err(Error::from)
+    }
+
+    /// The relays our public posts are sent to
+    pub fn list_accepted(conn: &mut Connection) -> Result<Vec<Self>> {
+        relays::table.filter(relays::state.eq(RelayState::Accepted as i32)).load::<Self>(conn).map_err(Error::from)
+    }
+
+    /// The accepted relay a key used to sign a request belongs to
+    pub fn find_by_key_id(conn: &mut Connection, key_id: &str) -> Result<Self> {
+        let actor_id = key_id.split('#').next().unwrap_or(key_id);
+        Self::find_by_actor_id(conn, actor_id).and_then(|relay| {
+            if relay.is_accepted() {
+                Ok(relay)
+            } else {
+                Err(Error::NotFound)
+            }
+        })
+    }
+
+    /// Checks an activity of `actor` the relay forwarded to us.
+    ///
+    /// Mastodon-style relays forward the activities of other instances as they are, in requests they sign themselves:
+    /// their signature only tells that the request comes from them, so the activity must also have the LD signature
+    /// of its actor.
+    pub fn verify_forwarded<S: Signer>(
+        &self,
+        headers: &HeaderMap<'_>,
+        digest: &Digest,
+        act: &serde_json::Value,
+        actor: &S,
+    ) -> std::result::Result<(), SignatureError> {
+        if headers.contains("Signature-Input") {
+            verify_http_message_signature(self, &self.actor_id, Some(&self.actor_id), headers, Some(digest))?;
+        } else {
+            verify_http_headers_strict(self, &self.actor_id, Some(&self.actor_id), headers, Some(digest))?;
+        }
+        if act.clone().verify(actor) {
+            Ok(())
+        } else {
+            Err(SignatureError::InvalidSignature)
+        }
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        self.state == RelayState::Accepted as i32
+    }
+
+    pub fn is_rejected(&self) -> bool {
+        self.state == RelayState::Rejected as i32
+    }
+
+    /// Registers a relay, from the URL of its inbox (Mastodon-style relays) or of its actor (LitePub relays).
+    ///
+    /// The relay actor is fetched, and the relay is pending until it accepts the [`Relay::follow_activity`].
+    pub fn subscribe(conn: &mut Connection, url: &str) -> Result<Self> {
+        let url = url.trim();
+        if Instance::is_blocked(conn, url)? {
+            return Err(Error::InvalidValue);
+        }
+        // Mastodon-style relays are given by their inbox, next to their actor
+        let actor_url = match url.strip_suffix("/inbox") {
+            Some(base) => format!("{}/actor", base),
+            None => url.to_owned(),
+        };
+
+        let sender = Instance::get_local_instance_user().ok_or(Error::NotFound)?;
+        let actor = request::get(&actor_url, sender, CONFIG.proxy().cloned())?.text()?;
+        let actor = serde_json::from_str::<serde_json::Value>(&actor)?;
+        let actor_id = actor["id"].as_str().ok_or(Error::MissingApProperty)?.to_owned();
+        let inbox_url = actor["inbox"].as_str().ok_or(Error::MissingApProperty)?.to_owned();
+        let public_key = actor["publicKey"]["publicKeyPem"].as_str().ok_or(Error::MissingApProperty)?.to_owned();
+        let litepub = is_litepub_actor(&actor);
+
+        conn.transaction(|conn| {
+            diesel::delete(relays::table.filter(relays::actor_id.eq(&actor_id))).execute(conn)?;
+            Self::insert(
+                conn,
+                NewRelay {
+                    follow_id: format!("{}#relays/{}", sender.ap_url, Utc::now().timestamp()),
+                    follow_object: if litepub {
+                        actor_id.clone()
+                    } else {
+                        PUBLIC_VISIBILITY.to_owned()
+                    },
+                    actor_id,
+                    inbox_url,
+                    public_key,
+                },
+            )
+        })
+    }
+
+    /// LitePub relays are followed directly, Mastodon-style ones through the public collection
+    pub fn is_litepub(&self) -> bool {
+        self.follow_object == self.actor_id
+    }
+
+    /// The `Announce` the instance actor sends to LitePub relays for a new public post.
+    ///
+    /// These relays only forward what the actor subscribed to them sends, not the activities of other users. Each
+    /// announce has its own id, so that relays don't take it for one they already forwarded.
+    pub fn announce_activity(post: &Post) -> Result<Announce> {
+        let sender = Instance::get_local_instance_user().ok_or(Error::NotFound)?;
+        let mut act = Announce::new(sender.ap_url.parse::<IriString>()?, post.ap_url.parse::<IriString>()?);
+        act.set_id(format!("{}#relays/announce/{}", post.ap_url, random_hex()).parse::<IriString>()?);
+        act.set_many_tos(vec![PUBLIC_VISIBILITY.parse::<IriString>()?]);
+        act.set_many_ccs(vec![sender.followers_endpoint.parse::<IriString>()?]);
+        Ok(act)
+    }
+
+    /// The [`Relay::announce_activity`] of `post` for `relays`, if some of them are LitePub relays
+    pub fn announce_for(relays: &[Self], post: &Post) -> Result<Option<Announce>> {
+        if relays.iter().any(Relay::is_litepub) {
+            Self::announce_activity(post).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Shares a new public post through `relays`: Mastodon-style ones get the `Create` of its author, and LitePub
+    /// ones the `announce` of the instance actor, built with [`Relay::announce_for`].
+    pub fn share(author: &User, create: Create, announce: Option<Announce>, relays: Vec<Self>) {
+        let (litepub, mastodon): (Vec<_>, Vec<_>) = relays.into_iter().partition(Relay::is_litepub);
+        if !mastodon.is_empty() {
+            broadcast(author, create, mastodon, CONFIG.proxy().cloned());
+        }
+        if let (false, Some(announce)) = (litepub.is_empty(), announce) {
+            if let Some(sender) = Instance::get_local_instance_user() {
+                broadcast(sender, announce, litepub, CONFIG.proxy().cloned());
+            }
+        }
+    }
+
+    /// The `Follow` the instance actor sends to subscribe to the relay
+    pub fn follow_activity(&self) -> Result<Follow> {
+        let sender = Instance::get_local_instance_user().ok_or(Error::NotFound)?;
+        let mut act = Follow::new(sender.ap_url.parse::<IriString>()?, self.follow_object.parse::<IriString>()?);
+        act.set_id(self.follow_id.parse::<IriString>()?);
+        act.set_many_tos(vec![self.actor_id.parse::<IriString>()?]);
+        Ok(act)
+    }
+
+    /// The `Undo` the instance actor sends to unsubscribe from the relay
+    pub fn undo_activity(&self) -> Result<Undo> {
+        let sender = Instance::get_local_instance_user().ok_or(Error::NotFound)?;
+        let mut undo = Undo::new(sender.ap_url.parse::<IriString>()?, self.follow_activity()?.into_any_base()?);
+        undo.set_id(format!("{}/undo", self.follow_id).parse::<IriString>()?);
+        undo.set_many_tos(vec![self.actor_id.parse::<IriString>()?]);
+        Ok(undo)
+    }
+
+    fn set_state(&self, conn: &mut Connection, state: RelayState) -> Result<()> {
+        diesel::update(self).set(relays::state.eq(state as i32)).execute(conn).map(|_| ()).map_err(Error::from)
+    }
+
+    /// Handles an activity sent by the relay: the answer to our `Follow`, or content announced by other instances
+    pub async fn receive(&self, conn: &mut Connection, act: serde_json::Value) -> Result<InboxResult> {
+        let object_id = act["object"].as_str().or_else(|| act["object"]["id"].as_str());
+        match act["type"].as_str() {
+            Some("Accept") if object_id == Some(self.follow_id.as_str()) => {
+                self.set_state(conn, RelayState::Accepted)?;
+                Ok(InboxResult::Other)
+            }
+            Some("Reject") if object_id == Some(self.follow_id.as_str()) => {
+                self.set_state(conn, RelayState::Rejected)?;
+                Ok(InboxResult::Other)
+            }
+            Some("Announce") if self.is_accepted() => {
+                let id = object_id.ok_or(Error::MissingApProperty)?;
+                if Instance::is_blocked(conn, id)? {
+                    return Ok(InboxResult::Other);
+                }
+                // Relays announce all kinds of objects, the ones that are not articles are ignored
+                match Post::from_id(conn, id, None, CONFIG.proxy()).await {
+                    Ok(post) => Ok(InboxResult::Post(post)),
+                    Err((_, e)) => {
+                        debug!("Couldn't fetch {}, announced by {}: {:?}", id, self.actor_id, e);
+                        Ok(InboxResult::Other)
+                    }
+                }
+            }
+            _ => Ok(InboxResult::Other),
+        }
+    }
+
+    pub fn delete(&self, conn: &mut Connection) -> Result<()> {
+        diesel::delete(self).execute(conn).map(|_| ()).map_err(Error::from)
+    }
+}
+
+impl AsActor<&mut Connection> for Relay {
+    fn get_inbox_url(&self) -> String {
+        self.inbox_url.clone()
+    }
+
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+/// Whether `actor` is a LitePub relay, that uses the LitePub vocabulary in its JSON-LD context
+fn is_litepub_actor(actor: &serde_json::Value) -> bool {
+    let context = match &actor["@context"] {
+        serde_json::Value::Array(context) => context.iter().collect::<Vec<_>>(),
+        context => vec![context],
+    };
+    context.into_iter().filter_map(serde_json::Value::as_str).any(|context| context.contains("litepub"))
+}
+
+/// Relays can only be used to check the signatures of the requests they send
+impl Signer for Relay {
+    fn get_key_id(&self) -> String {
+        format!("{}#main-key", self.actor_id)
+    }
+
+    fn sign(&self, _to_sign: &str) -> SignResult<Vec<u8>> {
+        Err(SignError())
+    }
+
+    fn verify(&self, data: &str, signature: &[u8]) -> SignResult<bool> {
+        let key = PKey::from_rsa(Rsa::public_key_from_pem(self.public_key.as_ref())?)?;
+        let mut verifier = openssl::sign::Verifier::new(MessageDigest::sha256(), &key)?;
+        verifier.update(data.as_bytes())?;
+        verifier.verify(signature).map_err(SignError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{inbox::inbox, tests::db, users::tests as user_tests};
+    use diesel::Connection;
+    use futures::executor::block_on;
+    use rocket::http::Header;
+
+    #[test]
+    fn test_relay_answers() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let new = |actor_id: &str| NewRelay {
+                actor_id: actor_id.to_owned(),
+                inbox_url: format!("{}/inbox", actor_id),
+                public_key: String::new(),
+                follow_id: format!("https://plu.me/@/__instance__/#relays/{}", actor_id.len()),
+                follow_object: PUBLIC_VISIBILITY.to_owned(),
+            };
+            let accepting = Relay::insert(conn, new("https://relay.example")).unwrap();
+            let rejecting = Relay::insert(conn, new("https://other-relay.example")).unwrap();
+            assert!(Relay::list_accepted(conn).unwrap().is_empty());
+
+            block_on(inbox(
+                conn,
+                json!({
+                    "type": "Accept",
+                    "actor": accepting.actor_id,
+                    "object": { "type": "Follow", "id": accepting.follow_id },
+                }),
+            ))
+            .unwrap();
+            block_on(inbox(
+                conn,
+                json!({ "type": "Reject", "actor": rejecting.actor_id, "object": rejecting.follow_id }),
+            ))
+            .unwrap();
+            // Relays can't accept follows that are not theirs
+            block_on(inbox(
+                conn,
+                json!({ "type": "Accept", "actor": rejecting.actor_id, "object": accepting.follow_id }),
+            ))
+            .unwrap();
+
+            let accepted = Relay::list_accepted(conn).unwrap();
+            assert_eq!(accepted.len(), 1);
+            assert_eq!(accepted[0].id, accepting.id);
+            assert!(Relay::get(conn, rejecting.id).unwrap().is_rejected());
+
+            Ok(())
+        });
+    }
+
+    /// The headers of a request to our inbox signed with the key of `relay`, which is the one of `key_holder`
+    fn relay_request(relay: &Relay, key_holder: &User, body: &str) -> HeaderMap<'static> {
+        let values = [
+            ("(request-target)", "post /inbox".to_owned()),
+            ("host", "plu.me".to_owned()),
+            ("date", Utc::now().format("%a, %d %b %Y %T GMT").to_string()),
+            ("digest", Digest::digest(body).to_str().unwrap().to_owned()),
+        ];
+        let to_sign = values.iter().map(|(name, value)| format!("{}: {}", name, value)).collect::<Vec<_>>().join("\n");
+        let signature = openssl::base64::encode_block(&key_holder.sign(&to_sign).unwrap());
+
+        let mut headers = HeaderMap::new();
+        headers.add(Header::new(
+            "Signature",
+            format!(
+                "keyId=\"{}\",algorithm=\"rsa-sha256\",headers=\"{}\",signature=\"{}\"",
+                relay.get_key_id(),
+                values.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(" "),
+                signature
+            ),
+        ));
+        for (name, value) in values {
+            headers.add(Header::new(name, value));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_forwarded_activity() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let users = user_tests::fill_database(conn);
+            // The relay has the key of a user, so that its requests can be signed here
+            let relay = Relay::insert(
+                conn,
+                NewRelay {
+                    actor_id: "https://relay.example/actor".to_owned(),
+                    inbox_url: "https://relay.example/inbox".to_owned(),
+                    public_key: users[1].public_key.clone(),
+                    follow_id: "https://plu.me/@/__instance__/#relays/1".to_owned(),
+                    follow_object: PUBLIC_VISIBILITY.to_owned(),
+                },
+            )
+            .unwrap();
+            // Only accepted relays can forward activities
+            assert!(Relay::find_by_key_id(conn, &relay.get_key_id()).is_err());
+            relay.set_state(conn, RelayState::Accepted).unwrap();
+            let relay = Relay::find_by_key_id(conn, &relay.get_key_id()).unwrap();
+
+            let mut act = json!({
+                "@context": "https://www.w3.org/ns/activitystreams",
+                "id": format!("{}/activities/1", users[0].ap_url),
+                "type": "Create",
+                "actor": users[0].ap_url,
+                "object": {
+                    "id": format!("{}/notes/1", users[0].ap_url),
+                    "type": "Note",
+                    "attributedTo": users[0].ap_url,
+                    "content": "Hello",
+                },
+            });
+            act.sign(&users[0]).unwrap();
+            let body = act.to_string();
+            let headers = relay_request(&relay, &users[1], &body);
+            let digest = Digest::from_body(&body);
+            assert_eq!(relay.verify_forwarded(&headers, &digest, &act, &users[0]), Ok(()));
+            // The activity must be signed by its actor, not only by the relay
+            assert_eq!(
+                relay.verify_forwarded(&headers, &digest, &act, &users[1]),
+                Err(SignatureError::InvalidSignature)
+            );
+
+            let mut changed = act.clone();
+            changed["object"]["content"] = json!("Bye");
+            let body = changed.to_string();
+            let headers = relay_request(&relay, &users[1], &body);
+            assert_eq!(
+                relay.verify_forwarded(&headers, &Digest::from_body(&body), &changed, &users[0]),
+                Err(SignatureError::InvalidSignature)
+            );
+            // Requests that are not signed by the relay are refused
+            let headers = relay_request(&relay, &users[0], &body);
+            assert_eq!(
+                relay.verify_forwarded(&headers, &Digest::from_body(&body), &changed, &users[0]),
+                Err(SignatureError::InvalidSignature)
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_is_litepub_actor() {
+        assert!(is_litepub_actor(&json!({
+            "@context": [
+                "https://www.w3.org/ns/activitystreams",
+                "https://pleroma.example/schemas/litepub-0.1.jsonld",
+                { "@language": "und" },
+            ],
+            "id": "https://pleroma.example/relay",
+            "type": "Application",
+        })));
+        assert!(!is_litepub_actor(&json!({
+            "@context": ["https://www.w3.org/ns/activitystreams", "https://w3id.org/security/v1"],
+            "id": "https://relay.example/actor",
+            "type": "Service",
+        })));
+        assert!(!is_litepub_actor(&json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": "https://relay.example/actor",
+        })));
+    }
+}
@@ -0,0 +1,463 @@
+use crate::{
+    blogs::{Blog, NewBlog},
+    inbox::InboxResult,
+    instance::Instance,
+    post_authors::{NewPostAuthor, PostAuthor},
+    posts::{NewPost, Post},
+    safe_string::SafeString,
+    timeline::{Kind, Timeline},
+    users::User,
+    Connection, Error, Result, CONFIG,
+};
+use activitystreams::activity::{Create, Update};
+use chrono::DateTime;
+use openssl::sha::sha256;
+use plume_common::{
+    activity_pub::{
+        inbox::{AsActor, AsObject, FromId},
+        sign::Signer,
+        PUBLIC_VISIBILITY,
+    },
+    utils::escape,
+};
+use serde_json::Value;
+use tracing::debug;
+use url::Url;
+
+/// The types of objects that are shown as articles, besides `Article`
+const INGESTED_TYPES: [&str; 3] = ["Note", "Page", "Question"];
+
+/// How long the titles made from the beginning of a note can be
+const TITLE_LENGTH: usize = 80;
+
+/// A note, page or poll published by an account local users follow, to be shown as an article when `INGEST_NOTES` is
+/// enabled.
+///
+/// Microblogging servers don't publish articles: this makes following people there from Plume useful. The notes of an
+/// author are gathered in a blog that only exists on this instance, see [`notes_blog`].
+pub struct RemoteNote {
+    object: Value,
+}
+
+/// The values of a property that can be a single value or an array
+fn values(property: &Value) -> Vec<&Value> {
+    match property {
+        Value::Array(values) => values.iter().collect(),
+        Value::Null => vec![],
+        value => vec![value],
+    }
+}
+
+fn is_public(object: &Value) -> bool {
+    ["to", "cc"]
+        .iter()
+        .flat_map(|field| values(&object[field]))
+        .filter_map(|audience| audience.as_str())
+        .any(|audience| audience == PUBLIC_VISIBILITY || audience == "as:Public" || audience == "Public")
+}
+
+/// Whether `object` was published by `author`: it must be attributed to them, and come from their server, so that
+/// they can't make an object of another server theirs
+fn is_published_by(object: &Value, author: &User) -> bool {
+    let attributed = values(&object["attributedTo"])
+        .into_iter()
+        .filter_map(|actor| actor.as_str().or_else(|| actor["id"].as_str()))
+        .any(|actor| actor == author.ap_url);
+    let id = object["id"].as_str().and_then(|id| Url::parse(id).ok());
+    let actor = Url::parse(&author.ap_url).ok();
+    let same_origin = match (id, actor) {
+        (Some(id), Some(actor)) => id.origin() == actor.origin(),
+        _ => false,
+    };
+    attributed && same_origin
+}
+
+/// The content of the object, followed by the choices of a poll and the attached media
+fn render(object: &Value) -> String {
+    let mut html = object["content"].as_str().unwrap_or_default().to_owned();
+
+    let choices = values(&object["oneOf"]).into_iter().chain(values(&object["anyOf"])).collect::<Vec<_>>();
+    if !choices.is_empty() {
+        html.push_str("<ul>");
+        for name in choices.into_iter().filter_map(|choice| choice["name"].as_str()) {
+            html.push_str(&format!("<li>{}</li>", escape(name)));
+        }
+        html.push_str("</ul>");
+    }
+
+    for attachment in values(&object["attachment"]) {
+        let url = match attachment["url"].as_str().or_else(|| attachment["url"]["href"].as_str()) {
+            Some(url) => url,
+            None => continue,
+        };
+        let name = attachment["name"].as_str().unwrap_or_default();
+        let is_image = attachment["type"] == "Image"
+            || attachment["mediaType"].as_str().map_or(false, |media_type| media_type.starts_with("image/"));
+        if is_image {
+            html.push_str(&format!(r#"<figure><img src="{}" alt="{}"></figure>"#, escape(url), escape(name)));
+        } else {
+            let text = if name.is_empty() {
+                url
+            } else {
+                name
+            };
+            html.push_str(&format!(r#"<p><a href="{}">{}</a></p>"#, escape(url), escape(text)));
+        }
+    }
+    html
+}
+
+/// A title for objects that don't have a name: the beginning of their text
+fn excerpt(html: &str) -> String {
+    let text = ammonia::Builder::empty().clean(html).to_string();
+    let mut title = String::new();
+    for word in text.split_whitespace() {
+        // Keep some room for the space before the word, and for the ellipsis
+        if title.chars().count() + word.chars().count() + 2 > TITLE_LENGTH {
+            title.push('…');
+            break;
+        }
+        if !title.is_empty() {
+            title.push(' ');
+        }
+        title.push_str(word);
+    }
+    title
+}
+
+/// The name of the object, or the beginning of its content, or the name of its author if it has neither
+fn title(object: &Value, content: &str, author: &User) -> String {
+    object["name"]
+        .as_str()
+        .filter(|name| !name.trim().is_empty())
+        .map(String::from)
+        .or_else(|| Some(excerpt(content)).filter(|title| !title.is_empty()))
+        .unwrap_or_else(|| author.name())
+}
+
+/// The slug of an ingested object: the end of its id, which is unique for a given author on most servers, or a hash
+/// of the whole id
+fn slug(id: &str) -> String {
+    Url::parse(id)
+        .ok()
+        .and_then(|url| {
+            url.path_segments().and_then(|segments| segments.filter(|s| !s.is_empty()).last().map(String::from))
+        })
+        .map(|segment| {
+            segment.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_').collect::<String>()
+        })
+        .filter(|segment| !segment.is_empty())
+        .unwrap_or_else(|| {
+            let hash = sha256(id.as_bytes()).iter().take(8).map(|b| format!("{:02x}", b)).collect::<String>();
+            format!("note-{}", hash)
+        })
+}
+
+/// The blog the notes of `author` are shown in, created the first time one of their notes is received.
+///
+/// Its name can't be the one of an actual actor of their instance (names can't contain a `~`), so it never takes the
+/// place of one of their blogs, or of one of the groups of their instance, and it is kept as is in the URLs of its
+/// pages. It has no key: nothing can be signed in its name, and the key of the author can change without it knowing.
+pub fn notes_blog(conn: &mut Connection, author: &User) -> Result<Blog> {
+    let ap_url = format!("{}#notes", author.ap_url);
+    Blog::find_by_ap_url(conn, &ap_url).or_else(|_| {
+        Blog::insert(
+            conn,
+            NewBlog {
+                actor_id: format!("{}~notes", author.username),
+                title: author.name(),
+                summary: String::new(),
+                outbox_url: author.outbox_url.clone(),
+                inbox_url: author.inbox_url.clone(),
+                instance_id: author.instance_id,
+                ap_url,
+                private_key: None,
+                public_key: String::new(),
+                summary_html: author.summary_html.clone(),
+                icon_id: author.avatar_id,
+                banner_id: None,
+                theme: None,
+            },
+        )
+    })
+}
+
+/// Tells whether `blog` only gathers the notes of a remote user. It is not an actual actor, and can't be followed.
+pub fn is_notes_blog(blog: &Blog) -> bool {
+    blog.private_key.is_none() && blog.ap_url.ends_with("#notes")
+}
+
+impl RemoteNote {
+    /// Saves the object as a post of `author`, if it is public, not a reply, published by them, and if someone here
+    /// follows its author.
+    /// Returns `None` for the objects that are not shown.
+    pub async fn ingest(&self, conn: &mut Connection, author: &User) -> Result<Option<Post>> {
+        let object = &self.object;
+        let kind = object["type"].as_str().unwrap_or_default();
+        if !INGESTED_TYPES.contains(&kind)
+            || !object["inReplyTo"].is_null()
+            || !is_public(object)
+            || !is_published_by(object, author)
+            || author.is_local()
+            || !author.has_local_followers(conn)?
+        {
+            return Ok(None);
+        }
+
+        let id = object["id"].as_str().ok_or(Error::MissingApProperty)?;
+        if Post::find_by_ap_url(conn, id).is_ok() {
+            return Ok(None);
+        }
+
+        let content = render(object);
+        let title = title(object, &content, author);
+        let blog = notes_blog(conn, author)?;
+        // The ends of the ids of two objects can be the same, but not their slugs
+        let mut unique_slug = slug(id);
+        let mut n = 1;
+        while Post::find_by_slug(conn, &unique_slug, blog.id).is_ok() {
+            n += 1;
+            unique_slug = format!("{}-{}", slug(id), n);
+        }
+        let post = Post::insert(
+            conn,
+            NewPost {
+                blog_id: blog.id,
+                slug: unique_slug,
+                title,
+                content: SafeString::new(&content),
+                published: true,
+                license: String::new(),
+                ap_url: id.to_owned(),
+                creation_date: object["published"]
+                    .as_str()
+                    .and_then(|published| DateTime::parse_from_rfc3339(published).ok())
+                    .map(|published| published.naive_utc()),
+                // Content warnings of microblogging servers
+                subtitle: object["summary"].as_str().unwrap_or_default().to_owned(),
+                source: String::new(),
+                cover_id: None,
+            },
+        )?;
+        PostAuthor::insert(
+            conn,
+            NewPostAuthor {
+                post_id: post.id,
+                author_id: author.id,
+            },
+        )?;
+
+        Timeline::add_to_all_timelines(conn, &post, &Kind::Original).await?;
+        Ok(Some(post))
+    }
+
+    /// Applies the new version of an ingested object to its post. The objects that were not shown are ignored.
+    pub fn update(&self, conn: &mut Connection, author: &User) -> Result<()> {
+        let object = &self.object;
+        if !INGESTED_TYPES.contains(&object["type"].as_str().unwrap_or_default()) {
+            return Err(Error::InvalidValue);
+        }
+        let id = object["id"].as_str().ok_or(Error::MissingApProperty)?;
+        let mut post = match Post::find_by_ap_url(conn, id) {
+            Ok(post) => post,
+            Err(_) => {
+                debug!("Ignored the update of {}, which is not shown here", id);
+                return Ok(());
+            }
+        };
+        if !is_published_by(object, author) || !post.is_author(conn, author.id)? {
+            return Err(Error::Unauthorized);
+        }
+
+        let content = render(object);
+        post.title = title(object, &content, author);
+        post.content = SafeString::new(&content);
+        post.subtitle = object["summary"].as_str().unwrap_or_default().to_owned();
+        post.update(conn)?;
+        Ok(())
+    }
+}
+
+impl FromId<Connection> for RemoteNote {
+    type Error = Error;
+    type Object = Value;
+
+    fn from_db(_: &mut Connection, _: &str) -> Result<Self> {
+        // Always fail because we always want to look at the AP object
+        Err(Error::NotFound)
+    }
+
+    async fn from_activity(_: &mut Connection, object: Value) -> Result<Self> {
+        Ok(RemoteNote {
+            object,
+        })
+    }
+
+    fn get_sender() -> &'static dyn Signer {
+        Instance::get_local_instance_user().expect("Failed to local instance user")
+    }
+}
+
+impl AsObject<User, Create, &mut Connection> for RemoteNote {
+    type Error = Error;
+    type Output = InboxResult;
+
+    async fn activity(self, conn: &mut Connection, actor: User, _id: &str) -> Result<InboxResult> {
+        if !CONFIG.ingest_notes {
+            return Ok(InboxResult::Other);
+        }
+        match self.ingest(conn, &actor).await? {
+            Some(post) => Ok(InboxResult::Post(post)),
+            None => {
+                debug!("Ignored {} from {}", self.object["id"], actor.ap_url);
+                Ok(InboxResult::Other)
+            }
+        }
+    }
+}
+
+impl AsObject<User, Update, &mut Connection> for RemoteNote {
+    type Error = Error;
+    type Output = ();
+
+    async fn activity(self, conn: &mut Connection, actor: User, _id: &str) -> Result<()> {
+        self.update(conn, &actor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        follows::{Follow, NewFollow},
+        inbox::tests::fill_database,
+        newsletters::NewsletterSubscription,
+        schema::users,
+        tests::db,
+    };
+    use diesel::{Connection, ExpressionMethods, RunQueryDsl};
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    #[test]
+    fn ingest() {
+        let conn = &db();
+        conn.test_transaction::<_, Error, _>(|| {
+            let (_posts, users, _blogs) = fill_database(conn);
+            let remote = Instance::find_by_domain(conn, "1plu.me")?;
+            diesel::update(&users[1])
+                .set((users::instance_id.eq(remote.id), users::ap_url.eq("https://1plu.me/@/user/")))
+                .execute(conn)?;
+            let author = User::get(conn, users[1].id)?;
+            let note = |id: &str, extra: Value| {
+                let mut note = json!({
+                    "id": format!("https://1plu.me/notes/{}", id),
+                    "type": "Note",
+                    "attributedTo": author.ap_url,
+                    "content": "<p>Hello from a microblog, with a text that is long enough to need a shorter title than the whole note</p>",
+                    "attachment": [{ "type": "Document", "mediaType": "image/png", "url": "https://1plu.me/a.png" }],
+                    "to": [PUBLIC_VISIBILITY],
+                });
+                for (key, value) in extra.as_object().unwrap() {
+                    note[key] = value.clone();
+                }
+                RemoteNote { object: note }
+            };
+
+            // A blog of the instance of the author, with the same name
+            let group = Blog::insert(
+                conn,
+                NewBlog {
+                    actor_id: author.username.clone(),
+                    title: "A group".into(),
+                    summary: String::new(),
+                    outbox_url: "https://1plu.me/~/group/outbox".into(),
+                    inbox_url: "https://1plu.me/~/group/inbox".into(),
+                    instance_id: remote.id,
+                    ap_url: "https://1plu.me/~/group/".into(),
+                    private_key: None,
+                    public_key: String::new(),
+                    summary_html: SafeString::new(""),
+                    icon_id: None,
+                    banner_id: None,
+                    theme: None,
+                },
+            )?;
+
+            // Nobody follows the author yet
+            assert!(block_on(note("1", json!({})).ingest(conn, &author))?.is_none());
+            Follow::insert(
+                conn,
+                NewFollow {
+                    follower_id: users[0].id,
+                    following_id: author.id,
+                    ap_url: "https://plu.me/follows/1".into(),
+                },
+            )?;
+
+            let post = block_on(note("1", json!({})).ingest(conn, &author))?.unwrap();
+            assert_eq!(post.title, "Hello from a microblog, with a text that is long enough to need a shorter title…");
+            assert_eq!(post.slug, "1");
+            assert!(post.content.get().contains("<img src=\"https://1plu.me/a.png\""));
+            assert_eq!(post.get_authors(conn)?[0].id, author.id);
+            let blog = post.get_blog(conn)?;
+            assert_eq!(blog.ap_url, format!("{}#notes", author.ap_url));
+            assert_ne!(blog.fqn, group.fqn);
+            assert_eq!(block_on(Blog::find_by_fqn(conn, &group.fqn))?.id, group.id);
+            assert!(is_notes_blog(&blog) && !is_notes_blog(&group));
+            assert!(NewsletterSubscription::start(conn, &blog, "reader@example.com", "en").is_err());
+            // The blog is found from the URL of the post, that is not escaped
+            let url = post.url(conn)?;
+            let name = url.split('/').nth(2).unwrap();
+            assert!(!name.contains('%'));
+            assert_eq!(block_on(Blog::find_by_fqn(conn, name))?.id, blog.id);
+            // The blog can't be used to sign anything
+            assert!(blog.public_key.is_empty());
+            // Notes are only saved once
+            assert!(block_on(note("1", json!({})).ingest(conn, &author))?.is_none());
+            // Another object whose id ends the same way gets another slug
+            let same_end = json!({ "id": "https://1plu.me/objects/1" });
+            assert_eq!(block_on(note("1", same_end).ingest(conn, &author))?.unwrap().slug, "1-2");
+
+            // Edits of the notes are shown, edits of notes that are not are ignored
+            note("1", json!({ "content": "<p>Edited</p>", "summary": "CW" })).update(conn, &author)?;
+            let post = Post::get(conn, post.id)?;
+            assert_eq!(post.title, "Edited");
+            assert_eq!(post.subtitle, "CW");
+            note("9", json!({})).update(conn, &author)?;
+            assert!(Post::find_by_ap_url(conn, "https://1plu.me/notes/9").is_err());
+
+            let poll = json!({ "type": "Question", "oneOf": [{ "name": "Yes" }, { "name": "No" }] });
+            let poll = block_on(note("2", poll).ingest(conn, &author))?.unwrap();
+            assert!(poll.content.get().contains("<li>Yes</li>"));
+            assert_eq!(poll.get_blog(conn)?.id, post.get_blog(conn)?.id);
+
+            // Replies, private notes and other kinds of objects are not shown
+            let reply = json!({ "inReplyTo": "https://1plu.me/notes/1" });
+            assert!(block_on(note("3", reply).ingest(conn, &author))?.is_none());
+            let private = json!({ "to": [author.followers_endpoint] });
+            assert!(block_on(note("4", private).ingest(conn, &author))?.is_none());
+            let event = json!({ "type": "Event" });
+            assert!(block_on(note("5", event).ingest(conn, &author))?.is_none());
+
+            // Objects of other people, or of other servers, can't be shown as the notes of the author
+            let unattributed = json!({ "attributedTo": null });
+            assert!(block_on(note("6", unattributed).ingest(conn, &author))?.is_none());
+            let other_author = json!({ "attributedTo": users[0].ap_url });
+            assert!(block_on(note("7", other_author).ingest(conn, &author))?.is_none());
+            let other_server = json!({ "id": "https://plu.me/notes/8" });
+            assert!(block_on(note("8", other_server).ingest(conn, &author))?.is_none());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn slug_of_ids_without_path() {
+        assert_eq!(slug("https://1plu.me/notes/1"), "1");
+        let first = slug("https://1plu.me/?note=1");
+        assert!(first.starts_with("note-"));
+        assert_eq!(first, slug("https://1plu.me/?note=1"));
+        assert_ne!(first, slug("https://1plu.me/?note=2"));
+    }
+}
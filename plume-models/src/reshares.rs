@@ -171,6 +171,7 @@ impl AsObject<User, Undo, &mut Connection> for Reshare {
             if let Ok(notif) = Notification::find(conn, notification_kind::RESHARE, self.id) {
                 diesel::delete(&notif).execute(conn)?;
             }
+            Timeline::reevaluate_counts(conn, &Post::get(conn, self.post_id)?).await?;
 
             Ok(())
         } else {
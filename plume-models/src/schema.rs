@@ -30,6 +30,27 @@ table! {
     }
 }
 
+table! {
+    blog_feed_items (id) {
+        id -> Int4,
+        feed_id -> Int4,
+        post_id -> Int4,
+        guid -> Varchar,
+        url -> Varchar,
+    }
+}
+
+table! {
+    blog_feeds (id) {
+        id -> Int4,
+        blog_id -> Int4,
+        author_id -> Int4,
+        url -> Varchar,
+        last_fetched_at -> Nullable<Timestamp>,
+        last_error -> Nullable<Varchar>,
+    }
+}
+
 table! {
     blogs (id) {
         id -> Int4,
@@ -63,6 +84,17 @@ table! {
         sensitive -> Bool,
         spoiler_text -> Text,
         public_visibility -> Bool,
+        source -> Text,
+    }
+}
+
+table! {
+    comment_edits (id) {
+        id -> Int4,
+        comment_id -> Int4,
+        content -> Text,
+        spoiler_text -> Text,
+        creation_date -> Timestamp,
     }
 }
 
@@ -84,6 +116,17 @@ table! {
     }
 }
 
+table! {
+    email_preferences (id) {
+        id -> Int4,
+        user_id -> Int4,
+        kind -> Varchar,
+        frequency -> Int4,
+        lang -> Varchar,
+        last_sent_date -> Timestamp,
+    }
+}
+
 table! {
     email_signups (id) {
         id -> Int4,
@@ -93,6 +136,14 @@ table! {
     }
 }
 
+table! {
+    email_suppressions (id) {
+        id -> Int4,
+        email -> Varchar,
+        creation_date -> Timestamp,
+    }
+}
+
 table! {
     follows (id) {
         id -> Int4,
@@ -102,6 +153,19 @@ table! {
     }
 }
 
+table! {
+    inbox_jobs (id) {
+        id -> Int4,
+        activity -> Text,
+        actor_id -> Varchar,
+        attempts -> Int4,
+        next_attempt_date -> Timestamp,
+        last_error -> Nullable<Text>,
+        failed -> Bool,
+        creation_date -> Timestamp,
+    }
+}
+
 table! {
     instances (id) {
         id -> Int4,
@@ -171,6 +235,27 @@ table! {
     }
 }
 
+table! {
+    newsletter_subscriptions (id) {
+        id -> Int4,
+        blog_id -> Int4,
+        email -> Varchar,
+        token -> Varchar,
+        confirmed -> Bool,
+        expiration_date -> Timestamp,
+        unsubscribe_token -> Varchar,
+        lang -> Varchar,
+    }
+}
+
+table! {
+    notification_markers (id) {
+        id -> Int4,
+        user_id -> Int4,
+        last_read_id -> Int4,
+    }
+}
+
 table! {
     notifications (id) {
         id -> Int4,
@@ -212,6 +297,33 @@ table! {
         subtitle -> Text,
         source -> Text,
         cover_id -> Nullable<Int4>,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    push_subscriptions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        endpoint -> Varchar,
+        p256dh -> Varchar,
+        auth -> Varchar,
+        lang -> Varchar,
+        creation_date -> Timestamp,
+        last_notification_id -> Int4,
+    }
+}
+
+table! {
+    relays (id) {
+        id -> Int4,
+        actor_id -> Varchar,
+        inbox_url -> Varchar,
+        public_key -> Text,
+        follow_id -> Varchar,
+        follow_object -> Varchar,
+        state -> Int4,
+        creation_date -> Timestamp,
     }
 }
 
@@ -248,6 +360,18 @@ table! {
         user_id -> Nullable<Int4>,
         name -> Varchar,
         query -> Varchar,
+        notify -> Bool,
+        feed_token -> Nullable<Varchar>,
+        uses_counts -> Bool,
+    }
+}
+
+table! {
+    timeline_markers (id) {
+        id -> Int4,
+        user_id -> Int4,
+        timeline_id -> Int4,
+        last_read_date -> Timestamp,
     }
 }
 
@@ -275,6 +399,16 @@ table! {
         role -> Int4,
         preferred_theme -> Nullable<Varchar>,
         hide_custom_css -> Bool,
+        ed25519_private_key -> Nullable<Text>,
+        ed25519_public_key -> Nullable<Text>,
+    }
+}
+
+table! {
+    vapid_keys (id) {
+        id -> Int4,
+        private_key -> Text,
+        public_key -> Varchar,
     }
 }
 
@@ -282,11 +416,17 @@ joinable!(api_tokens -> apps (app_id));
 joinable!(api_tokens -> users (user_id));
 joinable!(blog_authors -> blogs (blog_id));
 joinable!(blog_authors -> users (author_id));
+joinable!(blog_feed_items -> blog_feeds (feed_id));
+joinable!(blog_feed_items -> posts (post_id));
+joinable!(blog_feeds -> blogs (blog_id));
+joinable!(blog_feeds -> users (author_id));
 joinable!(blogs -> instances (instance_id));
+joinable!(comment_edits -> comments (comment_id));
 joinable!(comment_seers -> comments (comment_id));
 joinable!(comment_seers -> users (user_id));
 joinable!(comments -> posts (post_id));
 joinable!(comments -> users (author_id));
+joinable!(email_preferences -> users (user_id));
 joinable!(likes -> posts (post_id));
 joinable!(likes -> users (user_id));
 joinable!(list_elems -> blogs (blog_id));
@@ -296,42 +436,59 @@ joinable!(lists -> users (user_id));
 joinable!(mentions -> comments (comment_id));
 joinable!(mentions -> posts (post_id));
 joinable!(mentions -> users (mentioned_id));
+joinable!(newsletter_subscriptions -> blogs (blog_id));
+joinable!(notification_markers -> users (user_id));
 joinable!(notifications -> users (user_id));
 joinable!(post_authors -> posts (post_id));
 joinable!(post_authors -> users (author_id));
 joinable!(posts -> blogs (blog_id));
 joinable!(posts -> medias (cover_id));
+joinable!(push_subscriptions -> users (user_id));
 joinable!(reshares -> posts (post_id));
 joinable!(reshares -> users (user_id));
 joinable!(tags -> posts (post_id));
 joinable!(timeline -> posts (post_id));
 joinable!(timeline -> timeline_definition (timeline_id));
 joinable!(timeline_definition -> users (user_id));
+joinable!(timeline_markers -> timeline_definition (timeline_id));
+joinable!(timeline_markers -> users (user_id));
 joinable!(users -> instances (instance_id));
 
 allow_tables_to_appear_in_same_query!(
     api_tokens,
     apps,
     blog_authors,
+    blog_feed_items,
+    blog_feeds,
     blogs,
     comments,
+    comment_edits,
     comment_seers,
     email_blocklist,
+    email_preferences,
     email_signups,
+    email_suppressions,
     follows,
+    inbox_jobs,
     instances,
     likes,
     list_elems,
     lists,
     medias,
     mentions,
+    newsletter_subscriptions,
+    notification_markers,
     notifications,
     password_reset_requests,
     post_authors,
     posts,
+    push_subscriptions,
+    relays,
     reshares,
     tags,
     timeline,
     timeline_definition,
+    timeline_markers,
     users,
+    vapid_keys,
 );
@@ -174,6 +174,8 @@ mod tests {
                     summary_html: SafeString::new(""),
                     role: 0,
                     fqn: random_hex(),
+                    ed25519_private_key: None,
+                    ed25519_public_key: None,
                 },
             )
             .unwrap();
@@ -4,24 +4,30 @@ mod searcher;
 mod tokenizer;
 pub use self::query::PlumeQuery as Query;
 pub use self::searcher::*;
-pub use self::tokenizer::TokenizerKind;
+pub use self::tokenizer::{LanguageAnalyzer, TokenizerKind};
 
 #[cfg(test)]
 pub(crate) mod tests {
-    use super::{Query, Searcher};
+    use super::{IndexReport, Query, ReindexOptions, Searcher, SearcherError};
     use crate::{
         blogs::tests::fill_database,
         config::SearchTokenizerConfig,
         post_authors::*,
         posts::{NewPost, Post},
         safe_string::SafeString,
+        schema::blogs,
         tests::db,
-        CONFIG,
+        Error, CONFIG,
     };
-    use diesel::Connection;
+    use diesel::{Connection, ExpressionMethods, RunQueryDsl};
     use plume_common::utils::random_hex;
     use std::env::temp_dir;
+    use std::fs::create_dir_all;
     use std::str::FromStr;
+    use tantivy::{
+        schema::{SchemaBuilder, INDEXED, STORED},
+        Index,
+    };
 
     pub(crate) fn get_searcher(tokenizers: &SearchTokenizerConfig) -> Searcher {
         let dir = temp_dir().join(&format!("plume-test-{}", random_hex()));
@@ -119,6 +125,27 @@ pub(crate) mod tests {
         Searcher::open(&dir, &CONFIG.search_tokenizers).unwrap(); //verify it's well created
     }
 
+    #[test]
+    fn outdated_schema() {
+        let dir = temp_dir().join(format!("plume-test-{}", random_hex()));
+        {
+            let mut schema_builder = SchemaBuilder::default();
+            schema_builder.add_i64_field("post_id", STORED | INDEXED);
+            create_dir_all(&dir).unwrap();
+            Index::create_in_dir(&dir, schema_builder.build()).unwrap();
+        }
+        assert!(matches!(
+            Searcher::open(&dir, &CONFIG.search_tokenizers),
+            Err(Error::Search(SearcherError::OutdatedSchemaError))
+        ));
+        {
+            // It still has to be filled
+            let searcher = Searcher::recreate(&dir, &CONFIG.search_tokenizers).unwrap();
+            assert_eq!(searcher.reindex_checkpoint().unwrap().last_post_id, -1);
+        }
+        Searcher::open(&dir, &CONFIG.search_tokenizers).unwrap();
+    }
+
     #[test]
     fn search() {
         let conn = &db();
@@ -173,6 +200,121 @@ pub(crate) mod tests {
         });
     }
 
+    #[test]
+    fn search_stemmed() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let searcher = get_searcher(&CONFIG.search_tokenizers);
+            let blog = &fill_database(conn).1[0];
+
+            let title = random_hex()[..8].to_owned();
+            let post = Post::insert(
+                conn,
+                NewPost {
+                    blog_id: blog.id,
+                    slug: title.clone(),
+                    title,
+                    content: SafeString::new(
+                        "The children were running through the gardens while their parents were quietly reading books.",
+                    ),
+                    published: true,
+                    license: "CC-BY-SA".to_owned(),
+                    ap_url: "".to_owned(),
+                    creation_date: None,
+                    subtitle: "".to_owned(),
+                    source: "".to_owned(),
+                    cover_id: None,
+                },
+            )
+            .unwrap();
+            searcher.add_document(conn, &post).unwrap();
+            searcher.commit();
+
+            assert_eq!(searcher.search_document(conn, Query::from_str("runs").unwrap(), (0, 1))[0].id, post.id);
+            assert_eq!(
+                searcher.search_document(conn, Query::from_str("garden lang:english").unwrap(), (0, 1))[0].id,
+                post.id
+            );
+            assert!(searcher.search_document(conn, Query::from_str("garden lang:french").unwrap(), (0, 1)).is_empty());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn reindex_and_verify() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let searcher = get_searcher(&CONFIG.search_tokenizers);
+            let blog = &fill_database(conn).1[0];
+
+            let posts = (0..3)
+                .map(|_| {
+                    let title = random_hex()[..8].to_owned();
+                    Post::insert(
+                        conn,
+                        NewPost {
+                            blog_id: blog.id,
+                            slug: title.clone(),
+                            title,
+                            content: SafeString::new(""),
+                            published: true,
+                            license: "CC-BY-SA".to_owned(),
+                            ap_url: "".to_owned(),
+                            creation_date: None,
+                            subtitle: "".to_owned(),
+                            source: "".to_owned(),
+                            cover_id: None,
+                        },
+                    )
+                    .unwrap()
+                })
+                .collect::<Vec<_>>();
+
+            let report = searcher.verify(conn).unwrap();
+            assert!(posts.iter().all(|p| report.missing.contains(&p.id)));
+
+            let mut progress = vec![];
+            let options = ReindexOptions {
+                batch_size: 2,
+                ..ReindexOptions::default()
+            };
+            searcher.reindex(conn, &options, |done, total| progress.push((done, total))).unwrap();
+            let total = progress[0].1;
+            assert_eq!(progress.last(), Some(&(total, total)));
+            assert!(searcher.reindex_checkpoint().is_none());
+            assert_eq!(
+                searcher.search_document(conn, Query::from_str(&posts[1].title).unwrap(), (0, 1))[0].id,
+                posts[1].id
+            );
+            assert_eq!(searcher.verify(conn).unwrap(), IndexReport::default());
+
+            // Renaming the blog changes what its posts are indexed with
+            diesel::update(blog).set(blogs::title.eq("Renamed")).execute(conn).unwrap();
+            let report = searcher.verify(conn).unwrap();
+            assert!(posts.iter().all(|p| report.outdated.contains(&p.id)));
+            searcher.reindex(conn, &ReindexOptions::default(), |_, _| {}).unwrap();
+            assert!(searcher.verify(conn).unwrap().is_clean());
+
+            posts[0].delete(conn).unwrap();
+            let report = searcher.verify(conn).unwrap();
+            assert_eq!(report.stale, vec![posts[0].id]);
+
+            // deleted posts are only cleaned up by a full reindexing
+            let options = ReindexOptions {
+                since: Some(posts[2].updated_at),
+                ..ReindexOptions::default()
+            };
+            let mut processed = 0;
+            searcher.reindex(conn, &options, |done, _| processed = done).unwrap();
+            assert!(processed >= 1);
+            assert_eq!(searcher.verify(conn).unwrap().stale, vec![posts[0].id]);
+
+            searcher.reindex(conn, &ReindexOptions::default(), |_, _| {}).unwrap();
+            assert!(searcher.verify(conn).unwrap().is_clean());
+            Ok(())
+        });
+    }
+
     #[cfg(feature = "search-lindera")]
     #[test]
     fn search_japanese() {
@@ -1,7 +1,14 @@
-use crate::search::searcher::Searcher;
+use crate::search::{searcher::Searcher, LanguageAnalyzer};
 use chrono::{naive::NaiveDate, offset::Utc, Datelike};
+use itertools::Itertools;
 use std::{cmp, ops::Bound};
-use tantivy::{query::*, schema::*, Term};
+use tantivy::{
+    query::*,
+    schema::*,
+    tokenizer::{TextAnalyzer, TokenStream},
+    Term,
+};
+use whatlang::{detect as detect_lang, Lang};
 
 //Generate functions for advanced search
 macro_rules! gen_func {
@@ -102,7 +109,12 @@ macro_rules! gen_display {
 
 // convert PlumeQuery to Tantivy's Query
 macro_rules! gen_to_query {
-    ( $self:ident, $result:ident; normal: $($normal:ident),*; oneoff: $($oneoff:ident),*) => {
+    ( $self:ident, $result:ident, $analyzers:ident; analyzed: $($analyzed:ident),*; normal: $($normal:ident),*; oneoff: $($oneoff:ident),*) => {
+        $(  // text fields, also searched with language analyzers
+            for (occur, token) in $self.$analyzed {
+                $result.push((occur, Self::text_to_query(&token, stringify!($analyzed), &$analyzers)));
+            }
+        )*
         $(  // classic fields
             for (occur, token) in $self.$normal {
                 $result.push((occur, Self::token_to_query(&token, stringify!($normal))));
@@ -151,10 +163,32 @@ impl PlumeQuery {
         self.from_str_req(query.trim())
     }
 
+    /// Whether this query can return anything: it needs at least one criterion that posts must or may fulfill, and
+    /// not only ones they must not
+    pub fn is_valid(&self) -> bool {
+        self.before.is_some()
+            || self.after.is_some()
+            || [
+                &self.text,
+                &self.title,
+                &self.subtitle,
+                &self.content,
+                &self.tag,
+                &self.instance,
+                &self.author,
+                &self.blog,
+                &self.lang,
+                &self.license,
+            ]
+            .iter()
+            .any(|criteria| criteria.iter().any(|(occur, _)| *occur != Occur::MustNot))
+    }
+
     /// Convert this Query to a Tantivy Query
     pub fn into_query(self) -> BooleanQuery {
         let mut result: Vec<(Occur, Box<dyn Query>)> = Vec::new();
-        gen_to_query!(self, result; normal: title, subtitle, content, tag;
+        let analyzers = self.language_analyzers();
+        gen_to_query!(self, result, analyzers; analyzed: title, subtitle, content; normal: tag;
                       oneoff: instance, author, blog, lang, license);
 
         for (occur, token) in self.text {
@@ -163,17 +197,17 @@ impl PlumeQuery {
                 Occur::Must => {
                     // a Must mean this must be in one of title subtitle or content, not in all 3
                     let subresult = vec![
-                        (Occur::Should, Self::token_to_query(&token, "title")),
-                        (Occur::Should, Self::token_to_query(&token, "subtitle")),
-                        (Occur::Should, Self::token_to_query(&token, "content")),
+                        (Occur::Should, Self::text_to_query(&token, "title", &analyzers)),
+                        (Occur::Should, Self::text_to_query(&token, "subtitle", &analyzers)),
+                        (Occur::Should, Self::text_to_query(&token, "content", &analyzers)),
                     ];
 
                     result.push((Occur::Must, Box::new(BooleanQuery::from(subresult))));
                 }
                 occur => {
-                    result.push((occur, Self::token_to_query(&token, "title")));
-                    result.push((occur, Self::token_to_query(&token, "subtitle")));
-                    result.push((occur, Self::token_to_query(&token, "content")));
+                    result.push((occur, Self::text_to_query(&token, "title", &analyzers)));
+                    result.push((occur, Self::text_to_query(&token, "subtitle", &analyzers)));
+                    result.push((occur, Self::text_to_query(&token, "content", &analyzers)));
                 }
             }
         }
@@ -272,6 +306,73 @@ impl PlumeQuery {
         self.from_str_req(query)
     }
 
+    // the language analyzers to search with: the ones of the languages asked with `lang:`,
+    // or of the language of the query if it can be detected, or all of them otherwise
+    fn language_analyzers(&self) -> Vec<LanguageAnalyzer> {
+        let mut analyzers = Vec::new();
+        for (_, lang) in self.lang.iter().filter(|(occur, _)| *occur != Occur::MustNot) {
+            let lang = Lang::all().iter().find(|l| {
+                l.name().eq_ignore_ascii_case(lang)
+                    || l.eng_name().eq_ignore_ascii_case(lang)
+                    || l.code().eq_ignore_ascii_case(lang)
+            });
+            if let Some(analyzer) = lang.and_then(|l| LanguageAnalyzer::for_lang(*l)) {
+                if !analyzers.contains(&analyzer) {
+                    analyzers.push(analyzer);
+                }
+            }
+        }
+        if !analyzers.is_empty() {
+            return analyzers;
+        }
+
+        let text = self
+            .text
+            .iter()
+            .chain(&self.title)
+            .chain(&self.subtitle)
+            .chain(&self.content)
+            .filter(|(occur, _)| *occur != Occur::MustNot)
+            .map(|(_, token)| token)
+            .join(" ");
+        detect_lang(&text)
+            .filter(|info| info.is_reliable())
+            .and_then(|info| LanguageAnalyzer::for_lang(info.lang()))
+            .map(|analyzer| vec![analyzer])
+            .unwrap_or_else(LanguageAnalyzer::all)
+    }
+
+    // map a token of a text field to a query on this field and on its language specific versions
+    fn text_to_query(token: &str, field_name: &str, analyzers: &[LanguageAnalyzer]) -> Box<dyn Query> {
+        let mut subresult = vec![(Occur::Should, Self::token_to_query(token, field_name))];
+        for analyzer in analyzers {
+            let field = Searcher::schema().get_field(&analyzer.field_name(field_name)).unwrap();
+            let mut terms = Vec::new();
+            let mut text_analyzer = TextAnalyzer::from(*analyzer);
+            let mut stream = text_analyzer.token_stream(token);
+            while let Some(analyzed) = stream.next() {
+                terms.push(Term::from_field_text(field, &analyzed.text));
+            }
+
+            let query: Box<dyn Query> = match terms.len() {
+                0 => continue,
+                1 => Box::new(TermQuery::new(terms.remove(0), IndexRecordOption::WithFreqsAndPositions)),
+                // n-grams have no meaningful positions, all of them have to be found instead
+                _ if analyzer.is_ngram() => Box::new(BooleanQuery::from(
+                    terms
+                        .into_iter()
+                        .map(|term| {
+                            (Occur::Must, Box::new(TermQuery::new(term, IndexRecordOption::Basic)) as Box<dyn Query>)
+                        })
+                        .collect::<Vec<_>>(),
+                )),
+                _ => Box::new(PhraseQuery::new(terms)),
+            };
+            subresult.push((Occur::Should, query));
+        }
+        Box::new(BooleanQuery::from(subresult))
+    }
+
     // map a token and it's field to a query
     fn token_to_query(token: &str, field_name: &str) -> Box<dyn Query> {
         let token = token.to_lowercase();
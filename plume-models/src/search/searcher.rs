@@ -1,20 +1,35 @@
 use crate::{
-    config::SearchTokenizerConfig, instance::Instance, posts::Post, schema::posts, search::query::PlumeQuery,
-    tags::Tag, Connection, Error, Result,
+    blogs::Blog,
+    config::SearchTokenizerConfig,
+    instance::Instance,
+    posts::Post,
+    schema::posts,
+    search::{query::PlumeQuery, LanguageAnalyzer},
+    tags::Tag,
+    Connection, Error, Result,
 };
-use chrono::{Datelike, Utc};
+use chrono::{Datelike, NaiveDateTime, Utc};
 use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
 use itertools::Itertools;
-use std::collections::{hash_map::Entry, HashMap};
+use once_cell::sync::OnceCell;
+use openssl::sha::sha256;
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 use std::fs;
 use std::{cmp, fs::create_dir_all, io, path::Path, sync::Mutex};
 use tantivy::{
-    collector::TopDocs, directory::MmapDirectory, error::TantivyError, schema::*, Index, IndexReader, IndexWriter,
-    ReloadPolicy, Term,
+    collector::{Count, DocSetCollector, TopDocs},
+    directory::MmapDirectory,
+    error::TantivyError,
+    query::AllQuery,
+    schema::*,
+    Index, IndexReader, IndexWriter, ReloadPolicy, SingleSegmentIndexWriter, Term,
 };
 use tracing::warn;
 use whatlang::{detect as detect_lang, Lang};
 
+/// The memory the writer of the index of a single post can use. Such an index is tiny, no need for the usual 15MB.
+const POST_INDEX_MEMORY: usize = 1_000_000;
+
 #[derive(Debug)]
 pub enum SearcherError {
     IndexCreationError,
@@ -22,9 +37,59 @@ pub enum SearcherError {
     IndexOpeningError,
     IndexEditionError,
     InvalidIndexDataError,
+    /// The index was created with an older schema, and has to be filled again
+    OutdatedSchemaError,
     DocumentError,
 }
 
+/// Where an interrupted reindexing stopped.
+///
+/// It is saved as the payload of each intermediate commit, so that it always
+/// matches what is actually in the index.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ReindexCheckpoint {
+    pub last_post_id: i32,
+    pub since: Option<NaiveDateTime>,
+}
+
+pub struct ReindexOptions {
+    /// Only reindex posts modified after this date
+    pub since: Option<NaiveDateTime>,
+    /// Number of posts indexed between two commits
+    pub batch_size: i64,
+    /// Continue from the checkpoint of an interrupted reindexing, if there is one
+    pub resume: bool,
+}
+
+impl Default for ReindexOptions {
+    fn default() -> Self {
+        ReindexOptions {
+            since: None,
+            batch_size: 1000,
+            resume: true,
+        }
+    }
+}
+
+/// Differences between the search index and the database.
+#[derive(Debug, Default, PartialEq)]
+pub struct IndexReport {
+    /// Published posts that are not in the index
+    pub missing: Vec<i32>,
+    /// Indexed posts that were deleted or unpublished
+    pub stale: Vec<i32>,
+    /// Posts indexed more than once
+    pub duplicated: Vec<i32>,
+    /// Posts indexed with values that changed since, like their tags or the title of their blog
+    pub outdated: Vec<i32>,
+}
+
+impl IndexReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.stale.is_empty() && self.duplicated.is_empty() && self.outdated.is_empty()
+    }
+}
+
 pub struct Searcher {
     index: Index,
     reader: IndexReader,
@@ -53,6 +118,8 @@ impl Searcher {
 
         schema_builder.add_i64_field("post_id", STORED | INDEXED);
         schema_builder.add_i64_field("creation_date", INDEXED);
+        // A hash of everything else that is indexed, to find the outdated documents
+        schema_builder.add_text_field("fingerprint", STORED);
 
         schema_builder.add_text_field("instance", tag_indexing.clone());
         schema_builder.add_text_field("author", tag_indexing.clone());
@@ -66,25 +133,142 @@ impl Searcher {
         schema_builder.add_text_field("lang", property_indexing.clone());
         schema_builder.add_text_field("license", property_indexing);
 
+        for analyzer in LanguageAnalyzer::all() {
+            let language_indexing = TextOptions::default().set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(&analyzer.tokenizer_name())
+                    .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+            );
+            for field in &["content", "subtitle", "title"] {
+                schema_builder.add_text_field(&analyzer.field_name(field), language_indexing.clone());
+            }
+        }
+
         schema_builder.build()
     }
 
-    pub fn open_or_recreate(path: &dyn AsRef<Path>, tokenizers: &SearchTokenizerConfig) -> Self {
-        let mut open_searcher = Self::open(path, tokenizers);
-        if let Err(Error::Search(SearcherError::InvalidIndexDataError)) = open_searcher {
-            if Self::create(path, tokenizers).is_err() {
-                let backup_path = format!("{}.{}", path.as_ref().display(), Utc::now().timestamp());
-                let backup_path = Path::new(&backup_path);
-                fs::rename(path, backup_path).expect("main: error on backing up search index directory for recreating");
-                if Self::create(path, tokenizers).is_ok() {
-                    if fs::remove_dir_all(backup_path).is_err() {
-                        warn!("error on removing backup directory: {}. it remains", backup_path.display());
-                    }
-                } else {
-                    panic!("main: error on recreating search index in new index format. remove search index and run `plm search init` manually");
+    fn register_tokenizers(index: &Index, tokenizers: &SearchTokenizerConfig) {
+        let tokenizer_manager = index.tokenizers();
+        tokenizer_manager.register("tag_tokenizer", tokenizers.tag_tokenizer);
+        tokenizer_manager.register("content_tokenizer", tokenizers.content_tokenizer);
+        tokenizer_manager.register("property_tokenizer", tokenizers.property_tokenizer);
+        for analyzer in LanguageAnalyzer::all() {
+            tokenizer_manager.register(&analyzer.tokenizer_name(), analyzer);
+        }
+    }
+
+    /// Builds the document indexed for `post`. `blog` and `instance_domain` are passed in
+    /// so that callers indexing many posts can cache them.
+    fn post_document(
+        conn: &mut Connection,
+        schema: &Schema,
+        post: &Post,
+        blog: Blog,
+        instance_domain: String,
+    ) -> Result<TantivyDocument> {
+        let post_id = schema.get_field("post_id").unwrap();
+        let creation_date = schema.get_field("creation_date").unwrap();
+
+        let instance = schema.get_field("instance").unwrap();
+        let author = schema.get_field("author").unwrap();
+        let tag = schema.get_field("tag").unwrap();
+
+        let blog_name = schema.get_field("blog").unwrap();
+        let content = schema.get_field("content").unwrap();
+        let subtitle = schema.get_field("subtitle").unwrap();
+        let title = schema.get_field("title").unwrap();
+
+        let lang = schema.get_field("lang").unwrap();
+        let license = schema.get_field("license").unwrap();
+
+        let detected_lang = detect_lang(post.content.get()).and_then(|i| {
+            if i.is_reliable() {
+                Some(i.lang())
+            } else {
+                None
+            }
+        });
+        let mut document = doc!(
+            post_id => i64::from(post.id),
+            author => post.get_authors(conn)?.into_iter().map(|u| u.fqn).join(" "),
+            creation_date => i64::from(post.creation_date.num_days_from_ce()),
+            instance => instance_domain,
+            tag => Tag::for_post(conn, post.id)?.into_iter().map(|t| t.tag).join(" "),
+            blog_name => blog.title,
+            content => post.content.get().clone(),
+            subtitle => post.subtitle.clone(),
+            title => post.title.clone(),
+            lang => detected_lang.unwrap_or(Lang::Eng).name(),
+            license => post.license.clone(),
+        );
+
+        if let Some(analyzer) = detected_lang.and_then(LanguageAnalyzer::for_lang) {
+            document.add_text(schema.get_field(&analyzer.field_name("content")).unwrap(), post.content.get());
+            document.add_text(schema.get_field(&analyzer.field_name("subtitle")).unwrap(), &post.subtitle);
+            document.add_text(schema.get_field(&analyzer.field_name("title")).unwrap(), &post.title);
+        }
+
+        let fingerprint =
+            sha256(document.to_json(schema).as_bytes()).iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        document.add_text(schema.get_field("fingerprint").unwrap(), fingerprint);
+        Ok(document)
+    }
+
+    /// Builds the document indexed for `post`, caching the domains of the instances in `instance_cache`.
+    fn cached_post_document(
+        conn: &mut Connection,
+        schema: &Schema,
+        post: &Post,
+        instance_cache: &mut HashMap<i32, String>,
+    ) -> Result<TantivyDocument> {
+        // TODO we could joins to reduce per-post sql calls even more
+        let blog = post.get_blog(conn)?;
+        if let Entry::Vacant(e) = instance_cache.entry(blog.instance_id) {
+            e.insert(Instance::get(conn, blog.instance_id)?.public_domain);
+        }
+        let instance_domain = instance_cache[&blog.instance_id].clone();
+        Self::post_document(conn, schema, post, blog, instance_domain)
+    }
+
+    /// Replaces the index at `path` with an empty one.
+    ///
+    /// It is left with a checkpoint from before the first post, so that the next reindexing fills it completely.
+    pub fn recreate(path: &dyn AsRef<Path>, tokenizers: &SearchTokenizerConfig) -> Result<Self> {
+        if Self::create(path, tokenizers).is_err() {
+            let backup_path = format!("{}.{}", path.as_ref().display(), Utc::now().timestamp());
+            let backup_path = Path::new(&backup_path);
+            fs::rename(path, backup_path).expect("main: error on backing up search index directory for recreating");
+            if Self::create(path, tokenizers).is_ok() {
+                if fs::remove_dir_all(backup_path).is_err() {
+                    warn!("error on removing backup directory: {}. it remains", backup_path.display());
                 }
+            } else {
+                panic!("main: error on recreating search index in new index format. remove search index and run `plm search init` manually");
             }
-            open_searcher = Self::open(path, tokenizers);
+        }
+        let searcher = Self::open(path, tokenizers)?;
+        {
+            let mut writer = searcher.writer.lock().unwrap();
+            let mut commit = writer.as_mut().unwrap().prepare_commit().map_err(|_| SearcherError::IndexEditionError)?;
+            commit.set_payload(&serde_json::to_string(&ReindexCheckpoint {
+                last_post_id: -1,
+                since: None,
+            })?);
+            commit.commit().map_err(|_| SearcherError::IndexEditionError)?;
+        }
+        Ok(searcher)
+    }
+
+    /// Opens the index at `path`, recreating it if it has an older format or schema.
+    ///
+    /// A recreated index has a checkpoint, see [`Searcher::reindex_checkpoint`]: it still has to be filled.
+    pub fn open_or_recreate(path: &dyn AsRef<Path>, tokenizers: &SearchTokenizerConfig) -> Self {
+        let mut open_searcher = Self::open(path, tokenizers);
+        if let Err(Error::Search(SearcherError::InvalidIndexDataError | SearcherError::OutdatedSchemaError)) =
+            open_searcher
+        {
+            open_searcher = Self::recreate(path, tokenizers);
+            warn!("the search index has been recreated, and has to be filled again");
         }
         match open_searcher {
             Ok(s) => s,
@@ -128,12 +312,7 @@ Then try to restart Plume
         )
         .map_err(|_| SearcherError::IndexCreationError)?;
 
-        {
-            let tokenizer_manager = index.tokenizers();
-            tokenizer_manager.register("tag_tokenizer", tokenizers.tag_tokenizer);
-            tokenizer_manager.register("content_tokenizer", tokenizers.content_tokenizer);
-            tokenizer_manager.register("property_tokenizer", tokenizers.property_tokenizer);
-        } //to please the borrow checker
+        Self::register_tokenizers(&index, tokenizers);
         Ok(Self {
             writer: Mutex::new(Some(index.writer(50_000_000).map_err(|_| SearcherError::WriteLockAcquisitionError)?)),
             reader: index
@@ -149,12 +328,13 @@ Then try to restart Plume
         let index = Index::open(MmapDirectory::open(path).map_err(|_| SearcherError::IndexOpeningError)?)
             .map_err(|_| SearcherError::IndexOpeningError)?;
 
-        {
-            let tokenizer_manager = index.tokenizers();
-            tokenizer_manager.register("tag_tokenizer", tokenizers.tag_tokenizer);
-            tokenizer_manager.register("content_tokenizer", tokenizers.content_tokenizer);
-            tokenizer_manager.register("property_tokenizer", tokenizers.property_tokenizer);
-        } //to please the borrow checker
+        // The index was created with an older schema, and needs to be recreated and filled again
+        let index_schema = index.schema();
+        if Self::schema().fields().any(|(_, entry)| index_schema.get_field(entry.name()).is_err()) {
+            return Err(SearcherError::OutdatedSchemaError.into());
+        }
+
+        Self::register_tokenizers(&index, tokenizers);
         let writer = index.writer(50_000_000).map_err(|_| SearcherError::WriteLockAcquisitionError)?;
 
         writer.garbage_collect_files();
@@ -182,42 +362,13 @@ Then try to restart Plume
             return Ok(());
         }
 
-        let schema = self.index.schema();
-
-        let post_id = schema.get_field("post_id").unwrap();
-        let creation_date = schema.get_field("creation_date").unwrap();
-
-        let instance = schema.get_field("instance").unwrap();
-        let author = schema.get_field("author").unwrap();
-        let tag = schema.get_field("tag").unwrap();
-
-        let blog_name = schema.get_field("blog").unwrap();
-        let content = schema.get_field("content").unwrap();
-        let subtitle = schema.get_field("subtitle").unwrap();
-        let title = schema.get_field("title").unwrap();
-
-        let lang = schema.get_field("lang").unwrap();
-        let license = schema.get_field("license").unwrap();
+        let blog = post.get_blog(conn)?;
+        let instance_domain = Instance::get(conn, blog.instance_id)?.public_domain;
+        let document = Self::post_document(conn, &self.index.schema(), post, blog, instance_domain)?;
 
         let mut writer = self.writer.lock().unwrap();
         let writer = writer.as_mut().unwrap();
-
-        writer.add_document(doc!(
-            post_id => i64::from(post.id),
-            author => post.get_authors(conn)?.into_iter().map(|u| u.fqn).join(" "),
-            creation_date => i64::from(post.creation_date.num_days_from_ce()),
-            instance => {
-                let post = post.get_blog(conn)?;
-                Instance::get(conn, post.instance_id)?.public_domain
-            },
-            tag => Tag::for_post(conn, post.id)?.into_iter().map(|t| t.tag).join(" "),
-            blog_name => post.get_blog(conn)?.title,
-            content => post.content.get().clone(),
-            subtitle => post.subtitle.clone(),
-            title => post.title.clone(),
-            lang => detect_lang(post.content.get()).and_then(|i| if i.is_reliable() { Some(i.lang()) } else {None} ).unwrap_or(Lang::Eng).name(),
-            license => post.license.clone(),
-        )).map_err(|_| SearcherError::DocumentError)?;
+        writer.add_document(document).map_err(|_| SearcherError::DocumentError)?;
 
         Ok(())
     }
@@ -262,17 +413,6 @@ Then try to restart Plume
         writer.delete_all_documents().unwrap();
 
         let schema = self.index.schema();
-        let post_id = schema.get_field("post_id").unwrap();
-        let creation_date = schema.get_field("creation_date").unwrap();
-        let instance = schema.get_field("instance").unwrap();
-        let author = schema.get_field("author").unwrap();
-        let tag = schema.get_field("tag").unwrap();
-        let blog_name = schema.get_field("blog").unwrap();
-        let content = schema.get_field("content").unwrap();
-        let subtitle = schema.get_field("subtitle").unwrap();
-        let title = schema.get_field("title").unwrap();
-        let lang = schema.get_field("lang").unwrap();
-        let license = schema.get_field("license").unwrap();
 
         let mut instance_cache = HashMap::new();
 
@@ -286,24 +426,8 @@ Then try to restart Plume
                 .limit(PAGE_SIZE)
                 .load::<Post>(conn)?;
             for post in posts.iter() {
-                // TODO we could joins to reduce per-post sql calls even more
-                let blog = post.get_blog(conn)?;
-                if let Entry::Vacant(e) = instance_cache.entry(blog.instance_id) {
-                    e.insert(Instance::get(conn, blog.instance_id)?.public_domain);
-                }
-                writer.add_document(doc!(
-                    post_id => i64::from(post.id),
-                    author => post.get_authors(conn)?.into_iter().map(|u| u.fqn).join(" "),
-                    creation_date => i64::from(post.creation_date.num_days_from_ce()),
-                    instance => instance_cache[&blog.instance_id].clone(),
-                    tag => Tag::for_post(conn, post.id)?.into_iter().map(|t| t.tag).join(" "),
-                    blog_name => blog.title,
-                    content => post.content.get().clone(),
-                    subtitle => post.subtitle.clone(),
-                    title => post.title.clone(),
-                    lang => detect_lang(post.content.get()).and_then(|i| if i.is_reliable() { Some(i.lang()) } else {None} ).unwrap_or(Lang::Eng).name(),
-                    license => post.license.clone(),
-                )).map_err(|_| SearcherError::DocumentError)?;
+                let document = Self::cached_post_document(conn, &schema, post, &mut instance_cache)?;
+                writer.add_document(document).map_err(|_| SearcherError::DocumentError)?;
                 cursor = post.id;
             }
             if posts.len() < PAGE_SIZE as usize {
@@ -312,6 +436,169 @@ Then try to restart Plume
         }
     }
 
+    /// The checkpoint left by an interrupted reindexing, if any.
+    pub fn reindex_checkpoint(&self) -> Option<ReindexCheckpoint> {
+        let payload = self.index.load_metas().ok()?.payload?;
+        serde_json::from_str(&payload).ok()
+    }
+
+    /// Reindexes posts by batches, committing after each of them.
+    ///
+    /// `progress` is called with the number of processed posts and the total number
+    /// of posts to process. If the process is interrupted, the next call resumes
+    /// after the last committed batch (unless `options.resume` is false, or `since`
+    /// changed). A full reindexing also removes deleted posts from the index.
+    pub fn reindex<F: FnMut(i64, i64)>(
+        &self,
+        conn: &mut Connection,
+        options: &ReindexOptions,
+        mut progress: F,
+    ) -> Result<()> {
+        let since = options.since;
+        let batch_size = cmp::max(1, options.batch_size);
+        let mut cursor = match self.reindex_checkpoint() {
+            Some(checkpoint) if options.resume && checkpoint.since == since => checkpoint.last_post_id,
+            _ => -1,
+        };
+
+        let mut total = posts::table.filter(posts::id.gt(cursor)).into_boxed();
+        if let Some(since) = since {
+            total = total.filter(posts::updated_at.ge(since));
+        }
+        let total = total.count().get_result::<i64>(conn)?;
+        let mut done = 0;
+        progress(done, total);
+
+        let schema = self.index.schema();
+        let post_id = schema.get_field("post_id").unwrap();
+        let mut instance_cache = HashMap::new();
+        loop {
+            let mut query =
+                posts::table.filter(posts::id.gt(cursor)).order(posts::id.asc()).limit(batch_size).into_boxed();
+            if let Some(since) = since {
+                query = query.filter(posts::updated_at.ge(since));
+            }
+            let posts = query.load::<Post>(conn)?;
+            let finished = (posts.len() as i64) < batch_size;
+
+            let mut writer = self.writer.lock().unwrap();
+            let writer = writer.as_mut().unwrap();
+            for post in posts.iter() {
+                writer.delete_term(Term::from_field_i64(post_id, i64::from(post.id)));
+                if post.published {
+                    let document = Self::cached_post_document(conn, &schema, post, &mut instance_cache)?;
+                    writer.add_document(document).map_err(|_| SearcherError::DocumentError)?;
+                }
+                cursor = post.id;
+            }
+
+            let mut commit = writer.prepare_commit().map_err(|_| SearcherError::IndexEditionError)?;
+            if !finished {
+                commit.set_payload(&serde_json::to_string(&ReindexCheckpoint {
+                    last_post_id: cursor,
+                    since,
+                })?);
+            }
+            commit.commit().map_err(|_| SearcherError::IndexEditionError)?;
+
+            done += posts.len() as i64;
+            progress(done, total);
+            if finished {
+                break;
+            }
+        }
+        self.reader.reload().map_err(|_| SearcherError::IndexEditionError)?;
+
+        if since.is_none() {
+            // Every post was just indexed again, only the entries of deleted posts can remain
+            let published = posts::table
+                .filter(posts::published.eq(true))
+                .select(posts::id)
+                .load::<i32>(conn)?
+                .into_iter()
+                .collect::<HashSet<_>>();
+            let stale =
+                self.indexed_fingerprints()?.into_keys().filter(|id| !published.contains(id)).collect::<Vec<_>>();
+            if !stale.is_empty() {
+                let mut writer = self.writer.lock().unwrap();
+                let writer = writer.as_mut().unwrap();
+                for id in stale {
+                    writer.delete_term(Term::from_field_i64(post_id, i64::from(id)));
+                }
+                writer.commit().map_err(|_| SearcherError::IndexEditionError)?;
+                self.reader.reload().map_err(|_| SearcherError::IndexEditionError)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares the committed content of the index with the published posts.
+    ///
+    /// Each published post is indexed again in memory, to find the entries that don't match it anymore.
+    pub fn verify(&self, conn: &mut Connection) -> Result<IndexReport> {
+        let schema = self.index.schema();
+        let fingerprint = schema.get_field("fingerprint").unwrap();
+        let indexed = self.indexed_fingerprints()?;
+
+        let mut report = IndexReport::default();
+        let mut published = HashSet::new();
+        let mut instance_cache = HashMap::new();
+        const PAGE_SIZE: i64 = 8192;
+        let mut cursor = -1;
+        loop {
+            let posts = posts::table
+                .filter(posts::published.eq(true))
+                .filter(posts::id.gt(cursor))
+                .order(posts::id.asc())
+                .limit(PAGE_SIZE)
+                .load::<Post>(conn)?;
+            for post in posts.iter() {
+                published.insert(post.id);
+                cursor = post.id;
+                let fingerprints = match indexed.get(&post.id) {
+                    Some(fingerprints) => fingerprints,
+                    None => {
+                        report.missing.push(post.id);
+                        continue;
+                    }
+                };
+                if fingerprints.len() > 1 {
+                    report.duplicated.push(post.id);
+                }
+                let document = Self::cached_post_document(conn, &schema, post, &mut instance_cache)?;
+                let expected = document.get_first(fingerprint).and_then(|v| v.as_str());
+                if fingerprints.iter().any(|f| Some(f.as_str()) != expected) {
+                    report.outdated.push(post.id);
+                }
+            }
+            if posts.len() < PAGE_SIZE as usize {
+                break;
+            }
+        }
+        report.stale = indexed.keys().filter(|id| !published.contains(id)).copied().sorted().collect();
+        Ok(report)
+    }
+
+    /// The fingerprints of the committed entries of each post
+    fn indexed_fingerprints(&self) -> Result<HashMap<i32, Vec<String>>> {
+        let schema = self.index.schema();
+        let post_id = schema.get_field("post_id").unwrap();
+        let fingerprint = schema.get_field("fingerprint").unwrap();
+        self.reader.reload().map_err(|_| SearcherError::InvalidIndexDataError)?;
+        let searcher = self.reader.searcher();
+
+        let mut indexed = HashMap::<i32, Vec<String>>::new();
+        let docs = searcher.search(&AllQuery, &DocSetCollector).map_err(|_| SearcherError::InvalidIndexDataError)?;
+        for address in docs {
+            let doc = searcher.doc::<TantivyDocument>(address).map_err(|_| SearcherError::InvalidIndexDataError)?;
+            if let Some(id) = doc.get_first(post_id).and_then(|v| v.as_i64()) {
+                let fingerprint = doc.get_first(fingerprint).and_then(|v| v.as_str()).unwrap_or_default();
+                indexed.entry(id as i32).or_default().push(fingerprint.to_owned());
+            }
+        }
+        Ok(indexed)
+    }
+
     pub fn commit(&self) {
         let mut writer = self.writer.lock().unwrap();
         writer.as_mut().unwrap().commit().unwrap();
@@ -322,3 +609,67 @@ Then try to restart Plume
         self.writer.lock().unwrap().take();
     }
 }
+
+/// A post indexed alone in memory, to check whether searches would return it.
+///
+/// The index has the same schema and tokenizers as the main one, so this works even before the main index is
+/// committed. It is built the first time a search is checked, and reused for the next ones: one `PostIndex` should
+/// be used for all the searches a post is checked against.
+#[derive(Default)]
+pub struct PostIndex {
+    /// `None` if the post is not published, and can't be found
+    reader: OnceCell<Option<IndexReader>>,
+}
+
+impl PostIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks whether `post` would be returned when searching for `query`
+    pub fn matches(
+        &self,
+        conn: &mut Connection,
+        post: &Post,
+        query: PlumeQuery,
+        tokenizers: &SearchTokenizerConfig,
+    ) -> Result<bool> {
+        let reader = self.reader.get_or_try_init(|| Self::build(conn, post, tokenizers))?;
+        match reader {
+            Some(reader) => {
+                let count = reader
+                    .searcher()
+                    .search(&query.into_query(), &Count)
+                    .map_err(|_| SearcherError::IndexEditionError)?;
+                Ok(count > 0)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn build(conn: &mut Connection, post: &Post, tokenizers: &SearchTokenizerConfig) -> Result<Option<IndexReader>> {
+        if !post.published {
+            return Ok(None);
+        }
+
+        let schema = Searcher::schema();
+        let blog = post.get_blog(conn)?;
+        let instance_domain = Instance::get(conn, blog.instance_id)?.public_domain;
+        let document = Searcher::post_document(conn, &schema, post, blog, instance_domain)?;
+
+        let index = Index::create_in_ram(schema);
+        Searcher::register_tokenizers(&index, tokenizers);
+        let mut writer =
+            SingleSegmentIndexWriter::new(index, POST_INDEX_MEMORY).map_err(|_| SearcherError::IndexCreationError)?;
+        writer.add_document(document).map_err(|_| SearcherError::DocumentError)?;
+        let index = writer.finalize().map_err(|_| SearcherError::IndexEditionError)?;
+
+        Ok(Some(
+            index
+                .reader_builder()
+                .reload_policy(ReloadPolicy::Manual)
+                .try_into()
+                .map_err(|_| SearcherError::IndexOpeningError)?,
+        ))
+    }
+}
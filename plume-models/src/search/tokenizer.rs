@@ -2,6 +2,7 @@
 use lindera_tantivy::tokenizer::LinderaTokenizer;
 use std::str::CharIndices;
 use tantivy::tokenizer::*;
+use whatlang::Lang;
 
 #[derive(Clone, Copy)]
 pub enum TokenizerKind {
@@ -29,6 +30,119 @@ impl From<TokenizerKind> for TextAnalyzer {
     }
 }
 
+/// Language specific analysis of the title, subtitle and content of posts.
+///
+/// These are used in addition to the tokenizers of `SearchTokenizerConfig`: each post
+/// is also indexed in the fields of the analyzer of its detected language, so that an
+/// instance can have posts in many languages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LanguageAnalyzer {
+    /// Words are stemmed, so that "running" matches "run"
+    Stemmed(Language),
+    /// Lindera morphological analysis if available, n-grams otherwise
+    Japanese,
+    /// n-grams, for languages not separating words with spaces
+    Cjk,
+}
+
+impl LanguageAnalyzer {
+    const STEMMED: [(Lang, Language, &'static str); 18] = [
+        (Lang::Ara, Language::Arabic, "ar"),
+        (Lang::Dan, Language::Danish, "da"),
+        (Lang::Nld, Language::Dutch, "nl"),
+        (Lang::Eng, Language::English, "en"),
+        (Lang::Fin, Language::Finnish, "fi"),
+        (Lang::Fra, Language::French, "fr"),
+        (Lang::Deu, Language::German, "de"),
+        (Lang::Ell, Language::Greek, "el"),
+        (Lang::Hun, Language::Hungarian, "hu"),
+        (Lang::Ita, Language::Italian, "it"),
+        (Lang::Nob, Language::Norwegian, "no"),
+        (Lang::Por, Language::Portuguese, "pt"),
+        (Lang::Ron, Language::Romanian, "ro"),
+        (Lang::Rus, Language::Russian, "ru"),
+        (Lang::Spa, Language::Spanish, "es"),
+        (Lang::Swe, Language::Swedish, "sv"),
+        (Lang::Tam, Language::Tamil, "ta"),
+        (Lang::Tur, Language::Turkish, "tr"),
+    ];
+
+    /// All the available analyzers
+    pub fn all() -> Vec<LanguageAnalyzer> {
+        Self::STEMMED
+            .iter()
+            .map(|(_, language, _)| LanguageAnalyzer::Stemmed(*language))
+            .chain(vec![LanguageAnalyzer::Japanese, LanguageAnalyzer::Cjk])
+            .collect()
+    }
+
+    /// The analyzer to use for a detected language, if there is one
+    pub fn for_lang(lang: Lang) -> Option<LanguageAnalyzer> {
+        match lang {
+            Lang::Jpn => Some(LanguageAnalyzer::Japanese),
+            Lang::Cmn | Lang::Kor => Some(LanguageAnalyzer::Cjk),
+            lang => Self::STEMMED
+                .iter()
+                .find(|(l, _, _)| *l == lang)
+                .map(|(_, language, _)| LanguageAnalyzer::Stemmed(*language)),
+        }
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            LanguageAnalyzer::Stemmed(language) => {
+                Self::STEMMED.iter().find(|(_, l, _)| l == language).map(|(_, _, suffix)| *suffix).unwrap()
+            }
+            LanguageAnalyzer::Japanese => "ja",
+            LanguageAnalyzer::Cjk => "cjk",
+        }
+    }
+
+    pub fn tokenizer_name(&self) -> String {
+        format!("lang_{}", self.suffix())
+    }
+
+    /// The name of the field where `field` is indexed with this analyzer
+    pub fn field_name(&self, field: &str) -> String {
+        format!("{}_{}", field, self.suffix())
+    }
+
+    /// Whether tokens are n-grams, and thus have no meaningful positions
+    pub fn is_ngram(&self) -> bool {
+        match self {
+            LanguageAnalyzer::Stemmed(_) => false,
+            #[cfg(feature = "search-lindera")]
+            LanguageAnalyzer::Japanese => false,
+            #[cfg(not(feature = "search-lindera"))]
+            LanguageAnalyzer::Japanese => true,
+            LanguageAnalyzer::Cjk => true,
+        }
+    }
+}
+
+impl From<LanguageAnalyzer> for TextAnalyzer {
+    fn from(analyzer: LanguageAnalyzer) -> TextAnalyzer {
+        match analyzer {
+            LanguageAnalyzer::Stemmed(language) => TextAnalyzer::builder(SimpleTokenizer::default())
+                .filter(RemoveLongFilter::limit(40))
+                .filter(LowerCaser)
+                .filter(Stemmer::new(language))
+                .build(),
+            #[cfg(feature = "search-lindera")]
+            LanguageAnalyzer::Japanese => {
+                TextAnalyzer::builder(LinderaTokenizer::new("decompose", "")).filter(LowerCaser).build()
+            }
+            #[cfg(not(feature = "search-lindera"))]
+            LanguageAnalyzer::Japanese => {
+                TextAnalyzer::builder(NgramTokenizer::new(1, 2, false).unwrap()).filter(LowerCaser).build()
+            }
+            LanguageAnalyzer::Cjk => {
+                TextAnalyzer::builder(NgramTokenizer::new(1, 2, false).unwrap()).filter(LowerCaser).build()
+            }
+        }
+    }
+}
+
 /// Tokenize the text by splitting on whitespaces. Pretty much a copy of Tantivy's SimpleTokenizer,
 /// but not splitting on punctuation
 #[derive(Clone)]
@@ -0,0 +1,161 @@
+use super::{Timeline, TimelineEvent};
+use crate::{
+    db_conn::{DbConn, DbPool},
+    lists::List,
+    ACTOR_SYS, CONFIG, TIMELINE_CHAN,
+};
+use futures::executor::ThreadPool;
+use once_cell::sync::Lazy;
+use riker::actors::{Actor, ActorFactoryArgs, ActorRefFactory, Context, Sender, Subscribe, Tell};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use tracing::error;
+
+/// Where the background reevaluation of a timeline is at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReevaluationProgress {
+    /// The reevaluation didn't start yet
+    Queued,
+    /// `Running(done, total)`: `done` posts out of `total` have been checked
+    Running(i64, i64),
+}
+
+/// The timelines waiting to be reevaluated, and the progress of the ones being reevaluated.
+///
+/// A timeline can be running and queued at the same time, if it changed again during its reevaluation: the task
+/// running it then runs it again, so that a timeline is never reevaluated twice at once.
+#[derive(Default)]
+struct Reevaluations {
+    queued: HashSet<i32>,
+    running: HashMap<i32, (i64, i64)>,
+}
+
+static REEVALUATIONS: Lazy<Mutex<Reevaluations>> = Lazy::new(|| Mutex::new(Reevaluations::default()));
+
+pub(super) fn progress(timeline_id: i32) -> Option<ReevaluationProgress> {
+    let reevaluations = REEVALUATIONS.lock().unwrap();
+    match reevaluations.running.get(&timeline_id) {
+        Some(&(done, total)) => Some(ReevaluationProgress::Running(done, total)),
+        None if reevaluations.queued.contains(&timeline_id) => Some(ReevaluationProgress::Queued),
+        None => None,
+    }
+}
+
+/// Evaluates timelines again when their query, or a list they use, changes
+pub struct TimelineActor {
+    conn: DbPool,
+    pool: ThreadPool,
+}
+
+impl TimelineActor {
+    pub fn init(conn: DbPool) {
+        let actor =
+            ACTOR_SYS.actor_of_args::<TimelineActor, _>("timeline", conn).expect("Failed to initialize timeline actor");
+
+        TIMELINE_CHAN.tell(
+            Subscribe {
+                actor: Box::new(actor),
+                topic: "*".into(),
+            },
+            None,
+        )
+    }
+}
+
+impl Actor for TimelineActor {
+    type Msg = TimelineEvent;
+
+    fn recv(&mut self, _ctx: &Context<Self::Msg>, msg: Self::Msg, _sender: Sender) {
+        use TimelineEvent::*;
+
+        // The events are sent once the changes are committed, see `Timeline::publish_updated` and
+        // `List::publish_updated`
+        let mut conn = match self.conn.get() {
+            Ok(conn) => DbConn(conn),
+            _ => {
+                error!("Failed to get database connection");
+                return;
+            }
+        };
+        let timelines = match msg {
+            TimelineUpdated(id) => Timeline::get(&mut conn, id).map(|timeline| vec![timeline]),
+            ListUpdated(id) => List::get(&mut conn, id).and_then(|list| Timeline::list_using_list(&mut conn, &list)),
+        };
+        let timelines = match timelines {
+            Ok(timelines) => timelines,
+            Err(e) => {
+                error!("Couldn't find the timelines to reevaluate: {:?}", e);
+                return;
+            }
+        };
+
+        // A list is often edited many times in a row: don't queue timelines that are already waiting. They are read
+        // again when their turn comes, so they see all the changes. The ones that are running are queued for the
+        // task running them.
+        let ids = {
+            let mut reevaluations = REEVALUATIONS.lock().unwrap();
+            let Reevaluations {
+                queued,
+                running,
+            } = &mut *reevaluations;
+            timelines
+                .into_iter()
+                .map(|timeline| timeline.id)
+                .filter(|id| queued.insert(*id) && !running.contains_key(id))
+                .collect::<Vec<_>>()
+        };
+        if !ids.is_empty() {
+            self.pool.spawn_ok(reevaluate(ids, conn));
+        }
+    }
+}
+
+impl ActorFactoryArgs<DbPool> for TimelineActor {
+    fn create_args(conn: DbPool) -> Self {
+        let pool: ThreadPool = ThreadPool::new().unwrap();
+        Self {
+            conn,
+            pool,
+        }
+    }
+}
+
+async fn reevaluate(ids: Vec<i32>, mut conn: DbConn) {
+    for id in ids {
+        {
+            let mut reevaluations = REEVALUATIONS.lock().unwrap();
+            reevaluations.queued.remove(&id);
+            reevaluations.running.insert(id, (0, 0));
+        }
+        loop {
+            let res = match Timeline::get(&mut conn, id) {
+                Ok(timeline) => {
+                    timeline
+                        .reevaluate(&mut conn, CONFIG.timeline_reevaluation_window, |done, total| {
+                            REEVALUATIONS.lock().unwrap().running.insert(id, (done, total));
+                        })
+                        .await
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                error!("Couldn't reevaluate timeline {}: {:?}", id, e);
+            }
+
+            // Run it again if it changed while it was reevaluated
+            let again = {
+                let mut reevaluations = REEVALUATIONS.lock().unwrap();
+                if reevaluations.queued.remove(&id) {
+                    reevaluations.running.insert(id, (0, 0));
+                    true
+                } else {
+                    reevaluations.running.remove(&id);
+                    false
+                }
+            };
+            if !again {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use super::Timeline;
+use crate::{
+    posts::Post,
+    schema::{posts, timeline, timeline_markers},
+    Connection, Error, Result,
+};
+use chrono::NaiveDateTime;
+use diesel::{self, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+
+/// Remembers up to where a user read a timeline
+#[derive(Clone, Debug, Queryable, Identifiable)]
+pub struct TimelineMarker {
+    pub id: i32,
+    pub user_id: i32,
+    pub timeline_id: i32,
+    /// The creation date of the most recent post the user read
+    pub last_read_date: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = timeline_markers)]
+struct NewTimelineMarker {
+    user_id: i32,
+    timeline_id: i32,
+    last_read_date: NaiveDateTime,
+}
+
+impl TimelineMarker {
+    insert!(timeline_markers, NewTimelineMarker);
+    get!(timeline_markers);
+
+    pub fn find_for_user(conn: &mut Connection, user_id: i32, timeline: &Timeline) -> Result<Option<Self>> {
+        timeline_markers::table
+            .filter(timeline_markers::user_id.eq(user_id))
+            .filter(timeline_markers::timeline_id.eq(timeline.id))
+            .first(conn)
+            .optional()
+            .map_err(Error::from)
+    }
+
+    /// Marks the posts of `timeline` up to `post` as read by `user_id`.
+    ///
+    /// The marker is created the first time. It never goes back: reading older posts again doesn't make the newer ones
+    /// unread.
+    pub fn advance(conn: &mut Connection, user_id: i32, timeline: &Timeline, post: &Post) -> Result<Self> {
+        match Self::find_for_user(conn, user_id, timeline)? {
+            Some(marker) if marker.last_read_date >= post.creation_date => Ok(marker),
+            Some(marker) => {
+                diesel::update(&marker).set(timeline_markers::last_read_date.eq(post.creation_date)).execute(conn)?;
+                Self::get(conn, marker.id)
+            }
+            None => Self::insert(
+                conn,
+                NewTimelineMarker {
+                    user_id,
+                    timeline_id: timeline.id,
+                    last_read_date: post.creation_date,
+                },
+            ),
+        }
+    }
+
+    /// Counts the posts of the timeline that were published after this marker
+    pub fn count_unread(&self, conn: &mut Connection) -> Result<i64> {
+        timeline::table
+            .filter(timeline::timeline_id.eq(self.timeline_id))
+            .inner_join(posts::table)
+            .filter(posts::creation_date.gt(self.last_read_date))
+            .count()
+            .get_result(conn)
+            .map_err(Error::from)
+    }
+}
@@ -1,16 +1,27 @@
 use crate::{
     lists::List,
+    live::{self, LiveEvent},
+    notifications::{notification_kind, NewNotification, Notification},
     posts::Post,
-    schema::{posts, timeline, timeline_definition},
-    Connection, Error, Result,
+    schema::{likes, posts, reshares, timeline, timeline_definition, users},
+    search::PostIndex,
+    users::User,
+    Connection, Error, Result, TIMELINE_CHAN,
 };
 use diesel::{self, BoolExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl};
+use plume_common::utils::random_hex;
+use riker::actors::{Publish, Tell};
 use std::cmp::Ordering;
+use std::collections::HashSet;
 
+pub mod actor;
+pub mod marker;
 pub(crate) mod query;
 
+pub use self::actor::ReevaluationProgress;
+pub use self::marker::TimelineMarker;
 pub use self::query::Kind;
-pub use self::query::{QueryError, TimelineQuery};
+pub use self::query::{Explanation, QueryError, TimelineQuery};
 
 #[derive(Clone, Debug, PartialEq, Eq, Queryable, Identifiable, AsChangeset)]
 #[diesel(table_name = timeline_definition)]
@@ -19,6 +30,12 @@ pub struct Timeline {
     pub user_id: Option<i32>,
     pub name: String,
     pub query: String,
+    /// Whether the owner of this timeline gets a notification when a post is added to it
+    pub notify: bool,
+    /// The secret token in the URL of the private feeds of this timeline, if its owner enabled them
+    pub feed_token: Option<String>,
+    /// Whether the query depends on the number of likes, reshares or comments of the posts
+    pub uses_counts: bool,
 }
 
 #[derive(Default, Insertable)]
@@ -27,6 +44,8 @@ pub struct NewTimeline {
     user_id: Option<i32>,
     name: String,
     query: String,
+    notify: bool,
+    uses_counts: bool,
 }
 
 #[derive(Default, Insertable)]
@@ -39,6 +58,7 @@ struct TimelineEntry {
 impl Timeline {
     insert!(timeline_definition, NewTimeline);
     get!(timeline_definition);
+    find_by!(timeline_definition, find_by_feed_token, feed_token as &str);
 
     pub fn find_for_user_by_name(conn: &mut Connection, user_id: Option<i32>, name: &str) -> Result<Self> {
         if let Some(user_id) = user_id {
@@ -95,70 +115,152 @@ impl Timeline {
         })
     }
 
+    /// Lists the timelines whose query uses `list`
+    pub fn list_using_list(conn: &mut Connection, list: &List) -> Result<Vec<Self>> {
+        let (name, kind) = (list.name.clone(), list.kind());
+        Ok(Self::list_for_user(conn, list.user_id)?
+            .into_iter()
+            .filter(|timeline| {
+                TimelineQuery::parse(&timeline.query)
+                    .map(|query| query.list_used_lists().contains(&(name.clone(), kind)))
+                    .unwrap_or(false)
+            })
+            .collect())
+    }
+
     pub fn new_for_user(conn: &mut Connection, user_id: i32, name: String, query_string: String) -> Result<Timeline> {
-        {
-            let query = TimelineQuery::parse(&query_string)?; // verify the query is valid
-            if let Some(err) = query.list_used_lists().into_iter().find_map(|(name, kind)| {
-                let list = List::find_for_user_by_name(conn, Some(user_id), &name).map(|l| l.kind() == kind);
-                match list {
-                    Ok(true) => None,
-                    Ok(false) => Some(Error::TimelineQuery(QueryError::RuntimeError(format!(
-                        "list '{}' has the wrong type for this usage",
-                        name
-                    )))),
-                    Err(_) => {
-                        Some(Error::TimelineQuery(QueryError::RuntimeError(format!("list '{}' was not found", name))))
-                    }
-                }
-            }) {
-                return Err(err);
-            }
-        }
+        let query = TimelineQuery::parse(&query_string)?; // verify the query is valid
+        Self::check_lists(conn, Some(user_id), &query)?;
+        Self::insert(
+            conn,
+            NewTimeline {
+                user_id: Some(user_id),
+                name,
+                query: query_string,
+                notify: false,
+                uses_counts: query.uses_counts(),
+            },
+        )
+    }
+
+    /// Creates a personal timeline made of the posts matching a search query.
+    pub fn new_saved_search(
+        conn: &mut Connection,
+        user_id: i32,
+        name: String,
+        search: &str,
+        notify: bool,
+    ) -> Result<Timeline> {
+        let query_string = format!("search {}", query::quote(search));
+        let uses_counts = TimelineQuery::parse(&query_string)?.uses_counts(); // verify the search is valid
         Self::insert(
             conn,
             NewTimeline {
                 user_id: Some(user_id),
                 name,
                 query: query_string,
+                notify,
+                uses_counts,
             },
         )
     }
 
     pub fn new_for_instance(conn: &mut Connection, name: String, query_string: String) -> Result<Timeline> {
-        {
-            let query = TimelineQuery::parse(&query_string)?; // verify the query is valid
-            if let Some(err) = query.list_used_lists().into_iter().find_map(|(name, kind)| {
-                let list = List::find_for_user_by_name(conn, None, &name).map(|l| l.kind() == kind);
-                match list {
-                    Ok(true) => None,
-                    Ok(false) => Some(Error::TimelineQuery(QueryError::RuntimeError(format!(
-                        "list '{}' has the wrong type for this usage",
-                        name
-                    )))),
-                    Err(_) => {
-                        Some(Error::TimelineQuery(QueryError::RuntimeError(format!("list '{}' was not found", name))))
-                    }
-                }
-            }) {
-                return Err(err);
-            }
-        }
+        let query = TimelineQuery::parse(&query_string)?; // verify the query is valid
+        Self::check_lists(conn, None, &query)?;
         Self::insert(
             conn,
             NewTimeline {
                 user_id: None,
                 name,
                 query: query_string,
+                notify: false,
+                uses_counts: query.uses_counts(),
             },
         )
     }
 
+    /// Verifies that the lists used by `query` exist, and have the right type.
+    fn check_lists(conn: &mut Connection, user_id: Option<i32>, query: &TimelineQuery<'_>) -> Result<()> {
+        for (name, kind) in query.list_used_lists() {
+            match List::find_for_user_by_name(conn, user_id, &name) {
+                Ok(list) if list.kind() == kind => {}
+                Ok(list) => {
+                    return Err(QueryError::RuntimeError(format!(
+                        "list '{}' is a list of {:?}s, but a list of {:?}s is expected here",
+                        name,
+                        list.kind(),
+                        kind
+                    ))
+                    .into())
+                }
+                Err(_) => return Err(QueryError::RuntimeError(format!("list '{}' was not found", name)).into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluates `query` against the `count` latest published posts, without saving anything.
+    ///
+    /// Each post comes with the detail of which clauses of the query it matched.
+    pub async fn preview(
+        conn: &mut Connection,
+        user_id: Option<i32>,
+        query: &str,
+        count: i64,
+    ) -> Result<Vec<(Post, Explanation)>> {
+        let parsed = TimelineQuery::parse(query)?;
+        Self::check_lists(conn, user_id, &parsed)?;
+        let timeline = Timeline {
+            id: 0,
+            user_id,
+            name: String::new(),
+            query: query.to_owned(),
+            notify: false,
+            feed_token: None,
+            uses_counts: parsed.uses_counts(),
+        };
+
+        let posts = posts::table
+            .filter(posts::published.eq(true))
+            .order(posts::creation_date.desc())
+            .limit(count)
+            .load::<Post>(conn)?;
+        let mut res = Vec::with_capacity(posts.len());
+        for post in posts {
+            let explanation = parsed.explain(conn, &timeline, &post, &PostIndex::new(), &Kind::Original).await;
+            res.push((post, explanation));
+        }
+        Ok(res)
+    }
+
+    /// Saves this timeline. If its query changed, it is evaluated again in the background.
     pub fn update(&self, conn: &mut Connection) -> Result<Self> {
-        diesel::update(self).set(self).execute(conn)?;
+        let previous = Self::get(conn, self.id)?;
+        let updated = Timeline {
+            uses_counts: TimelineQuery::parse(&self.query)?.uses_counts(),
+            ..self.clone()
+        };
+        diesel::update(self).set(&updated).execute(conn)?;
+        if previous.query != self.query {
+            self.publish_updated();
+        }
         let timeline = Self::get(conn, self.id)?;
         Ok(timeline)
     }
 
+    /// Gives this timeline a new feed token, making the previous private feed URLs invalid
+    pub fn reset_feed_token(&self, conn: &mut Connection) -> Result<Self> {
+        diesel::update(self).set(timeline_definition::feed_token.eq(random_hex())).execute(conn)?;
+        Self::get(conn, self.id)
+    }
+
+    /// Disables the private feeds of this timeline
+    pub fn revoke_feed_token(&self, conn: &mut Connection) -> Result<Self> {
+        diesel::update(self).set(timeline_definition::feed_token.eq(None::<String>)).execute(conn)?;
+        Self::get(conn, self.id)
+    }
+
     pub fn delete(&self, conn: &mut Connection) -> Result<()> {
         diesel::delete(self).execute(conn).map(|_| ()).map_err(Error::from)
     }
@@ -188,12 +290,101 @@ impl Timeline {
             .map_err(Error::from)
     }
 
+    /// Whether `user_id` can read this timeline: instance timelines can be read by anyone, even when not logged in,
+    /// and personal ones only by their owner
+    pub fn is_visible_to(&self, user_id: Option<i32>) -> bool {
+        self.user_id.is_none() || (user_id.is_some() && self.user_id == user_id)
+    }
+
+    /// Counts the posts of this timeline that `user_id` didn't read yet. Nothing is unread in the timelines they never
+    /// opened.
+    pub fn count_unread(&self, conn: &mut Connection, user_id: i32) -> Result<i64> {
+        match TimelineMarker::find_for_user(conn, user_id, self)? {
+            Some(marker) => marker.count_unread(conn),
+            None => Ok(0),
+        }
+    }
+
+    /// Adds `post` to all the timelines it matches.
+    ///
+    /// Likes and reshares also change the counts of the post, so the timelines depending
+    /// on them are evaluated again for the post itself.
     pub async fn add_to_all_timelines(conn: &mut Connection, post: &Post, kind: &Kind) -> Result<()> {
         let timelines = timeline_definition::table.load::<Self>(conn).map_err(Error::from)?;
+        // The post is indexed once for all the saved searches
+        let index = PostIndex::new();
+        let mut notified = HashSet::new();
+        Self::add_to_timelines(conn, timelines, post, &index, kind, &mut notified).await?;
+
+        if *kind != Kind::Original {
+            Self::reevaluate_counts_notifying(conn, post, &index, &mut notified).await?;
+        }
+        Ok(())
+    }
+
+    /// Evaluates the timelines depending on the number of likes, reshares or comments again
+    /// for `post`, after one of these numbers changed. The post is added to the ones it now
+    /// matches, and removed from the ones it doesn't match anymore.
+    pub async fn reevaluate_counts(conn: &mut Connection, post: &Post) -> Result<()> {
+        Self::reevaluate_counts_notifying(conn, post, &PostIndex::new(), &mut HashSet::new()).await
+    }
+
+    async fn reevaluate_counts_notifying(
+        conn: &mut Connection,
+        post: &Post,
+        index: &PostIndex,
+        notified: &mut HashSet<i32>,
+    ) -> Result<()> {
+        let timelines = Self::list_using_counts(conn)?;
+        for t in &timelines {
+            if !t.includes_post(conn, post)? {
+                continue;
+            }
+            let query = TimelineQuery::parse(&t.query)?;
+            if !t.matches_any_kind(conn, &query, post, index).await? {
+                t.remove_post(conn, post)?;
+            }
+        }
+        Self::add_to_timelines(conn, timelines, post, index, &Kind::Original, notified).await
+    }
+
+    fn list_using_counts(conn: &mut Connection) -> Result<Vec<Self>> {
+        timeline_definition::table
+            .filter(timeline_definition::uses_counts.eq(true))
+            .load::<Self>(conn)
+            .map_err(Error::from)
+    }
 
+    async fn add_to_timelines(
+        conn: &mut Connection,
+        timelines: Vec<Self>,
+        post: &Post,
+        index: &PostIndex,
+        kind: &Kind,
+        notified: &mut HashSet<i32>,
+    ) -> Result<()> {
         for t in timelines {
-            if t.matches(conn, post, kind).await? {
+            let query = TimelineQuery::parse(&t.query)?;
+            if query.matches(conn, &t, post, index, kind).await? && !t.includes_post(conn, post)? {
                 t.add_post(conn, post)?;
+                live::publish_after_commit(LiveEvent::TimelineEntry {
+                    timeline_id: t.id,
+                    user_id: t.user_id,
+                    post_id: post.id,
+                });
+                if let Some(user_id) = t.user_id.filter(|_| t.notify) {
+                    // only notify once per post, even if it was added to many timelines
+                    if notified.insert(user_id) && !post.is_author(conn, user_id)? {
+                        Notification::insert(
+                            conn,
+                            NewNotification {
+                                user_id,
+                                kind: notification_kind::TIMELINE.to_string(),
+                                object_id: post.id,
+                            },
+                        )?;
+                    }
+                }
             }
         }
         Ok(())
@@ -213,7 +404,7 @@ impl Timeline {
     }
 
     pub fn remove_post(&self, conn: &mut Connection, post: &Post) -> Result<bool> {
-        if self.includes_post(conn, post)? {
+        if !self.includes_post(conn, post)? {
             return Ok(false);
         }
         diesel::delete(timeline::table.filter(timeline::timeline_id.eq(self.id)).filter(timeline::post_id.eq(post.id)))
@@ -228,10 +419,95 @@ impl Timeline {
 
     pub async fn matches(&self, conn: &mut Connection, post: &Post, kind: &Kind) -> Result<bool> {
         let query = TimelineQuery::parse(&self.query)?;
-        query.matches(conn, self, post, kind).await
+        query.matches(conn, self, post, &PostIndex::new(), kind).await
+    }
+
+    /// Evaluates the query again against the `window` latest published posts, adding the ones
+    /// that now match and removing the ones that don't anymore.
+    ///
+    /// `progress` is called with the number of posts checked so far, and their total.
+    pub async fn reevaluate(
+        &self,
+        conn: &mut Connection,
+        window: i64,
+        mut progress: impl FnMut(i64, i64),
+    ) -> Result<()> {
+        let query = TimelineQuery::parse(&self.query)?;
+        let posts = posts::table
+            .filter(posts::published.eq(true))
+            .order(posts::creation_date.desc())
+            .limit(window)
+            .load::<Post>(conn)?;
+
+        let total = posts.len() as i64;
+        progress(0, total);
+        for (done, post) in posts.iter().enumerate() {
+            if self.matches_any_kind(conn, &query, post, &PostIndex::new()).await? {
+                self.add_post(conn, post)?;
+            } else {
+                self.remove_post(conn, post)?;
+            }
+            progress(done as i64 + 1, total);
+        }
+        Ok(())
     }
 
-    fn includes_post(&self, conn: &mut Connection, post: &Post) -> Result<bool> {
+    /// Whether `post` matches `query` by itself, or because of one of its likes or reshares
+    async fn matches_any_kind(
+        &self,
+        conn: &mut Connection,
+        query: &TimelineQuery<'_>,
+        post: &Post,
+        index: &PostIndex,
+    ) -> Result<bool> {
+        if query.matches(conn, self, post, index, &Kind::Original).await? {
+            return Ok(true);
+        }
+
+        let likers = likes::table
+            .filter(likes::post_id.eq(post.id))
+            .inner_join(users::table)
+            .select(users::all_columns)
+            .load::<User>(conn)?;
+        for user in likers {
+            if query.matches(conn, self, post, index, &Kind::Like(user)).await? {
+                return Ok(true);
+            }
+        }
+
+        let resharers = reshares::table
+            .filter(reshares::post_id.eq(post.id))
+            .inner_join(users::table)
+            .select(users::all_columns)
+            .load::<User>(conn)?;
+        for user in resharers {
+            if query.matches(conn, self, post, index, &Kind::Reshare(user)).await? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The progress of the background reevaluation of this timeline, if there is one
+    pub fn reevaluation_progress(&self) -> Option<ReevaluationProgress> {
+        actor::progress(self.id)
+    }
+
+    /// Asks for this timeline to be evaluated again, once the change is committed
+    fn publish_updated(&self) {
+        let id = self.id;
+        live::after_commit(move || {
+            TIMELINE_CHAN.tell(
+                Publish {
+                    msg: TimelineEvent::TimelineUpdated(id),
+                    topic: "timeline.updated".into(),
+                },
+                None,
+            )
+        });
+    }
+
+    pub fn includes_post(&self, conn: &mut Connection, post: &Post) -> Result<bool> {
         diesel::dsl::select(diesel::dsl::exists(
             timeline::table.filter(timeline::timeline_id.eq(self.id)).filter(timeline::post_id.eq(post.id)),
         ))
@@ -240,12 +516,22 @@ impl Timeline {
     }
 }
 
+/// Changes after which some timelines have to be evaluated again
+#[derive(Clone, Debug)]
+pub enum TimelineEvent {
+    /// The query of the timeline with this id changed
+    TimelineUpdated(i32),
+    /// The content of the list with this id changed
+    ListUpdated(i32),
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         blogs::tests as blogTests,
         follows::*,
+        likes::{Like, NewLike},
         lists::ListType,
         post_authors::{NewPostAuthor, PostAuthor},
         posts::NewPost,
@@ -678,6 +964,287 @@ mod tests {
         });
     }*/
 
+    #[test]
+    fn test_matches_search() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = blogTests::fill_database(conn);
+
+            let gnu_post = Post::insert(
+                conn,
+                NewPost {
+                    blog_id: blogs[0].id,
+                    slug: "slug".to_string(),
+                    title: "About Linux".to_string(),
+                    content: SafeString::new("you must say GNU/Linux, not Linux!!!"),
+                    published: true,
+                    license: "GPL".to_string(),
+                    ap_url: "".to_string(),
+                    creation_date: None,
+                    subtitle: "".to_string(),
+                    source: "you must say GNU/Linux, not Linux!!!".to_string(),
+                    cover_id: None,
+                },
+            )
+            .unwrap();
+
+            let tl =
+                Timeline::new_saved_search(conn, users[0].id, "GNU search".to_owned(), "+gnu -windows", true).unwrap();
+            assert!(tl.notify);
+            assert!(tl.matches(conn, &gnu_post, Kind::Original).unwrap());
+            tl.delete(conn).unwrap();
+            let tl = Timeline::new_saved_search(
+                conn,
+                users[0].id,
+                "Quoted search".to_owned(),
+                r#"title:"about linux""#,
+                false,
+            )
+            .unwrap();
+            assert!(!tl.notify);
+            assert!(tl.matches(conn, &gnu_post, Kind::Original).unwrap());
+            tl.delete(conn).unwrap();
+            let tl = Timeline::new_saved_search(conn, users[0].id, "Windows search".to_owned(), "title:windows", false)
+                .unwrap();
+            assert!(!tl.matches(conn, &gnu_post, Kind::Original).unwrap());
+            tl.delete(conn).unwrap();
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_add_to_all_timelines_notify() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = blogTests::fill_database(conn);
+
+            Timeline::new_saved_search(conn, users[1].id, "GNU search".to_owned(), "gnu", true).unwrap();
+            Timeline::new_for_user(conn, users[1].id, "GNU timeline".to_owned(), "license in [GPL]".to_owned())
+                .unwrap();
+            let before = Notification::find_for_user(conn, &users[1]).unwrap().len();
+
+            let gnu_post = Post::insert(
+                conn,
+                NewPost {
+                    blog_id: blogs[0].id,
+                    slug: "slug".to_string(),
+                    title: "About Linux".to_string(),
+                    content: SafeString::new("you must say GNU/Linux, not Linux!!!"),
+                    published: true,
+                    license: "GPL".to_string(),
+                    ap_url: "".to_string(),
+                    creation_date: None,
+                    subtitle: "".to_string(),
+                    source: "you must say GNU/Linux, not Linux!!!".to_string(),
+                    cover_id: None,
+                },
+            )
+            .unwrap();
+            Timeline::add_to_all_timelines(conn, &gnu_post, Kind::Original).unwrap();
+            // adding it a second time should not notify again
+            Timeline::add_to_all_timelines(conn, &gnu_post, Kind::Original).unwrap();
+
+            let notifs = Notification::find_for_user(conn, &users[1]).unwrap();
+            assert_eq!(notifs.len(), before + 1);
+            assert_eq!(notifs[0].kind, notification_kind::TIMELINE);
+            assert_eq!(notifs[0].object_id, gnu_post.id);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_matches_counts() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = blogTests::fill_database(conn);
+
+            let post = Post::insert(
+                conn,
+                NewPost {
+                    blog_id: blogs[0].id,
+                    slug: "slug".to_string(),
+                    title: "About Linux".to_string(),
+                    content: SafeString::new("<p>you must say GNU/Linux, not Linux!!!</p><img src=\"gnu.png\">"),
+                    published: true,
+                    license: "GPL".to_string(),
+                    ap_url: "".to_string(),
+                    creation_date: None,
+                    subtitle: "".to_string(),
+                    source: "you must say GNU/Linux, not Linux!!!".to_string(),
+                    cover_id: None,
+                },
+            )
+            .unwrap();
+
+            let tl = Timeline::new_for_user(
+                conn,
+                users[0].id,
+                "Popular".to_owned(),
+                "likes >= 1 and word_count > 5 and has_media and published after 2000-01-01".to_owned(),
+            )
+            .unwrap();
+            assert!(!tl.matches(conn, &post, Kind::Original).unwrap());
+            Timeline::reevaluate_counts(conn, &post).unwrap();
+            assert!(!tl.includes_post(conn, &post).unwrap());
+
+            assert!(tl.uses_counts);
+            let like = Like::insert(conn, NewLike::new(&post, &users[1])).unwrap();
+            assert!(tl.matches(conn, &post, Kind::Original).unwrap());
+            Timeline::reevaluate_counts(conn, &post).unwrap();
+            assert!(tl.includes_post(conn, &post).unwrap());
+
+            // The post is removed when it isn't liked anymore
+            diesel::delete(&like).execute(conn).unwrap();
+            Timeline::reevaluate_counts(conn, &post).unwrap();
+            assert!(!tl.includes_post(conn, &post).unwrap());
+
+            let tl = Timeline::new_for_user(
+                conn,
+                users[0].id,
+                "Long".to_owned(),
+                "word_count > 100 or comments > 0 or published before 2000-01-01".to_owned(),
+            )
+            .unwrap();
+            assert!(!tl.matches(conn, &post, Kind::Original).unwrap());
+
+            let tl =
+                Timeline::new_for_user(conn, users[0].id, "Short".to_owned(), "word_count < 10".to_owned()).unwrap();
+            assert!(!tl.uses_counts);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_reevaluate() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = blogTests::fill_database(conn);
+
+            let post = Post::insert(
+                conn,
+                NewPost {
+                    blog_id: blogs[0].id,
+                    slug: "reevaluated".to_string(),
+                    title: "Reevaluated".to_string(),
+                    content: SafeString::new("<p>Hello</p>"),
+                    published: true,
+                    license: "GPL".to_string(),
+                    ap_url: "".to_string(),
+                    creation_date: None,
+                    subtitle: "".to_string(),
+                    source: "Hello".to_string(),
+                    cover_id: None,
+                },
+            )
+            .unwrap();
+
+            let mut tl =
+                Timeline::new_for_user(conn, users[0].id, "Mine".to_owned(), "license in [GPL]".to_owned()).unwrap();
+            assert!(!tl.includes_post(conn, &post).unwrap());
+
+            let mut calls = vec![];
+            tl.reevaluate(conn, 100, |done, total| calls.push((done, total))).unwrap();
+            assert!(tl.includes_post(conn, &post).unwrap());
+            let total = calls[0].1;
+            assert_eq!(calls, (0..=total).map(|done| (done, total)).collect::<Vec<_>>());
+
+            tl.query = "license in [MIT]".to_owned();
+            let tl = tl.update(conn).unwrap();
+            tl.reevaluate(conn, 100, |_, _| ()).unwrap();
+            assert!(!tl.includes_post(conn, &post).unwrap());
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_markers() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, blogs) = blogTests::fill_database(conn);
+
+            let tl = Timeline::new_for_user(conn, users[0].id, "Markers".to_owned(), "all".to_owned()).unwrap();
+            assert!(tl.is_visible_to(Some(users[0].id)));
+            assert!(!tl.is_visible_to(Some(users[1].id)));
+            assert!(!tl.is_visible_to(None));
+            let posts = ["older", "newer"]
+                .iter()
+                .enumerate()
+                .map(|(i, slug)| {
+                    let post = Post::insert(
+                        conn,
+                        NewPost {
+                            blog_id: blogs[0].id,
+                            slug: slug.to_string(),
+                            title: slug.to_string(),
+                            content: SafeString::new(""),
+                            published: true,
+                            license: "GPL".to_string(),
+                            ap_url: "".to_string(),
+                            creation_date: Some(
+                                chrono::NaiveDate::from_ymd_opt(2020, 1, 1 + i as u32)
+                                    .unwrap()
+                                    .and_hms_opt(0, 0, 0)
+                                    .unwrap(),
+                            ),
+                            subtitle: "".to_string(),
+                            source: "".to_string(),
+                            cover_id: None,
+                        },
+                    )
+                    .unwrap();
+                    tl.add_post(conn, &post).unwrap();
+                    post
+                })
+                .collect::<Vec<_>>();
+
+            assert_eq!(tl.count_unread(conn, users[0].id).unwrap(), 0);
+            assert!(TimelineMarker::find_for_user(conn, users[0].id, &tl).unwrap().is_none());
+
+            TimelineMarker::advance(conn, users[0].id, &tl, &posts[0]).unwrap();
+            assert_eq!(tl.count_unread(conn, users[0].id).unwrap(), 1);
+            assert_eq!(tl.count_unread(conn, users[1].id).unwrap(), 0);
+
+            let marker = TimelineMarker::advance(conn, users[0].id, &tl, &posts[1]).unwrap();
+            assert_eq!(marker.last_read_date, posts[1].creation_date);
+            assert_eq!(tl.count_unread(conn, users[0].id).unwrap(), 0);
+
+            // the marker doesn't go back
+            let marker = TimelineMarker::advance(conn, users[0].id, &tl, &posts[0]).unwrap();
+            assert_eq!(marker.last_read_date, posts[1].creation_date);
+            assert_eq!(tl.count_unread(conn, users[0].id).unwrap(), 0);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_feed_token() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let (users, _) = blogTests::fill_database(conn);
+
+            let tl = Timeline::new_for_user(conn, users[0].id, "Private".to_owned(), "all".to_owned()).unwrap();
+            assert!(tl.feed_token.is_none());
+
+            let tl = tl.reset_feed_token(conn).unwrap();
+            let token = tl.feed_token.clone().unwrap();
+            assert_eq!(Timeline::find_by_feed_token(conn, &token).unwrap(), tl);
+
+            let tl = tl.reset_feed_token(conn).unwrap();
+            assert_ne!(tl.feed_token.as_deref(), Some(token.as_str()));
+            assert!(Timeline::find_by_feed_token(conn, &token).is_err());
+
+            let tl = tl.revoke_feed_token(conn).unwrap();
+            assert!(tl.feed_token.is_none());
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn test_matches_keyword() {
         let conn = &db();
@@ -695,7 +1262,7 @@ mod tests {
                     license: "GPL".to_string(),
                     ap_url: "".to_string(),
                     creation_date: None,
-                    subtitle: "Stallman is our god".to_string(),
+                    subtitle: r#"Stallman is "our god""#.to_string(),
                     source: "you must say GNU/Linux, not Linux!!!".to_string(),
                     cover_id: None,
                 },
@@ -735,6 +1302,16 @@ mod tests {
             .unwrap();
             assert!(!tl.matches(conn, &gnu_post, Kind::Original).unwrap());
             tl.delete(conn).unwrap();
+            // Quoted values are compared unescaped
+            let tl = Timeline::new_for_user(
+                conn,
+                users[0].id,
+                "Quoted subtitle".to_owned(),
+                r#"subtitle contains "is \"our god\"""#.to_owned(),
+            )
+            .unwrap();
+            assert!(tl.matches(conn, &gnu_post, Kind::Original).unwrap());
+            tl.delete(conn).unwrap();
 
             let tl = Timeline::new_for_user(
                 conn,
@@ -1,13 +1,17 @@
 use crate::{
     blogs::Blog,
+    comments::Comment,
     lists::{self, ListType},
     posts::Post,
+    search::{PostIndex, Query as SearchQuery},
     tags::Tag,
     timeline::Timeline,
     users::User,
-    Connection, Result,
+    Connection, Result, CONFIG,
 };
+use chrono::NaiveDate;
 use plume_common::activity_pub::inbox::AsActor;
+use std::str::FromStr;
 use whatlang::{self, Lang};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -17,6 +21,18 @@ pub enum QueryError {
     RuntimeError(String),
 }
 
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::SyntaxError(start, len, message) => {
+                write!(f, "{} (at character {} to {})", message, start, start + len)
+            }
+            QueryError::UnexpectedEndOfQuery => write!(f, "Unexpected end of query"),
+            QueryError::RuntimeError(message) => write!(f, "{}", message),
+        }
+    }
+}
+
 pub type QueryResult<T> = std::result::Result<T, QueryError>;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -86,7 +102,7 @@ impl<'a> std::fmt::Display for Token<'a> {
 }
 
 macro_rules! gen_tokenizer {
-    ( ($c:ident,$i:ident), $state:ident, $quote:ident; $([$char:tt, $variant:tt]),*) => {
+    ( ($c:ident,$i:ident), $state:ident, $quote:ident, $escaped:ident; $([$char:tt, $variant:tt]),*) => {
         match $c {
             space if !*$quote && space.is_whitespace() => match $state.take() {
                 Some(v) => vec![v],
@@ -98,20 +114,24 @@ macro_rules! gen_tokenizer {
                     None => vec![Token::$variant($i)],
                 },
             )*
-            '"' => {
+            '"' if !*$escaped => {
                 *$quote = !*$quote;
                 vec![]
             },
-            _ => match $state.take() {
-                Some(Token::Word(b, l, _)) => {
-                    *$state = Some(Token::Word(b, l+1, &""));
-                    vec![]
-                },
-                None => {
-                    *$state = Some(Token::Word($i,1,&""));
-                    vec![]
-                },
-                _ => unreachable!(),
+            c => {
+                // inside quotes, a backslash escapes the next character
+                *$escaped = *$quote && !*$escaped && c == '\\';
+                match $state.take() {
+                    Some(Token::Word(b, l, _)) => {
+                        *$state = Some(Token::Word(b, l+1, &""));
+                        vec![]
+                    },
+                    None => {
+                        *$state = Some(Token::Word($i,1,&""));
+                        vec![]
+                    },
+                    _ => unreachable!(),
+                }
             }
         }
     }
@@ -122,8 +142,8 @@ fn lex(stream: &str) -> Vec<Token<'_>> {
         .chars()
         .chain(" ".chars()) // force a last whitespace to empty scan's state
         .zip(0..)
-        .scan((None, false), |(state, quote), (c, i)| {
-            Some(gen_tokenizer!((c,i), state, quote;
+        .scan((None, false, false), |(state, quote, escaped), (c, i)| {
+            Some(gen_tokenizer!((c,i), state, quote, escaped;
                                 ['(', LParent],  [')', RParent],
                                 ['[', LBracket], [']', RBracket],
                                 [',', Comma]))
@@ -139,6 +159,67 @@ fn lex(stream: &str) -> Vec<Token<'_>> {
         .collect()
 }
 
+/// Quotes a value so that it is read back as a single word by the lexer.
+pub(crate) fn quote(value: &str) -> String {
+    let mut res = String::with_capacity(value.len() + 2);
+    res.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            res.push('\\');
+        }
+        res.push(c);
+    }
+    res.push('"');
+    res
+}
+
+/// Reverts the backslash escaping allowed inside quoted words.
+fn unescape(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => res.extend(chars.next()),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Quotes a word of a query if the lexer would not read it back as is.
+/// `word` is kept escaped, as it is in the query.
+fn display_word(word: &str) -> String {
+    if word.is_empty() || word.chars().any(|c| c.is_whitespace() || "()[],\"\\".contains(c)) {
+        format!("\"{}\"", word)
+    } else {
+        word.to_owned()
+    }
+}
+
+/// Details of how a post was evaluated by a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    /// The clause that was evaluated
+    pub clause: String,
+    /// Whether the post matched this clause, or the reason it could not be evaluated
+    pub result: std::result::Result<bool, String>,
+    /// The explanations of the sub-clauses, if this clause is an `and` or an `or`
+    pub children: Vec<Explanation>,
+}
+
+impl Explanation {
+    pub fn matched(&self) -> bool {
+        self.result == Ok(true)
+    }
+}
+
+fn error_message(err: crate::Error) -> String {
+    match err {
+        crate::Error::TimelineQuery(QueryError::RuntimeError(message)) => message,
+        err => format!("{:?}", err),
+    }
+}
+
 /// Private internals of TimelineQuery
 #[derive(Debug, Clone, PartialEq)]
 enum TQ<'a> {
@@ -149,12 +230,19 @@ enum TQ<'a> {
 
 impl<'a> TQ<'a> {
     #[async_recursion::async_recursion]
-    async fn matches(&self, conn: &mut Connection, timeline: &Timeline, post: &Post, kind: &Kind) -> Result<bool> {
+    async fn matches(
+        &self,
+        conn: &mut Connection,
+        timeline: &Timeline,
+        post: &Post,
+        index: &PostIndex,
+        kind: &Kind,
+    ) -> Result<bool> {
         match self {
             TQ::Or(inner) => {
                 let mut res = false;
                 for e in inner {
-                    res = e.matches(conn, timeline, post, kind).await?;
+                    res = e.matches(conn, timeline, post, index, kind).await?;
                     if res {
                         break;
                     }
@@ -164,14 +252,77 @@ impl<'a> TQ<'a> {
             TQ::And(inner) => {
                 let mut res = true;
                 for e in inner {
-                    res = res && e.matches(conn, timeline, post, kind).await?;
+                    res = res && e.matches(conn, timeline, post, index, kind).await?;
                     if !res {
                         break;
                     }
                 }
                 Ok(res)
             }
-            TQ::Arg(inner, invert) => Ok(inner.matches(conn, timeline, post, kind).await? ^ invert),
+            TQ::Arg(inner, invert) => Ok(inner.matches(conn, timeline, post, index, kind).await? ^ invert),
+        }
+    }
+
+    #[async_recursion::async_recursion]
+    async fn explain(
+        &self,
+        conn: &mut Connection,
+        timeline: &Timeline,
+        post: &Post,
+        index: &PostIndex,
+        kind: &Kind,
+    ) -> Explanation {
+        match self {
+            TQ::Or(inner) | TQ::And(inner) => {
+                let mut children = Vec::with_capacity(inner.len());
+                for e in inner {
+                    children.push(e.explain(conn, timeline, post, index, kind).await);
+                }
+                // unlike `matches`, every sub-clause is evaluated, but the result is the same
+                let decisive = matches!(self, TQ::Or(_));
+                let result = if children.iter().any(|c| c.result == Ok(decisive)) {
+                    Ok(decisive)
+                } else if let Some(Err(e)) = children.iter().map(|c| &c.result).find(|r| r.is_err()) {
+                    Err(e.clone())
+                } else {
+                    Ok(!decisive)
+                };
+                Explanation {
+                    clause: self.to_string(),
+                    result,
+                    children,
+                }
+            }
+            TQ::Arg(inner, invert) => Explanation {
+                clause: self.to_string(),
+                result: inner
+                    .matches(conn, timeline, post, index, kind)
+                    .await
+                    .map(|r| r ^ invert)
+                    .map_err(error_message),
+                children: vec![],
+            },
+        }
+    }
+
+    fn write_tree(&self, out: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        match self {
+            TQ::Or(inner) | TQ::And(inner) => {
+                out.push_str(&format!(
+                    "{}{}\n",
+                    indent,
+                    if let TQ::Or(_) = self {
+                        "or"
+                    } else {
+                        "and"
+                    }
+                ));
+                for e in inner {
+                    e.write_tree(out, depth + 1);
+                }
+            }
+            TQ::Arg(_, _) => out.push_str(&format!("{}{}\n", indent, self)),
         }
     }
 
@@ -180,7 +331,7 @@ impl<'a> TQ<'a> {
             TQ::Or(inner) => inner.iter().flat_map(TQ::list_used_lists).collect(),
             TQ::And(inner) => inner.iter().flat_map(TQ::list_used_lists).collect(),
             TQ::Arg(Arg::In(typ, List::List(name)), _) => vec![(
-                (*name).to_string(),
+                unescape(name),
                 match typ {
                     WithList::Blog => ListType::Blog,
                     WithList::Author {
@@ -194,25 +345,123 @@ impl<'a> TQ<'a> {
             TQ::Arg(_, _) => vec![],
         }
     }
+
+    /// The words of the query naming the list `list`
+    fn list_uses(&self, list: &str) -> Vec<&'a str> {
+        match self {
+            TQ::Or(inner) | TQ::And(inner) => inner.iter().flat_map(|e| e.list_uses(list)).collect(),
+            TQ::Arg(Arg::In(_, List::List(name)), _) if unescape(name) == list => vec![*name],
+            TQ::Arg(_, _) => vec![],
+        }
+    }
+
+    fn uses_counts(&self) -> bool {
+        match self {
+            TQ::Or(inner) | TQ::And(inner) => inner.iter().any(TQ::uses_counts),
+            TQ::Arg(Arg::Count(counter, _, _), _) => *counter != Counter::WordCount,
+            TQ::Arg(_, _) => false,
+        }
+    }
+}
+
+impl<'a> std::fmt::Display for TQ<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TQ::Or(inner) => write!(f, "{}", inner.iter().map(TQ::to_string).collect::<Vec<_>>().join(" or ")),
+            TQ::And(inner) => write!(
+                f,
+                "{}",
+                inner
+                    .iter()
+                    .map(|e| match e {
+                        TQ::Or(_) => format!("({})", e),
+                        e => e.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" and ")
+            ),
+            TQ::Arg(arg, true) => write!(f, "not {}", arg),
+            TQ::Arg(arg, false) => write!(f, "{}", arg),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 enum Arg<'a> {
     In(WithList, List<'a>),
     Contains(WithContains, &'a str),
+    Search(&'a str),
+    Published(Comparison, NaiveDate),
+    Count(Counter, Comparison, i64),
     Boolean(Bool),
 }
 
 impl<'a> Arg<'a> {
-    pub async fn matches(&self, conn: &mut Connection, timeline: &Timeline, post: &Post, kind: &Kind) -> Result<bool> {
+    pub async fn matches(
+        &self,
+        conn: &mut Connection,
+        timeline: &Timeline,
+        post: &Post,
+        index: &PostIndex,
+        kind: &Kind,
+    ) -> Result<bool> {
         match self {
             Arg::In(t, l) => t.matches(conn, timeline, post, l, kind).await,
-            Arg::Contains(t, v) => t.matches(post, v),
+            Arg::Contains(t, v) => t.matches(post, &unescape(v)),
+            Arg::Search(q) => index.matches(conn, post, search_query(q), &CONFIG.search_tokenizers),
+            Arg::Published(cmp, date) => Ok(cmp.compare(post.creation_date.date(), *date)),
+            Arg::Count(counter, cmp, value) => Ok(cmp.compare(counter.count(conn, post)?, *value)),
             Arg::Boolean(t) => t.matches(conn, timeline, post, kind),
         }
     }
 }
 
+impl<'a> std::fmt::Display for Arg<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Arg::In(
+                WithList::Author {
+                    boosts,
+                    likes,
+                },
+                list,
+            ) => {
+                write!(f, "author in {}", list)?;
+                if !boosts {
+                    write!(f, " exclude reshares")?;
+                }
+                if *likes {
+                    write!(f, " include likes")?;
+                }
+                Ok(())
+            }
+            Arg::In(with, list) => write!(f, "{} in {}", with.keyword(), list),
+            Arg::Contains(with, word) => write!(f, "{} contains {}", with.keyword(), display_word(word)),
+            Arg::Search(query) => write!(f, "search \"{}\"", query),
+            Arg::Published(Comparison::Less, date) => write!(f, "published before {}", date.format("%Y-%m-%d")),
+            Arg::Published(_, date) => write!(f, "published after {}", date.format("%Y-%m-%d")),
+            Arg::Count(counter, cmp, value) => write!(f, "{} {} {}", counter.keyword(), cmp.symbol(), value),
+            Arg::Boolean(Bool::Followed {
+                boosts,
+                likes,
+            }) => {
+                write!(f, "followed")?;
+                if !boosts {
+                    write!(f, " exclude reshares")?;
+                }
+                if *likes {
+                    write!(f, " include likes")?;
+                }
+                Ok(())
+            }
+            Arg::Boolean(Bool::HasCover) => write!(f, "has_cover"),
+            Arg::Boolean(Bool::HasMedia) => write!(f, "has_media"),
+            Arg::Boolean(Bool::Local) => write!(f, "local"),
+            Arg::Boolean(Bool::All) => write!(f, "all"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum WithList {
     Blog,
@@ -226,6 +475,18 @@ enum WithList {
 }
 
 impl WithList {
+    fn keyword(&self) -> &'static str {
+        match self {
+            WithList::Blog => "blog",
+            WithList::Author {
+                ..
+            } => "author",
+            WithList::License => "license",
+            WithList::Tags => "tags",
+            WithList::Lang => "lang",
+        }
+    }
+
     pub async fn matches(
         &self,
         conn: &mut Connection,
@@ -236,7 +497,7 @@ impl WithList {
     ) -> Result<bool> {
         match list {
             List::List(name) => {
-                let list = lists::List::find_for_user_by_name(conn, timeline.user_id, name)?;
+                let list = lists::List::find_for_user_by_name(conn, timeline.user_id, &unescape(name))?;
                 match (self, list.kind()) {
                     (WithList::Blog, ListType::Blog) => list.contains_blog(conn, post.blog_id),
                     (
@@ -282,9 +543,11 @@ impl WithList {
                             .name();
                         list.contains_prefix(conn, lang)
                     }
-                    (_, _) => Err(QueryError::RuntimeError(format!(
-                        "The list '{}' is of the wrong type for this usage",
-                        name
+                    (_, kind) => Err(QueryError::RuntimeError(format!(
+                        "The list '{}' is a list of {:?}s, it can't be used with '{} in'",
+                        name,
+                        kind,
+                        self.keyword()
                     ))
                     .into()),
                 }
@@ -365,6 +628,14 @@ enum WithContains {
 }
 
 impl WithContains {
+    fn keyword(&self) -> &'static str {
+        match self {
+            WithContains::Title => "title",
+            WithContains::Subtitle => "subtitle",
+            WithContains::Content => "content",
+        }
+    }
+
     pub fn matches(&self, post: &Post, value: &str) -> Result<bool> {
         match self {
             WithContains::Title => Ok(post.title.contains(value)),
@@ -374,6 +645,97 @@ impl WithContains {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            "=" | "==" => Some(Comparison::Equal),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            ">" => Some(Comparison::Greater),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Equal => "=",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Greater => ">",
+        }
+    }
+
+    pub fn compare<T: PartialOrd>(&self, value: T, reference: T) -> bool {
+        match self {
+            Comparison::Less => value < reference,
+            Comparison::LessOrEqual => value <= reference,
+            Comparison::Equal => value == reference,
+            Comparison::GreaterOrEqual => value >= reference,
+            Comparison::Greater => value > reference,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Counter {
+    Likes,
+    Reshares,
+    Comments,
+    WordCount,
+}
+
+impl Counter {
+    fn keyword(&self) -> &'static str {
+        match self {
+            Counter::Likes => "likes",
+            Counter::Reshares => "reshares",
+            Counter::Comments => "comments",
+            Counter::WordCount => "word_count",
+        }
+    }
+
+    pub fn count(&self, conn: &mut Connection, post: &Post) -> Result<i64> {
+        match self {
+            Counter::Likes => post.count_likes(conn),
+            Counter::Reshares => post.count_reshares(conn),
+            Counter::Comments => Comment::count_for_post(conn, post.id),
+            Counter::WordCount => Ok(word_count(post.content.get()) as i64),
+        }
+    }
+}
+
+/// Counts the words of an HTML text, ignoring tags
+fn word_count(html: &str) -> usize {
+    let mut in_tag = false;
+    let text = html
+        .chars()
+        .map(|c| match c {
+            '<' => {
+                in_tag = true;
+                ' '
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                ' '
+            }
+            _ if in_tag => ' ',
+            c => c,
+        })
+        .collect::<String>();
+    text.split_whitespace().count()
+}
+
 #[derive(Debug, Clone, PartialEq)]
 enum Bool {
     Followed {
@@ -381,6 +743,7 @@ enum Bool {
         likes: bool,
     },
     HasCover,
+    HasMedia,
     Local,
     All,
 }
@@ -418,6 +781,10 @@ impl Bool {
                 }
             }
             Bool::HasCover => Ok(post.cover_id.is_some()),
+            Bool::HasMedia => {
+                let content = post.content.get();
+                Ok(post.cover_id.is_some() || ["<img", "<video", "<audio"].iter().any(|tag| content.contains(tag)))
+            }
             Bool::Local => Ok(post.get_blog(conn)?.is_local() && *kind == Kind::Original),
             Bool::All => Ok(*kind == Kind::Original),
         }
@@ -430,6 +797,17 @@ enum List<'a> {
     Array(Vec<&'a str>),
 }
 
+impl<'a> std::fmt::Display for List<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            List::List(name) => write!(f, "{}", display_word(name)),
+            List::Array(words) => {
+                write!(f, "[{}]", words.iter().map(|w| display_word(w)).collect::<Vec<_>>().join(", "))
+            }
+        }
+    }
+}
+
 fn parse_s<'a, 'b>(mut stream: &'b [Token<'a>]) -> QueryResult<(&'b [Token<'a>], TQ<'a>)> {
     let mut res = Vec::new();
     let (left, token) = parse_a(stream)?;
@@ -568,7 +946,54 @@ fn parse_d<'a, 'b>(mut stream: &'b [Token<'a>]) -> QueryResult<(&'b [Token<'a>],
             (Token::Word(_, _, contains), t) if contains == &"contains" => t.get_error(Token::Word(0, 0, "any word")),
             (t, _) => t.get_error(Token::Word(0, 0, "'contains'")),
         },
-        s @ "followed" | s @ "has_cover" | s @ "local" | s @ "all" => match s {
+        "search" => match stream.get(1).ok_or(QueryError::UnexpectedEndOfQuery)? {
+            t @ Token::Word(_, _, q) => {
+                if search_query(q).is_valid() {
+                    Ok((&stream[2..], Arg::Search(q)))
+                } else {
+                    t.get_error(Token::Word(0, 0, "a search query with at least one criterion to match"))
+                }
+            }
+            t => t.get_error(Token::Word(0, 0, "a search query")),
+        },
+        "published" => {
+            let cmp = match stream.get(1).ok_or(QueryError::UnexpectedEndOfQuery)? {
+                Token::Word(_, _, "after") => Comparison::Greater,
+                Token::Word(_, _, "before") => Comparison::Less,
+                t => return t.get_error(Token::Word(0, 0, "one of 'after' or 'before'")),
+            };
+            match stream.get(2).ok_or(QueryError::UnexpectedEndOfQuery)? {
+                t @ Token::Word(_, _, w) => match NaiveDate::parse_from_str(w, "%Y-%m-%d") {
+                    Ok(date) => Ok((&stream[3..], Arg::Published(cmp, date))),
+                    Err(_) => t.get_error(Token::Word(0, 0, "a date (YYYY-MM-DD)")),
+                },
+                t => t.get_error(Token::Word(0, 0, "a date (YYYY-MM-DD)")),
+            }
+        }
+        s @ "likes" | s @ "reshares" | s @ "comments" | s @ "word_count" => {
+            let counter = match s {
+                "likes" => Counter::Likes,
+                "reshares" => Counter::Reshares,
+                "comments" => Counter::Comments,
+                "word_count" => Counter::WordCount,
+                _ => unreachable!(),
+            };
+            let cmp = match stream.get(1).ok_or(QueryError::UnexpectedEndOfQuery)? {
+                t @ Token::Word(_, _, w) => match Comparison::from_token(w) {
+                    Some(cmp) => cmp,
+                    None => return t.get_error(Token::Word(0, 0, "one of '<', '<=', '=', '>=' or '>'")),
+                },
+                t => return t.get_error(Token::Word(0, 0, "one of '<', '<=', '=', '>=' or '>'")),
+            };
+            match stream.get(2).ok_or(QueryError::UnexpectedEndOfQuery)? {
+                t @ Token::Word(_, _, w) => match w.parse::<i64>() {
+                    Ok(value) => Ok((&stream[3..], Arg::Count(counter, cmp, value))),
+                    Err(_) => t.get_error(Token::Word(0, 0, "a number")),
+                },
+                t => t.get_error(Token::Word(0, 0, "a number")),
+            }
+        }
+        s @ "followed" | s @ "has_cover" | s @ "has_media" | s @ "local" | s @ "all" => match s {
             "followed" => {
                 let mut boosts = true;
                 let mut likes = false;
@@ -596,6 +1021,7 @@ fn parse_d<'a, 'b>(mut stream: &'b [Token<'a>]) -> QueryResult<(&'b [Token<'a>],
                 ))
             }
             "has_cover" => Ok((&stream[1..], Arg::Boolean(Bool::HasCover))),
+            "has_media" => Ok((&stream[1..], Arg::Boolean(Bool::HasMedia))),
             "local" => Ok((&stream[1..], Arg::Boolean(Bool::Local))),
             "all" => Ok((&stream[1..], Arg::Boolean(Bool::All))),
             _ => unreachable!(),
@@ -604,11 +1030,17 @@ fn parse_d<'a, 'b>(mut stream: &'b [Token<'a>]) -> QueryResult<(&'b [Token<'a>],
             0,
             0,
             "one of 'blog', 'author', 'license', 'tags', 'lang', \
-             'title', 'subtitle', 'content', 'followed', 'has_cover', 'local' or 'all'",
+             'title', 'subtitle', 'content', 'search', 'published', 'likes', 'reshares', 'comments', \
+             'word_count', 'followed', 'has_cover', 'has_media', 'local' or 'all'",
         )),
     }
 }
 
+/// The search query written in a `search` clause
+fn search_query(q: &str) -> SearchQuery {
+    SearchQuery::from_str(&unescape(q)).unwrap_or_default()
+}
+
 fn parse_l<'a, 'b>(stream: &'b [Token<'a>]) -> QueryResult<(&'b [Token<'a>], List<'a>)> {
     match stream.first().ok_or(QueryError::UnexpectedEndOfQuery)? {
         Token::LBracket(_) => {
@@ -656,13 +1088,82 @@ impl<'a> TimelineQuery<'a> {
             .map(TimelineQuery)
     }
 
-    pub async fn matches(&self, conn: &mut Connection, timeline: &Timeline, post: &Post, kind: &Kind) -> Result<bool> {
-        self.0.matches(conn, timeline, post, kind).await
+    /// Whether `post` matches this query. `index` is only used for `search` clauses, and should be the same for all
+    /// the queries `post` is checked against.
+    pub async fn matches(
+        &self,
+        conn: &mut Connection,
+        timeline: &Timeline,
+        post: &Post,
+        index: &PostIndex,
+        kind: &Kind,
+    ) -> Result<bool> {
+        self.0.matches(conn, timeline, post, index, kind).await
     }
 
     pub fn list_used_lists(&self) -> Vec<(String, ListType)> {
         self.0.list_used_lists()
     }
+
+    /// Returns `query`, with every use of the list named `old` replaced by `new`.
+    ///
+    /// The query is written again from its tokens, so the spaces between them may change.
+    pub fn rename_list(query: &str, old: &str, new: &str) -> QueryResult<String> {
+        let new = if new.is_empty() || new.chars().any(|c| c.is_whitespace() || "()[],\"\\".contains(c)) {
+            quote(new)
+        } else {
+            new.to_owned()
+        };
+        // Words are slices of the query: the ones naming the list are found by their address
+        let uses = TimelineQuery::parse(query)?.0.list_uses(old);
+
+        let mut res = String::with_capacity(query.len());
+        let mut previous = None;
+        for token in lex(query) {
+            let spaced = !matches!(previous, None | Some(Token::LParent(_)) | Some(Token::LBracket(_)))
+                && !matches!(token, Token::RParent(_) | Token::RBracket(_) | Token::Comma(_));
+            if spaced {
+                res.push(' ');
+            }
+            match token {
+                Token::Word(_, _, w) if uses.iter().any(|name| std::ptr::eq(*name, w)) => res.push_str(&new),
+                Token::Word(_, _, w) => res.push_str(&display_word(w)),
+                t => res.push_str(t.get_text()),
+            }
+            previous = Some(token);
+        }
+        Ok(res)
+    }
+
+    /// Whether matching depends on the number of likes, reshares or comments of a post
+    pub fn uses_counts(&self) -> bool {
+        self.0.uses_counts()
+    }
+
+    /// Evaluates every clause of the query for `post`, instead of stopping at the first decisive one.
+    pub async fn explain(
+        &self,
+        conn: &mut Connection,
+        timeline: &Timeline,
+        post: &Post,
+        index: &PostIndex,
+        kind: &Kind,
+    ) -> Explanation {
+        self.0.explain(conn, timeline, post, index, kind).await
+    }
+
+    /// An indented representation of the parse tree of the query, one clause per line.
+    pub fn dump_tree(&self) -> String {
+        let mut res = String::new();
+        self.0.write_tree(&mut res, 0);
+        res
+    }
+}
+
+impl<'a> std::fmt::Display for TimelineQuery<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
 #[cfg(test)]
@@ -684,6 +1185,21 @@ mod tests {
                 Token::Word(17, 29, "something quoted with , and ["),
             ]
         );
+
+        assert_eq!(
+            lex(r#"search "title:\"a b\" c\\""#),
+            vec![Token::Word(0, 6, "search"), Token::Word(8, 17, r#"title:\"a b\" c\\"#)]
+        );
+    }
+
+    #[test]
+    fn test_quote() {
+        for value in &["simple", "with space", r#"title:"a b" -c"#, r#"back\slash"#] {
+            let quoted = quote(value);
+            let tokens = lex(&quoted);
+            assert_eq!(tokens.len(), 1);
+            assert_eq!(&unescape(tokens[0].get_text()), value);
+        }
     }
 
     #[test]
@@ -764,6 +1280,41 @@ mod tests {
                 TQ::Arg(Arg::Boolean(Bool::All), false),
             ])
         );
+
+        let dates = TimelineQuery::parse(r#"published after 2026-01-01 and published before 2026-02-01"#).unwrap();
+        assert_eq!(
+            dates.0,
+            TQ::And(vec![
+                TQ::Arg(Arg::Published(Comparison::Greater, NaiveDate::from_ymd_opt(2026, 1, 1).unwrap()), false),
+                TQ::Arg(Arg::Published(Comparison::Less, NaiveDate::from_ymd_opt(2026, 2, 1).unwrap()), false),
+            ])
+        );
+
+        let counts = TimelineQuery::parse(
+            r#"likes > 10 or reshares >= 3 or comments = 5 or word_count <= 1000 or word_count < 10 and has_media"#,
+        )
+        .unwrap();
+        assert_eq!(
+            counts.0,
+            TQ::Or(vec![
+                TQ::Arg(Arg::Count(Counter::Likes, Comparison::Greater, 10), false),
+                TQ::Arg(Arg::Count(Counter::Reshares, Comparison::GreaterOrEqual, 3), false),
+                TQ::Arg(Arg::Count(Counter::Comments, Comparison::Equal, 5), false),
+                TQ::Arg(Arg::Count(Counter::WordCount, Comparison::LessOrEqual, 1000), false),
+                TQ::And(vec![
+                    TQ::Arg(Arg::Count(Counter::WordCount, Comparison::Less, 10), false),
+                    TQ::Arg(Arg::Boolean(Bool::HasMedia), false),
+                ]),
+            ])
+        );
+        assert!(counts.uses_counts());
+        assert!(!TimelineQuery::parse("word_count > 10 or published after 2026-01-01").unwrap().uses_counts());
+
+        let search = TimelineQuery::parse(r#"search "rust -title:golang" and not local"#).unwrap();
+        assert_eq!(
+            search.0,
+            TQ::And(vec![TQ::Arg(Arg::Search("rust -title:golang"), false), TQ::Arg(Arg::Boolean(Bool::Local), true)])
+        );
     }
 
     #[test]
@@ -799,8 +1350,8 @@ mod tests {
                 0,
                 11,
                 "Syntax Error: Expected one of 'blog', \
-'author', 'license', 'tags', 'lang', 'title', 'subtitle', 'content', 'followed', 'has_cover', \
-'local' or 'all', got 'not_a_field'"
+'author', 'license', 'tags', 'lang', 'title', 'subtitle', 'content', 'search', 'published', 'likes', \
+'reshares', 'comments', 'word_count', 'followed', 'has_cover', 'has_media', 'local' or 'all', got 'not_a_field'"
                     .to_owned()
             )
         );
@@ -835,10 +1386,105 @@ mod tests {
         let got_bracket = TimelineQuery::parse(r#"lang in []"#).unwrap_err();
         assert_eq!(got_bracket, QueryError::SyntaxError(9, 1, "Syntax Error: Expected any word, got ']'".to_owned()));
 
+        let expect_search_query = TimelineQuery::parse(r#"search ("#).unwrap_err();
+        assert_eq!(
+            expect_search_query,
+            QueryError::SyntaxError(7, 1, "Syntax Error: Expected a search query, got '('".to_owned())
+        );
+
+        let empty_search = TimelineQuery::parse(r#"search "-rust""#).unwrap_err();
+        assert_eq!(
+            empty_search,
+            QueryError::SyntaxError(
+                8,
+                5,
+                "Syntax Error: Expected a search query with at least one criterion to match, got '-rust'".to_owned()
+            )
+        );
+
+        let expect_date = TimelineQuery::parse(r#"published after yesterday"#).unwrap_err();
+        assert_eq!(
+            expect_date,
+            QueryError::SyntaxError(16, 9, "Syntax Error: Expected a date (YYYY-MM-DD), got 'yesterday'".to_owned())
+        );
+
+        let expect_comparison = TimelineQuery::parse(r#"likes after 10"#).unwrap_err();
+        assert_eq!(
+            expect_comparison,
+            QueryError::SyntaxError(
+                6,
+                5,
+                "Syntax Error: Expected one of '<', '<=', '=', '>=' or '>', got 'after'".to_owned()
+            )
+        );
+
+        let expect_number = TimelineQuery::parse(r#"likes > many"#).unwrap_err();
+        assert_eq!(
+            expect_number,
+            QueryError::SyntaxError(8, 4, "Syntax Error: Expected a number, got 'many'".to_owned())
+        );
+
         let got_par = TimelineQuery::parse(r#"lang in [a, ("#).unwrap_err();
         assert_eq!(got_par, QueryError::SyntaxError(12, 1, "Syntax Error: Expected any word, got '('".to_owned()));
     }
 
+    #[test]
+    fn test_display() {
+        for query in &[
+            r#"lang in [fr, en] and (license in my_fav_lic or not followed) or title contains "Plume is amazing""#,
+            r#"author in [a, "b c"] exclude reshares include likes or followed include likes"#,
+            r#"search "title:\"a b\"" and not has_media and has_cover and local or all"#,
+            r#"published after 2026-01-01 and published before 2026-02-01 and likes >= 3 and word_count < 10"#,
+        ] {
+            let q = TimelineQuery::parse(query).unwrap();
+            assert_eq!(&q.to_string(), query);
+            assert_eq!(TimelineQuery::parse(&q.to_string()).unwrap().0, q.0);
+        }
+
+        let q = TimelineQuery::parse(r#"(followed and local) or ((has_cover))"#).unwrap();
+        assert_eq!(q.to_string(), "followed and local or has_cover");
+    }
+
+    #[test]
+    fn test_rename_list() {
+        let q = r#"author in friends or blog in friends or tags in [friends] or lang in others"#;
+        assert_eq!(
+            TimelineQuery::rename_list(q, "friends", "my friends").unwrap(),
+            r#"author in "my friends" or blog in "my friends" or tags in [friends] or lang in others"#
+        );
+        let q = r#"(author in "my friends"  and local) or (blog in "my friends")"#;
+        assert_eq!(
+            TimelineQuery::rename_list(q, "my friends", "friends").unwrap(),
+            r#"(author in friends and local) or (blog in friends)"#
+        );
+
+        // Names are compared and written unescaped
+        let renamed = TimelineQuery::rename_list("blog in friends or tags in [a]", "friends", r#"a"b"#).unwrap();
+        assert_eq!(renamed, r#"blog in "a\"b" or tags in [a]"#);
+        let query = TimelineQuery::parse(&renamed).unwrap();
+        assert_eq!(query.list_used_lists(), vec![(r#"a"b"#.to_owned(), ListType::Blog)]);
+        assert_eq!(
+            TimelineQuery::rename_list(&renamed, r#"a"b"#, "friends").unwrap(),
+            "blog in friends or tags in [a]"
+        );
+    }
+
+    #[test]
+    fn test_dump_tree() {
+        let q = TimelineQuery::parse(r#"lang in [fr, en] and (license in my_fav_lic or not followed) or all"#).unwrap();
+        assert_eq!(
+            q.dump_tree(),
+            "or\n  and\n    lang in [fr, en]\n    or\n      license in my_fav_lic\n      not followed\n  all\n"
+        );
+    }
+
+    #[test]
+    fn test_word_count() {
+        assert_eq!(word_count(""), 0);
+        assert_eq!(word_count("<p>Hello <strong>world</strong></p><p>Second paragraph</p>"), 4);
+        assert_eq!(word_count(r#"<img src="a b c.png"/>"#), 0);
+    }
+
     #[test]
     fn test_list_used_lists() {
         let q = TimelineQuery::parse(r#"lang in [fr, en] and blog in blogs or author in my_fav_authors or tags in hashtag and lang in spoken or license in copyleft"#)
@@ -5,7 +5,7 @@ use crate::{
     ITEMS_PER_PAGE, USER_CHAN,
 };
 use activitystreams::{
-    activity::Delete,
+    activity::{Delete, Update},
     actor::{ApActor, AsApActor, Endpoints, Person},
     base::{AnyBase, Base},
     collection::{OrderedCollection, OrderedCollectionPage},
@@ -30,8 +30,12 @@ use plume_common::{
     activity_pub::{
         inbox::{AsActor, AsObject, FromId},
         request::get,
-        sign::{gen_keypair, Error as SignError, Result as SignResult, Signer},
-        ActivityStream, ApSignature, CustomPerson, Id, IntoId, PublicKey, ToAsString, ToAsUri, PUBLIC_VISIBILITY,
+        sign::{
+            ed25519_to_multibase, gen_ed25519_keypair, gen_keypair, sign_ed25519, verify_ed25519, Algorithm,
+            Error as SignError, Result as SignResult, Signer,
+        },
+        ActivityStream, ApSignature, AssertionMethod, CustomPerson, Id, IntoId, Multikey, PublicKey, ToAsString,
+        ToAsUri, PUBLIC_VISIBILITY,
     },
     utils,
 };
@@ -85,6 +89,10 @@ pub struct User {
     pub role: i32,
     pub preferred_theme: Option<String>,
     pub hide_custom_css: bool,
+    /// The key local users can sign with, along with their RSA key
+    pub ed25519_private_key: Option<String>,
+    /// The Ed25519 key of the user, published in their `assertionMethod`, as PEM
+    pub ed25519_public_key: Option<String>,
 }
 
 #[derive(Default, Insertable)]
@@ -107,6 +115,8 @@ pub struct NewUser {
     pub summary_html: SafeString,
     pub role: i32,
     pub fqn: String,
+    pub ed25519_private_key: Option<String>,
+    pub ed25519_public_key: Option<String>,
 }
 
 pub const AUTH_COOKIE: &str = "user_id";
@@ -277,43 +287,56 @@ impl User {
     }
 
     pub fn refetch(&self, conn: &mut Connection) -> Result<()> {
-        User::fetch(&self.ap_url.clone()).and_then(|json| {
-            let avatar = json
-                .icon()
-                .and_then(|icon| icon.iter().next())
-                .and_then(|i| i.clone().extend::<Image, ImageType>().ok())
-                .and_then(|image| image)
-                .and_then(|image| image.id_unchecked().map(|url| url.to_string()))
-                .and_then(|url| Media::save_remote(conn, url, self).ok());
-
-            let pub_key = &json.ext_one.public_key.public_key_pem;
-            diesel::update(self)
-                .set((
-                    users::username.eq(json.ap_actor_ref().preferred_username().ok_or(Error::MissingApProperty)?),
-                    users::display_name.eq(json
-                        .ap_actor_ref()
-                        .name()
-                        .ok_or(Error::MissingApProperty)?
-                        .to_as_string()
-                        .ok_or(Error::MissingApProperty)?),
-                    users::outbox_url.eq(json.ap_actor_ref().outbox()?.ok_or(Error::MissingApProperty)?.as_str()),
-                    users::inbox_url.eq(json.ap_actor_ref().inbox()?.as_str()),
-                    users::summary.eq(SafeString::new(
-                        &json.ap_actor_ref().summary().and_then(|summary| summary.to_as_string()).unwrap_or_default(),
-                    )),
-                    users::followers_endpoint.eq(json
-                        .ap_actor_ref()
-                        .followers()?
-                        .ok_or(Error::MissingApProperty)?
-                        .as_str()),
-                    users::avatar_id.eq(avatar.map(|a| a.id)),
-                    users::last_fetched_date.eq(Utc::now().naive_utc()),
-                    users::public_key.eq(pub_key),
-                ))
-                .execute(conn)
-                .map(|_| ())
-                .map_err(Error::from)
-        })
+        User::fetch(&self.ap_url.clone()).and_then(|json| self.update_from_activity(conn, &json))
+    }
+
+    /// Updates a remote profile with its ActivityPub representation, fetched or sent in an `Update`
+    fn update_from_activity(&self, conn: &mut Connection, json: &CustomPerson) -> Result<()> {
+        let actor = json.ap_actor_ref();
+        let outbox_url = actor.outbox()?.ok_or(Error::MissingApProperty)?;
+        let inbox_url = actor.inbox()?;
+        let followers_endpoint = actor.followers()?.ok_or(Error::MissingApProperty)?;
+
+        let avatar_url = json
+            .icon()
+            .and_then(|icon| icon.iter().next())
+            .and_then(|i| i.clone().extend::<Image, ImageType>().ok())
+            .and_then(|image| image)
+            .and_then(|image| {
+                image.url().and_then(|url| url.to_as_uri()).or_else(|| image.id_unchecked().map(|url| url.to_string()))
+            });
+        let current_avatar = self.avatar_id.and_then(|id| Media::get(conn, id).ok());
+        let avatar_id = match avatar_url {
+            // The avatar didn't change
+            Some(url) if current_avatar.as_ref().and_then(|a| a.remote_url.as_ref()) == Some(&url) => self.avatar_id,
+            // The current avatar is kept if the new one can't be saved
+            Some(url) => Media::save_remote(conn, url, self).map(|a| a.id).ok().or(self.avatar_id),
+            None => None,
+        };
+
+        let pub_key = &json.ext_one.public_key.public_key_pem;
+        diesel::update(self)
+            .set((
+                users::username.eq(actor.preferred_username().ok_or(Error::MissingApProperty)?),
+                users::display_name.eq(actor
+                    .name()
+                    .ok_or(Error::MissingApProperty)?
+                    .to_as_string()
+                    .ok_or(Error::MissingApProperty)?),
+                users::outbox_url.eq(outbox_url.as_str()),
+                users::inbox_url.eq(inbox_url.as_str()),
+                users::summary.eq(SafeString::new(
+                    &actor.summary().and_then(|summary| summary.to_as_string()).unwrap_or_default(),
+                )),
+                users::followers_endpoint.eq(followers_endpoint.as_str()),
+                users::avatar_id.eq(avatar_id),
+                users::last_fetched_date.eq(Utc::now().naive_utc()),
+                users::public_key.eq(pub_key),
+                users::ed25519_public_key.eq(json.ext_two.ed25519_public_key()),
+            ))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
     }
 
     pub fn hash_pass(pass: &str) -> Result<String> {
@@ -574,6 +597,19 @@ impl User {
         users::table.filter(users::id.eq_any(follows)).count().get_result(conn).map_err(Error::from)
     }
 
+    /// Whether someone on this instance follows this user
+    pub fn has_local_followers(&self, conn: &mut Connection) -> Result<bool> {
+        use crate::schema::follows;
+        let follows = Follow::belonging_to(self).select(follows::follower_id);
+        users::table
+            .filter(users::id.eq_any(follows))
+            .filter(users::instance_id.eq(Instance::get_local()?.id))
+            .count()
+            .get_result::<i64>(conn)
+            .map(|n| n > 0)
+            .map_err(Error::from)
+    }
+
     pub fn get_followers_page(&self, conn: &mut Connection, (min, max): (i32, i32)) -> Result<Vec<User>> {
         use crate::schema::follows;
         let follows = Follow::belonging_to(self).select(follows::follower_id);
@@ -689,6 +725,29 @@ impl User {
         }
     }
 
+    /// Gives an Ed25519 key to the local users that were created before these keys were introduced
+    pub fn generate_missing_ed25519_keys(conn: &mut Connection) -> Result<()> {
+        let users = users::table
+            .filter(users::private_key.is_not_null())
+            .filter(users::ed25519_private_key.is_null())
+            .load::<User>(conn)?;
+        for user in users {
+            let (public_key, private_key) = gen_ed25519_keypair();
+            diesel::update(&user)
+                .set((
+                    users::ed25519_public_key.eq(String::from_utf8(public_key).or(Err(Error::Signature))?),
+                    users::ed25519_private_key.eq(String::from_utf8(private_key).or(Err(Error::Signature))?),
+                ))
+                .execute(conn)?;
+        }
+        Ok(())
+    }
+
+    /// The id of the Ed25519 key of the user, in their `assertionMethod`
+    fn ed25519_key_id(&self) -> String {
+        format!("{}#ed25519-key", self.ap_url)
+    }
+
     pub fn to_activity(&self, conn: &mut Connection) -> Result<CustomPerson> {
         let mut actor = ApActor::new(self.inbox_url.parse()?, Person::new());
         let ap_url = self.ap_url.parse::<IriString>()?;
@@ -724,7 +783,33 @@ impl User {
             actor.set_icon(avatar.into_any_base()?);
         }
 
-        Ok(CustomPerson::new(actor, ap_signature))
+        let assertion_method = AssertionMethod {
+            assertion_method: self
+                .ed25519_public_key
+                .as_ref()
+                .and_then(|key| ed25519_to_multibase(key).ok())
+                .map(|multibase| {
+                    Ok::<_, Error>(vec![Multikey {
+                        id: self.ed25519_key_id().parse()?,
+                        kind: "Multikey".into(),
+                        controller: self.ap_url.parse()?,
+                        public_key_multibase: multibase,
+                    }])
+                })
+                .transpose()?,
+        };
+
+        Ok(CustomPerson::new(actor, ap_signature, assertion_method))
+    }
+
+    /// The `Update` sent to the followers when the profile is edited
+    pub fn update_activity(&self, conn: &mut Connection) -> Result<Update> {
+        let mut act =
+            Update::new(self.ap_url.parse::<IriString>()?, Base::retract(self.to_activity(conn)?)?.into_generic()?);
+        act.set_id(format!("{}#update-{}", self.ap_url, Utc::now().timestamp()).parse::<IriString>()?);
+        act.set_many_tos(vec![PUBLIC_VISIBILITY.parse::<IriString>()?]);
+        act.set_many_ccs(vec![self.followers_endpoint.parse::<IriString>()?]);
+        Ok(act)
     }
 
     pub fn delete_activity(&self, conn: &mut Connection) -> Result<Delete> {
@@ -870,6 +955,7 @@ impl FromId<Connection> for User {
             summary_html: SafeString::new(&summary),
             summary,
             public_key: acct.ext_one.public_key.public_key_pem.to_string(),
+            ed25519_public_key: acct.ext_two.ed25519_public_key(),
             shared_inbox_url: actor.endpoints()?.and_then(|e| e.shared_inbox.as_ref().map(|inbox| inbox.to_string())),
             followers_endpoint: actor.followers()?.ok_or(Error::MissingApProperty)?.to_string(),
             ..NewUser::default()
@@ -954,6 +1040,77 @@ impl AsObject<User, Delete, &mut Connection> for User {
     }
 }
 
+/// The new profile of a remote user, from an `Update` it sent
+pub struct UserUpdate {
+    ap_url: String,
+    actor: CustomPerson,
+}
+
+/// Whether `url` is on the same server as `ap_url`
+fn same_origin(url: &str, ap_url: &str) -> bool {
+    match (url::Url::parse(url), url::Url::parse(ap_url)) {
+        (Ok(url), Ok(ap_url)) => url.origin() == ap_url.origin(),
+        _ => false,
+    }
+}
+
+impl FromId<Connection> for UserUpdate {
+    type Error = Error;
+    type Object = CustomPerson;
+
+    fn from_db(_: &mut Connection, _: &str) -> Result<Self> {
+        // Always fail because we always want to deserialize the AP object
+        Err(Error::NotFound)
+    }
+
+    fn deref(
+        _: &str,
+        _: Option<reqwest::Proxy>,
+    ) -> std::result::Result<CustomPerson, (Option<serde_json::Value>, Error)> {
+        // Profiles are always sent with their updates: anything else is the update of another kind of object, that
+        // shouldn't be fetched for nothing
+        Err((None, Error::NotFound))
+    }
+
+    async fn from_activity(_: &mut Connection, actor: CustomPerson) -> Result<Self> {
+        Ok(UserUpdate {
+            ap_url: actor.ap_actor_ref().id_unchecked().ok_or(Error::MissingApProperty)?.to_string(),
+            actor,
+        })
+    }
+
+    fn get_sender() -> &'static dyn Signer {
+        Instance::get_local_instance_user().expect("Failed to local instance user")
+    }
+}
+
+impl AsObject<User, Update, &mut Connection> for UserUpdate {
+    type Error = Error;
+    type Output = ();
+
+    async fn activity(self, conn: &mut Connection, actor: User, _id: &str) -> Result<()> {
+        // Users can only update their own profile
+        if self.ap_url != actor.ap_url || actor.is_local() {
+            return Err(Error::Unauthorized);
+        }
+        // Endpoints can only move to the server of the user: an update could otherwise redirect what is sent to them.
+        // The profile fetched from its own URL is trusted as it is.
+        let person = self.actor.ap_actor_ref();
+        let endpoints = [
+            (person.outbox()?.map(|url| url.as_str()), actor.outbox_url.as_str()),
+            (Some(person.inbox()?.as_str()), actor.inbox_url.as_str()),
+            (person.followers()?.map(|url| url.as_str()), actor.followers_endpoint.as_str()),
+        ];
+        let moved_away = endpoints
+            .iter()
+            .any(|(new, current)| new.is_some_and(|new| new != *current && !same_origin(new, &actor.ap_url)));
+        if moved_away {
+            return Err(Error::Unauthorized);
+        }
+        actor.update_from_activity(conn, &self.actor)
+    }
+}
+
 impl Signer for User {
     fn get_key_id(&self) -> String {
         format!("{}#main-key", self.ap_url)
@@ -972,6 +1129,29 @@ impl Signer for User {
         verifier.update(data.as_bytes())?;
         verifier.verify(signature).map_err(SignError::from)
     }
+
+    fn verify_with(&self, algorithm: Algorithm, data: &str, signature: &[u8]) -> SignResult<bool> {
+        match (algorithm, &self.ed25519_public_key) {
+            (Algorithm::RsaSha256, _) => self.verify(data, signature),
+            (Algorithm::Ed25519, Some(key)) => verify_ed25519(key, data, signature),
+            (Algorithm::Ed25519, None) => Ok(false),
+        }
+    }
+
+    fn key_id_with(&self, algorithm: Algorithm) -> Option<String> {
+        match algorithm {
+            Algorithm::RsaSha256 => Some(self.get_key_id()),
+            Algorithm::Ed25519 => self.ed25519_private_key.as_ref().map(|_| self.ed25519_key_id()),
+        }
+    }
+
+    fn sign_with(&self, algorithm: Algorithm, to_sign: &str) -> SignResult<Vec<u8>> {
+        match (algorithm, &self.ed25519_private_key) {
+            (Algorithm::RsaSha256, _) => self.sign(to_sign),
+            (Algorithm::Ed25519, Some(key)) => sign_ed25519(key, to_sign),
+            (Algorithm::Ed25519, None) => Err(SignError()),
+        }
+    }
 }
 
 impl PartialEq for User {
@@ -998,6 +1178,7 @@ impl NewUser {
         password: Option<String>,
     ) -> Result<User> {
         let (pub_key, priv_key) = gen_keypair();
+        let (ed25519_pub_key, ed25519_priv_key) = gen_ed25519_keypair();
         let instance = Instance::get_local()?;
         let blocklisted = BlocklistedEmail::matches_blocklist(conn, &email)?;
         if let Some(x) = blocklisted {
@@ -1024,6 +1205,8 @@ impl NewUser {
                 followers_endpoint: instance.compute_box(USER_PREFIX, &username, "followers"),
                 fqn: username,
                 avatar_id: None,
+                ed25519_private_key: Some(String::from_utf8(ed25519_priv_key).or(Err(Error::Signature))?),
+                ed25519_public_key: Some(String::from_utf8(ed25519_pub_key).or(Err(Error::Signature))?),
             },
         )?;
 
@@ -1053,6 +1236,7 @@ impl From<UserEvent> for Arc<User> {
 pub(crate) mod tests {
     use super::*;
     use crate::{
+        inbox::inbox,
         instance::{tests as instance_tests, Instance},
         medias::{Media, NewMedia},
         tests::db,
@@ -1060,6 +1244,7 @@ pub(crate) mod tests {
     };
     use assert_json_diff::assert_json_eq;
     use diesel::{Connection, SaveChangesDsl};
+    use futures::executor::block_on;
     use serde_json::to_value;
 
     pub(crate) fn fill_database(conn: &Conn) -> Vec<User> {
@@ -1239,6 +1424,21 @@ pub(crate) mod tests {
         });
     }
 
+    #[test]
+    fn sign_with_ed25519() {
+        let conn = &db();
+        conn.test_transaction::<_, (), _>(|| {
+            let users = fill_database(conn);
+            let key_id = users[0].key_id_with(Algorithm::Ed25519).unwrap();
+            assert_eq!(key_id, format!("{}#ed25519-key", users[0].ap_url));
+
+            let signature = users[0].sign_with(Algorithm::Ed25519, "data").unwrap();
+            assert!(users[0].verify_with(Algorithm::Ed25519, "data", &signature).unwrap());
+            assert!(!users[0].verify_with(Algorithm::Ed25519, "other data", &signature).unwrap());
+            Ok(())
+        });
+    }
+
     #[test]
     fn get_local_page() {
         let conn = &db();
@@ -1281,6 +1481,7 @@ pub(crate) mod tests {
             assert_eq!(user.instance_id, users[0].instance_id);
             assert_eq!(user.ap_url, users[0].ap_url);
             assert_eq!(user.public_key, users[0].public_key);
+            assert_eq!(user.ed25519_public_key, users[0].ed25519_public_key);
             assert_eq!(user.shared_inbox_url, users[0].shared_inbox_url);
             assert_eq!(user.followers_endpoint, users[0].followers_endpoint);
             assert_eq!(user.avatar_url(&conn), users[0].avatar_url(&conn));
@@ -1290,6 +1491,99 @@ pub(crate) mod tests {
         });
     }
 
+    #[test]
+    fn remote_profile_update() {
+        let conn = &db();
+        conn.test_transaction::<_, Error, _>(|| {
+            let users = fill_database(conn);
+            let remote = Instance::find_by_domain(conn, "1plu.me")?;
+            diesel::update(&users[1]).set(users::instance_id.eq(remote.id)).execute(conn)?;
+            let user = User::get(conn, users[1].id)?;
+
+            let mut profile = to_value(user.to_activity(conn)?)?;
+            profile["name"] = json!("Renamed user");
+            profile["summary"] = json!("<p>Still no one</p>");
+            block_on(inbox(
+                conn,
+                json!({
+                    "id": format!("{}#update-1", user.ap_url),
+                    "type": "Update",
+                    "actor": user.ap_url,
+                    "object": profile,
+                }),
+            ))?;
+            let updated = User::get(conn, user.id)?;
+            assert_eq!(updated.display_name, "Renamed user");
+            assert_eq!(updated.summary_html.get(), "<p>Still no one</p>");
+
+            // The avatar is only saved again if it changed
+            profile["icon"] = json!({ "type": "Image", "url": "https://plu.me/static/avatar.png" });
+            let medias = Media::for_user(conn, user.id)?.len();
+            for i in 3..5 {
+                block_on(inbox(
+                    conn,
+                    json!({
+                        "id": format!("{}#update-{}", user.ap_url, i),
+                        "type": "Update",
+                        "actor": user.ap_url,
+                        "object": profile,
+                    }),
+                ))?;
+            }
+            let avatar_id = User::get(conn, user.id)?.avatar_id;
+            assert!(avatar_id.is_some());
+            assert_eq!(Media::for_user(conn, user.id)?.len(), medias + 1);
+
+            // The endpoints of a profile must be on its server
+            let mut hijacked = profile.clone();
+            hijacked["inbox"] = json!("https://evil.example/inbox");
+            assert!(block_on(inbox(
+                conn,
+                json!({
+                    "id": format!("{}#update-5", user.ap_url),
+                    "type": "Update",
+                    "actor": user.ap_url,
+                    "object": hijacked,
+                }),
+            ))
+            .is_err());
+            let updated = User::get(conn, user.id)?;
+            assert_eq!(updated.inbox_url, user.inbox_url);
+            assert_eq!(updated.avatar_id, avatar_id);
+
+            // Endpoints that were already on another server can be kept
+            diesel::update(&user).set(users::inbox_url.eq("https://inbox.example/inbox")).execute(conn)?;
+            hijacked["inbox"] = json!("https://inbox.example/inbox");
+            hijacked["name"] = json!("Moved user");
+            block_on(inbox(
+                conn,
+                json!({
+                    "id": format!("{}#update-6", user.ap_url),
+                    "type": "Update",
+                    "actor": user.ap_url,
+                    "object": hijacked,
+                }),
+            ))?;
+            assert_eq!(User::get(conn, user.id)?.display_name, "Moved user");
+
+            // Profiles can only be updated by their owner
+            let other = to_value(users[2].to_activity(conn)?)?;
+            assert!(block_on(inbox(
+                conn,
+                json!({
+                    "id": format!("{}#update-2", user.ap_url),
+                    "type": "Update",
+                    "actor": user.ap_url,
+                    "object": other,
+                }),
+            ))
+            .is_err());
+            assert_eq!(User::get(conn, users[2].id)?.display_name, users[2].display_name);
+
+            Ok(())
+        });
+    }
+
     #[test]
     fn to_activity() {
         let conn = db();
@@ -1308,6 +1602,12 @@ pub(crate) mod tests {
                 "name": "The admin",
                 "outbox": "https://plu.me/@/admin/outbox",
                 "preferredUsername": "admin",
+                "assertionMethod": [{
+                    "id": "https://plu.me/@/admin/#ed25519-key",
+                    "type": "Multikey",
+                    "controller": "https://plu.me/@/admin/",
+                    "publicKeyMultibase": ed25519_to_multibase(user.ed25519_public_key.as_ref().unwrap()).unwrap(),
+                }],
                 "publicKey": {
                     "id": "https://plu.me/@/admin/#main-key",
                     "owner": "https://plu.me/@/admin/",
@@ -1336,6 +1636,12 @@ pub(crate) mod tests {
                 "name": "Another user",
                 "outbox": "https://plu.me/@/other/outbox",
                 "preferredUsername": "other",
+                "assertionMethod": [{
+                    "id": "https://plu.me/@/other/#ed25519-key",
+                    "type": "Multikey",
+                    "controller": "https://plu.me/@/other/",
+                    "publicKeyMultibase": ed25519_to_multibase(other.ed25519_public_key.as_ref().unwrap()).unwrap(),
+                }],
                 "publicKey": {
                     "id": "https://plu.me/@/other/#main-key",
                     "owner": "https://plu.me/@/other/",
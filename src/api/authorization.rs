@@ -31,6 +31,11 @@ impl Scope for plume_models::posts::Post {
         "posts"
     }
 }
+impl Scope for plume_models::timeline::Timeline {
+    fn to_str() -> &'static str {
+        "timelines"
+    }
+}
 
 pub struct Authorization<A, S>(pub ApiToken, PhantomData<(A, S)>);
 
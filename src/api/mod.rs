@@ -30,6 +30,10 @@ impl<'r> Responder<'r, 'r> for ApiError {
                 "error": "You are not authorized to access this resource"
             }))
             .respond_to(req),
+            Error::TimelineQuery(err) => Json(json!({
+                "error": format!("Invalid query: {}", err)
+            }))
+            .respond_to(req),
             _ => Json(json!({
                 "error": "Server error"
             }))
@@ -79,3 +83,4 @@ pub fn oauth(query: OAuthRequest, mut conn: DbConn) -> Result<Json<serde_json::V
 pub mod apps;
 pub mod authorization;
 pub mod posts;
+pub mod timelines;
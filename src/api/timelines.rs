@@ -0,0 +1,83 @@
+use rocket::serde::json::Json;
+
+use crate::api::{authorization::*, Api};
+use plume_api::timelines::*;
+use plume_models::{db_conn::DbConn, posts::Post, timeline::*, Connection, Error, ITEMS_PER_PAGE};
+
+fn explanation_data(explanation: Explanation) -> ExplanationData {
+    ExplanationData {
+        clause: explanation.clause,
+        matched: explanation.result == Ok(true),
+        error: explanation.result.err(),
+        children: explanation.children.into_iter().map(explanation_data).collect(),
+    }
+}
+
+#[get("/timelines/preview?<query>&<count>")]
+pub async fn preview(
+    query: String,
+    count: Option<i64>,
+    auth: Authorization<Read, Timeline>,
+    mut conn: DbConn,
+) -> Api<TimelinePreviewData> {
+    let parsed = TimelineQuery::parse(&query).map_err(Error::TimelineQuery)?;
+    let count = count.unwrap_or_else(|| ITEMS_PER_PAGE.into()).clamp(1, 100);
+
+    let posts = Timeline::preview(&mut conn, Some(auth.0.user_id), &query, count)
+        .await?
+        .into_iter()
+        .map(|(post, explanation)| PostPreviewData {
+            post_id: post.id,
+            title: post.title,
+            matched: explanation.matched(),
+            explanation: explanation_data(explanation),
+        })
+        .collect();
+
+    Ok(Json(TimelinePreviewData {
+        query: parsed.to_string(),
+        tree: parsed.dump_tree(),
+        posts,
+    }))
+}
+
+/// Returns the timeline `id` if the user can read it
+fn visible_timeline(conn: &mut Connection, id: i32, user_id: i32) -> Result<Timeline, Error> {
+    let timeline = Timeline::get(conn, id)?;
+    if timeline.is_visible_to(Some(user_id)) {
+        Ok(timeline)
+    } else {
+        Err(Error::Unauthorized)
+    }
+}
+
+fn marker_data(conn: &mut Connection, timeline: &Timeline, user_id: i32) -> Result<TimelineMarkerData, Error> {
+    let marker = TimelineMarker::find_for_user(conn, user_id, timeline)?;
+    Ok(TimelineMarkerData {
+        timeline_id: timeline.id,
+        last_read_date: marker.map(|m| m.last_read_date.format("%Y-%m-%dT%H:%M:%S").to_string()),
+        unread: timeline.count_unread(conn, user_id)?,
+    })
+}
+
+#[get("/timelines/<id>/marker")]
+pub fn marker(id: i32, auth: Authorization<Read, Timeline>, mut conn: DbConn) -> Api<TimelineMarkerData> {
+    let timeline = visible_timeline(&mut conn, id, auth.0.user_id)?;
+    Ok(Json(marker_data(&mut conn, &timeline, auth.0.user_id)?))
+}
+
+#[post("/timelines/<id>/marker", data = "<payload>")]
+pub fn advance_marker(
+    id: i32,
+    payload: Json<NewTimelineMarkerData>,
+    auth: Authorization<Write, Timeline>,
+    mut conn: DbConn,
+) -> Api<TimelineMarkerData> {
+    let timeline = visible_timeline(&mut conn, id, auth.0.user_id)?;
+    let post = Post::get(&mut conn, payload.post_id)?;
+    if !post.published || !timeline.includes_post(&mut conn, &post)? {
+        return Err(Error::NotFound.into());
+    }
+    TimelineMarker::advance(&mut conn, auth.0.user_id, &timeline, &post)?;
+    Ok(Json(marker_data(&mut conn, &timeline, auth.0.user_id)?))
+}
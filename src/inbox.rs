@@ -1,19 +1,27 @@
 use plume_common::activity_pub::{
-    inbox::FromId,
     request::Digest,
-    sign::{verify_http_headers, Signable},
+    sign::{
+        signature_key_id, verify_http_headers, verify_http_headers_strict, verify_http_message_signature, Signable,
+        SignatureError, SignatureValidity, Signer,
+    },
+};
+use plume_models::{
+    blogs::Blog, db_conn::DbConn, headers::Headers, inbox::fetch_actor, inbox_jobs::InboxJob, instance::Instance,
+    relays::Relay, users::User, Error, CONFIG,
 };
-use plume_models::{db_conn::DbConn, headers::Headers, inbox::inbox, instance::Instance, users::User, Error, CONFIG};
 use rocket::serde::json::Error as JsonError;
 use rocket::{data::*, http::Status, response::status, Request};
 use serde::Deserialize;
 use tracing::warn;
 
+/// Checks that an incoming activity comes from its actor, and stores it for the workers to process it.
+///
+/// Only the verification is done while the sender waits: they get `202 Accepted` as soon as the activity is queued.
 pub async fn handle_incoming(
     mut conn: DbConn,
     data: SignedJson<serde_json::Value>,
     headers: Headers<'_>,
-) -> Result<String, status::BadRequest<&'static str>> {
+) -> Result<status::Accepted<()>, status::BadRequest<&'static str>> {
     let act = data.1;
     let sig = data.0;
 
@@ -23,37 +31,125 @@ pub async fn handle_incoming(
         .or_else(|| activity["actor"]["id"].as_str())
         .ok_or(status::BadRequest("Missing actor id for activity"))?;
 
-    let actor =
-        User::from_id(&mut conn, actor_id, None, CONFIG.proxy()).await.expect("instance::shared_inbox: user error");
-    if !verify_http_headers(&actor, &headers.0, &sig).is_secure() && !act.clone().verify(&actor) {
-        // maybe we just know an old key?
-        actor
-            .refetch(&mut conn)
-            .and_then(|_| User::get(&mut conn, actor.id))
-            .and_then(|u| {
-                if verify_http_headers(&u, &headers.0, &sig).is_secure() || act.clone().verify(&u) {
-                    Ok(())
-                } else {
-                    Err(Error::Signature)
-                }
-            })
-            .map_err(|_| {
-                warn!("Rejected invalid activity supposedly from {}, with headers {:?}", actor.username, headers.0);
-                status::BadRequest("Invalid signature")
-            })?;
+    // Activities from blocked instances are dropped before anything is fetched from there
+    if Instance::is_blocked(&mut conn, actor_id).map_err(|_| status::BadRequest("Invalid actor id"))? {
+        return Ok(status::Accepted(()));
+    }
+
+    // Relays are not users, they are only known after subscribing to them
+    if let Ok(relay) = Relay::find_by_actor_id(&mut conn, actor_id) {
+        check_http_signature(&relay, actor_id, &headers, &sig).map_err(|reason| {
+            warn!(actor = actor_id, ?reason, "Rejected an activity from a relay with an invalid signature");
+            status::BadRequest("Invalid signature")
+        })?;
+        return enqueue(&mut conn, &act, actor_id);
+    }
+
+    // Mastodon-style relays forward the activities of other instances with their own signature
+    let forwarding_relay =
+        signature_key_id(&headers.0).and_then(|key_id| Relay::find_by_key_id(&mut conn, &key_id).ok());
+    if let Some(relay) = forwarding_relay {
+        let actor = fetch_actor(&mut conn, actor_id).await.map_err(|e| {
+            warn!("Couldn't fetch {}: {:?}", actor_id, e);
+            status::BadRequest("Couldn't fetch the actor")
+        })?;
+        relay.verify_forwarded(&headers.0, &sig, &act, &actor).map_err(|reason| {
+            warn!(
+                actor = actor_id,
+                relay = relay.actor_id.as_str(),
+                ?reason,
+                "Rejected an activity forwarded by a relay"
+            );
+            status::BadRequest("Invalid signature")
+        })?;
+        return enqueue(&mut conn, &act, actor_id);
     }
 
-    if Instance::is_blocked(&mut conn, actor_id).map_err(|_| status::BadRequest("Can't tell if instance is blocked"))? {
-        return Ok(String::new());
+    // Only the blogs we already know can send their updates
+    if let Ok(blog) = Blog::find_by_ap_url(&mut conn, actor_id) {
+        if let Err(reason) = check_http_signature(&blog, actor_id, &headers, &sig) {
+            if !check_ld_signature(&act, &blog, &reason) {
+                warn!(actor = actor_id, ?reason, "Rejected an activity from a blog with an invalid signature");
+                return Err(status::BadRequest("Invalid signature"));
+            }
+        }
+        return enqueue(&mut conn, &act, actor_id);
     }
 
-    Ok(match inbox(&mut conn, act).await {
-        Ok(_) => String::new(),
-        Err(e) => {
-            warn!("Shared inbox error: {:?}", e);
-            format!("Error: {:?}", e)
+    let actor = fetch_actor(&mut conn, actor_id).await.map_err(|e| {
+        warn!("Couldn't fetch {}: {:?}", actor_id, e);
+        status::BadRequest("Couldn't fetch the actor")
+    })?;
+    if let Err(reason) = check_http_signature(&actor, actor_id, &headers, &sig) {
+        if !check_ld_signature(&act, &actor, &reason) {
+            // maybe we just know an old key?
+            actor
+                .refetch(&mut conn)
+                .and_then(|_| User::get(&mut conn, actor.id))
+                .and_then(|u| {
+                    if check_http_signature(&u, actor_id, &headers, &sig).is_ok()
+                        || check_ld_signature(&act, &u, &reason)
+                    {
+                        Ok(())
+                    } else {
+                        Err(Error::Signature)
+                    }
+                })
+                .map_err(|_| {
+                    warn!(
+                        actor = actor_id,
+                        ?reason,
+                        headers = ?headers.0,
+                        "Rejected an activity with an invalid signature"
+                    );
+                    status::BadRequest("Invalid signature")
+                })?;
         }
-    })
+    }
+
+    enqueue(&mut conn, &act, actor_id)
+}
+
+fn enqueue(
+    conn: &mut DbConn,
+    act: &serde_json::Value,
+    actor_id: &str,
+) -> Result<status::Accepted<()>, status::BadRequest<&'static str>> {
+    InboxJob::enqueue(conn, act, actor_id).map_err(|e| {
+        warn!("Couldn't queue an activity from {}: {:?}", actor_id, e);
+        status::BadRequest("Couldn't store the activity")
+    })?;
+    Ok(status::Accepted(()))
+}
+
+/// Checks the HTTP signature of an activity from `actor_id`, as strictly as the configuration requires
+fn check_http_signature<S: Signer + std::fmt::Debug>(
+    actor: &S,
+    actor_id: &str,
+    headers: &Headers<'_>,
+    digest: &Digest,
+) -> Result<(), SignatureError> {
+    // RFC 9421 signatures are always checked strictly, only the older draft is accepted with fewer guarantees
+    if headers.0.contains("Signature-Input") {
+        return verify_http_message_signature(actor, actor_id, None, &headers.0, Some(digest));
+    }
+    if CONFIG.strict_signatures {
+        return verify_http_headers_strict(actor, actor_id, None, &headers.0, Some(digest));
+    }
+    match verify_http_headers(actor, &headers.0, digest) {
+        SignatureValidity::Valid => Ok(()),
+        SignatureValidity::ValidNoDigest => Err(SignatureError::UnsignedHeader("digest")),
+        SignatureValidity::Absent => Err(SignatureError::Absent),
+        SignatureValidity::Outdated => Err(SignatureError::Outdated),
+        SignatureValidity::Invalid => Err(SignatureError::InvalidSignature),
+    }
+}
+
+/// Checks the LD signature of an activity, which only replaces its HTTP signature when signatures are not checked
+/// strictly, or when the activity was forwarded (its HTTP signature was then made by another actor, see
+/// [`SignatureError::ForeignKey`]): it doesn't tell when the request was made, so an old activity could be sent again
+fn check_ld_signature<S: Signer>(act: &serde_json::Value, actor: &S, reason: &SignatureError) -> bool {
+    (!CONFIG.strict_signatures || matches!(reason, SignatureError::ForeignKey { .. })) && act.clone().verify(actor)
 }
 
 const JSON_LIMIT: ByteUnit = ByteUnit::Megabyte(10);
@@ -1,5 +1,11 @@
 #![warn(clippy::too_many_arguments)]
-use lettre::Message;
+use lettre::{
+    message::{
+        header::{HeaderName, HeaderValue},
+        MessageBuilder, MultiPart,
+    },
+    Message,
+};
 use std::env;
 
 pub use self::mailer::*;
@@ -61,10 +67,33 @@ mod mailer {
     }
 }
 
-pub fn build_mail(dest: String, subject: String, body: String) -> Option<Message> {
-    let from = env::var("MAIL_ADDRESS")
+fn sender() -> String {
+    env::var("MAIL_ADDRESS")
         .or_else(|_| Ok(format!("{}@{}", env::var("MAIL_USER")?, env::var("MAIL_SERVER")?)) as Result<_, env::VarError>)
-        .expect("The email server is not configured correctly");
+        .expect("The email server is not configured correctly")
+}
+
+fn builder(dest: &str, subject: String) -> Option<MessageBuilder> {
+    Some(Message::builder().from(sender().parse().ok()?).to(dest.parse().ok()?).subject(subject))
+}
+
+pub fn build_mail(dest: String, subject: String, body: String) -> Option<Message> {
+    builder(&dest, subject)?.body(body).ok()
+}
+
+/// Builds a mail with both a plain text and an HTML version
+pub fn build_html_mail(dest: &str, subject: String, text: String, html: String) -> Option<Message> {
+    builder(dest, subject)?.multipart(MultiPart::alternative_plain_html(text, html)).ok()
+}
 
-    Message::builder().from(from.parse().ok()?).to(dest.parse().ok()?).subject(subject).body(body).ok()
+/// Builds an HTML mail that mail clients can unsubscribe from in one click (RFC 8058)
+pub fn build_newsletter(dest: &str, subject: String, text: String, html: String, unsubscribe: &str) -> Option<Message> {
+    builder(dest, subject)?
+        .raw_header(HeaderValue::new(HeaderName::new_from_ascii_str("List-Unsubscribe"), format!("<{}>", unsubscribe)))
+        .raw_header(HeaderValue::new(
+            HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+            "List-Unsubscribe=One-Click".to_owned(),
+        ))
+        .multipart(MultiPart::alternative_plain_html(text, html))
+        .ok()
 }
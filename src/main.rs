@@ -10,18 +10,23 @@ extern crate serde_json;
 use clap::Command;
 use diesel::r2d2::ConnectionManager;
 use plume_models::{
-    db_conn::{DbPool, PragmaForeignKey},
+    blog_feeds::BlogFeed,
+    db_conn::{DbConn, DbPool, PragmaForeignKey},
+    inbox_jobs::InboxJob,
     instance::Instance,
     remote_fetch_actor::RemoteFetchActor,
-    search::{actor::SearchActor, Searcher as UnmanagedSearcher},
+    search::{actor::SearchActor, ReindexOptions, Searcher as UnmanagedSearcher},
+    timeline::actor::TimelineActor,
+    users::User,
     Connection, CONFIG,
 };
 use rocket_csrf::Fairing;
 use scheduled_thread_pool::ScheduledThreadPool;
 use std::process::exit;
 use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
-use tracing::{error, warn};
+use tracing::{error, info, warn};
 
 init_i18n!(
     "plume", af, ar, bg, ca, cs, cy, da, de, el, en, eo, es, eu, fa, fi, fr, gl, he, hi, hr, hu, it, ja, ko, nb, nl,
@@ -82,6 +87,9 @@ and https://docs.joinplu.me/installation/init for more info.
     plume_models::migrations::run_pending_migrations(&mut conn).expect("Migrations error");
 
     Instance::cache_local(&mut conn);
+    if let Err(e) = User::generate_missing_ed25519_keys(&mut conn) {
+        warn!("Couldn't generate the Ed25519 keys of local users: {:?}", e);
+    }
     let _ = Instance::create_local_instance_user(&mut conn);
     Instance::cache_local_instance_user(&mut conn);
 
@@ -90,8 +98,41 @@ and https://docs.joinplu.me/installation/init for more info.
     let searcher = Arc::new(UnmanagedSearcher::open_or_recreate(&CONFIG.search_index, &CONFIG.search_tokenizers));
     RemoteFetchActor::init(dbpool.clone());
     SearchActor::init(searcher.clone(), dbpool.clone());
+    TimelineActor::init(dbpool.clone());
+    // fill the index in the background if it was just recreated, or if a refill was interrupted
+    if let Some(checkpoint) = searcher.reindex_checkpoint() {
+        let refill_searcher = searcher.clone();
+        let refill_pool = dbpool.clone();
+        thread::spawn(move || match refill_pool.get() {
+            Ok(conn) => {
+                info!("Filling the search index after post {}", checkpoint.last_post_id);
+                let options = ReindexOptions {
+                    since: checkpoint.since,
+                    ..ReindexOptions::default()
+                };
+                match refill_searcher.reindex(&mut DbConn(conn), &options, |_, _| {}) {
+                    Ok(()) => info!("The search index has been filled"),
+                    Err(e) => warn!("Couldn't fill the search index, run `plm search refill`: {:?}", e),
+                }
+            }
+            Err(_) => warn!("Couldn't get a database connection to fill the search index, run `plm search refill`"),
+        });
+    }
     let commiter = searcher.clone();
     workpool.execute_with_fixed_delay(Duration::from_secs(5), Duration::from_secs(60 * 30), move || commiter.commit());
+    let feeds_pool = dbpool.clone();
+    workpool.execute_with_fixed_delay(Duration::from_secs(60), Duration::from_secs(60), move || {
+        match feeds_pool.get() {
+            Ok(conn) => BlogFeed::poll_all(&mut DbConn(conn)),
+            Err(_) => warn!("Couldn't get a database connection to poll the external feeds"),
+        }
+    });
+
+    let inbox_pool = dbpool.clone();
+    workpool.execute_with_fixed_delay(Duration::from_secs(1), Duration::from_secs(2), move || match inbox_pool.get() {
+        Ok(conn) => InboxJob::process_due(&mut DbConn(conn)),
+        Err(_) => warn!("Couldn't get a database connection to process the incoming activities"),
+    });
 
     let search_unlocker = searcher.clone();
     ctrlc::set_handler(move || {
@@ -106,6 +147,23 @@ and https://docs.joinplu.me/installation/init for more info.
         warn!("Warning: the email server is not configured (or not completely).");
         warn!("Please refer to the documentation to see how to configure it.");
     }
+    let mail = Arc::new(Mutex::new(mail));
+    let digests_pool = dbpool.clone();
+    let digests_mail = mail.clone();
+    let translations = include_i18n!();
+    workpool.execute_with_fixed_delay(Duration::from_secs(60), Duration::from_secs(60), move || {
+        match digests_pool.get() {
+            Ok(conn) => routes::notifications::send_digests(&mut DbConn(conn), &digests_mail, &translations),
+            Err(_) => warn!("Couldn't get a database connection to mail the notifications"),
+        }
+    });
+
+    let push_pool = dbpool.clone();
+    let push_translations = include_i18n!();
+    workpool.execute_with_fixed_delay(Duration::from_secs(5), Duration::from_secs(5), move || match push_pool.get() {
+        Ok(conn) => routes::notifications::send_push_notifications(&mut DbConn(conn), &push_translations),
+        Err(_) => warn!("Couldn't get a database connection to send push notifications"),
+    });
 
     rocket::custom(CONFIG.rocket.clone().unwrap())
         .mount(
@@ -122,8 +180,16 @@ and https://docs.joinplu.me/installation/init for more info.
                 routes::blogs::edit,
                 routes::blogs::update,
                 routes::blogs::atom_feed,
+                routes::blogs::rss_feed,
+                routes::blogs::json_feed,
+                routes::blogs::feeds,
+                routes::blogs::add_feed,
+                routes::blogs::delete_feed,
                 routes::comments::create,
                 routes::comments::delete,
+                routes::comments::edit,
+                routes::comments::update,
+                routes::comments::history,
                 routes::comments::activity_pub,
                 routes::email_signups::create,
                 routes::email_signups::created,
@@ -136,6 +202,12 @@ and https://docs.joinplu.me/installation/init for more info.
                 routes::instance::admin_users,
                 routes::instance::admin_search_users,
                 routes::instance::admin_email_blocklist,
+                routes::instance::admin_inbox,
+                routes::instance::retry_inbox_job,
+                routes::instance::delete_inbox_job,
+                routes::instance::admin_relays,
+                routes::instance::add_relay,
+                routes::instance::delete_relay,
                 routes::instance::add_email_blocklist,
                 routes::instance::delete_email_blocklist,
                 routes::instance::edit_users,
@@ -149,14 +221,31 @@ and https://docs.joinplu.me/installation/init for more info.
                 routes::instance::web_manifest,
                 routes::likes::create,
                 routes::likes::create_auth,
+                routes::lists::index,
+                routes::lists::create,
+                routes::lists::details,
+                routes::lists::rename,
+                routes::lists::add,
+                routes::lists::remove,
+                routes::lists::delete,
                 routes::medias::list,
                 routes::medias::new,
                 routes::medias::upload,
                 routes::medias::details,
                 routes::medias::delete,
                 routes::medias::set_avatar,
+                routes::newsletters::subscribe,
+                routes::newsletters::confirm,
+                routes::newsletters::unsubscribe_page,
+                routes::newsletters::unsubscribe,
                 routes::notifications::notifications,
                 routes::notifications::notifications_auth,
+                routes::notifications::stream,
+                routes::notifications::settings,
+                routes::notifications::settings_auth,
+                routes::notifications::update_settings,
+                routes::notifications::push_subscribe,
+                routes::notifications::push_unsubscribe,
                 routes::posts::details,
                 routes::posts::activity_details,
                 routes::posts::edit,
@@ -170,6 +259,7 @@ and https://docs.joinplu.me/installation/init for more info.
                 routes::reshares::create,
                 routes::reshares::create_auth,
                 routes::search::search,
+                routes::search::save,
                 routes::session::new,
                 routes::session::create,
                 routes::session::delete,
@@ -182,7 +272,19 @@ and https://docs.joinplu.me/installation/init for more info.
                 routes::static_files,
                 routes::plume_media_files,
                 routes::tags::tag,
+                routes::tags::atom_feed,
+                routes::tags::rss_feed,
+                routes::tags::json_feed,
                 routes::timelines::details,
+                routes::timelines::mark_read,
+                routes::timelines::atom_feed,
+                routes::timelines::rss_feed,
+                routes::timelines::json_feed,
+                routes::timelines::private_atom_feed,
+                routes::timelines::private_rss_feed,
+                routes::timelines::private_json_feed,
+                routes::timelines::reset_feed_token,
+                routes::timelines::revoke_feed_token,
                 routes::timelines::new,
                 routes::timelines::create,
                 routes::timelines::edit,
@@ -209,6 +311,8 @@ and https://docs.joinplu.me/installation/init for more info.
                 routes::user::new,
                 routes::user::create,
                 routes::user::atom_feed,
+                routes::user::rss_feed,
+                routes::user::json_feed,
                 routes::well_known::host_meta,
                 routes::well_known::nodeinfo,
                 routes::well_known::webfinger,
@@ -224,13 +328,16 @@ and https://docs.joinplu.me/installation/init for more info.
                 api::posts::list,
                 api::posts::create,
                 api::posts::delete,
+                api::timelines::preview,
+                api::timelines::marker,
+                api::timelines::advance_marker,
             ],
         )
         .register(
             "/",
             catchers![routes::errors::not_found, routes::errors::unprocessable_entity, routes::errors::server_error],
         )
-        .manage(Arc::new(Mutex::new(mail)))
+        .manage(mail)
         .manage::<Arc<Mutex<Vec<routes::session::ResetRequest>>>>(Arc::new(Mutex::new(vec![])))
         .manage(dbpool)
         .manage(Arc::new(workpool))
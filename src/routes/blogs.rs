@@ -8,14 +8,15 @@ use rocket_i18n::I18n;
 use std::{borrow::Cow, collections::HashMap};
 use validator::{Validate, ValidationError, ValidationErrors};
 
-use crate::routes::{errors::ErrorPage, Page, RespondOrRedirect};
+use crate::routes::{errors::ErrorPage, FeedContent, FeedFormat, FeedInfo, Page, RespondOrRedirect};
 use crate::template_utils::{IntoContext, PostCard, Ructe};
 use crate::utils::requires_login;
-use plume_common::activity_pub::{ActivityStream, ApRequest, CustomGroup};
+use plume_common::activity_pub::{broadcast, ActivityStream, ApRequest, CustomGroup};
 use plume_common::utils;
 use plume_models::{
-    blog_authors::*, blogs::*, db_conn::DbConn, instance::Instance, medias::*, posts::Post, safe_string::SafeString,
-    users::User, Connection, PlumeRocket,
+    authorized_fetch::AuthorizedFetch, blog_authors::*, blog_feeds::*, blogs::*, db_conn::DbConn, instance::Instance,
+    medias::*, newsletters::NewsletterSubscription, posts::Post, safe_string::SafeString, users::User, Connection,
+    Error, PlumeRocket, CONFIG,
 };
 
 #[get("/~/<name>?<page>", rank = 2)]
@@ -32,6 +33,11 @@ pub async fn details(
     let articles_count = Post::count_for_blog(&mut conn, &blog)?;
     let authors = &blog.list_authors(&mut conn)?;
     let is_author = rockets.user.as_ref().and_then(|u| u.is_author_in(&mut conn, &blog).ok()).unwrap_or(false);
+    let newsletter_subscribers = if is_author {
+        NewsletterSubscription::count_for_blog(&mut conn, blog.id)?
+    } else {
+        0
+    };
 
     let blog_pages = Post::blog_page(&mut conn, &blog, page.limits())?;
     let posts = PostCard::from_posts(&mut conn, blog_pages, &rockets.user);
@@ -43,6 +49,7 @@ pub async fn details(
         blog_banner_url,
         authors,
         is_author,
+        newsletter_subscribers,
         page.0,
         Page::total(articles_count as i32),
         posts
@@ -50,7 +57,12 @@ pub async fn details(
 }
 
 #[get("/~/<name>", rank = 1)]
-pub async fn activity_details(name: String, mut conn: DbConn, _ap: ApRequest) -> Option<ActivityStream<CustomGroup>> {
+pub async fn activity_details(
+    name: String,
+    mut conn: DbConn,
+    _ap: ApRequest,
+    _fetch: AuthorizedFetch,
+) -> Option<ActivityStream<CustomGroup>> {
     let blog = Blog::find_by_fqn(&mut conn, &name).await.ok()?;
     Some(ActivityStream::new(blog.to_activity(&mut conn).ok()?))
 }
@@ -139,7 +151,10 @@ pub async fn delete(name: &str, mut conn: DbConn, rockets: PlumeRocket) -> Respo
     let blog = Blog::find_by_fqn(&mut conn, name).await.expect("blog::delete: blog not found");
 
     if rockets.user.clone().and_then(|u| u.is_author_in(&mut conn, &blog).ok()).unwrap_or(false) {
+        let delete_act = blog.delete_activity().expect("blog::delete: activity error");
+        let dest = User::one_by_instance(&mut conn).expect("blog::delete: dest error");
         blog.delete(&mut conn).expect("blog::expect: deletion error");
+        rockets.worker.execute(move || broadcast(&blog, delete_act, dest, CONFIG.proxy().cloned()));
         Flash::success(
             Redirect::to(uri!(super::instance::index)),
             i18n!(rockets.intl.catalog, "Your blog was deleted."),
@@ -265,6 +280,11 @@ pub async fn update(name: &str, form: Form<EditForm>, mut conn: DbConn, rockets:
             blog.banner_id = form.banner;
             blog.theme = form.theme.clone();
             blog.save(&mut conn).expect("Couldn't save blog changes");
+
+            let act = blog.update_activity(&mut conn).expect("blog::update: activity error");
+            let dest = User::one_by_instance(&mut conn).expect("blog::update: dest error");
+            let sender = blog.clone();
+            rockets.worker.execute(move || broadcast(&sender, act, dest, CONFIG.proxy().cloned()));
             Ok(Flash::success(
                 Redirect::to(uri!(details(name = name, page = _))),
                 i18n!(intl, "Your blog information have been updated."),
@@ -278,26 +298,152 @@ pub async fn update(name: &str, form: Form<EditForm>, mut conn: DbConn, rockets:
         .into()
 }
 
+/// Checks that `user` can manage the external feeds imported in `blog`
+fn check_feed_manager(conn: &mut Connection, blog: &Blog, user: &User) -> Result<(), ErrorPage> {
+    if user.is_admin() || user.is_author_in(conn, blog)? {
+        Ok(())
+    } else {
+        Err(Error::Unauthorized.into())
+    }
+}
+
+#[get("/~/<name>/feeds")]
+pub async fn feeds(name: &str, user: User, mut conn: DbConn, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let blog = Blog::find_by_fqn(&mut conn, name).await?;
+    check_feed_manager(&mut conn, &blog, &user)?;
+    let feeds = BlogFeed::list_for_blog(&mut conn, blog.id)?;
+    Ok(render!(blogs::feeds_html(&(&mut conn, &rockets).to_context(), &blog, feeds)))
+}
+
+#[derive(FromForm)]
+pub struct NewFeedForm {
+    pub url: String,
+}
+
+#[post("/~/<name>/feeds", data = "<form>")]
+pub async fn add_feed(
+    name: &str,
+    form: Form<NewFeedForm>,
+    user: User,
+    mut conn: DbConn,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let blog = Blog::find_by_fqn(&mut conn, name).await?;
+    check_feed_manager(&mut conn, &blog, &user)?;
+
+    let url = form.url.trim();
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Ok(Flash::error(
+            Redirect::to(uri!(feeds(name = name))),
+            i18n!(intl.catalog, "Please enter the address of an RSS or Atom feed."),
+        ));
+    }
+    if !utils::is_public_url(url) {
+        return Ok(Flash::error(
+            Redirect::to(uri!(feeds(name = name))),
+            i18n!(intl.catalog, "Only feeds that are publicly available on the Internet can be imported."),
+        ));
+    }
+    if BlogFeed::list_for_blog(&mut conn, blog.id)?.iter().any(|f| f.url == url) {
+        return Ok(Flash::error(
+            Redirect::to(uri!(feeds(name = name))),
+            i18n!(intl.catalog, "This feed is already imported in this blog."),
+        ));
+    }
+
+    // Admins who don't write in this blog attribute the articles to one of its authors
+    let author_id = if user.is_author_in(&mut conn, &blog)? {
+        user.id
+    } else {
+        blog.list_authors(&mut conn)?.first().ok_or(Error::NotFound)?.id
+    };
+    BlogFeed::insert(
+        &mut conn,
+        NewBlogFeed {
+            blog_id: blog.id,
+            author_id,
+            url: url.to_owned(),
+        },
+    )?;
+    Ok(Flash::success(
+        Redirect::to(uri!(feeds(name = name))),
+        i18n!(intl.catalog, "The feed has been added, its articles will appear in this blog in a few minutes."),
+    ))
+}
+
+#[post("/~/<name>/feeds/<id>/delete")]
+pub async fn delete_feed(
+    name: &str,
+    id: i32,
+    user: User,
+    mut conn: DbConn,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let blog = Blog::find_by_fqn(&mut conn, name).await?;
+    check_feed_manager(&mut conn, &blog, &user)?;
+    let feed = BlogFeed::get(&mut conn, id)?;
+    if feed.blog_id != blog.id {
+        return Err(Error::NotFound.into());
+    }
+
+    feed.delete(&mut conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(feeds(name = name))),
+        i18n!(intl.catalog, "This feed won't be imported anymore. The articles it brought are kept."),
+    ))
+}
+
 #[get("/~/<name>/outbox")]
-pub async fn outbox(name: &str, mut conn: DbConn) -> Option<ActivityStream<OrderedCollection>> {
+pub async fn outbox(
+    name: &str,
+    mut conn: DbConn,
+    _fetch: AuthorizedFetch,
+) -> Option<ActivityStream<OrderedCollection>> {
     let blog = Blog::find_by_fqn(&mut conn, name).await.ok()?;
     blog.outbox(&mut conn).ok()
 }
 #[allow(unused_variables)]
 #[get("/~/<name>/outbox?<page>")]
-pub async fn outbox_page(name: &str, page: Page, mut conn: DbConn) -> Option<ActivityStream<OrderedCollectionPage>> {
+pub async fn outbox_page(
+    name: &str,
+    page: Page,
+    mut conn: DbConn,
+    _fetch: AuthorizedFetch,
+) -> Option<ActivityStream<OrderedCollectionPage>> {
     let blog = Blog::find_by_fqn(&mut conn, name).await.ok()?;
     blog.outbox_page(&mut conn, page.limits()).ok()
 }
-#[get("/~/<name>/atom.xml")]
-pub async fn atom_feed(name: &str, mut conn: DbConn) -> Option<(ContentType, String)> {
-    let blog = Blog::find_by_fqn(&mut conn, name).await.ok()?;
-    let entries = Post::get_recents_for_blog(&mut conn, &blog, 15).ok()?;
-    let uri = Instance::get_local().ok()?.compute_box("~", name, "atom.xml");
-    let title = &blog.title;
-    let default_updated = &blog.creation_date;
-    let feed = super::build_atom_feed(entries, &uri, title, default_updated, &mut conn);
-    Some((ContentType::new("application", "atom+xml"), feed.to_string()))
+async fn feed(
+    name: &str,
+    format: FeedFormat,
+    content: Option<FeedContent>,
+    conn: &mut Connection,
+) -> Option<(ContentType, String)> {
+    let blog = Blog::find_by_fqn(conn, name).await.ok()?;
+    let entries = Post::get_recents_for_blog(conn, &blog, 15).ok()?;
+    let uri = Instance::get_local().ok()?.compute_box("~", name, format.file_name());
+    let info = FeedInfo {
+        uri: &uri,
+        home_page: &blog.ap_url,
+        title: &blog.title,
+        default_updated: &blog.creation_date,
+    };
+    Some(super::build_feed(format, content.unwrap_or_default(), entries, info, conn))
+}
+
+#[get("/~/<name>/atom.xml?<content>")]
+pub async fn atom_feed(name: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    feed(name, FeedFormat::Atom, content, &mut conn).await
+}
+
+#[get("/~/<name>/rss.xml?<content>")]
+pub async fn rss_feed(name: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    feed(name, FeedFormat::Rss, content, &mut conn).await
+}
+
+#[get("/~/<name>/feed.json?<content>")]
+pub async fn json_feed(name: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    feed(name, FeedFormat::Json, content, &mut conn).await
 }
 
 #[cfg(test)]
@@ -4,19 +4,20 @@ use rocket::{
     form::Form,
     response::{Flash, Redirect},
 };
-use validator::Validate;
+use validator::{Validate, ValidationErrors};
 
 use std::time::Duration;
 
-use crate::routes::errors::ErrorPage;
+use crate::routes::{errors::ErrorPage, RespondOrRedirect};
 use crate::template_utils::IntoContext;
 use plume_common::{
     activity_pub::{broadcast, ActivityStream, ApRequest},
     utils,
 };
 use plume_models::{
-    blogs::Blog, comments::*, db_conn::DbConn, inbox::inbox, instance::Instance, medias::Media, mentions::Mention,
-    posts::Post, safe_string::SafeString, tags::Tag, users::User, Error, PlumeRocket, CONFIG,
+    authorized_fetch::AuthorizedFetch, blogs::Blog, comment_edits::CommentEdit, comments::*, db_conn::DbConn,
+    inbox::inbox, instance::Instance, live, medias::Media, mentions::Mention, posts::Post, safe_string::SafeString,
+    tags::Tag, timeline::Timeline, users::User, Error, PlumeRocket, CONFIG,
 };
 
 #[derive(Default, FromForm, Debug, Validate)]
@@ -35,7 +36,7 @@ pub async fn create(
     user: User,
     mut conn: DbConn,
     rockets: PlumeRocket,
-) -> Result<Flash<Redirect>, Ructe> {
+) -> Result<RespondOrRedirect, ErrorPage> {
     let blog = Blog::find_by_fqn(&mut conn, blog_name).await.expect("comments::create: blog error");
     let post = Post::find_by_slug(&mut conn, slug, blog.id).expect("comments::create: post error");
     match form.validate() {
@@ -46,32 +47,40 @@ pub async fn create(
                 true,
                 Some(Media::get_media_processor(&mut conn, vec![&user])),
             );
-            let comm = Comment::insert(
-                &mut conn,
-                NewComment {
-                    content: SafeString::new(html.as_ref()),
-                    in_response_to_id: form.responding_to,
-                    post_id: post.id,
-                    author_id: user.id,
-                    ap_url: None,
-                    sensitive: !form.warning.is_empty(),
-                    spoiler_text: form.warning.clone(),
-                    public_visibility: true,
-                },
-            )
-            .expect("comments::create: insert error");
-            let new_comment = comm.create_activity(&mut conn).await.expect("comments::create: activity error");
-
-            // save mentions
+            let mut mention_activities = vec![];
             for ment in mentions {
-                let activity =
-                    &Mention::build_activity(&mut conn, &ment).await.expect("comments::create: build mention error");
-
-                Mention::from_activity(&mut conn, activity, comm.id, false, true)
-                    .expect("comments::create: mention save error");
+                mention_activities.push(
+                    Mention::build_activity(&mut conn, &ment).await.expect("comments::create: build mention error"),
+                );
             }
 
-            comm.notify(&mut conn).expect("comments::create: notify error");
+            // The notifications are only sent once the comment can be seen
+            let comm = live::transaction(&mut conn, |conn| {
+                let comm = Comment::insert(
+                    conn,
+                    NewComment {
+                        content: SafeString::new(html.as_ref()),
+                        in_response_to_id: form.responding_to,
+                        post_id: post.id,
+                        author_id: user.id,
+                        ap_url: None,
+                        sensitive: !form.warning.is_empty(),
+                        spoiler_text: form.warning.clone(),
+                        public_visibility: true,
+                        source: form.content.clone(),
+                    },
+                )?;
+                // save mentions
+                for activity in &mention_activities {
+                    Mention::from_activity(conn, activity, comm.id, false, true)?;
+                }
+                comm.notify(conn)?;
+                Ok(comm)
+            })
+            .expect("comments::create: insert error");
+            let new_comment = comm.create_activity(&mut conn).await.expect("comments::create: activity error");
+
+            Timeline::reevaluate_counts(&mut conn, &post).await.expect("comments::create: timeline error");
 
             // federate
             let dest = User::one_by_instance(&mut conn).expect("comments::create: dest error");
@@ -81,7 +90,8 @@ pub async fn create(
             Ok(Flash::success(
                 Redirect::to(uri!(super::posts::details(blog = blog_name, slug = slug, responding_to = _))),
                 i18n!(&rockets.intl.catalog, "Your comment has been posted."),
-            ))
+            )
+            .into())
         }
         Err(errors) => {
             // TODO: de-duplicate this code
@@ -100,8 +110,9 @@ pub async fn create(
             let following = user.is_following(&mut conn, author.id).expect("comments::create: following error");
             let author_avatar_url = author.avatar_url(&mut conn);
             let is_author = post.is_author(&mut conn, user.id).ok().unwrap_or(false);
+            let original_url = post.original_url(&mut conn)?;
 
-            Err(render!(posts::details_html(
+            Ok(render!(posts::details_html(
                 &(&mut conn, &rockets).to_context(),
                 post,
                 cover_url,
@@ -118,8 +129,10 @@ pub async fn create(
                 following,
                 author,
                 author_avatar_url,
-                is_author
-            )))
+                is_author,
+                original_url
+            ))
+            .into())
         }
     }
 }
@@ -152,12 +165,132 @@ pub async fn delete(
     ))
 }
 
+#[derive(Default, FromForm, Debug, Validate)]
+pub struct EditCommentForm {
+    #[validate(length(min = 1, message = "Your comment can't be empty"))]
+    pub content: String,
+    pub warning: String,
+}
+
+#[get("/~/<blog>/<slug>/comment/<id>/edit")]
+pub fn edit(
+    blog: &str,
+    slug: &str,
+    id: i32,
+    user: User,
+    mut conn: DbConn,
+    rockets: PlumeRocket,
+) -> Result<Ructe, ErrorPage> {
+    let comment = Comment::get(&mut conn, id)?;
+    if comment.author_id != user.id {
+        return Err(Error::Unauthorized.into());
+    }
+
+    // Comments posted before their source was kept can only be edited as HTML
+    let content = if comment.source.is_empty() {
+        comment.content.to_string()
+    } else {
+        comment.source.clone()
+    };
+    let form = EditCommentForm {
+        content,
+        warning: comment.spoiler_text.clone(),
+    };
+    Ok(render!(comments::edit_html(
+        &(&mut conn, &rockets).to_context(),
+        blog,
+        slug,
+        &comment,
+        &form,
+        ValidationErrors::default()
+    )))
+}
+
+#[post("/~/<blog>/<slug>/comment/<id>/edit", data = "<form>")]
+pub async fn update(
+    blog: &str,
+    slug: &str,
+    id: i32,
+    form: Form<EditCommentForm>,
+    user: User,
+    mut conn: DbConn,
+    rockets: PlumeRocket,
+) -> Result<RespondOrRedirect, ErrorPage> {
+    let comment = Comment::get(&mut conn, id)?;
+    if comment.author_id != user.id {
+        return Err(Error::Unauthorized.into());
+    }
+    if let Err(errors) = form.validate() {
+        return Ok(render!(comments::edit_html(
+            &(&mut conn, &rockets).to_context(),
+            blog,
+            slug,
+            &comment,
+            &*form,
+            errors
+        ))
+        .into());
+    }
+
+    let (html, mentions, _hashtags) = utils::md_to_html(
+        form.content.as_ref(),
+        Some(&Instance::get_local()?.public_domain),
+        true,
+        Some(Media::get_media_processor(&mut conn, vec![&user])),
+    );
+    let comment =
+        comment.edit(&mut conn, SafeString::new(html.as_ref()), form.content.clone(), form.warning.clone())?;
+
+    let mut new_mentions = vec![];
+    for ment in mentions {
+        if let Ok(mention) = Mention::build_activity(&mut conn, &ment).await {
+            new_mentions.push(mention);
+        }
+    }
+    comment.update_mentions(&mut conn, new_mentions)?;
+
+    // federate
+    let act = comment.update_activity(&mut conn).await?;
+    let dest = User::one_by_instance(&mut conn)?;
+    rockets.worker.execute(move || broadcast(&user, act, dest, CONFIG.proxy().cloned()));
+
+    Ok(Flash::success(
+        Redirect::to(format!(
+            "{}#comment-{}",
+            uri!(super::posts::details(blog = blog, slug = slug, responding_to = _)),
+            comment.id
+        )),
+        i18n!(&rockets.intl.catalog, "Your comment has been updated."),
+    )
+    .into())
+}
+
+#[get("/~/<blog>/<slug>/comment/<id>/history")]
+pub fn history(
+    blog: &str,
+    slug: &str,
+    id: i32,
+    user: Option<User>,
+    mut conn: DbConn,
+    rockets: PlumeRocket,
+) -> Result<Ructe, ErrorPage> {
+    let comment = Comment::get(&mut conn, id)?;
+    if !comment.can_see(&mut conn, user.as_ref()) {
+        return Err(Error::NotFound.into());
+    }
+
+    let author = comment.get_author(&mut conn)?;
+    let edits = CommentEdit::list_for_comment(&mut conn, comment.id)?;
+    Ok(render!(comments::history_html(&(&mut conn, &rockets).to_context(), blog, slug, &comment, &author, edits)))
+}
+
 #[get("/~/<_blog>/<_slug>/comment/<id>")]
 pub async fn activity_pub(
     _blog: &str,
     _slug: &str,
     id: i32,
     _ap: ApRequest,
+    _fetch: AuthorizedFetch,
     mut conn: DbConn,
 ) -> Option<ActivityStream<Note>> {
     match Comment::get(&mut conn, id) {
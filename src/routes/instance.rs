@@ -7,6 +7,7 @@ use rocket::{
 use rocket_i18n::I18n;
 use scheduled_thread_pool::ScheduledThreadPool;
 use std::str::FromStr;
+use tracing::warn;
 use validator::{Validate, ValidationErrors};
 
 use crate::inbox;
@@ -19,8 +20,10 @@ use plume_models::{
     comments::Comment,
     db_conn::DbConn,
     headers::Headers,
+    inbox_jobs::InboxJob,
     instance::*,
     posts::Post,
+    relays::Relay,
     safe_string::SafeString,
     timeline::Timeline,
     users::{Role, User},
@@ -247,6 +250,92 @@ pub fn admin_email_blocklist(
     Ok(render!(instance::emailblocklist_html(&(&mut conn, &rockets).to_context(), block_page, page.0, page_total)))
 }
 
+/// The incoming activities the workers gave up on
+#[get("/admin/inbox?<page>")]
+pub fn admin_inbox(
+    _admin: Admin,
+    page: Option<Page>,
+    mut conn: DbConn,
+    rockets: PlumeRocket,
+) -> Result<Ructe, ErrorPage> {
+    let page = page.unwrap_or_default();
+    let page_total = Page::total(InboxJob::count_failed(&mut conn)? as i32);
+    let jobs = InboxJob::page_failed(&mut conn, page.limits())?;
+    let pending = InboxJob::count_pending(&mut conn)?;
+    Ok(render!(instance::inbox_html(&(&mut conn, &rockets).to_context(), jobs, pending, page.0, page_total)))
+}
+
+#[post("/admin/inbox/<id>/retry")]
+pub fn retry_inbox_job(_admin: Admin, id: i32, mut conn: DbConn, intl: I18n) -> Result<Flash<Redirect>, ErrorPage> {
+    InboxJob::get(&mut conn, id)?.retry(&mut conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(admin_inbox(page = _))),
+        i18n!(intl.catalog, "This activity will be processed again."),
+    ))
+}
+
+#[post("/admin/inbox/<id>/delete")]
+pub fn delete_inbox_job(_admin: Admin, id: i32, mut conn: DbConn, intl: I18n) -> Result<Flash<Redirect>, ErrorPage> {
+    InboxJob::get(&mut conn, id)?.delete(&mut conn)?;
+    Ok(Flash::success(Redirect::to(uri!(admin_inbox(page = _))), i18n!(intl.catalog, "The activity has been deleted.")))
+}
+
+/// The relays the instance is subscribed to
+#[get("/admin/relays")]
+pub fn admin_relays(_admin: Admin, mut conn: DbConn, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let relays = Relay::list(&mut conn)?;
+    Ok(render!(instance::relays_html(&(&mut conn, &rockets).to_context(), relays)))
+}
+
+#[derive(FromForm)]
+pub struct NewRelayForm {
+    url: String,
+}
+
+#[post("/admin/relays", data = "<form>")]
+pub fn add_relay(
+    _admin: Admin,
+    form: Form<NewRelayForm>,
+    mut conn: DbConn,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let relay = match Relay::subscribe(&mut conn, &form.url) {
+        Ok(relay) => relay,
+        Err(e) => {
+            warn!("Couldn't subscribe to the relay {}: {:?}", form.url, e);
+            return Ok(Flash::error(
+                Redirect::to(uri!(admin_relays)),
+                i18n!(rockets.intl.catalog, "This relay could not be reached."),
+            ));
+        }
+    };
+    let follow = relay.follow_activity()?;
+    let sender = Instance::get_local_instance_user().ok_or(Error::NotFound)?;
+    rockets.worker.execute(move || broadcast(sender, follow, vec![relay], CONFIG.proxy().cloned()));
+    Ok(Flash::success(
+        Redirect::to(uri!(admin_relays)),
+        i18n!(rockets.intl.catalog, "A subscription request has been sent to the relay."),
+    ))
+}
+
+#[post("/admin/relays/<id>/delete")]
+pub fn delete_relay(
+    _admin: Admin,
+    id: i32,
+    mut conn: DbConn,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let relay = Relay::get(&mut conn, id)?;
+    relay.delete(&mut conn)?;
+    let undo = relay.undo_activity()?;
+    let sender = Instance::get_local_instance_user().ok_or(Error::NotFound)?;
+    rockets.worker.execute(move || broadcast(sender, undo, vec![relay], CONFIG.proxy().cloned()));
+    Ok(Flash::success(
+        Redirect::to(uri!(admin_relays)),
+        i18n!(rockets.intl.catalog, "You are not subscribed to this relay anymore."),
+    ))
+}
+
 /// A structure to handle forms that are a list of items on which actions are applied.
 /// This is for instance the case of the user list in the administration.
 #[derive(FromForm)]
@@ -361,7 +450,7 @@ pub async fn shared_inbox(
     conn: DbConn,
     data: inbox::SignedJson<serde_json::Value>,
     headers: Headers<'_>,
-) -> Result<String, status::BadRequest<&'static str>> {
+) -> Result<status::Accepted<()>, status::BadRequest<&'static str>> {
     inbox::handle_incoming(conn, data, headers).await
 }
 
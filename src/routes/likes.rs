@@ -5,7 +5,7 @@ use crate::routes::errors::ErrorPage;
 use crate::utils::requires_login;
 use plume_common::activity_pub::broadcast;
 use plume_models::{
-    blogs::Blog, db_conn::DbConn, inbox::inbox, likes, posts::Post, timeline::*, users::User, Error, PlumeRocket,
+    blogs::Blog, db_conn::DbConn, inbox::inbox, likes, live, posts::Post, timeline::*, users::User, Error, PlumeRocket,
     CONFIG,
 };
 
@@ -21,8 +21,11 @@ pub async fn create(
     let post = Post::find_by_slug(&mut conn, slug, b.id)?;
 
     if !user.has_liked(&mut conn, &post)? {
-        let like = likes::Like::insert(&mut conn, likes::NewLike::new(&post, &user))?;
-        like.notify(&mut conn)?;
+        let like = live::transaction(&mut conn, |conn| {
+            let like = likes::Like::insert(conn, likes::NewLike::new(&post, &user))?;
+            like.notify(conn)?;
+            Ok(like)
+        })?;
 
         Timeline::add_to_all_timelines(&mut conn, &post, &Kind::Like(user.clone())).await?;
 
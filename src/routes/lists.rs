@@ -0,0 +1,237 @@
+use rocket::{
+    form::Form,
+    response::{Flash, Redirect},
+};
+use rocket_i18n::I18n;
+use std::convert::TryFrom;
+
+use crate::routes::errors::ErrorPage;
+use crate::template_utils::{IntoContext, Ructe};
+use plume_models::{
+    blogs::Blog, db_conn::DbConn, lists::*, timeline::Timeline, users::User, Connection, Error, PlumeRocket,
+};
+
+/// An element of a list, as displayed on its page
+pub struct ListElement {
+    /// The value to send to remove this element from the list
+    pub value: String,
+    pub label: String,
+    pub url: Option<String>,
+}
+
+/// Returns the list `id` if it belongs to `user`
+fn own_list(conn: &mut Connection, id: i32, user: &User) -> Result<List, Error> {
+    let list = List::get(conn, id)?;
+    if list.user_id == Some(user.id) {
+        Ok(list)
+    } else {
+        Err(Error::Unauthorized)
+    }
+}
+
+fn list_elements(conn: &mut Connection, list: &List) -> Result<Vec<ListElement>, Error> {
+    Ok(match list.kind() {
+        ListType::User => list
+            .list_users(conn)?
+            .into_iter()
+            .map(|u| ListElement {
+                value: u.id.to_string(),
+                label: format!("{} (@{})", u.name(), u.fqn),
+                url: Some(uri!(super::user::details(name = &u.fqn)).to_string()),
+            })
+            .collect(),
+        ListType::Blog => list
+            .list_blogs(conn)?
+            .into_iter()
+            .map(|b| ListElement {
+                value: b.id.to_string(),
+                label: format!("{} (~{})", b.title, b.fqn),
+                url: Some(uri!(super::blogs::details(name = &b.fqn, page = _)).to_string()),
+            })
+            .collect(),
+        ListType::Word => list.list_words(conn)?.into_iter().map(ListElement::word).collect(),
+        ListType::Prefix => list.list_prefixes(conn)?.into_iter().map(ListElement::word).collect(),
+    })
+}
+
+impl ListElement {
+    fn word(word: String) -> Self {
+        ListElement {
+            value: word.clone(),
+            label: word,
+            url: None,
+        }
+    }
+}
+
+#[get("/lists")]
+pub fn index(user: User, mut conn: DbConn, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let lists = List::list_for_user(&mut conn, Some(user.id))?
+        .into_iter()
+        .map(|list| {
+            let timelines = Timeline::list_using_list(&mut conn, &list)?;
+            Ok((list, timelines))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    Ok(render!(lists::index_html(&(&mut conn, &rockets).to_context(), lists)))
+}
+
+#[derive(FromForm)]
+pub struct NewListForm {
+    pub name: String,
+    pub kind: i32,
+}
+
+#[post("/lists/new", data = "<form>")]
+pub fn create(form: Form<NewListForm>, user: User, mut conn: DbConn, intl: I18n) -> Result<Flash<Redirect>, ErrorPage> {
+    let name = form.name.trim();
+    let kind = ListType::try_from(form.kind).map_err(|_| Error::InvalidValue)?;
+    if name.is_empty() {
+        return Ok(Flash::error(Redirect::to(uri!(index)), i18n!(intl.catalog, "Please give a name to this list.")));
+    }
+    if List::find_for_user_by_name(&mut conn, Some(user.id), name).is_ok() {
+        return Ok(Flash::error(
+            Redirect::to(uri!(index)),
+            i18n!(intl.catalog, "You already have a list with this name."),
+        ));
+    }
+
+    let list = List::new(&mut conn, name, Some(&user), kind)?;
+    Ok(Flash::success(Redirect::to(uri!(details(id = list.id))), i18n!(intl.catalog, "Your list has been created.")))
+}
+
+#[get("/lists/<id>")]
+pub fn details(id: i32, user: User, mut conn: DbConn, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let list = own_list(&mut conn, id, &user)?;
+    let elements = list_elements(&mut conn, &list)?;
+    let timelines = Timeline::list_using_list(&mut conn, &list)?;
+    // Suggest the accounts the user follows when adding people
+    let suggestions = match list.kind() {
+        ListType::User => user.get_followed(&mut conn)?.into_iter().map(|u| u.fqn).collect(),
+        _ => vec![],
+    };
+    Ok(render!(lists::details_html(&(&mut conn, &rockets).to_context(), list, elements, timelines, suggestions)))
+}
+
+#[derive(FromForm)]
+pub struct RenameListForm {
+    pub name: String,
+}
+
+#[post("/lists/<id>/rename", data = "<form>")]
+pub fn rename(
+    id: i32,
+    form: Form<RenameListForm>,
+    user: User,
+    mut conn: DbConn,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let mut list = own_list(&mut conn, id, &user)?;
+    let name = form.name.trim();
+    if name.is_empty() {
+        return Ok(Flash::error(
+            Redirect::to(uri!(details(id = id))),
+            i18n!(intl.catalog, "Please give a name to this list."),
+        ));
+    }
+    if name != list.name && List::find_for_user_by_name(&mut conn, Some(user.id), name).is_ok() {
+        return Ok(Flash::error(
+            Redirect::to(uri!(details(id = id))),
+            i18n!(intl.catalog, "You already have a list with this name."),
+        ));
+    }
+
+    list.rename(&mut conn, name)?;
+    Ok(Flash::success(Redirect::to(uri!(details(id = id))), i18n!(intl.catalog, "Your list has been renamed.")))
+}
+
+#[derive(FromForm)]
+pub struct ListElementForm {
+    pub value: String,
+}
+
+#[post("/lists/<id>/add", data = "<form>")]
+pub async fn add(
+    id: i32,
+    form: Form<ListElementForm>,
+    user: User,
+    mut conn: DbConn,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let list = own_list(&mut conn, id, &user)?;
+    let value = form.value.trim();
+    if value.is_empty() {
+        return Ok(Flash::error(Redirect::to(uri!(details(id = id))), i18n!(intl.catalog, "Please enter a value.")));
+    }
+
+    // Users and blogs are given by their handle, and are fetched if they are not known yet
+    let added = match list.kind() {
+        ListType::User => match User::find_by_fqn(&mut conn, value.trim_start_matches('@')).await {
+            Ok(u) if !list.contains_user(&mut conn, u.id)? => list.add_users(&mut conn, &[u.id]).map(|_| true)?,
+            Ok(_) => false,
+            Err(_) => {
+                return Ok(Flash::error(
+                    Redirect::to(uri!(details(id = id))),
+                    i18n!(intl.catalog, "Couldn't find anyone named {0}."; value),
+                ))
+            }
+        },
+        ListType::Blog => match Blog::find_by_fqn(&mut conn, value.trim_start_matches('~')).await {
+            Ok(b) if !list.contains_blog(&mut conn, b.id)? => list.add_blogs(&mut conn, &[b.id]).map(|_| true)?,
+            Ok(_) => false,
+            Err(_) => {
+                return Ok(Flash::error(
+                    Redirect::to(uri!(details(id = id))),
+                    i18n!(intl.catalog, "Couldn't find a blog named {0}."; value),
+                ))
+            }
+        },
+        ListType::Word if !list.contains_word(&mut conn, value)? => {
+            list.add_words(&mut conn, &[value]).map(|_| true)?
+        }
+        ListType::Prefix if !list.list_prefixes(&mut conn)?.iter().any(|p| p == value) => {
+            list.add_prefixes(&mut conn, &[value]).map(|_| true)?
+        }
+        ListType::Word | ListType::Prefix => false,
+    };
+
+    if added {
+        Ok(Flash::success(
+            Redirect::to(uri!(details(id = id))),
+            i18n!(intl.catalog, "{0} has been added to this list."; value),
+        ))
+    } else {
+        Ok(Flash::error(
+            Redirect::to(uri!(details(id = id))),
+            i18n!(intl.catalog, "{0} is already in this list."; value),
+        ))
+    }
+}
+
+#[post("/lists/<id>/remove", data = "<form>")]
+pub fn remove(id: i32, form: Form<ListElementForm>, user: User, mut conn: DbConn) -> Result<Redirect, ErrorPage> {
+    let list = own_list(&mut conn, id, &user)?;
+    match list.kind() {
+        ListType::User => list.remove_users(&mut conn, &[form.value.parse().map_err(|_| Error::InvalidValue)?])?,
+        ListType::Blog => list.remove_blogs(&mut conn, &[form.value.parse().map_err(|_| Error::InvalidValue)?])?,
+        ListType::Word => list.remove_words(&mut conn, &[&form.value])?,
+        ListType::Prefix => list.remove_prefixes(&mut conn, &[&form.value])?,
+    }
+    Ok(Redirect::to(uri!(details(id = id))))
+}
+
+#[post("/lists/<id>/delete")]
+pub fn delete(id: i32, user: User, mut conn: DbConn, intl: I18n) -> Result<Flash<Redirect>, ErrorPage> {
+    let list = own_list(&mut conn, id, &user)?;
+    let timelines = Timeline::list_using_list(&mut conn, &list)?;
+    if !timelines.is_empty() {
+        let names = timelines.into_iter().map(|t| t.name).collect::<Vec<_>>().join(", ");
+        return Ok(Flash::error(
+            Redirect::to(uri!(details(id = id))),
+            i18n!(intl.catalog, "This list is still used by these timelines: {0}"; names),
+        ));
+    }
+
+    list.delete(&mut conn)?;
+    Ok(Flash::success(Redirect::to(uri!(index)), i18n!(intl.catalog, "Your list has been deleted.")))
+}
@@ -1,6 +1,7 @@
 use crate::routes::{errors::ErrorPage, Page};
 use crate::template_utils::{IntoContext, Ructe};
 use guid_create::GUID;
+use plume_common::activity_pub::broadcast;
 use plume_models::{db_conn::DbConn, medias::*, users::User, Error, PlumeRocket, CONFIG};
 use rocket::{
     form::Form,
@@ -119,15 +120,24 @@ pub fn delete(id: i32, user: User, mut conn: DbConn, intl: I18n) -> Result<Flash
 }
 
 #[post("/medias/<id>/avatar")]
-pub fn set_avatar(id: i32, user: User, mut conn: DbConn, intl: I18n) -> Result<Flash<Redirect>, ErrorPage> {
+pub fn set_avatar(id: i32, user: User, mut conn: DbConn, rockets: PlumeRocket) -> Result<Flash<Redirect>, ErrorPage> {
     let media = Media::get(&mut conn, id)?;
     if media.owner_id == user.id {
         user.set_avatar(&mut conn, media.id)?;
-        Ok(Flash::success(Redirect::to(uri!(details(id = id))), i18n!(intl.catalog, "Your avatar has been updated.")))
+
+        let user = User::get(&mut conn, user.id)?;
+        let act = user.update_activity(&mut conn)?;
+        let followers = user.get_followers(&mut conn)?;
+        rockets.worker.execute(move || broadcast(&user, act, followers, CONFIG.proxy().cloned()));
+
+        Ok(Flash::success(
+            Redirect::to(uri!(details(id = id))),
+            i18n!(rockets.intl.catalog, "Your avatar has been updated."),
+        ))
     } else {
         Ok(Flash::error(
             Redirect::to(uri!(details(id = id))),
-            i18n!(intl.catalog, "You are not allowed to use this media."),
+            i18n!(rockets.intl.catalog, "You are not allowed to use this media."),
         ))
     }
 }
@@ -1,29 +1,36 @@
 #![allow(clippy::needless_borrows_for_generic_args)]
 
 use crate::template_utils::Ructe;
-use atom_syndication::{ContentBuilder, Entry, EntryBuilder, Feed, FeedBuilder, LinkBuilder, Person, PersonBuilder};
+use atom_syndication::{
+    ContentBuilder, Entry, EntryBuilder, Feed, FeedBuilder, LinkBuilder, Person, PersonBuilder, Text,
+};
 use chrono::{naive::NaiveDateTime, DateTime, Duration, Utc};
-use plume_models::{posts::Post, Connection, CONFIG, ITEMS_PER_PAGE};
+use plume_models::{medias::Media, posts::Post, Connection, CONFIG, ITEMS_PER_PAGE};
+use quick_xml::{
+    events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event},
+    Writer,
+};
 use rocket::{
     form::{FromFormField, ValueField},
     fs::NamedFile,
     http::{
         hyper::header::{CACHE_CONTROL, ETAG},
-        uri::fmt::{FromUriParam, Query},
-        Header, Status,
+        impl_from_uri_param_identity,
+        uri::fmt::{Formatter, FromUriParam, Query, UriDisplay},
+        ContentType, Header, Status,
     },
     request::{self, FromRequest, Outcome, Request},
     response::{self, Flash, Redirect, Responder, Response},
 };
+use serde_json::json;
 use std::{
     collections::hash_map::DefaultHasher,
+    fmt,
     hash::Hasher,
+    io,
     path::{Path, PathBuf},
 };
 
-#[cfg(feature = "s3")]
-use rocket::http::ContentType;
-
 /// Special return type used for routes that "cannot fail", and instead
 /// `Redirect`, or `Flash<Redirect>`, when we cannot deliver a `Ructe` Response
 #[allow(clippy::large_enum_variant)]
@@ -124,37 +131,127 @@ pub struct RemoteForm {
     pub remote: String,
 }
 
+/// The formats in which feeds are available
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+    Json,
+}
+
+impl FeedFormat {
+    /// The last segment of the URL of feeds in this format
+    pub fn file_name(self) -> &'static str {
+        match self {
+            FeedFormat::Atom => "atom.xml",
+            FeedFormat::Rss => "rss.xml",
+            FeedFormat::Json => "feed.json",
+        }
+    }
+
+    pub fn content_type(self) -> ContentType {
+        match self {
+            FeedFormat::Atom => ContentType::new("application", "atom+xml"),
+            FeedFormat::Rss => ContentType::new("application", "rss+xml"),
+            FeedFormat::Json => ContentType::new("application", "feed+json"),
+        }
+    }
+}
+
+/// How much of each article is put in a feed, chosen with the `content` query parameter
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, FromFormField)]
+pub enum FeedContent {
+    #[default]
+    Full,
+    Summary,
+}
+
+impl UriDisplay<Query> for FeedContent {
+    fn fmt(&self, f: &mut Formatter<'_, Query>) -> fmt::Result {
+        f.write_value(match self {
+            FeedContent::Full => "full",
+            FeedContent::Summary => "summary",
+        })
+    }
+}
+
+impl_from_uri_param_identity!([Query] FeedContent);
+
+/// What a feed is about
+pub struct FeedInfo<'a> {
+    /// The URL of the feed itself
+    pub uri: &'a str,
+    /// The URL of the page showing the same articles
+    pub home_page: &'a str,
+    pub title: &'a str,
+    /// The date the feed was last updated on, if it has no entries. It should not change between two requests.
+    pub default_updated: &'a NaiveDateTime,
+}
+
+/// Renders `entries` as a feed in the requested format
+pub fn build_feed(
+    format: FeedFormat,
+    content: FeedContent,
+    entries: Vec<Post>,
+    info: FeedInfo<'_>,
+    conn: &mut Connection,
+) -> (ContentType, String) {
+    let feed = match format {
+        FeedFormat::Atom => {
+            build_atom_feed(entries, info.uri, info.title, info.default_updated, content, conn).to_string()
+        }
+        FeedFormat::Rss => build_rss_feed(entries, &info, content, conn),
+        FeedFormat::Json => build_json_feed(entries, &info, content, conn).to_string(),
+    };
+    (format.content_type(), feed)
+}
+
+fn feed_updated(entries: &[Post], default_updated: &NaiveDateTime) -> DateTime<Utc> {
+    let updated = entries.first().map(|p| &p.creation_date).unwrap_or(default_updated);
+    DateTime::<Utc>::from_naive_utc_and_offset(*updated, Utc)
+}
+
+/// The URL and the MIME type of the cover of a post
+fn feed_cover(post: &Post, conn: &mut Connection) -> Option<(String, &'static str)> {
+    let cover = Media::get(conn, post.cover_id?).ok()?;
+    Some((cover.url().ok()?, cover.mime_type()))
+}
+
 pub fn build_atom_feed(
     entries: Vec<Post>,
     uri: &str,
     title: &str,
     default_updated: &NaiveDateTime,
+    content: FeedContent,
     conn: &mut Connection,
 ) -> Feed {
-    let updated = if entries.is_empty() {
-        default_updated
-    } else {
-        &entries[0].creation_date
-    };
-
     FeedBuilder::default()
         .title(title)
         .id(uri)
-        .updated(DateTime::<Utc>::from_naive_utc_and_offset(*updated, Utc))
-        .entries(entries.into_iter().map(|p| post_to_atom(p, conn)).collect::<Vec<Entry>>())
+        .updated(feed_updated(&entries, default_updated))
+        .entries(entries.into_iter().map(|p| post_to_atom(p, content, conn)).collect::<Vec<Entry>>())
         .links(vec![LinkBuilder::default().href(uri).rel("self").mime_type("application/atom+xml".to_string()).build()])
         .build()
 }
 
-fn post_to_atom(post: Post, conn: &mut Connection) -> Entry {
-    EntryBuilder::default()
-        .title(format!("<![CDATA[{}]]>", post.title))
-        .content(
+fn post_to_atom(post: Post, content: FeedContent, conn: &mut Connection) -> Entry {
+    let mut links = vec![LinkBuilder::default().href(post.ap_url.clone()).build()];
+    if let Some((url, mime_type)) = feed_cover(&post, conn) {
+        links.push(LinkBuilder::default().href(url).rel("enclosure").mime_type(mime_type.to_string()).build());
+    }
+
+    let mut entry = EntryBuilder::default();
+    match content {
+        FeedContent::Full => entry.content(
             ContentBuilder::default()
                 .value(format!("<![CDATA[{}]]>", *post.content.get()))
                 .content_type("html".to_string())
                 .build(),
-        )
+        ),
+        FeedContent::Summary => entry.summary(Text::html(format!("<![CDATA[{}]]>", post.summary()))),
+    };
+    entry
+        .title(format!("<![CDATA[{}]]>", post.title))
         .authors(
             post.get_authors(conn)
                 .expect("Atom feed: author error")
@@ -167,17 +264,139 @@ fn post_to_atom(post: Post, conn: &mut Connection) -> Entry {
         .published(Some(DateTime::<Utc>::from_naive_utc_and_offset(post.creation_date, Utc).into()))
         .updated(DateTime::<Utc>::from_naive_utc_and_offset(post.creation_date, Utc))
         .id(post.ap_url.clone())
-        .links(vec![LinkBuilder::default().href(post.ap_url).build()])
+        .links(links)
         .build()
 }
 
+/// Writes a RSS 2.0 feed, see https://www.rssboard.org/rss-specification
+fn build_rss_feed(entries: Vec<Post>, info: &FeedInfo<'_>, content: FeedContent, conn: &mut Connection) -> String {
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    // Writing to memory can't fail
+    write_rss_feed(&mut writer, entries, info, content, conn).expect("RSS feed: write error");
+    String::from_utf8(writer.into_inner()).expect("RSS feed: encoding error")
+}
+
+fn write_rss_feed(
+    writer: &mut Writer<Vec<u8>>,
+    entries: Vec<Post>,
+    info: &FeedInfo<'_>,
+    content: FeedContent,
+    conn: &mut Connection,
+) -> io::Result<()> {
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)))?;
+    writer.write_event(Event::Start(BytesStart::new("rss").with_attributes([
+        ("version", "2.0"),
+        ("xmlns:atom", "http://www.w3.org/2005/Atom"),
+        ("xmlns:dc", "http://purl.org/dc/elements/1.1/"),
+    ])))?;
+    writer.write_event(Event::Start(BytesStart::new("channel")))?;
+    writer.create_element("title").write_text_content(BytesText::new(info.title))?;
+    writer.create_element("link").write_text_content(BytesText::new(info.home_page))?;
+    writer.create_element("description").write_text_content(BytesText::new(info.title))?;
+    writer
+        .create_element("atom:link")
+        .with_attributes([("href", info.uri), ("rel", "self"), ("type", "application/rss+xml")])
+        .write_empty()?;
+    let updated = feed_updated(&entries, info.default_updated).to_rfc2822();
+    writer.create_element("lastBuildDate").write_text_content(BytesText::new(&updated))?;
+
+    for post in entries {
+        let description = match content {
+            FeedContent::Full => post.content.get().clone(),
+            FeedContent::Summary => post.summary(),
+        };
+        let date = DateTime::<Utc>::from_naive_utc_and_offset(post.creation_date, Utc).to_rfc2822();
+        writer.write_event(Event::Start(BytesStart::new("item")))?;
+        writer.create_element("title").write_text_content(BytesText::new(&post.title))?;
+        writer.create_element("link").write_text_content(BytesText::new(&post.ap_url))?;
+        writer
+            .create_element("guid")
+            .with_attribute(("isPermaLink", "true"))
+            .write_text_content(BytesText::new(&post.ap_url))?;
+        writer.create_element("pubDate").write_text_content(BytesText::new(&date))?;
+        writer.create_element("description").write_text_content(BytesText::new(&description))?;
+        // The author element of RSS is an email address, that we don't want to expose
+        for author in post.get_authors(conn).unwrap_or_default() {
+            writer.create_element("dc:creator").write_text_content(BytesText::new(&author.display_name))?;
+        }
+        if let Some((url, mime_type)) = feed_cover(&post, conn) {
+            // The size of remote files is unknown, and 0 is what readers expect in this case
+            writer
+                .create_element("enclosure")
+                .with_attributes([("url", url.as_str()), ("length", "0"), ("type", mime_type)])
+                .write_empty()?;
+        }
+        writer.write_event(Event::End(BytesEnd::new("item")))?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::new("channel")))?;
+    writer.write_event(Event::End(BytesEnd::new("rss")))?;
+    Ok(())
+}
+
+/// Writes a JSON Feed 1.1, see https://www.jsonfeed.org/version/1.1/
+fn build_json_feed(
+    entries: Vec<Post>,
+    info: &FeedInfo<'_>,
+    content: FeedContent,
+    conn: &mut Connection,
+) -> serde_json::Value {
+    let items = entries
+        .into_iter()
+        .map(|post| {
+            let authors = post
+                .get_authors(conn)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|a| json!({ "name": a.display_name, "url": a.ap_url }))
+                .collect::<Vec<_>>();
+            let date = DateTime::<Utc>::from_naive_utc_and_offset(post.creation_date, Utc).to_rfc3339();
+            let mut item = json!({
+                "id": post.ap_url,
+                "url": post.ap_url,
+                "title": post.title,
+                "date_published": date,
+                "authors": authors,
+            });
+            match content {
+                FeedContent::Full => {
+                    item["content_html"] = json!(post.content.get());
+                    if !post.subtitle.is_empty() {
+                        item["summary"] = json!(post.subtitle);
+                    }
+                }
+                FeedContent::Summary => {
+                    let summary = post.summary();
+                    item["content_text"] = json!(summary);
+                    item["summary"] = json!(summary);
+                }
+            }
+            if let Some((url, mime_type)) = feed_cover(&post, conn) {
+                item["image"] = json!(url);
+                item["attachments"] = json!([{ "url": url, "mime_type": mime_type }]);
+            }
+            item
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": info.title,
+        "home_page_url": info.home_page,
+        "feed_url": info.uri,
+        "items": items,
+    })
+}
+
 pub mod blogs;
 pub mod comments;
 pub mod email_signups;
 pub mod errors;
 pub mod instance;
 pub mod likes;
+pub mod lists;
 pub mod medias;
+pub mod newsletters;
 pub mod notifications;
 pub mod posts;
 pub mod reshares;
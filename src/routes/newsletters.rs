@@ -0,0 +1,236 @@
+use crate::{
+    mail::{build_mail, build_newsletter, Mailer},
+    routes::errors::ErrorPage,
+    template_utils::{IntoContext, Ructe},
+};
+use gettext::Catalog;
+use lettre::{Message, Transport};
+use plume_common::utils::escape;
+use plume_models::{
+    blogs::Blog,
+    db_conn::{DbConn, DbPool},
+    newsletters::{EmailSuppression, NewsletterSubscription},
+    posts::Post,
+    Connection, Error, PlumeRocket, CONFIG,
+};
+use rocket::{
+    form::Form,
+    response::{Flash, Redirect},
+    State,
+};
+use rocket_i18n::Translations;
+use scheduled_thread_pool::ScheduledThreadPool;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+use validator::Validate;
+
+#[derive(FromForm, Validate)]
+pub struct SubscribeForm {
+    #[validate(email(message = "Invalid email"))]
+    pub email: String,
+}
+
+#[post("/~/<name>/newsletter", data = "<form>")]
+pub async fn subscribe(
+    name: String,
+    form: Form<SubscribeForm>,
+    mail: &State<Arc<Mutex<Mailer>>>,
+    mut conn: DbConn,
+    rockets: PlumeRocket,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let blog = Blog::find_by_fqn(&mut conn, &name).await?;
+    let back = Redirect::to(uri!(super::blogs::details(name = &name, page = _)));
+    let email = form.email.trim();
+    if form.validate().is_err() {
+        return Ok(Flash::error(back, i18n!(rockets.intl.catalog, "Please enter a valid email address.")));
+    }
+
+    let token = match NewsletterSubscription::start(&mut conn, &blog, email, &rockets.intl.lang) {
+        Ok(token) => token,
+        Err(Error::Blocklisted(true, msg)) => return Ok(Flash::error(back, msg)),
+        Err(Error::Blocklisted(false, _)) => None,
+        Err(e) => return Err(e.into()),
+    };
+    // Don't tell whether this address was already subscribed
+    if let Some(token) = token {
+        let url = format!("https://{}{}", CONFIG.base_url, uri!(confirm(token = token.to_string())));
+        let message = build_mail(
+            email.to_owned(),
+            i18n!(rockets.intl.catalog, "Subscription to {0}"; &blog.title),
+            i18n!(rockets.intl.catalog, "To receive the new articles of {0} by email, follow this link: {1}"; &blog.title, url),
+        )
+        .ok_or(Error::InvalidValue)?;
+        if let Some(ref mut mailer) = *mail.lock().unwrap() {
+            if let Err(e) = mailer.send(&message) {
+                warn!("Couldn't send the newsletter confirmation mail: {}", e);
+            }
+        }
+    }
+
+    Ok(Flash::success(
+        back,
+        i18n!(
+            rockets.intl.catalog,
+            "Check your inbox: you will receive the new articles once you confirmed your subscription."
+        ),
+    ))
+}
+
+#[get("/newsletter/<token>")]
+pub fn confirm(token: String, mut conn: DbConn, rockets: PlumeRocket) -> Result<Flash<Redirect>, ErrorPage> {
+    let subscription = NewsletterSubscription::find_by_token(&mut conn, &token)?;
+    let blog = Blog::get(&mut conn, subscription.blog_id)?;
+    let back = Redirect::to(uri!(super::blogs::details(name = &blog.fqn, page = _)));
+    match subscription.confirm(&mut conn) {
+        Ok(()) => Ok(Flash::success(
+            back,
+            i18n!(rockets.intl.catalog, "You will now receive the new articles of this blog by email."),
+        )),
+        Err(Error::Expired) => {
+            Ok(Flash::error(back, i18n!(rockets.intl.catalog, "This link has expired, please subscribe again.")))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[get("/newsletter/<token>/unsubscribe")]
+pub fn unsubscribe_page(token: String, mut conn: DbConn, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let subscription = NewsletterSubscription::find_by_unsubscribe_token(&mut conn, &token)?;
+    let blog = Blog::get(&mut conn, subscription.blog_id)?;
+    Ok(render!(newsletters::unsubscribe_html(&(&mut conn, &rockets).to_context(), &blog, &token)))
+}
+
+/// Also used by mail clients to unsubscribe in one click, as advertised in the headers of the newsletters
+#[post("/newsletter/<token>/unsubscribe")]
+pub fn unsubscribe(token: String, mut conn: DbConn, rockets: PlumeRocket) -> Result<Flash<Redirect>, ErrorPage> {
+    let subscription = NewsletterSubscription::find_by_unsubscribe_token(&mut conn, &token)?;
+    let blog = Blog::get(&mut conn, subscription.blog_id)?;
+    subscription.delete(&mut conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(super::blogs::details(name = &blog.fqn, page = _))),
+        i18n!(rockets.intl.catalog, "You won't receive the articles of this blog by email anymore."),
+    ))
+}
+
+fn newsletter_html(post: &Post, blog: &Blog, url: &str, unsubscribe: &str, intl: &Catalog) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>{title}</title></head>
+<body>
+<p><a href="{blog_url}">{blog}</a></p>
+<h1><a href="{url}">{title}</a></h1>
+{subtitle}
+{content}
+<p><a href="{url}">{read}</a></p>
+<hr>
+<p><small><a href="{unsubscribe}">{unsubscribe_label}</a></small></p>
+</body>
+</html>"#,
+        title = escape(&post.title),
+        blog_url = escape(&blog.ap_url),
+        blog = escape(&blog.title),
+        url = escape(url),
+        subtitle = if post.subtitle.is_empty() {
+            String::new()
+        } else {
+            format!("<h2>{}</h2>", escape(&post.subtitle))
+        },
+        content = post.content.get(),
+        read = escape(&i18n!(intl, "Read this article online")),
+        unsubscribe = escape(unsubscribe),
+        unsubscribe_label = escape(&i18n!(intl, "Unsubscribe from {0}"; &blog.title)),
+    )
+}
+
+fn newsletter_text(post: &Post, blog: &Blog, url: &str, unsubscribe: &str, intl: &Catalog) -> String {
+    format!(
+        "{blog}\n\n{title}\n\n{summary}\n\n{read}: {url}\n\n-- \n{unsubscribe_label}: {unsubscribe}\n",
+        blog = blog.title,
+        title = post.title,
+        summary = post.summary(),
+        read = i18n!(intl, "Read this article online"),
+        url = url,
+        unsubscribe_label = i18n!(intl, "Unsubscribe from {0}"; &blog.title),
+        unsubscribe = unsubscribe,
+    )
+}
+
+/// Mails a newly published article to the readers who subscribed to its blog.
+///
+/// Each mail is written in the language the reader subscribed in, or in the default language of the instance. The
+/// mails are sent in the background. Addresses the mail server permanently refuses are suppressed, so that they don't
+/// get any other newsletter until they subscribe again.
+pub fn send_post(
+    conn: &mut Connection,
+    post: &Post,
+    mail: &Arc<Mutex<Mailer>>,
+    dbpool: &DbPool,
+    worker: &ScheduledThreadPool,
+    translations: &Translations,
+) -> Result<(), Error> {
+    if post.original_url(conn)?.is_some() {
+        return Ok(());
+    }
+    let blog = post.get_blog(conn)?;
+    let url = format!("https://{}{}", CONFIG.base_url, post.url(conn)?);
+    let messages = NewsletterSubscription::list_recipients(conn, blog.id)?
+        .into_iter()
+        .filter_map(|subscription| {
+            let (_, intl) =
+                translations.iter().find(|(lang, _)| *lang == subscription.lang).or_else(|| translations.first())?;
+            let unsubscribe = format!(
+                "https://{}{}",
+                CONFIG.base_url,
+                uri!(unsubscribe(token = subscription.unsubscribe_token.clone()))
+            );
+            let message = build_newsletter(
+                &subscription.email,
+                format!("{} — {}", post.title, blog.title),
+                newsletter_text(post, &blog, &url, &unsubscribe, intl),
+                newsletter_html(post, &blog, &url, &unsubscribe, intl),
+                &unsubscribe,
+            )?;
+            Some((subscription.email, message))
+        })
+        .collect::<Vec<(String, Message)>>();
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    let mail = mail.clone();
+    let dbpool = dbpool.clone();
+    worker.execute(move || {
+        let mut bounced = vec![];
+        for (email, message) in messages {
+            // The mailer is only locked for one message at a time, so that other mails don't wait for all of them
+            let sent = match *mail.lock().unwrap() {
+                Some(ref mut mailer) => mailer.send(&message),
+                None => break,
+            };
+            match sent {
+                Ok(_) => {}
+                Err(e) if e.is_permanent() => {
+                    warn!("Newsletter refused for {}: {}", email, e);
+                    bounced.push(email);
+                }
+                Err(e) => warn!("Couldn't send the newsletter to {}: {}", email, e),
+            }
+        }
+        if bounced.is_empty() {
+            return;
+        }
+        match dbpool.get() {
+            Ok(conn) => {
+                let mut conn = DbConn(conn);
+                for email in bounced {
+                    if let Err(e) = EmailSuppression::suppress(&mut conn, &email) {
+                        warn!("Couldn't suppress {}: {:?}", email, e);
+                    }
+                }
+            }
+            Err(e) => warn!("Couldn't suppress the addresses that bounced: {}", e),
+        }
+    });
+    Ok(())
+}
@@ -1,13 +1,50 @@
-use rocket::response::{Flash, Redirect};
-use rocket_i18n::I18n;
+use lettre::Transport;
+use rocket::{
+    form::Form,
+    response::{
+        stream::{Event, EventStream},
+        Flash, Redirect,
+    },
+    tokio::{select, sync::broadcast::error::RecvError, task::spawn_blocking},
+    Shutdown, State,
+};
+use rocket_i18n::{I18n, Translations};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    sync::{Arc, Mutex},
+};
+use tracing::{debug, warn};
 
+use crate::mail::{build_html_mail, Mailer};
 use crate::routes::{errors::ErrorPage, Page};
-use crate::template_utils::{IntoContext, Ructe};
+use crate::template_utils::{translate_notification, IntoContext, Ructe};
 use crate::utils::requires_login;
-use plume_models::{db_conn::DbConn, notifications::Notification, posts::Post, users::User, PlumeRocket};
+use plume_models::{
+    db_conn::{DbConn, DbPool},
+    email_preferences::{EmailFrequency, EmailPreference},
+    live::{self, LiveEvent},
+    notifications::Notification,
+    posts::Post,
+    push_subscriptions::{NewPushSubscription, PushSubscription, VapidKeys},
+    users::User,
+    Connection, Error, PlumeRocket, CONFIG,
+};
+
+/// How many browsers are sent push notifications at once
+const PUSH_CONCURRENCY: usize = 8;
 
 pub type NotificationDetails = (Notification, Option<User>, Option<String>, Option<Post>, String);
 
+fn notification_details(conn: &mut Connection, n: Notification) -> NotificationDetails {
+    let actor = n.get_actor(conn).ok();
+    let url = n.get_url(conn);
+    let post = n.get_post(conn);
+    let post_url = post.as_ref().and_then(|p| p.url(conn).ok()).unwrap_or_default();
+    (n, actor, url, post, post_url)
+}
+
 #[get("/notifications?<page>")]
 pub fn notifications(
     user: User,
@@ -20,14 +57,9 @@ pub fn notifications(
 
     let notifs = Notification::page_for_user(&mut conn, &user, page.limits())?
         .into_iter()
-        .map(|n| {
-            let actor = n.get_actor(&mut conn).ok();
-            let url = n.get_url(&mut conn);
-            let post = n.get_post(&mut conn);
-            let post_url = post.as_ref().and_then(|p| p.url(&mut conn).ok()).unwrap_or_default();
-            (n, actor, url, post, post_url)
-        })
+        .map(|n| notification_details(&mut conn, n))
         .collect();
+    Notification::mark_all_read(&mut conn, user.id)?;
 
     Ok(render!(notifications::index_html(&(&mut conn, &rockets).to_context(), notifs, page.0, page_total)))
 }
@@ -39,3 +71,317 @@ pub fn notifications_auth(i18n: I18n, page: Option<Page>) -> Flash<Redirect> {
         uri!(notifications(page = page)),
     )
 }
+
+/// Streams the new notifications of the current user, and the articles added to their timelines, as Server-Sent
+/// Events.
+///
+/// The number of unread notifications is sent first, so that the badge is right from the start.
+#[get("/notifications/stream")]
+pub fn stream(user: User, dbpool: &State<DbPool>, intl: I18n, mut shutdown: Shutdown) -> EventStream![] {
+    let mut events = live::subscribe();
+    let dbpool = dbpool.inner().clone();
+    EventStream! {
+        let pool = dbpool.clone();
+        let user_id = user.id;
+        let unread = spawn_blocking(move || Notification::count_unread(&mut DbConn(pool.get().ok()?), user_id).ok())
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or(0);
+        yield Event::json(&json!({ "count": unread })).event("unread");
+
+        loop {
+            let event = select! {
+                event = events.recv() => match event {
+                    Ok(event) => event,
+                    Err(RecvError::Closed) => break,
+                    Err(RecvError::Lagged(missed)) => {
+                        debug!("The notification stream of user {} missed {} events", user.id, missed);
+                        continue;
+                    }
+                },
+                _ = &mut shutdown => break,
+            };
+
+            match event {
+                LiveEvent::Notification { user_id, notification_id } if user_id == user.id => {
+                    let pool = dbpool.clone();
+                    let details = spawn_blocking(move || {
+                        let mut conn = DbConn(pool.get().ok()?);
+                        let notif = Notification::get(&mut conn, notification_id).ok()?;
+                        Some(notification_details(&mut conn, notif))
+                    })
+                    .await
+                    .ok()
+                    .flatten();
+                    let (message, url) = match details {
+                        Some((notif, actor, url, _, _)) => {
+                            (Some(translate_notification(&intl.catalog, &notif, &actor)), url)
+                        }
+                        None => (None, None),
+                    };
+                    yield Event::json(&json!({
+                        "id": notification_id,
+                        "message": message,
+                        "url": url,
+                    }))
+                    .event("notification");
+                }
+                LiveEvent::TimelineEntry { timeline_id, user_id, post_id }
+                    if user_id.map_or(true, |id| id == user.id) =>
+                {
+                    yield Event::json(&json!({
+                        "timeline": timeline_id,
+                        "post": post_id,
+                    }))
+                    .event("timeline");
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[get("/notifications/settings")]
+pub fn settings(user: User, mut conn: DbConn, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
+    let frequencies = EmailPreference::frequencies_for_user(&mut conn, user.id)?;
+    let vapid_key = VapidKeys::get_or_create(&mut conn)?.public_key;
+    let push_subscriptions = PushSubscription::list_for_user(&mut conn, user.id)?;
+    Ok(render!(notifications::settings_html(
+        &(&mut conn, &rockets).to_context(),
+        frequencies,
+        vapid_key,
+        push_subscriptions
+    )))
+}
+
+#[get("/notifications/settings", rank = 2)]
+pub fn settings_auth(i18n: I18n) -> Flash<Redirect> {
+    requires_login(
+        &i18n!(i18n.catalog, "To change your notification settings, you need to be logged in"),
+        uri!(settings),
+    )
+}
+
+#[derive(FromForm)]
+pub struct EmailPreferencesForm {
+    /// The frequency of each kind of notification
+    pub frequency: HashMap<String, i32>,
+}
+
+#[post("/notifications/settings", data = "<form>")]
+pub fn update_settings(
+    form: Form<EmailPreferencesForm>,
+    user: User,
+    mut conn: DbConn,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    for (kind, frequency) in &form.frequency {
+        let frequency = EmailFrequency::try_from(*frequency).map_err(|_| Error::InvalidValue)?;
+        EmailPreference::set(&mut conn, user.id, kind, frequency, intl.lang)?;
+    }
+    Ok(Flash::success(Redirect::to(uri!(settings)), i18n!(intl.catalog, "Your notification settings have been saved.")))
+}
+
+#[derive(FromForm)]
+pub struct PushSubscriptionForm {
+    /// The subscription given by the browser, serialized as JSON
+    pub subscription: String,
+}
+
+#[post("/notifications/push", data = "<form>")]
+pub fn push_subscribe(
+    form: Form<PushSubscriptionForm>,
+    user: User,
+    mut conn: DbConn,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let subscription: serde_json::Value = serde_json::from_str(&form.subscription).map_err(Error::from)?;
+    PushSubscription::subscribe(
+        &mut conn,
+        NewPushSubscription {
+            user_id: user.id,
+            endpoint: subscription["endpoint"].as_str().ok_or(Error::InvalidValue)?,
+            p256dh: subscription["keys"]["p256dh"].as_str().ok_or(Error::InvalidValue)?,
+            auth: subscription["keys"]["auth"].as_str().ok_or(Error::InvalidValue)?,
+            lang: intl.lang,
+        },
+    )?;
+    Ok(Flash::success(
+        Redirect::to(uri!(settings)),
+        i18n!(intl.catalog, "This browser will now show your notifications."),
+    ))
+}
+
+#[post("/notifications/push/<id>/delete")]
+pub fn push_unsubscribe(id: i32, user: User, mut conn: DbConn, intl: I18n) -> Result<Flash<Redirect>, ErrorPage> {
+    let subscription = PushSubscription::get(&mut conn, id)?;
+    if subscription.user_id != user.id {
+        return Err(Error::Unauthorized.into());
+    }
+    subscription.delete(&mut conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(settings)),
+        i18n!(intl.catalog, "This browser won't show your notifications anymore."),
+    ))
+}
+
+/// Sends mentions, comments and follows to the browsers of their recipients as Web Push messages.
+///
+/// Run regularly by a scheduled job. Each browser remembers the last notification it was sent, so none is missed
+/// when the push services are slow. The browsers are reached a few at a time.
+pub fn send_push_notifications(conn: &mut Connection, translations: &Translations) {
+    let subscriptions = match PushSubscription::list_pending(conn) {
+        Ok(subscriptions) => subscriptions,
+        Err(e) => {
+            warn!("Couldn't list the push notifications to send: {:?}", e);
+            return;
+        }
+    };
+    if subscriptions.is_empty() {
+        return;
+    }
+    let keys = match VapidKeys::get_or_create(conn) {
+        Ok(keys) => keys,
+        Err(e) => {
+            warn!("Couldn't load the keys to sign push notifications with: {:?}", e);
+            return;
+        }
+    };
+
+    let mut messages = vec![];
+    for subscription in subscriptions {
+        let cat =
+            match translations.iter().find(|(lang, _)| *lang == subscription.lang).or_else(|| translations.first()) {
+                Some((_, cat)) => cat,
+                None => return,
+            };
+        let notifications = subscription.pending(conn).unwrap_or_default();
+        let last_id = match notifications.last() {
+            Some(n) => n.id,
+            None => continue,
+        };
+        let payloads = notifications
+            .into_iter()
+            .map(|n| {
+                let (n, actor, url, _, _) = notification_details(conn, n);
+                json!({
+                    "title": CONFIG.base_url,
+                    "body": translate_notification(cat, &n, &actor),
+                    "url": url.map(|url| format!("https://{}{}", CONFIG.base_url, url)),
+                })
+                .to_string()
+            })
+            .collect::<Vec<_>>();
+        messages.push((subscription, payloads, last_id));
+    }
+
+    for batch in messages.chunks(PUSH_CONCURRENCY) {
+        let results = std::thread::scope(|scope| {
+            batch
+                .iter()
+                .map(|(subscription, payloads, _)| {
+                    let keys = &keys;
+                    scope.spawn(move || {
+                        payloads.iter().try_for_each(|payload| subscription.push(keys, payload.as_bytes()))
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or(Err(Error::Request)))
+                .collect::<Vec<_>>()
+        });
+        for ((subscription, _, last_id), res) in batch.iter().zip(results) {
+            let res = match res {
+                Err(Error::NotFound) => subscription.delete(conn),
+                Err(e) => {
+                    warn!("Couldn't send a push notification to {}: {:?}", subscription.endpoint, e);
+                    subscription.mark_pushed(conn, *last_id)
+                }
+                Ok(()) => subscription.mark_pushed(conn, *last_id),
+            };
+            if let Err(e) = res {
+                warn!("Couldn't update the push subscription {}: {:?}", subscription.id, e);
+            }
+        }
+    }
+}
+
+/// Mails the notifications that are due to the users who asked for it.
+///
+/// Run regularly by a scheduled job, so that the same code handles immediate mails and digests.
+pub fn send_digests(conn: &mut Connection, mail: &Arc<Mutex<Mailer>>, translations: &Translations) {
+    let now = chrono::Utc::now().naive_utc();
+    let digests = match EmailPreference::due(conn, now) {
+        Ok(digests) => digests,
+        Err(e) => {
+            warn!("Couldn't gather the notifications to mail: {:?}", e);
+            return;
+        }
+    };
+    let settings_url = format!("https://{}{}", CONFIG.base_url, uri!(settings));
+
+    for digest in digests {
+        let email = match digest.user.email {
+            Some(ref email) => email.clone(),
+            None => continue,
+        };
+        let cat = match translations.iter().find(|(lang, _)| *lang == digest.lang).or_else(|| translations.first()) {
+            Some((_, cat)) => cat,
+            None => return,
+        };
+        let absolute = |url: String| format!("https://{}{}", CONFIG.base_url, url);
+        let notifications = digest
+            .notifications
+            .iter()
+            .cloned()
+            .map(|n| {
+                let (n, actor, url, post, post_url) = notification_details(conn, n);
+                (n, actor, url.map(absolute), post, absolute(post_url))
+            })
+            .collect::<Vec<_>>();
+
+        let text = notifications
+            .iter()
+            .map(|(n, actor, url, _, _)| match url {
+                Some(url) => format!("- {} {}", translate_notification(cat, n, actor), url),
+                None => format!("- {}", translate_notification(cat, n, actor)),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let text = format!(
+            "{}\n\n{}\n\n-- \n{}: {}\n",
+            i18n!(cat, "Hello {0}, here is what happened since our last mail:"; digest.user.name()),
+            text,
+            i18n!(cat, "Change which notifications you receive by email"),
+            settings_url
+        );
+        let html = render!(notifications::email_html(cat, &digest.user, notifications, &settings_url)).0;
+        let message = match build_html_mail(
+            &email,
+            i18n!(cat, "You have {0} new notification", "You have {0} new notifications"; digest.notifications.len()),
+            text,
+            String::from_utf8_lossy(&html).into_owned(),
+        ) {
+            Some(message) => message,
+            None => continue,
+        };
+
+        let res = match *mail.lock().unwrap() {
+            Some(ref mut mailer) => mailer.send(&message),
+            None => return,
+        };
+        match res {
+            Ok(_) => {}
+            // Don't try again and again to send to an address that doesn't exist
+            Err(e) if e.is_permanent() => warn!("Notifications refused for {}: {}", email, e),
+            Err(e) => {
+                warn!("Couldn't mail the notifications of {}: {}", digest.user.fqn, e);
+                continue;
+            }
+        }
+        if let Err(e) = digest.mark_sent(conn) {
+            warn!("Couldn't mark the notifications of {} as mailed: {:?}", digest.user.fqn, e);
+        }
+    }
+}
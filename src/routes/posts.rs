@@ -1,29 +1,37 @@
 use chrono::Utc;
 use rocket::form::Form;
 use rocket::response::{Flash, Redirect};
-use rocket_i18n::I18n;
+use rocket::State;
+use rocket_i18n::{I18n, Translations};
 use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
     time::Duration,
 };
+use tracing::warn;
 use validator::{Validate, ValidationError, ValidationErrors};
 
-use crate::routes::{comments::NewCommentForm, errors::ErrorPage, ContentLen, Page, RemoteForm, RespondOrRedirect};
+use crate::mail::Mailer;
+use crate::routes::{
+    comments::NewCommentForm, errors::ErrorPage, newsletters, ContentLen, Page, RemoteForm, RespondOrRedirect,
+};
 use crate::template_utils::{IntoContext, PostCard, Ructe};
 use crate::utils::requires_login;
 use plume_common::activity_pub::{broadcast, ActivityStream, ApRequest, LicensedArticle};
 use plume_common::utils::md_to_html;
 use plume_models::{
+    authorized_fetch::AuthorizedFetch,
     blogs::*,
     comments::{Comment, CommentTree},
-    db_conn::DbConn,
+    db_conn::{DbConn, DbPool},
     inbox::inbox,
     instance::Instance,
     medias::Media,
     mentions::Mention,
     post_authors::*,
     posts::*,
+    relays::Relay,
     safe_string::SafeString,
     tags::*,
     timeline::*,
@@ -99,6 +107,7 @@ pub async fn details(
     let cover_url = post.cover_url(&mut conn).unwrap_or_default();
     let author_avatar_url = author.avatar_url(&mut conn);
     let is_author = user.as_ref().and_then(|u| post.is_author(&mut conn, u.id).ok()).unwrap_or(false);
+    let original_url = post.original_url(&mut conn)?;
 
     Ok(render!(posts::details_html(
         &(&mut conn, &rockets).to_context(),
@@ -117,7 +126,8 @@ pub async fn details(
         is_following,
         author,
         author_avatar_url,
-        is_author
+        is_author,
+        original_url
     )))
 }
 
@@ -126,6 +136,7 @@ pub async fn activity_details(
     blog: &str,
     slug: &str,
     _ap: ApRequest,
+    _fetch: AuthorizedFetch,
     mut conn: DbConn,
 ) -> Result<ActivityStream<LicensedArticle>, Option<String>> {
     let blog = Blog::find_by_fqn(&mut conn, blog).await.map_err(|_| None)?;
@@ -192,6 +203,12 @@ pub async fn edit(
             i18n!(intl, "You are not an author of this blog.")
         )));
     }
+    if post.original_url(&mut conn)?.is_some() {
+        return Ok(render!(errors::not_authorized_html(
+            &(&mut conn, &rockets).to_context(),
+            i18n!(intl, "This article was imported from another website, it can't be edited here.")
+        )));
+    }
 
     let source = if !post.source.is_empty() {
         post.source.clone()
@@ -242,9 +259,12 @@ pub async fn update(
     slug: &str,
     cl: ContentLen,
     form: Form<NewPostForm>,
+    mail: &State<Arc<Mutex<Mailer>>>,
+    dbpool: &State<DbPool>,
+    translations: &State<Translations>,
     mut conn: DbConn,
     rockets: PlumeRocket,
-) -> RespondOrRedirect {
+) -> Result<RespondOrRedirect, ErrorPage> {
     let b = Blog::find_by_fqn(&mut conn, blog).await.expect("post::update: blog error");
     let mut post = Post::find_by_slug(&mut conn, slug, b.id).expect("post::update: find by slug error");
     let user = rockets.user.clone().unwrap();
@@ -272,7 +292,7 @@ pub async fn update(
         );
     }
 
-    if errors.is_empty() {
+    Ok(if errors.is_empty() {
         if !user.is_author_in(&mut conn, &b).expect("posts::update: is author in error") {
             // actually it's not "Ok"…
             Flash::error(
@@ -280,6 +300,12 @@ pub async fn update(
                 i18n!(&intl, "You are not allowed to publish on this blog."),
             )
             .into()
+        } else if post.original_url(&mut conn)?.is_some() {
+            Flash::error(
+                Redirect::to(uri!(details(blog = blog, slug = slug, responding_to = _))),
+                i18n!(&intl, "This article was imported from another website, it can't be edited here."),
+            )
+            .into()
         } else {
             let authors = b.list_authors(&mut conn).expect("Could not get author list");
 
@@ -343,9 +369,19 @@ pub async fn update(
                 if newly_published {
                     let act = post.create_activity(&mut conn).expect("post::update: act error");
                     let dest = User::one_by_instance(&mut conn).expect("post::update: dest error");
-                    rockets.worker.execute(move || broadcast(&user, act, dest, CONFIG.proxy().cloned()));
+                    let relays = Relay::list_accepted(&mut conn)?;
+                    let announce = Relay::announce_for(&relays, &post)?;
+                    rockets.worker.execute(move || {
+                        broadcast(&user, act.clone(), dest, CONFIG.proxy().cloned());
+                        Relay::share(&user, act, announce, relays);
+                    });
 
                     Timeline::add_to_all_timelines(&mut conn, &post, &Kind::Original).await.ok();
+                    if let Err(e) =
+                        newsletters::send_post(&mut conn, &post, mail, dbpool, &rockets.worker, translations)
+                    {
+                        warn!("Couldn't send the newsletter of {}: {:?}", post.ap_url, e);
+                    }
                 } else {
                     let act = post.update_activity(&mut conn).expect("post::update: act error");
                     let dest = User::one_by_instance(&mut conn).expect("posts::update: dest error");
@@ -374,7 +410,7 @@ pub async fn update(
             cl.0
         ))
         .into()
-    }
+    })
 }
 
 #[derive(Default, FromForm, Validate)]
@@ -405,6 +441,9 @@ pub async fn create(
     blog_name: &str,
     form: Form<NewPostForm>,
     cl: ContentLen,
+    mail: &State<Arc<Mutex<Mailer>>>,
+    dbpool: &State<DbPool>,
+    translations: &State<Translations>,
     mut conn: DbConn,
     rockets: PlumeRocket,
 ) -> Result<RespondOrRedirect, ErrorPage> {
@@ -507,10 +546,19 @@ pub async fn create(
 
             let act = post.create_activity(&mut conn).expect("posts::create: activity error");
             let dest = User::one_by_instance(&mut conn).expect("posts::create: dest error");
+            // Public articles are also shared through the relays
+            let relays = Relay::list_accepted(&mut conn)?;
+            let announce = Relay::announce_for(&relays, &post)?;
             let worker = &rockets.worker;
-            worker.execute(move || broadcast(&user, act, dest, CONFIG.proxy().cloned()));
+            worker.execute(move || {
+                broadcast(&user, act.clone(), dest, CONFIG.proxy().cloned());
+                Relay::share(&user, act, announce, relays);
+            });
 
             Timeline::add_to_all_timelines(&mut conn, &post, &Kind::Original).await?;
+            if let Err(e) = newsletters::send_post(&mut conn, &post, mail, dbpool, worker, translations) {
+                warn!("Couldn't send the newsletter of {}: {:?}", post.ap_url, e);
+            }
         }
 
         Ok(Flash::success(
@@ -1,8 +1,13 @@
-use crate::routes::Page;
-use crate::template_utils::{IntoContext, PostCard, Ructe};
+use crate::routes::{errors::ErrorPage, Page};
+use crate::template_utils::{encode_query_param, IntoContext, PostCard, Ructe};
 use chrono::offset::Utc;
-use plume_models::{db_conn::DbConn, search::Query, PlumeRocket};
-use rocket::http::uri::fmt::{Ignorable, Query as RocketQuery};
+use plume_models::{db_conn::DbConn, search::Query, timeline::Timeline, users::User, PlumeRocket, ITEMS_PER_PAGE};
+use rocket::{
+    form::Form,
+    http::uri::fmt::{Ignorable, Query as RocketQuery},
+    response::{Flash, Redirect},
+};
+use rocket_i18n::I18n;
 use std::str::FromStr;
 
 #[derive(Default, FromForm)]
@@ -79,3 +84,40 @@ pub fn search(query: SearchQuery, mut conn: DbConn, rockets: PlumeRocket) -> Ruc
         render!(search::result_html(&(&mut conn, &rockets).to_context(), &str_query, pc, page.0, next_page))
     }
 }
+
+#[derive(Default, FromForm)]
+pub struct SaveSearchForm {
+    pub name: String,
+    pub query: String,
+    pub notify: bool,
+}
+
+#[post("/search/save", data = "<form>")]
+pub fn save(
+    form: Form<SaveSearchForm>,
+    user: User,
+    mut conn: DbConn,
+    rockets: PlumeRocket,
+    intl: I18n,
+) -> Result<Flash<Redirect>, ErrorPage> {
+    let name = form.name.trim();
+    if name.is_empty() || form.query.trim().is_empty() {
+        return Ok(Flash::error(
+            Redirect::to(format!("/search?q={}", encode_query_param(&form.query))),
+            i18n!(intl.catalog, "Please give a name to this timeline."),
+        ));
+    }
+
+    let timeline = Timeline::new_saved_search(&mut conn, user.id, name.to_owned(), &form.query, form.notify)?;
+
+    // Fill the new timeline with the articles that already match
+    let query = Query::from_str(&form.query).unwrap_or_default();
+    for post in rockets.searcher.search_document(&mut conn, query, (0, ITEMS_PER_PAGE)) {
+        timeline.add_post(&mut conn, &post)?;
+    }
+
+    Ok(Flash::success(
+        Redirect::to(uri!(super::timelines::details(id = timeline.id, page = _))),
+        i18n!(intl.catalog, "Your search has been saved as a timeline."),
+    ))
+}
@@ -1,6 +1,7 @@
-use crate::routes::{errors::ErrorPage, Page};
+use crate::routes::{errors::ErrorPage, FeedContent, FeedFormat, FeedInfo, Page};
 use crate::template_utils::{IntoContext, PostCard, Ructe};
-use plume_models::{db_conn::DbConn, posts::Post, PlumeRocket};
+use plume_models::{db_conn::DbConn, instance::Instance, posts::Post, Connection, PlumeRocket, CONFIG};
+use rocket::http::ContentType;
 
 #[get("/tag/<name>?<page>")]
 pub fn tag(name: &str, page: Option<Page>, mut conn: DbConn, rockets: PlumeRocket) -> Result<Ructe, ErrorPage> {
@@ -12,3 +13,37 @@ pub fn tag(name: &str, page: Option<Page>, mut conn: DbConn, rockets: PlumeRocke
 
     Ok(render!(tags::index_html(&(&mut conn, &rockets).to_context(), name.to_string(), posts, page.0, page_total)))
 }
+
+fn feed(
+    name: &str,
+    format: FeedFormat,
+    content: Option<FeedContent>,
+    conn: &mut Connection,
+) -> Option<(ContentType, String)> {
+    let entries = Post::list_by_tag(conn, name.to_string(), (0, 15)).ok()?;
+    let instance = Instance::get_local().ok()?;
+    let uri = instance.compute_box("tag", name, format.file_name());
+    let home_page = format!("https://{}{}", CONFIG.base_url, uri!(tag(name = name, page = _)));
+    let info = FeedInfo {
+        uri: &uri,
+        home_page: &home_page,
+        title: &format!("#{}", name),
+        default_updated: &instance.creation_date,
+    };
+    Some(super::build_feed(format, content.unwrap_or_default(), entries, info, conn))
+}
+
+#[get("/tag/<name>/atom.xml?<content>")]
+pub fn atom_feed(name: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    feed(name, FeedFormat::Atom, content, &mut conn)
+}
+
+#[get("/tag/<name>/rss.xml?<content>")]
+pub fn rss_feed(name: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    feed(name, FeedFormat::Rss, content, &mut conn)
+}
+
+#[get("/tag/<name>/feed.json?<content>")]
+pub fn json_feed(name: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    feed(name, FeedFormat::Json, content, &mut conn)
+}
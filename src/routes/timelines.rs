@@ -1,34 +1,221 @@
 #![allow(dead_code)]
 
-use crate::routes::Page;
+use crate::routes::{FeedContent, FeedFormat, FeedInfo, Page};
 use crate::template_utils::IntoContext;
 use crate::{
     routes::errors::ErrorPage,
     template_utils::{PostCard, Ructe},
 };
-use plume_models::{db_conn::DbConn, timeline::*, PlumeRocket};
-use rocket::response::Redirect;
+use plume_models::{
+    db_conn::DbConn, instance::Instance, posts::Post, timeline::*, users::User, Connection, Error, PlumeRocket, CONFIG,
+    ITEMS_PER_PAGE,
+};
+use rocket::{
+    form::Form,
+    http::ContentType,
+    response::{Flash, Redirect},
+};
+use rocket_i18n::I18n;
+
+/// Where the current user is at in a timeline
+pub struct ReadingState {
+    /// The number of posts they didn't read yet
+    pub unread: i64,
+    /// The page where the last post they read is, if they ever marked this timeline as read. Until they do, no post
+    /// is unread.
+    pub left_off_page: Option<i32>,
+    /// The most recent post of the current page
+    pub newest_post: Option<i32>,
+}
 
 #[get("/timeline/<id>?<page>")]
 pub fn details(id: i32, mut conn: DbConn, rockets: PlumeRocket, page: Option<Page>) -> Result<Ructe, ErrorPage> {
     let page = page.unwrap_or_default();
-    let all_tl = Timeline::list_all_for_user(&mut conn, rockets.user.clone().map(|u| u.id))?;
+    let user_id = rockets.user.as_ref().map(|u| u.id);
+    let all_tl = Timeline::list_all_for_user(&mut conn, user_id)?
+        .into_iter()
+        .map(|t| {
+            let unread = match user_id {
+                Some(user_id) => t.count_unread(&mut conn, user_id)?,
+                None => 0,
+            };
+            Ok((t, unread))
+        })
+        .collect::<Result<Vec<_>, ErrorPage>>()?;
     let tl = Timeline::get(&mut conn, id)?;
+    if !tl.is_visible_to(user_id) {
+        return Err(Error::Unauthorized.into());
+    }
     let total_posts = tl.count_posts(&mut conn)?;
 
     let pages = tl.get_page(&mut conn, page.limits())?;
+    let reading = match user_id {
+        Some(user_id) => {
+            // The marker is only created when the user marks the timeline as read for the first time
+            let marker = TimelineMarker::find_for_user(&mut conn, user_id, &tl)?;
+            let unread = tl.count_unread(&mut conn, user_id)?;
+            Some(ReadingState {
+                unread,
+                left_off_page: marker.map(|_| (unread / i64::from(ITEMS_PER_PAGE)) as i32 + 1),
+                newest_post: pages.first().map(|p| p.id),
+            })
+        }
+        None => None,
+    };
     let posts = PostCard::from_posts(&mut conn, pages, &rockets.user);
+    let owner = tl.user_id.is_some() && tl.user_id == user_id;
+    // Only the owner of the timeline cares about its reevaluation
+    let progress = tl.reevaluation_progress().filter(|_| owner);
 
     Ok(render!(timelines::details_html(
         &(&mut conn, &rockets).to_context(),
         tl,
         posts,
         all_tl,
+        progress,
+        reading,
+        owner,
         page.0,
         Page::total(total_posts as i32)
     )))
 }
 
+#[derive(FromForm)]
+pub struct MarkReadForm {
+    /// The most recent post that was read
+    pub post: i32,
+}
+
+#[post("/timeline/<id>/read?<page>", data = "<form>")]
+pub fn mark_read(
+    id: i32,
+    page: Option<Page>,
+    form: Form<MarkReadForm>,
+    user: User,
+    mut conn: DbConn,
+) -> Result<Redirect, ErrorPage> {
+    let tl = Timeline::get(&mut conn, id)?;
+    if !tl.is_visible_to(Some(user.id)) {
+        return Err(Error::Unauthorized.into());
+    }
+    let post = Post::get(&mut conn, form.post)?;
+    if !tl.includes_post(&mut conn, &post)? {
+        return Err(Error::NotFound.into());
+    }
+    TimelineMarker::advance(&mut conn, user.id, &tl, &post)?;
+    Ok(Redirect::to(uri!(details(id = id, page = page))))
+}
+
+fn feed(
+    tl: &Timeline,
+    uri: &str,
+    format: FeedFormat,
+    content: Option<FeedContent>,
+    conn: &mut Connection,
+) -> Option<(ContentType, String)> {
+    let entries = tl.get_latest(conn, 15).ok()?;
+    let home_page = format!("https://{}{}", CONFIG.base_url, uri!(details(id = tl.id, page = _)));
+    let instance = Instance::get_local().ok()?;
+    let info = FeedInfo {
+        uri,
+        home_page: &home_page,
+        title: &tl.name,
+        default_updated: &instance.creation_date,
+    };
+    Some(super::build_feed(format, content.unwrap_or_default(), entries, info, conn))
+}
+
+fn public_feed(
+    id: i32,
+    format: FeedFormat,
+    content: Option<FeedContent>,
+    conn: &mut Connection,
+) -> Option<(ContentType, String)> {
+    let tl = Timeline::get(conn, id).ok()?;
+    // Personal timelines are only available with their private feed token
+    if tl.user_id.is_some() {
+        return None;
+    }
+    let uri = Instance::get_local().ok()?.compute_box("timeline", &id.to_string(), format.file_name());
+    feed(&tl, &uri, format, content, conn)
+}
+
+fn private_feed(
+    token: &str,
+    format: FeedFormat,
+    content: Option<FeedContent>,
+    conn: &mut Connection,
+) -> Option<(ContentType, String)> {
+    let tl = Timeline::find_by_feed_token(conn, token).ok()?;
+    let uri = Instance::get_local().ok()?.compute_box("timeline/feed", token, format.file_name());
+    feed(&tl, &uri, format, content, conn)
+}
+
+#[get("/timeline/<id>/atom.xml?<content>")]
+pub fn atom_feed(id: i32, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    public_feed(id, FeedFormat::Atom, content, &mut conn)
+}
+
+#[get("/timeline/<id>/rss.xml?<content>")]
+pub fn rss_feed(id: i32, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    public_feed(id, FeedFormat::Rss, content, &mut conn)
+}
+
+#[get("/timeline/<id>/feed.json?<content>")]
+pub fn json_feed(id: i32, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    public_feed(id, FeedFormat::Json, content, &mut conn)
+}
+
+#[get("/timeline/feed/<token>/atom.xml?<content>")]
+pub fn private_atom_feed(token: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    private_feed(token, FeedFormat::Atom, content, &mut conn)
+}
+
+#[get("/timeline/feed/<token>/rss.xml?<content>")]
+pub fn private_rss_feed(token: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    private_feed(token, FeedFormat::Rss, content, &mut conn)
+}
+
+#[get("/timeline/feed/<token>/feed.json?<content>")]
+pub fn private_json_feed(token: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    private_feed(token, FeedFormat::Json, content, &mut conn)
+}
+
+/// Returns the timeline `id` if it is a personal timeline of `user`
+fn own_timeline(conn: &mut Connection, id: i32, user: &User) -> Result<Timeline, ErrorPage> {
+    let tl = Timeline::get(conn, id)?;
+    if tl.user_id == Some(user.id) {
+        Ok(tl)
+    } else {
+        Err(Error::Unauthorized.into())
+    }
+}
+
+#[post("/timeline/<id>/feed-token")]
+pub fn reset_feed_token(id: i32, user: User, mut conn: DbConn, intl: I18n) -> Result<Flash<Redirect>, ErrorPage> {
+    let tl = own_timeline(&mut conn, id, &user)?;
+    let message = if tl.feed_token.is_some() {
+        i18n!(
+            intl.catalog,
+            "The private feeds of this timeline have new addresses. The previous ones don't work anymore."
+        )
+    } else {
+        i18n!(intl.catalog, "The private feeds of this timeline are now enabled.")
+    };
+    tl.reset_feed_token(&mut conn)?;
+    Ok(Flash::success(Redirect::to(uri!(details(id = id, page = _))), message))
+}
+
+#[post("/timeline/<id>/feed-token/revoke")]
+pub fn revoke_feed_token(id: i32, user: User, mut conn: DbConn, intl: I18n) -> Result<Flash<Redirect>, ErrorPage> {
+    let tl = own_timeline(&mut conn, id, &user)?;
+    tl.revoke_feed_token(&mut conn)?;
+    Ok(Flash::success(
+        Redirect::to(uri!(details(id = id, page = _))),
+        i18n!(intl.catalog, "The private feeds of this timeline are now disabled."),
+    ))
+}
+
 // TODO
 
 #[get("/timeline/new")]
@@ -13,25 +13,30 @@ use std::{borrow::Cow, collections::HashMap};
 use validator::{Validate, ValidationError, ValidationErrors};
 
 use crate::inbox as crate_inbox;
-use crate::routes::{email_signups::EmailSignupForm, errors::ErrorPage, Page, RemoteForm, RespondOrRedirect};
+use crate::routes::{
+    email_signups::EmailSignupForm, errors::ErrorPage, FeedContent, FeedFormat, FeedInfo, Page, RemoteForm,
+    RespondOrRedirect,
+};
 use crate::template_utils::{default_avatar, IntoContext, PostCard, Ructe};
 use crate::utils::requires_login;
 use plume_common::activity_pub::{broadcast, ActivityStream, ApRequest, CustomPerson};
 use plume_common::utils::md_to_html;
 use plume_models::{
+    authorized_fetch::AuthorizedFetch,
     blogs::Blog,
     db_conn::DbConn,
     follows,
     headers::Headers,
     inbox::inbox as local_inbox,
     instance::Instance,
+    live,
     medias::Media,
     posts::Post,
     reshares::Reshare,
     safe_string::SafeString,
     signups::{self, Strategy as SignupStrategy},
     users::*,
-    Error, PlumeRocket, CONFIG,
+    Connection, Error, PlumeRocket, CONFIG,
 };
 
 #[get("/me")]
@@ -112,15 +117,18 @@ pub async fn follow(
         rockets.worker.execute(move || broadcast(&user, delete_act, vec![target], CONFIG.proxy().cloned()));
         msg
     } else {
-        let f = follows::Follow::insert(
-            &mut conn,
-            follows::NewFollow {
-                follower_id: user.id,
-                following_id: target.id,
-                ap_url: String::new(),
-            },
-        )?;
-        f.notify(&mut conn)?;
+        let f = live::transaction(&mut conn, |conn| {
+            let f = follows::Follow::insert(
+                conn,
+                follows::NewFollow {
+                    follower_id: user.id,
+                    following_id: target.id,
+                    ap_url: String::new(),
+                },
+            )?;
+            f.notify(conn)?;
+            Ok(f)
+        })?;
 
         let act = f.to_activity(&mut conn)?;
         let msg = i18n!(rockets.intl.catalog, "You are now following {}."; target.name());
@@ -258,7 +266,12 @@ pub async fn followed(
 }
 
 #[get("/@/<name>", rank = 1)]
-pub async fn activity_details(name: String, mut conn: DbConn, _ap: ApRequest) -> Option<ActivityStream<CustomPerson>> {
+pub async fn activity_details(
+    name: String,
+    mut conn: DbConn,
+    _ap: ApRequest,
+    _fetch: AuthorizedFetch,
+) -> Option<ActivityStream<CustomPerson>> {
     let user = User::find_by_fqn(&mut conn, &name).await.ok()?;
     Some(ActivityStream::new(user.to_activity(&mut conn).ok()?))
 }
@@ -324,7 +337,7 @@ pub fn update(
     mut conn: DbConn,
     mut user: User,
     form: Form<UpdateUserForm>,
-    intl: I18n,
+    rockets: PlumeRocket,
 ) -> Result<Flash<Redirect>, ErrorPage> {
     user.display_name = form.display_name.clone();
     user.email = Some(form.email.clone());
@@ -342,7 +355,11 @@ pub fn update(
     user.hide_custom_css = form.hide_custom_css;
     user.save(&mut conn)?;
 
-    Ok(Flash::success(Redirect::to(uri!(me)), i18n!(intl.catalog, "Your profile has been updated.")))
+    let act = user.update_activity(&mut conn)?;
+    let followers = user.get_followers(&mut conn)?;
+    rockets.worker.execute(move || broadcast(&user, act, followers, CONFIG.proxy().cloned()));
+
+    Ok(Flash::success(Redirect::to(uri!(me)), i18n!(rockets.intl.catalog, "Your profile has been updated.")))
 }
 
 #[post("/@/<name>/delete")]
@@ -482,12 +499,21 @@ pub fn create(
 }
 
 #[get("/@/<name>/outbox")]
-pub async fn outbox(name: &str, mut conn: DbConn) -> Option<ActivityStream<OrderedCollection>> {
+pub async fn outbox(
+    name: &str,
+    mut conn: DbConn,
+    _fetch: AuthorizedFetch,
+) -> Option<ActivityStream<OrderedCollection>> {
     let user = User::find_by_fqn(&mut conn, name).await.ok()?;
     user.outbox(&mut conn).ok()
 }
 #[get("/@/<name>/outbox?<page>")]
-pub async fn outbox_page(name: &str, page: Page, mut conn: DbConn) -> Option<ActivityStream<OrderedCollectionPage>> {
+pub async fn outbox_page(
+    name: &str,
+    page: Page,
+    mut conn: DbConn,
+    _fetch: AuthorizedFetch,
+) -> Option<ActivityStream<OrderedCollectionPage>> {
     let user = User::find_by_fqn(&mut conn, name).await.ok()?;
     user.outbox_page(&mut conn, page.limits()).ok()
 }
@@ -497,13 +523,18 @@ pub async fn inbox(
     data: crate::inbox::SignedJson<serde_json::Value>,
     headers: Headers<'_>,
     mut conn: DbConn,
-) -> Result<String, status::BadRequest<&'static str>> {
+) -> Result<status::Accepted<()>, status::BadRequest<&'static str>> {
     User::find_by_fqn(&mut conn, name).await.map_err(|_| status::BadRequest("User not found"))?;
     crate_inbox::handle_incoming(conn, data, headers).await
 }
 
 #[get("/@/<name>/followers", rank = 1)]
-pub async fn ap_followers(name: &str, mut conn: DbConn, _ap: ApRequest) -> Option<ActivityStream<OrderedCollection>> {
+pub async fn ap_followers(
+    name: &str,
+    mut conn: DbConn,
+    _ap: ApRequest,
+    _fetch: AuthorizedFetch,
+) -> Option<ActivityStream<OrderedCollection>> {
     let user = User::find_by_fqn(&mut conn, name).await.ok()?;
     let followers = user
         .get_followers(&mut conn)
@@ -519,14 +550,35 @@ pub async fn ap_followers(name: &str, mut conn: DbConn, _ap: ApRequest) -> Optio
     Some(ActivityStream::new(coll))
 }
 
-#[get("/@/<name>/atom.xml")]
-pub async fn atom_feed(name: &str, mut conn: DbConn) -> Option<(ContentType, String)> {
-    let conn = &mut conn;
+async fn feed(
+    name: &str,
+    format: FeedFormat,
+    content: Option<FeedContent>,
+    conn: &mut Connection,
+) -> Option<(ContentType, String)> {
     let author = User::find_by_fqn(conn, name).await.ok()?;
     let entries = Post::get_recents_for_author(conn, &author, 15).ok()?;
-    let uri = Instance::get_local().ok()?.compute_box("@", name, "atom.xml");
-    let title = &author.display_name;
-    let default_updated = &author.creation_date;
-    let feed = super::build_atom_feed(entries, &uri, title, default_updated, conn);
-    Some((ContentType::new("application", "atom+xml"), feed.to_string()))
+    let uri = Instance::get_local().ok()?.compute_box("@", name, format.file_name());
+    let info = FeedInfo {
+        uri: &uri,
+        home_page: &author.ap_url,
+        title: &author.display_name,
+        default_updated: &author.creation_date,
+    };
+    Some(super::build_feed(format, content.unwrap_or_default(), entries, info, conn))
+}
+
+#[get("/@/<name>/atom.xml?<content>")]
+pub async fn atom_feed(name: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    feed(name, FeedFormat::Atom, content, &mut conn).await
+}
+
+#[get("/@/<name>/rss.xml?<content>")]
+pub async fn rss_feed(name: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    feed(name, FeedFormat::Rss, content, &mut conn).await
+}
+
+#[get("/@/<name>/feed.json?<content>")]
+pub async fn json_feed(name: &str, content: Option<FeedContent>, mut conn: DbConn) -> Option<(ContentType, String)> {
+    feed(name, FeedFormat::Json, content, &mut conn).await
 }
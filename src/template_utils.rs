@@ -1,4 +1,7 @@
-use plume_models::{comments::CommentTree, db_conn::DbConn, notifications::*, users::User, PlumeRocket};
+use plume_models::{
+    comments::CommentTree, db_conn::DbConn, email_preferences::EmailFrequency, lists::ListType, notifications::*,
+    users::User, PlumeRocket,
+};
 
 use crate::templates::Html;
 use gettext::Catalog;
@@ -79,10 +82,32 @@ pub fn translate_notification(cat: &Catalog, notif: &Notification, actor: &Optio
         notification_kind::LIKE => i18n!(cat, "{0} liked your article."; &name),
         notification_kind::MENTION => i18n!(cat, "{0} mentioned you."; &name),
         notification_kind::RESHARE => i18n!(cat, "{0} boosted your article."; &name),
+        notification_kind::TIMELINE => i18n!(cat, "{0} published an article matching one of your timelines."; &name),
         _ => unreachable!("translate_notification: Unknow type"),
     }
 }
 
+/// The name of a kind of notification, for the email settings
+pub fn i18n_notification_kind(cat: &Catalog, kind: &str) -> String {
+    match kind {
+        notification_kind::COMMENT => i18n!(cat, "Comments on your articles"),
+        notification_kind::FOLLOW => i18n!(cat, "New subscribers"),
+        notification_kind::LIKE => i18n!(cat, "Likes"),
+        notification_kind::MENTION => i18n!(cat, "Mentions"),
+        notification_kind::RESHARE => i18n!(cat, "Boosts"),
+        _ => kind.to_owned(),
+    }
+}
+
+pub fn i18n_email_frequency(cat: &Catalog, frequency: EmailFrequency) -> String {
+    match frequency {
+        EmailFrequency::Off => i18n!(cat, "Never"),
+        EmailFrequency::Immediate => i18n!(cat, "Right away"),
+        EmailFrequency::Daily => i18n!(cat, "Once a day"),
+        EmailFrequency::Weekly => i18n!(cat, "Once a week"),
+    }
+}
+
 pub fn i18n_timeline_name(cat: &Catalog, tl: &str) -> String {
     match tl {
         "Your feed" => i18n!(cat, "Your feed"),
@@ -93,6 +118,24 @@ pub fn i18n_timeline_name(cat: &Catalog, tl: &str) -> String {
     }
 }
 
+/// The number of unread articles, to show next to the name of a timeline
+pub fn unread_badge(unread: i64) -> String {
+    match unread {
+        0 => String::new(),
+        1..=99 => format!(r#" <span class="badge">{}</span>"#, unread),
+        _ => r#" <span class="badge">99+</span>"#.to_owned(),
+    }
+}
+
+pub fn i18n_list_kind(cat: &Catalog, kind: ListType) -> String {
+    match kind {
+        ListType::User => i18n!(cat, "Users"),
+        ListType::Blog => i18n!(cat, "Blogs"),
+        ListType::Word => i18n!(cat, "Words"),
+        ListType::Prefix => i18n!(cat, "Prefixes"),
+    }
+}
+
 pub enum Size {
     Small,
     Medium,